@@ -0,0 +1,19 @@
+#![no_main]
+/* Copyright James Lomax 2020 */
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use raildata_core::{Station, StationList};
+use raildata_core::fixed_links::{parse_fixed_links, parse_fixed_links_lenient};
+
+fuzz_target!(|data: &[u8]| {
+    let stations = StationList::new(vec![
+        Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+        Station::simple("KNGX", "LONDON KINGS CROSS", "KGX")
+    ]);
+
+    let mut issues = Vec::new();
+    let _ = parse_fixed_links_lenient(&stations, &mut Cursor::new(data), &mut issues);
+    let _ = parse_fixed_links(&stations, &mut Cursor::new(data));
+});
@@ -0,0 +1,13 @@
+#![no_main]
+/* Copyright James Lomax 2020 */
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use raildata_core::StationList;
+
+fuzz_target!(|data: &[u8]| {
+    let mut issues = Vec::new();
+    let _ = StationList::read_msn_file_lenient(&mut Cursor::new(data), &mut issues);
+    let _ = StationList::read_msn_file(&mut Cursor::new(data));
+});
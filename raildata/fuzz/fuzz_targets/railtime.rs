@@ -0,0 +1,12 @@
+#![no_main]
+/* Copyright James Lomax 2020 */
+
+use libfuzzer_sys::fuzz_target;
+use raildata_core::RailTime;
+
+fuzz_target!(|s: &str| {
+    if let Some(t) = RailTime::from_24h(s) {
+        assert!(t.secs() < 24*60*60);
+        assert_eq!(RailTime::from_24h(&t.to_24h()), Some(t));
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+/* Copyright James Lomax 2020 */
+
+use libfuzzer_sys::fuzz_target;
+use raildata_core::extract_record_field;
+
+fuzz_target!(|input: (&str, u8, u8)| {
+    let (rec, offset, len) = input;
+    if let Ok(field) = extract_record_field("fuzz", rec, offset as usize, len as usize) {
+        assert!(rec.contains(field));
+    }
+});
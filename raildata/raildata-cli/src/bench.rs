@@ -0,0 +1,130 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raildata::config::Config;
+use raildata::{Engine, JourneyConstraints, QueryScratch, RailTime, StationId};
+
+use crate::utils::{invalid_input, load};
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// Number of random queries to run
+    #[arg(long, default_value_t = 1000)]
+    queries: usize,
+
+    /// Seed for choosing the queries, so runs can be compared
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+
+    /// Minutes to allow for each change of train [default: from config, 15]
+    #[arg(long)]
+    contingency: Option<u32>,
+
+    /// Minutes after the departure time in which the first train may leave [default: from config, 60]
+    #[arg(long)]
+    flexi_depart: Option<u32>,
+
+    /// Time searches from each origin to every station instead, with both Dijkstra and Connection Scan
+    #[arg(long)]
+    all_times: bool
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[idx]
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/**
+ * Runs randomised origin/destination queries between stations with services,
+ * departing between 05:00 and 22:00, and reports the latency distribution.
+ * With --all-times each query is from a random origin to every station instead.
+ */
+pub fn run(args: BenchArgs, config: &Config) -> io::Result<()> {
+    let contingency = args.contingency.unwrap_or(config.routing.contingency_mins)*60;
+    let flexi_depart = args.flexi_depart.unwrap_or(config.routing.flexi_depart_mins)*60;
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+
+    let mut served = vec![false; rail.stations.count()];
+    for service in rail.timetable.services() {
+        for stop in &service.stops {
+            served[stop.station] = true;
+        }
+    }
+    let stations: Vec<StationId> = (0..served.len()).filter(|id| served[*id]).collect();
+    if stations.is_empty() || args.queries == 0 {
+        return Err(invalid_input("Nothing to benchmark".to_string()));
+    }
+
+    if args.all_times {
+        for engine in [Engine::Dijkstra, Engine::ConnectionScan] {
+            let mut rng = StdRng::seed_from_u64(args.seed);
+            let mut latencies = Vec::with_capacity(args.queries);
+            let mut reachable = 0;
+
+            eprintln!("Running {} one-to-all queries with {:?}...", args.queries, engine);
+            let mut scratch = QueryScratch::new(&rail.graph);
+            for _ in 0..args.queries {
+                let origin = stations[rng.gen_range(0..stations.len())];
+                let depart = RailTime::new(rng.gen_range(5..22), rng.gen_range(0..60));
+
+                let start = Instant::now();
+                let times = rail.graph.compute_all_times_with(engine, &mut scratch, None, depart, origin, contingency, flexi_depart);
+                latencies.push(start.elapsed());
+                reachable += times.iter().filter(|time| time.is_some()).count();
+            }
+
+            println!("{:?}", engine);
+            report(&mut latencies, format!("{} ({} stations reached)", args.queries, reachable));
+        }
+        return Ok(());
+    }
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let mut latencies = Vec::with_capacity(args.queries);
+    let mut reachable = 0;
+
+    eprintln!("Running {} queries over {} stations...", args.queries, stations.len());
+    let mut scratch = QueryScratch::new(&rail.graph);
+    for _ in 0..args.queries {
+        let origin = stations[rng.gen_range(0..stations.len())];
+        let dest = stations[rng.gen_range(0..stations.len())];
+        let depart = RailTime::new(rng.gen_range(5..22), rng.gen_range(0..60));
+
+        let start = Instant::now();
+        let journeys = rail.graph.compute_journeys(&mut scratch, None, depart, origin, vec![dest], contingency, flexi_depart, &JourneyConstraints::default());
+        latencies.push(start.elapsed());
+
+        if journeys[0].time != u32::MAX {
+            reachable += 1;
+        }
+    }
+
+    report(&mut latencies, format!("{} ({} reachable)", args.queries, reachable));
+    Ok(())
+}
+
+fn report(latencies: &mut [Duration], queries: String) {
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+
+    println!("Queries:   {}", queries);
+    println!("Mean:      {:>9.3} ms", ms(total) / latencies.len() as f64);
+    println!("Min:       {:>9.3} ms", ms(latencies[0]));
+    println!("p50:       {:>9.3} ms", ms(percentile(latencies, 0.5)));
+    println!("p90:       {:>9.3} ms", ms(percentile(latencies, 0.9)));
+    println!("p99:       {:>9.3} ms", ms(percentile(latencies, 0.99)));
+    println!("Max:       {:>9.3} ms", ms(*latencies.last().unwrap()));
+}
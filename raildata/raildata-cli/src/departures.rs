@@ -0,0 +1,73 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+
+use chrono::Timelike;
+use clap::Args;
+use raildata::config::Config;
+use raildata::RailTime;
+use serde_json::{json, Value};
+
+use crate::utils::{crs, fmt_time, load, parse_time, station_by_crs};
+
+#[derive(Args)]
+pub struct DeparturesArgs {
+    /// CRS code of the station
+    station: String,
+
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// Time to show departures from (HH:MM or "now")
+    #[arg(long, default_value = "now", value_parser = parse_board_time)]
+    from: RailTime,
+
+    /// Number of departures to show
+    #[arg(long, default_value_t = 20)]
+    count: usize,
+
+    /// Print the departures as JSON rather than a board
+    #[arg(long)]
+    json: bool
+}
+
+fn parse_board_time(s: &str) -> Result<RailTime, String> {
+    if s == "now" {
+        let now = chrono::Local::now();
+        Ok(RailTime::new(now.hour(), now.minute()))
+    } else {
+        parse_time(s)
+    }
+}
+
+pub fn run(args: DeparturesArgs, config: &Config) -> io::Result<()> {
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+    let station = station_by_crs(&rail.stations, &args.station)?;
+
+    // Every call passengers can board at over the next day
+    let departures = rail.timetable.departures(station, args.from, 24*60*60);
+
+    let name = &rail.stations.get(station).unwrap().names[0];
+    if args.json {
+        let board: Vec<Value> = departures.iter().take(args.count).map(|departure| json!({
+            "time": fmt_time(&departure.stop.departure.time()),
+            "destination": crs(&rail.stations, departure.destination),
+            "destination_name": rail.stations.get(departure.destination).unwrap().names[0],
+            "platform": departure.stop.platform.as_ref().map(|platform| platform.as_str()),
+            "train_uid": departure.service.train_uid,
+            "operator": departure.service.operator
+        })).collect();
+        println!("{}", serde_json::to_string_pretty(&json!({ "station": name, "departures": board }))?);
+        return Ok(());
+    }
+
+    println!("Departures from {} after {}", name, fmt_time(&args.from));
+    println!("{:<6} {:<30} Train", "Time", "Destination");
+    for departure in departures.iter().take(args.count) {
+        let dest = &rail.stations.get(departure.destination).unwrap().names[0];
+        println!("{:<6} {:<30} {}", fmt_time(&departure.stop.departure.time()), dest, departure.service.train_uid);
+    }
+
+    Ok(())
+}
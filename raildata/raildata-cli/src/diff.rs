@@ -0,0 +1,57 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+
+use clap::Args;
+use raildata::diff::diff;
+
+use crate::utils::load;
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// File prefix of the old timetable extract
+    old: String,
+
+    /// File prefix of the new timetable extract
+    new: String,
+
+    /// Maximum number of entries to list per change type
+    #[arg(long, default_value_t = 50)]
+    limit: usize
+}
+
+fn print_section(title: &str, items: &[String], limit: usize) {
+    if items.is_empty() {
+        return;
+    }
+
+    println!("{} ({}):", title, items.len());
+    for chunk in items.iter().take(limit).collect::<Vec<_>>().chunks(10) {
+        let line: Vec<&str> = chunk.iter().map(|s| s.as_str()).collect();
+        println!("  {}", line.join(" "));
+    }
+    if items.len() > limit {
+        println!("  ... and {} more", items.len() - limit);
+    }
+}
+
+pub fn run(args: DiffArgs) -> io::Result<()> {
+    let old = load(&args.old)?;
+    let new = load(&args.new)?;
+
+    let d = diff(&old.stations, &old.timetable, &new.stations, &new.timetable);
+    if d.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+
+    print_section("Added stations", &d.added_stations, args.limit);
+    print_section("Removed stations", &d.removed_stations, args.limit);
+    print_section("Changed stations", &d.changed_stations, args.limit);
+    print_section("Added services", &d.added_services, args.limit);
+    print_section("Removed services", &d.removed_services, args.limit);
+    print_section("Retimed services", &d.retimed_services, args.limit);
+    print_section("Rerouted services", &d.rerouted_services, args.limit);
+
+    Ok(())
+}
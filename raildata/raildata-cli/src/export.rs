@@ -0,0 +1,56 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use raildata::config::Config;
+use raildata::{export, gtfs};
+
+use crate::utils::load;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Gtfs,
+    Csv,
+    Sqlite
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum)]
+    format: Format,
+
+    /// Output directory (SQLite exports are written to raildata.sqlite inside it)
+    #[arg(long, short = 'o')]
+    output: PathBuf
+}
+
+pub fn run(args: ExportArgs, config: &Config) -> io::Result<()> {
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+
+    match args.format {
+        Format::Gtfs => gtfs::write_gtfs(&rail, &args.output)?,
+        Format::Csv => export::write_csv(&rail, &args.output)?,
+        Format::Sqlite => write_sqlite(&rail, &args.output)?
+    }
+
+    eprintln!("Exported to {}", args.output.display());
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn write_sqlite(rail: &raildata::RailServices, dir: &std::path::Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    export::write_sqlite(rail, &dir.join("raildata.sqlite"))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn write_sqlite(_rail: &raildata::RailServices, _dir: &std::path::Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "raildata was built without the sqlite feature"))
+}
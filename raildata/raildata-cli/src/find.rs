@@ -0,0 +1,110 @@
+/* Copyright James Lomax 2020 */
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use clap::Args;
+use raildata::config::Config;
+use raildata::{StationId, StationList};
+use serde_json::{json, Value};
+
+use crate::utils::load;
+
+#[derive(Args)]
+pub struct FindArgs {
+    /// Station name, CRS or TIPLOC to search for. Names needn't be exact, the closest are listed first
+    query: String,
+
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// Maximum number of stations to list
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+
+    /// Print the stations as JSON rather than a table
+    #[arg(long)]
+    json: bool
+}
+
+// Abbreviations commonly typed for words in station names
+const ABBREVIATIONS: [(&str, &str); 4] = [("X", "CROSS"), ("RD", "ROAD"), ("INTL", "INTERNATIONAL"), ("PKWY", "PARKWAY")];
+
+fn expand(word: &str) -> &str {
+    ABBREVIATIONS.iter().find(|(abbr, _)| *abbr == word).map(|(_, full)| *full).unwrap_or(word)
+}
+
+/**
+ * Stations matching $query. An exact CRS or TIPLOC match comes first, then
+ * stations with names like the query, best match first.
+ */
+fn search(stations: &StationList, query: &str) -> Vec<StationId> {
+    let query = query.trim().to_uppercase();
+    let mut found = Vec::new();
+
+    if let Some(s) = stations.get_by_crs(&query) {
+        found.push(s.id);
+    }
+    if let Some(s) = stations.get_by_tiploc(&query) {
+        if !found.contains(&s.id) {
+            found.push(s.id);
+        }
+    }
+
+    let expanded: Vec<&str> = query.split_whitespace().map(expand).collect();
+    for (id, _) in stations.name_search(&expanded.join(" ")) {
+        if !found.contains(&id) {
+            found.push(id);
+        }
+    }
+    found
+}
+
+pub fn run(args: FindArgs, config: &Config) -> io::Result<()> {
+    // Only the MSN is needed from a CIF extract, an archive or GTFS feed is loaded whole
+    let prefix = args.data.as_deref().unwrap_or(&config.data.prefix);
+    let msn = format!("{}.MSN", prefix);
+    let stations = if Path::new(&msn).exists() {
+        StationList::read_msn_file(&mut BufReader::new(File::open(msn)?))?
+    } else {
+        load(prefix)?.stations
+    };
+
+    let found = search(&stations, &args.query);
+    if args.json {
+        let matches: Vec<Value> = found.iter().take(args.limit).map(|id| {
+            let s = stations.get(*id).unwrap();
+            let ll = s.lat_lon();
+            json!({
+                "crs": s.crs_code,
+                "name": s.names[0],
+                "tiplocs": s.tiplocs,
+                "lat": ll.map(|ll| ll.lat),
+                "lon": ll.map(|ll| ll.lon)
+            })
+        }).collect();
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+        return Ok(());
+    }
+    if found.is_empty() {
+        eprintln!("No stations match '{}'", args.query);
+        return Ok(());
+    }
+
+    println!("{:<4} {:<30} {:<24} {:>9} {:>10}", "CRS", "Name", "TIPLOCs", "Lat", "Lon");
+    for id in found.iter().take(args.limit) {
+        let s = stations.get(*id).unwrap();
+        let (lat, lon) = match s.lat_lon() {
+            Some(ll) => (format!("{:.5}", ll.lat), format!("{:.5}", ll.lon)),
+            None => (String::new(), String::new())
+        };
+        println!("{:<4} {:<30} {:<24} {:>9} {:>10}", s.crs_code, s.names[0], s.tiplocs.join(","), lat, lon);
+    }
+    if found.len() > args.limit {
+        println!("... and {} more", found.len() - args.limit);
+    }
+
+    Ok(())
+}
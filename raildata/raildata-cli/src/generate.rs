@@ -0,0 +1,57 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+
+use clap::Args;
+use raildata::testing::{Network, NetworkSpec};
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// File prefix to write the .MSN, .FLF and .MCA files to (e.g. data/SYNTH)
+    prefix: String,
+
+    /// Number of stations
+    #[arg(long, default_value_t = 100)]
+    stations: usize,
+
+    /// Number of lines, each with services in both directions
+    #[arg(long, default_value_t = 12)]
+    lines: usize,
+
+    /// Stations called at by each line
+    #[arg(long, default_value_t = 8)]
+    stops_per_line: usize,
+
+    /// Minutes between services on a line
+    #[arg(long, default_value_t = 30)]
+    headway: u32,
+
+    /// Number of walking links between nearby stations
+    #[arg(long, default_value_t = 20)]
+    transfer_links: usize,
+
+    /// Seed for the generator, the same seed gives the same network
+    #[arg(long, default_value_t = 0)]
+    seed: u64
+}
+
+pub fn run(args: GenerateArgs) -> io::Result<()> {
+    let spec = NetworkSpec {
+        stations: args.stations,
+        lines: args.lines,
+        stops_per_line: args.stops_per_line,
+        headway_mins: args.headway,
+        transfer_links: args.transfer_links,
+        seed: args.seed,
+        ..NetworkSpec::default()
+    };
+    if spec.stations < 2 || spec.stations > 26*26*26 || spec.headway_mins == 0 {
+        return Err(crate::utils::invalid_input("Expected between 2 and 17576 stations and a positive headway".to_string()));
+    }
+
+    let network = Network::generate(&spec);
+    network.write_cif(&args.prefix)?;
+    eprintln!("Wrote {} stations, {} fixed legs and {} services to {}.*",
+        network.stations.count(), network.fixedlinks.len(), network.timetable.services().len(), args.prefix);
+    Ok(())
+}
@@ -0,0 +1,112 @@
+/* Copyright James Lomax 2020 */
+
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+use clap::Args;
+use raildata::config::Config;
+use raildata::geojson::{feature_collection, station_feature};
+#[cfg(feature = "png")]
+use raildata::heatmap::{Heatmap, HeatmapStyle};
+use raildata::render::Overlay;
+use raildata::{QueryScratch, RailTime};
+use serde_json::json;
+
+use crate::utils::{load, parse_time, station_by_crs, write_map};
+
+#[cfg(feature = "png")]
+const HEATMAP_WIDTH: u32 = 800;
+
+#[derive(Args)]
+pub struct IsochroneArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// CRS code of the origin station
+    #[arg(long)]
+    from: String,
+
+    /// Earliest departure time (HH:MM)
+    #[arg(long, value_parser = parse_time)]
+    depart: RailTime,
+
+    /// Comma separated upper bounds (minutes) of the travel time bands
+    #[arg(long, value_delimiter = ',', default_value = "30,60,90,120")]
+    bands: Vec<u32>,
+
+    /// Minutes to allow for each change of train [default: from config, 15]
+    #[arg(long)]
+    contingency: Option<u32>,
+
+    /// Minutes after the departure time in which the first train may leave [default: from config, 60]
+    #[arg(long)]
+    flexi_depart: Option<u32>,
+
+    /// Output file, defaults to stdout
+    #[arg(long, short = 'o')]
+    output: Option<String>,
+
+    /// Also draw the bands on a map, written as SVG or PNG by extension
+    #[arg(long)]
+    map: Option<String>,
+
+    /// Also write a PNG heatmap of travel times, spread from the stations at 20km/h, up to the largest band
+    #[cfg(feature = "png")]
+    #[arg(long)]
+    heatmap: Option<String>
+}
+
+/**
+ * Writes a GeoJSON FeatureCollection with a point for each station reachable
+ * within the largest band, tagged with its travel time and band.
+ */
+pub fn run(args: IsochroneArgs, config: &Config) -> io::Result<()> {
+    let contingency = args.contingency.unwrap_or(config.routing.contingency_mins)*60;
+    let flexi_depart = args.flexi_depart.unwrap_or(config.routing.flexi_depart_mins)*60;
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+    let origin = station_by_crs(&rail.stations, &args.from)?;
+
+    let mut bands = args.bands.clone();
+    bands.sort_unstable();
+
+    let times = rail.graph.compute_all_times(&mut QueryScratch::new(&rail.graph), None, args.depart, origin, contingency, flexi_depart);
+
+    let mut features = Vec::new();
+    for (station, time) in rail.stations.iter().zip(times.iter()) {
+        let minutes = match time {
+            Some(t) => t / 60,
+            None => continue
+        };
+
+        if let Some(band) = bands.iter().find(|b| minutes <= **b) {
+            if let Some(mut feature) = station_feature(station) {
+                feature["properties"]["minutes"] = json!(minutes);
+                feature["properties"]["band"] = json!(band);
+                features.push(feature);
+            }
+        }
+    }
+
+    if let Some(path) = &args.map {
+        write_map(path, &rail.stations, &Overlay::Isochrone { times: &times, bands: &bands })?;
+    }
+
+    #[cfg(feature = "png")]
+    if let Some(path) = &args.heatmap {
+        let style = HeatmapStyle { max_time: bands.last().copied().unwrap_or(0)*60, ..HeatmapStyle::default() };
+        std::fs::write(path, Heatmap::new(&rail.stations, &times, style).image(HEATMAP_WIDTH).encode_png()?)?;
+    }
+
+    eprintln!("{} stations within {} minutes", features.len(), bands.last().unwrap_or(&0));
+
+    let collection = feature_collection(features);
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+    serde_json::to_writer_pretty(&mut out, &collection)?;
+    writeln!(out)
+}
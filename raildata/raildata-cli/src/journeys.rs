@@ -0,0 +1,141 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+
+use chrono::NaiveDate;
+use clap::Args;
+use raildata::config::Config;
+use raildata::overlay::TimetableView;
+use raildata::realtime::load_realtime;
+use raildata::render::Overlay;
+use raildata::{format, Journey, JourneyConstraints, Link, QueryScratch, RailTime, Reservations, StationList};
+use serde_json::{json, Value};
+
+use crate::utils::{crs, fmt_time, load, parse_date, parse_time, station_by_crs, write_map};
+
+#[derive(Args)]
+pub struct JourneysArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// CRS code of the origin station
+    #[arg(long)]
+    from: String,
+
+    /// Comma separated CRS codes of the destination stations
+    #[arg(long, required = true, value_delimiter = ',')]
+    to: Vec<String>,
+
+    /// Earliest departure time (HH:MM)
+    #[arg(long, value_parser = parse_time)]
+    depart: RailTime,
+
+    /// Date of travel (YYYY-MM-DD), to only use trains running then [default: any day]
+    #[arg(long, value_parser = parse_date)]
+    date: Option<NaiveDate>,
+
+    /// Minutes to allow for each change of train [default: from config, 15]
+    #[arg(long)]
+    contingency: Option<u32>,
+
+    /// Minutes after the departure time in which the first train may leave [default: from config, 60]
+    #[arg(long)]
+    flexi_depart: Option<u32>,
+
+    /// Darwin Push Port snapshot (XML) to search the current running of trains on the date, or today
+    #[arg(long)]
+    realtime: Option<String>,
+
+    /// Also draw the journeys on a map, written as SVG or PNG by extension
+    #[arg(long)]
+    map: Option<String>,
+
+    /// Print the journeys as JSON rather than itineraries
+    #[arg(long)]
+    json: bool
+}
+
+// $journey's legs and times, null if no journey was found
+fn journey_json(stations: &StationList, view: &TimetableView, journey: &Journey) -> Value {
+    if journey.time == u32::MAX {
+        return Value::Null;
+    }
+    let legs: Vec<Value> = journey.legs().iter().map(|leg| {
+        let (service, train_uid) = match leg.link {
+            Link::Rail(rl) => (json!(rl.service), json!(view.service(rl.service).map(|service| &service.train_uid))),
+            _ => (Value::Null, Value::Null)
+        };
+        json!({
+            "from": crs(stations, leg.from),
+            "to": crs(stations, leg.to),
+            "depart": fmt_time(&leg.depart),
+            "depart_day": leg.depart_day,
+            "arrive": fmt_time(&leg.arrive),
+            "arrive_day": leg.arrive_day,
+            "wait_mins": leg.wait / 60,
+            "mode": match leg.link {
+                Link::Rail(_) => "Rail".to_string(),
+                Link::Fixed(fl) => format!("{:?}", fl.kind),
+                Link::Dummy => String::new()
+            },
+            "service": service,
+            "train_uid": train_uid
+        })
+    }).collect();
+    json!({ "minutes": journey.time / 60, "changes": journey.changes(), "legs": legs })
+}
+
+pub fn run(args: JourneysArgs, config: &Config) -> io::Result<()> {
+    let contingency = args.contingency.unwrap_or(config.routing.contingency_mins)*60;
+    let flexi_depart = args.flexi_depart.unwrap_or(config.routing.flexi_depart_mins)*60;
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+
+    let origin = station_by_crs(&rail.stations, &args.from)?;
+    let mut dests = Vec::new();
+    for dst in &args.to {
+        dests.push(station_by_crs(&rail.stations, dst)?);
+    }
+
+    let realtime = match &args.realtime {
+        Some(path) => vec![load_realtime(&rail, path, args.date.unwrap_or_else(|| chrono::Local::now().date_naive()))?],
+        None => Vec::new()
+    };
+    let view = TimetableView::on(&rail, &realtime, realtime.first().map(|overlay| overlay.start).or(args.date));
+    let graph = view.graph();
+    let journeys = graph.compute_journeys(&mut QueryScratch::new(graph), view.date, args.depart, origin, dests.clone(), contingency, flexi_depart, &JourneyConstraints::default());
+
+    if args.json {
+        let found: Vec<Value> = dests.iter().zip(journeys.iter()).map(|(dest, journey)| json!({
+            "from": crs(&rail.stations, origin),
+            "to": crs(&rail.stations, *dest),
+            "journey": journey_json(&rail.stations, &view, journey)
+        })).collect();
+        println!("{}", serde_json::to_string_pretty(&found)?);
+    } else {
+        for (dest, journey) in dests.iter().zip(journeys.iter()) {
+            println!();
+            if journey.time == u32::MAX {
+                println!("{} -> {}: no journey found", crs(&rail.stations, origin), crs(&rail.stations, *dest));
+                continue;
+            }
+            print!("{}", format::journey_text(&rail.stations, journey));
+            for link in &journey.links {
+                if let Link::Rail(rl) = link {
+                    match view.service(rl.service).map_or(Reservations::None, |service| service.reservations) {
+                        Reservations::Compulsory => println!("  Service {}: seat reservation compulsory", rl.service),
+                        Reservations::BicyclesEssential => println!("  Service {}: cycle reservations required", rl.service),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &args.map {
+        let found: Vec<Journey> = journeys.into_iter().filter(|j| j.time != u32::MAX).collect();
+        write_map(path, &rail.stations, &Overlay::Journeys(&found))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/* Copyright James Lomax 2020 */
+
+mod utils;
+mod journeys;
+mod validate;
+mod stats;
+mod isochrone;
+mod departures;
+mod export;
+mod bench;
+mod diff;
+mod find;
+mod matrix;
+mod prepare;
+mod generate;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use raildata::config::Config;
+
+/// Query RDG timetable data without running the server
+#[derive(Parser)]
+#[command(name = "raildata", version)]
+struct Cli {
+    /// Config file [default: $RAILDATA_CONFIG, or raildata.toml if present]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compute the fastest journeys from one station to others
+    Journeys(journeys::JourneysArgs),
+    /// Check a timetable extract for data-quality problems
+    Validate(validate::ValidateArgs),
+    /// Print summary statistics about a timetable extract
+    Stats(stats::StatsArgs),
+    /// Write the stations reachable from an origin as banded GeoJSON
+    Isochrone(isochrone::IsochroneArgs),
+    /// Print a departure board for a station from the scheduled data
+    Departures(departures::DeparturesArgs),
+    /// Convert a timetable extract to GTFS, CSV or SQLite
+    Export(export::ExportArgs),
+    /// Benchmark journey queries between random stations
+    Bench(bench::BenchArgs),
+    /// Report the differences between two timetable extracts
+    Diff(diff::DiffArgs),
+    /// Search for stations by name, CRS or TIPLOC
+    #[command(alias = "lookup")]
+    Find(find::FindArgs),
+    /// Write a CSV matrix of journey times between listed stations
+    Matrix(matrix::MatrixArgs),
+    /// Download and unpack the latest timetable extract
+    Prepare(prepare::PrepareArgs),
+    /// Write a synthetic timetable extract, for testing without the RDG data
+    Generate(generate::GenerateArgs)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match cli.command {
+        Command::Journeys(args) => journeys::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Validate(args) => validate::run(args),
+        Command::Stats(args) => stats::run(args).map(|_| ExitCode::SUCCESS),
+        Command::Isochrone(args) => isochrone::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Departures(args) => departures::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Export(args) => export::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Bench(args) => bench::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Diff(args) => diff::run(args).map(|_| ExitCode::SUCCESS),
+        Command::Find(args) => find::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Matrix(args) => matrix::run(args, &config).map(|_| ExitCode::SUCCESS),
+        Command::Prepare(args) => prepare::run(args).map(|_| ExitCode::SUCCESS),
+        Command::Generate(args) => generate::run(args).map(|_| ExitCode::SUCCESS)
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,90 @@
+/* Copyright James Lomax 2020 */
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use clap::Args;
+use raildata::config::Config;
+use raildata::{QueryScratch, RailTime};
+
+use crate::utils::{crs, load, parse_time, station_by_crs};
+
+#[derive(Args)]
+pub struct MatrixArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), the .zip archive or a GTFS feed directory [default: from config]
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+
+    /// File listing one CRS code per line. Blank lines and lines starting with # are ignored
+    #[arg(long)]
+    stations: String,
+
+    /// Earliest departure time (HH:MM)
+    #[arg(long, value_parser = parse_time)]
+    depart: RailTime,
+
+    /// Minutes to allow for each change of train [default: from config, 15]
+    #[arg(long)]
+    contingency: Option<u32>,
+
+    /// Minutes after the departure time in which the first train may leave [default: from config, 60]
+    #[arg(long)]
+    flexi_depart: Option<u32>,
+
+    /// Output file, defaults to stdout
+    #[arg(long, short = 'o')]
+    output: Option<String>
+}
+
+fn read_station_list(path: &str) -> io::Result<Vec<String>> {
+    let mut codes = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let code = line.trim();
+        if !code.is_empty() && !code.starts_with('#') {
+            codes.push(code.to_string());
+        }
+    }
+    Ok(codes)
+}
+
+/**
+ * Writes a CSV with a row per origin and a column per destination, giving the
+ * journey time in minutes. Unreachable pairs are left empty.
+ */
+pub fn run(args: MatrixArgs, config: &Config) -> io::Result<()> {
+    let contingency = args.contingency.unwrap_or(config.routing.contingency_mins)*60;
+    let flexi_depart = args.flexi_depart.unwrap_or(config.routing.flexi_depart_mins)*60;
+    let codes = read_station_list(&args.stations)?;
+    let rail = load(args.data.as_deref().unwrap_or(&config.data.prefix))?;
+
+    let mut ids = Vec::with_capacity(codes.len());
+    for code in &codes {
+        ids.push(station_by_crs(&rail.stations, code)?);
+    }
+
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+    let mut w = csv::Writer::from_writer(out);
+
+    let mut header = vec!["origin".to_string()];
+    header.extend(ids.iter().map(|id| crs(&rail.stations, *id).to_string()));
+    w.write_record(&header)?;
+
+    let mut scratch = QueryScratch::new(&rail.graph);
+    for (i, origin) in ids.iter().enumerate() {
+        eprintln!("Computing times from {} ({}/{})", crs(&rail.stations, *origin), i + 1, ids.len());
+        let times = rail.graph.compute_all_times(&mut scratch, None, args.depart, *origin, contingency, flexi_depart);
+
+        let mut row = vec![crs(&rail.stations, *origin).to_string()];
+        row.extend(ids.iter().map(|dst| match times[*dst] {
+            Some(t) => (t / 60).to_string(),
+            None => String::new()
+        }));
+        w.write_record(&row)?;
+    }
+
+    w.flush()
+}
@@ -0,0 +1,51 @@
+/* Copyright James Lomax 2020 */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use raildata::download::{download, extract};
+
+use crate::utils::load;
+
+#[derive(Args)]
+pub struct PrepareArgs {
+    /// File with the RDG open data username and password on the first two lines
+    #[arg(long, required_unless_present = "zip")]
+    credentials: Option<String>,
+
+    /// Use an already downloaded timetable archive instead of downloading one
+    #[arg(long, conflicts_with = "credentials")]
+    zip: Option<String>,
+
+    /// Directory to write the timetable files to
+    #[arg(long, short = 'o', default_value = "data")]
+    out: String
+}
+
+pub fn run(args: PrepareArgs) -> io::Result<()> {
+    let out = Path::new(&args.out);
+    fs::create_dir_all(out)?;
+
+    let archive = match (&args.zip, &args.credentials) {
+        (Some(zip), _) => PathBuf::from(zip),
+        (None, Some(credentials)) => {
+            let dest = out.join("timetable.zip");
+            eprintln!("Downloading timetable to {}...", dest.display());
+            download(Path::new(credentials), &dest)?;
+            dest
+        }
+        (None, None) => unreachable!("clap requires --credentials or --zip")
+    };
+
+    eprintln!("Extracting {}...", archive.display());
+    let prefix = extract(&archive, out)?;
+    let prefix = prefix.to_string_lossy();
+
+    // Check the extract loads before declaring it ready
+    load(&prefix)?;
+    println!("Timetable ready, pass '-d {}' to other commands", prefix);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+use std::mem::size_of;
+
+use clap::Args;
+use raildata::timetable::Stop;
+use raildata::{RailServices, Service, Station};
+
+use crate::utils::{crs, fmt_time, load};
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748)
+    prefix: String,
+
+    /// Number of entries to list for the busiest stations and longest services
+    #[arg(long, default_value_t = 10)]
+    top: usize
+}
+
+fn service_duration(service: &Service) -> u32 {
+    match (service.stops.first(), service.stops.last()) {
+        (Some(first), Some(last)) => last.arrival.since(&first.departure),
+        _ => 0
+    }
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+pub fn run(args: StatsArgs) -> io::Result<()> {
+    let rail = load(&args.prefix)?;
+
+    print_counts(&rail);
+    print_coverage(&rail);
+    print_busiest(&rail, args.top);
+    print_longest(&rail, args.top);
+    print_memory(&rail);
+
+    Ok(())
+}
+
+fn print_counts(rail: &RailServices) {
+    let stops: usize = rail.timetable.services().iter().map(|s| s.stops.len()).sum();
+    let (edges, min, max) = rail.graph.stat_edges();
+
+    println!("Stations:    {}", rail.stations.count());
+    println!("Fixed links: {}", rail.fixedlinks.len());
+    println!("Services:    {} ({} stops)", rail.timetable.services().len(), stops);
+    println!("Graph edges: {} (min/max per station {}/{})", edges, min, max);
+}
+
+fn print_coverage(rail: &RailServices) {
+    println!();
+    match &rail.timetable.header {
+        Some(header) => {
+            println!("Extract {} ({}), {} on {}", header.file_ref, header.mainframe_identity,
+                if header.update { "update" } else { "full" }, header.extracted);
+            println!("Covers {} to {}", header.start_date, header.end_date);
+        }
+        None => println!("No extract header, coverage dates unknown")
+    }
+}
+
+fn print_busiest(rail: &RailServices, top: usize) {
+    let mut departures = vec![0usize; rail.stations.count()];
+    for service in rail.timetable.services() {
+        if let Some((_, stops)) = service.stops.split_last() {
+            for stop in stops {
+                departures[stop.station] += 1;
+            }
+        }
+    }
+
+    let mut busiest: Vec<(usize, usize)> = departures.into_iter().enumerate().collect();
+    busiest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    println!();
+    println!("Busiest stations by departures:");
+    for (id, count) in busiest.iter().take(top) {
+        let station = rail.stations.get(*id).unwrap();
+        println!("  {:<4} {:<30} {}", station.crs_code, station.names[0], count);
+    }
+}
+
+fn print_longest(rail: &RailServices, top: usize) {
+    let mut longest: Vec<(&Service, u32)> = rail.timetable.services().iter()
+        .filter(|s| !s.stops.is_empty())
+        .map(|s| (s, service_duration(s)))
+        .collect();
+    longest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+
+    println!();
+    println!("Longest services:");
+    for (service, duration) in longest.iter().take(top) {
+        let first = service.stops.first().unwrap();
+        let last = service.stops.last().unwrap();
+        println!("  {:<6} {} {} -> {} {}  {} stops, {}h{:02}m", service.train_uid,
+            fmt_time(&first.departure.time()), crs(&rail.stations, first.station),
+            crs(&rail.stations, last.station), fmt_time(&last.arrival.time()),
+            service.stops.len(), duration / 3600, (duration % 3600) / 60);
+    }
+}
+
+fn print_memory(rail: &RailServices) {
+    let strings = |v: &Vec<String>| v.capacity() * size_of::<String>() + v.iter().map(|s| s.capacity()).sum::<usize>();
+
+    let stations: usize = rail.stations.iter()
+        .map(|s| size_of::<Station>() + strings(&s.tiplocs) + strings(&s.names) + s.crs_code.capacity())
+        .sum();
+    let services: usize = rail.timetable.services().iter()
+        .map(|s| size_of::<Service>() + s.train_uid.capacity() + s.stops.capacity() * size_of::<Stop>())
+        .sum();
+    let graph = rail.graph.heap_size();
+
+    println!();
+    println!("Estimated memory (excluding lookup tables):");
+    println!("  Stations:  {:>8.1} MiB", mib(stations));
+    println!("  Timetable: {:>8.1} MiB", mib(services));
+    println!("  Graph:     {:>8.1} MiB", mib(graph));
+}
@@ -0,0 +1,74 @@
+/* Copyright James Lomax 2020 */
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use chrono::NaiveDate;
+use raildata::render::{render_svg, Overlay};
+use raildata::{load_dataset_with_warnings, RailServices, RailTime, StationId, StationList};
+
+pub fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/** Load the timetable files sharing $prefix, in a .zip archive or a GTFS feed directory, reporting progress on stderr */
+pub fn load(prefix: &str) -> io::Result<RailServices> {
+    eprintln!("Loading rail database from {}... (this can take a while)", prefix);
+    let mut warnings = Vec::new();
+    let rail = load_dataset_with_warnings(prefix, &mut warnings).inspect_err(|e| {
+        if let Some(record) = e.record() {
+            eprintln!("Bad record: {}", record);
+        }
+    })?;
+    eprintln!("Loaded {} stations, {} fixed legs and {} services", rail.stations.count(), rail.fixedlinks.len(), rail.timetable.services().len());
+    if !warnings.is_empty() {
+        eprintln!("{} records skipped or merged while loading, see `raildata validate` for details", warnings.len());
+    }
+    Ok(rail)
+}
+
+/** Parse a time given either as "09:30" or "0930" */
+pub fn parse_time(s: &str) -> Result<RailTime, String> {
+    let compact = s.replacen(':', "", 1);
+    match RailTime::from_24h(&compact) {
+        Some(t) if compact.len() == 4 => Ok(t),
+        _ => Err(format!("Could not parse time '{}', expected HH:MM", s))
+    }
+}
+
+/** Parse a date given as "2020-09-14" */
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    raildata::overlay::parse_date(s).map_err(|_| format!("Could not parse date '{}', expected YYYY-MM-DD", s))
+}
+
+/** Format a time as "09:30" */
+pub fn fmt_time(t: &RailTime) -> String {
+    let s = t.to_24h();
+    format!("{}:{}", &s[0..2], &s[2..4])
+}
+
+pub fn station_by_crs(stations: &StationList, crs: &str) -> io::Result<StationId> {
+    match stations.get_by_crs(&crs.to_uppercase()) {
+        Some(station) => Ok(station.id),
+        None => Err(invalid_input(format!("Could not find CRS {}", crs)))
+    }
+}
+
+pub fn crs(stations: &StationList, id: StationId) -> &str {
+    &stations.get(id).unwrap().crs_code
+}
+
+/** Render a map to $path, as PNG or SVG depending on its extension */
+pub fn write_map(path: &str, stations: &StationList, overlay: &Overlay) -> io::Result<()> {
+    const WIDTH: u32 = 800;
+    const HEIGHT: u32 = 1000;
+
+    let path = Path::new(path);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => render_svg(&mut BufWriter::new(File::create(path)?), stations, overlay, WIDTH, HEIGHT),
+        #[cfg(feature = "png")]
+        Some("png") => raildata::render::render_png(path, stations, overlay, WIDTH, HEIGHT),
+        _ => Err(invalid_input(format!("Unsupported map format {}, expected .svg or .png", path.display())))
+    }
+}
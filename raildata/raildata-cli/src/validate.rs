@@ -0,0 +1,98 @@
+/* Copyright James Lomax 2020 */
+
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::Args;
+use raildata::validate::{check, validate_files, ParseIssue, Report};
+
+use crate::utils::{load, station_by_crs};
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// File prefix of the timetable extract (e.g. ttis/RJTTF748), or a .zip archive or GTFS feed
+    /// directory, which are loaded as usual and so only checked once loaded
+    prefix: String,
+
+    /// CRS code of a station every other should be reachable from
+    #[arg(long)]
+    hub: Option<String>,
+
+    /// Also fail when there are warnings
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Maximum number of entries to list per check
+    #[arg(long, default_value_t = 20)]
+    limit: usize
+}
+
+fn print_section<T>(title: &str, items: &[T], limit: usize, fmt: impl Fn(&T) -> String) {
+    if items.is_empty() {
+        return;
+    }
+
+    println!("{} ({}):", title, items.len());
+    for item in items.iter().take(limit) {
+        println!("  {}", fmt(item));
+    }
+    if items.len() > limit {
+        println!("  ... and {} more", items.len() - limit);
+    }
+}
+
+fn fmt_issue(file: &str, issue: &ParseIssue) -> String {
+    format!("{} line {}: {}", file, issue.line, issue.message)
+}
+
+// Timetable files are parsed leniently to find every bad record, anything else is loaded and then checked
+fn report(args: &ValidateArgs) -> io::Result<Report> {
+    let hub = args.hub.as_deref().map(str::to_uppercase);
+    if Path::new(&format!("{}.MSN", args.prefix)).exists() {
+        return Ok(validate_files(&args.prefix, hub.as_deref())?);
+    }
+    let rail = load(&args.prefix)?;
+    let hub = hub.map(|crs| station_by_crs(&rail.stations, &crs)).transpose()?;
+    Ok(check(&rail.stations, &rail.fixedlinks, &rail.timetable, hub))
+}
+
+/**
+ * Exit codes: 0 if the extract is clean, 1 if there are errors (or warnings
+ * with --deny-warnings), 2 if the files couldn't be read.
+ */
+pub fn run(args: ValidateArgs) -> io::Result<ExitCode> {
+    let report = report(&args)?;
+    let limit = args.limit;
+
+    println!("Errors:");
+    print_section("Bad MSN records", &report.msn_issues, limit, |i| fmt_issue("MSN", i));
+    print_section("Bad FLF records", &report.flf_issues, limit, |i| fmt_issue("FLF", i));
+    print_section("Bad MCA records", &report.mca_issues, limit, |i| fmt_issue("MCA", i));
+    print_section("Services with fewer than two stops", &report.empty_services, limit, |uid| uid.clone());
+    print_section("Services going back in time", &report.non_monotonic_services, limit, |uid| uid.clone());
+
+    println!("Warnings:");
+    print_section("Unknown TIPLOCs with public calls", &report.unknown_tiplocs, limit, |(tiploc, count)| {
+        format!("{} ({} calls)", tiploc, count)
+    });
+    print_section("Disconnected stations", &report.disconnected_stations, limit, |crs| crs.clone());
+    print_section("Stations unreachable from the hub", &report.unreachable_stations, limit, |crs| crs.clone());
+    print_section("Duplicate fixed links", &report.duplicate_fixed_links, limit, |l| {
+        format!("{:?} between {} and {} ({} times)", l.kind, l.a, l.b, l.count)
+    });
+    print_section("Stations with fixed links to themselves", &report.self_fixed_links, limit, |crs| crs.clone());
+    print_section("Unknown fixed link kinds", &report.unknown_link_kinds, limit, |(kind, count)| {
+        format!("{} ({} links)", kind, count)
+    });
+
+    let errors = report.error_count();
+    let warnings = report.warning_count();
+    println!("{} errors, {} warnings", errors, warnings);
+
+    if errors > 0 || (args.deny_warnings && warnings > 0) {
+        Ok(ExitCode::from(1))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -0,0 +1,173 @@
+/* Copyright James Lomax 2020 */
+
+// Real-time running from Darwin Push Port messages, as streamed or in the
+// snapshots of the day so far. Only what affects journey times is read: the
+// forecast and actual times at each location (TS train status messages) and
+// locations cancelled from schedules.
+
+use std::io::Read;
+
+use chrono::NaiveDate;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::error::{RailDataError, Result};
+use crate::record_parsing::parse_date_or_invalid;
+use crate::timetable::RailTime;
+
+/** Changes to the running of one train on one day */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunningUpdate {
+    // Darwin's ID for the train on the day
+    pub rid: String,
+    pub train_uid: String,
+    // The day the train starts, its schedule's date
+    pub start_date: NaiveDate,
+    pub locations: Vec<LocationUpdate>
+}
+
+/** Estimated or actual times at a location, None where Darwin has no forecast */
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocationUpdate {
+    pub tiploc: String,
+    pub arrival: Option<RailTime>,
+    pub departure: Option<RailTime>,
+    pub cancelled: bool
+}
+
+// Schedule location elements, which may be cancelled
+const SCHEDULE_LOCATIONS: [&str; 7] = ["OR", "OPOR", "IP", "OPIP", "PP", "DT", "OPDT"];
+
+fn attr<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes.iter().find(|a| a.name.local_name == name).map(|a| a.value.as_str())
+}
+
+fn required<'a>(attributes: &'a [OwnedAttribute], element: &str, name: &str) -> Result<&'a str> {
+    attr(attributes, name).ok_or_else(|| RailDataError::RealTime(format!("{} without {}", element, name)))
+}
+
+/** Parse a Darwin time, "HH:MM" or "HH:MM:SS" (the seconds are dropped) */
+fn darwin_time(s: &str, fieldname: &str) -> Result<RailTime> {
+    let invalid = || RailDataError::InvalidTime { field: fieldname.to_string(), value: s.to_string() };
+    let mut parts = s.split(':');
+    let (hrs, mins) = match (parts.next(), parts.next()) {
+        (Some(hrs), Some(mins)) => (hrs.parse::<u32>().map_err(|_| invalid())?, mins.parse::<u32>().map_err(|_| invalid())?),
+        _ => return Err(invalid())
+    };
+    if hrs >= 24 || mins >= 60 {
+        return Err(invalid());
+    }
+    Ok(RailTime::new(hrs, mins))
+}
+
+// The actual time if the train has got there, otherwise the estimate
+fn forecast(attributes: &[OwnedAttribute]) -> Result<Option<RailTime>> {
+    match attr(attributes, "at").or_else(|| attr(attributes, "et")) {
+        Some(t) => darwin_time(t, "forecast").map(Some),
+        None => Ok(None)
+    }
+}
+
+fn running_update(attributes: &[OwnedAttribute], element: &str) -> Result<RunningUpdate> {
+    Ok(RunningUpdate {
+        rid: required(attributes, element, "rid")?.to_string(),
+        train_uid: required(attributes, element, "uid")?.to_string(),
+        start_date: parse_date_or_invalid(required(attributes, element, "ssd")?, "%Y-%m-%d", "ssd")?,
+        locations: Vec::new()
+    })
+}
+
+/**
+ * Read the train status and schedule messages from a Push Port document, e.g. a
+ * snapshot. Updates are in document order, so later ones for a train supersede
+ * earlier ones at the same locations.
+ */
+pub fn read_push_port(reader: impl Read) -> Result<Vec<RunningUpdate>> {
+    let mut updates = Vec::new();
+    // The TS or schedule being read, if any
+    let mut current: Option<RunningUpdate> = None;
+
+    for event in EventReader::new(reader) {
+        match event.map_err(|e| RailDataError::RealTime(e.to_string()))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let element = name.local_name.as_str();
+                match (element, current.as_mut()) {
+                    ("TS", _) | ("schedule", _) => current = Some(running_update(&attributes, element)?),
+                    ("Location", Some(update)) => {
+                        update.locations.push(LocationUpdate {
+                            tiploc: required(&attributes, element, "tpl")?.to_string(),
+                            arrival: None,
+                            departure: None,
+                            cancelled: false
+                        });
+                    }
+                    ("arr", Some(update)) => {
+                        if let Some(location) = update.locations.last_mut() {
+                            location.arrival = forecast(&attributes)?;
+                        }
+                    }
+                    ("dep", Some(update)) => {
+                        if let Some(location) = update.locations.last_mut() {
+                            location.departure = forecast(&attributes)?;
+                        }
+                    }
+                    (location, Some(update)) if SCHEDULE_LOCATIONS.contains(&location) => {
+                        update.locations.push(LocationUpdate {
+                            tiploc: required(&attributes, element, "tpl")?.to_string(),
+                            arrival: None,
+                            departure: None,
+                            cancelled: attr(&attributes, "can") == Some("true")
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } if name.local_name == "TS" || name.local_name == "schedule" => {
+                if let Some(update) = current.take() {
+                    updates.push(update);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tracing::debug!(updates = updates.len(), "Read Push Port messages");
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_port() {
+        let example = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport xmlns="http://www.thalesgroup.com/rtti/PushPort/v16" xmlns:ns5="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3" ts="2020-06-05T09:10:00" version="16.0">
+  <uR updateOrigin="TD">
+    <TS rid="202006058712345" uid="C12345" ssd="2020-06-05">
+      <ns5:Location tpl="CAMBDGE" wtd="09:00" ptd="09:00"><ns5:dep at="09:04" src="TD"/></ns5:Location>
+      <ns5:Location tpl="KNGX" wta="09:50" pta="09:50"><ns5:arr et="09:57:30" src="Darwin"/></ns5:Location>
+    </TS>
+    <schedule rid="202006058754321" uid="C54321" trainId="1A23" ssd="2020-06-05" toc="GN">
+      <OR tpl="CAMBDGE" act="TB" ptd="10:00" wtd="10:00"/>
+      <IP tpl="ROYSTON" act="T " pta="10:20" ptd="10:21" wta="10:20" wtd="10:21" can="true"/>
+      <DT tpl="KNGX" act="TF" pta="11:00" wta="11:00"/>
+    </schedule>
+  </uR>
+</Pport>"#;
+
+        let updates = read_push_port(example.as_bytes()).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].train_uid, "C12345");
+        assert_eq!(updates[0].start_date, NaiveDate::from_ymd_opt(2020, 6, 5).unwrap());
+        assert_eq!(updates[0].locations, vec![
+            LocationUpdate { tiploc: "CAMBDGE".to_string(), arrival: None, departure: Some(RailTime::new(9, 4)), cancelled: false },
+            LocationUpdate { tiploc: "KNGX".to_string(), arrival: Some(RailTime::new(9, 57)), departure: None, cancelled: false }
+        ]);
+        let cancelled: Vec<&str> = updates[1].locations.iter().filter(|l| l.cancelled).map(|l| l.tiploc.as_str()).collect();
+        assert_eq!(cancelled, vec!["ROYSTON"]);
+
+        assert!(read_push_port(r#"<Pport><uR><TS uid="C12345" ssd="2020-06-05"/></uR></Pport>"#.as_bytes()).is_err());
+        assert!(read_push_port("<Pport><uR>".as_bytes()).is_err());
+    }
+}
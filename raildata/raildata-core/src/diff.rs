@@ -0,0 +1,167 @@
+/** Copyright James Lomax 2020 */
+
+use std::collections::BTreeMap;
+
+use crate::stations::{Station, StationList};
+use crate::timetable::{Service, Timetable};
+
+/** Differences between two timetable extracts. Stations are given by CRS and services by train UID */
+#[derive(Debug, Default, PartialEq)]
+pub struct TimetableDiff {
+    pub added_stations: Vec<String>,
+    pub removed_stations: Vec<String>,
+    // Stations whose names, TIPLOCs, change time or location changed
+    pub changed_stations: Vec<String>,
+    pub added_services: Vec<String>,
+    pub removed_services: Vec<String>,
+    // Services calling at the same stations at different times
+    pub retimed_services: Vec<String>,
+    // Services calling at a different sequence of stations
+    pub rerouted_services: Vec<String>
+}
+
+impl TimetableDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn station_changed(a: &Station, b: &Station) -> bool {
+    a.names != b.names || a.tiplocs != b.tiplocs || a.min_change_time != b.min_change_time
+        || a.gref_east != b.gref_east || a.gref_north != b.gref_north
+}
+
+// Calling pattern of a service by CRS, with HHMM times
+fn calls<'a>(stations: &'a StationList, service: &Service) -> Vec<(&'a str, String, String)> {
+    service.stops.iter().map(|stop| {
+        (stations.get(stop.station).unwrap().crs_code.as_str(), stop.arrival.to_24h(), stop.departure.to_24h())
+    }).collect()
+}
+
+fn by_uid(timetable: &Timetable) -> BTreeMap<&str, Vec<&Service>> {
+    let mut map: BTreeMap<&str, Vec<&Service>> = BTreeMap::new();
+    for service in timetable.services() {
+        map.entry(&service.train_uid).or_default().push(service);
+    }
+    map
+}
+
+/**
+ * Compare two extracts. A train UID can have several schedules, these are
+ * paired up in file order and any left over are added or removed.
+ */
+pub fn diff(old_stations: &StationList, old: &Timetable, new_stations: &StationList, new: &Timetable) -> TimetableDiff {
+    let mut diff = TimetableDiff::default();
+
+    let old_crs: BTreeMap<&str, &Station> = old_stations.iter().map(|s| (s.crs_code.as_str(), s)).collect();
+    let new_crs: BTreeMap<&str, &Station> = new_stations.iter().map(|s| (s.crs_code.as_str(), s)).collect();
+
+    for (crs, station) in &old_crs {
+        match new_crs.get(crs) {
+            Some(other) if station_changed(station, other) => diff.changed_stations.push(crs.to_string()),
+            Some(_) => {}
+            None => diff.removed_stations.push(crs.to_string())
+        }
+    }
+    for crs in new_crs.keys().filter(|crs| !old_crs.contains_key(*crs)) {
+        diff.added_stations.push(crs.to_string());
+    }
+
+    let old_uids = by_uid(old);
+    let new_uids = by_uid(new);
+
+    for (uid, old_services) in &old_uids {
+        let new_services = new_uids.get(uid).map(|v| v.as_slice()).unwrap_or(&[]);
+
+        for (i, service) in old_services.iter().enumerate() {
+            match new_services.get(i) {
+                Some(other) => {
+                    let a = calls(old_stations, service);
+                    let b = calls(new_stations, other);
+                    if a != b {
+                        let same_route = a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.0 == y.0);
+                        if same_route {
+                            diff.retimed_services.push(uid.to_string());
+                        } else {
+                            diff.rerouted_services.push(uid.to_string());
+                        }
+                    }
+                }
+                None => diff.removed_services.push(uid.to_string())
+            }
+        }
+
+        for _ in old_services.len()..new_services.len() {
+            diff.added_services.push(uid.to_string());
+        }
+    }
+
+    for (uid, new_services) in &new_uids {
+        if !old_uids.contains_key(uid) {
+            for _ in new_services.iter() {
+                diff.added_services.push(uid.to_string());
+            }
+        }
+    }
+    diff.added_services.sort();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timetable::Stop;
+
+    fn service(uid: &str, stops: Vec<Stop>) -> Service {
+        Service::simple(0, uid, stops)
+    }
+
+    #[test]
+    fn test_diff() {
+        let old_stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX"),
+            Station::simple("ELYY", "Ely", "ELY")
+        ]);
+        let new_stations = StationList::new(vec![
+            Station::simple("KINGSX", "London Kings Cross", "KGX"),
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("STEVNGE", "Stevenage", "SVG")
+        ]);
+
+        let old = Timetable::new(
+            None,
+            vec![
+                service("A00001", vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1100", "1100")]),
+                service("A00002", vec![Stop::simple(0, "1200", "1200"), Stop::simple(1, "1300", "1300")]),
+                service("A00003", vec![Stop::simple(0, "1400", "1400"), Stop::simple(1, "1500", "1500")]),
+                service("A00004", vec![Stop::simple(2, "1400", "1400"), Stop::simple(0, "1500", "1500")])
+            ],
+            Vec::new()
+        );
+        // Same services but IDs of CBG and KGX are swapped in the new list
+        let new = Timetable::new(
+            None,
+            vec![
+                service("A00001", vec![Stop::simple(1, "1000", "1000"), Stop::simple(0, "1100", "1100")]),
+                service("A00002", vec![Stop::simple(1, "1205", "1205"), Stop::simple(0, "1305", "1305")]),
+                service("A00003", vec![Stop::simple(1, "1400", "1400"), Stop::simple(2, "1430", "1430"), Stop::simple(0, "1500", "1500")]),
+                service("A00005", vec![Stop::simple(0, "1400", "1400"), Stop::simple(2, "1500", "1500")])
+            ],
+            Vec::new()
+        );
+
+        let d = diff(&old_stations, &old, &new_stations, &new);
+        assert_eq!(d, TimetableDiff {
+            added_stations: vec!["SVG".to_string()],
+            removed_stations: vec!["ELY".to_string()],
+            changed_stations: vec![],
+            added_services: vec!["A00005".to_string()],
+            removed_services: vec!["A00004".to_string()],
+            retimed_services: vec!["A00002".to_string()],
+            rerouted_services: vec!["A00003".to_string()]
+        });
+        assert!(diff(&old_stations, &old, &old_stations, &old).is_empty());
+    }
+}
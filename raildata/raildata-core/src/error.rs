@@ -0,0 +1,205 @@
+/** Copyright James Lomax 2020 */
+
+use std::io;
+
+use thiserror::Error;
+
+/** The kinds of timetable file, to tell which an error came from */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    // Master station names
+    Msn,
+    // Fixed links
+    Flf,
+    // Schedules (CIF)
+    Mca,
+    // Anything else, e.g. a config file or a Darwin snapshot
+    Other
+}
+
+impl FileKind {
+    /** The kind of the file at $path, by its extension */
+    pub fn from_path(path: &str) -> Self {
+        let ext = std::path::Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        match ext.to_ascii_uppercase().as_str() {
+            "MSN" => FileKind::Msn,
+            "FLF" => FileKind::Flf,
+            "MCA" | "CIF" => FileKind::Mca,
+            _ => FileKind::Other
+        }
+    }
+}
+
+// Longest record kept with an error, the width of a CIF record
+const MAX_RECORD: usize = 80;
+
+/** Errors from reading and parsing the timetable files */
+#[derive(Debug, Error)]
+pub enum RailDataError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    // A fixed width record too short to contain one of its fields
+    #[error("Bad record length {len} (while parsing field {field})")]
+    RecordLength { field: String, len: usize },
+
+    #[error("Could not parse field {field} '{value}'")]
+    InvalidField { field: String, value: String },
+
+    #[error("Could not parse time field {field} '{value}'")]
+    InvalidTime { field: String, value: String },
+
+    // A record referring to a station (by name, TIPLOC or CRS) which isn't in the station list
+    #[error("Reference to non-existent station {0}")]
+    UnknownStation(String),
+
+    #[error("EOF/short line while reading service")]
+    TruncatedService,
+
+    // A service (by train UID) with fewer than two stops, so it can't be travelled on
+    #[error("Service {0} has fewer than two stops")]
+    ShortService(String),
+
+    // A timetable without an HD record, where the dates it covers are needed
+    #[error("No header record giving the dates covered")]
+    MissingHeader,
+
+    // An update extract which doesn't follow on from the timetable it's applied to, by file reference
+    #[error("Update follows extract {found}, expected {expected}")]
+    UpdateSequence { expected: String, found: String },
+
+    // A malformed config file or override
+    #[error("Bad config: {0}")]
+    Config(String),
+
+    // A cache file of loaded services which is corrupt or from another version
+    #[error("Bad cache: {0}")]
+    Cache(String),
+
+    // A Darwin real-time message which can't be read
+    #[error("Bad real-time message: {0}")]
+    RealTime(String),
+
+    // Wraps an error with the line it was found on and the start of that line, empty if not known
+    #[error("On line {line}: {source}")]
+    AtLine { line: usize, record: String, source: Box<RailDataError> },
+
+    // Wraps an error with the file it was found in, and what kind of file that is
+    #[error("In {file}: {source}")]
+    InFile { file: String, kind: FileKind, source: Box<RailDataError> }
+}
+
+pub type Result<T> = std::result::Result<T, RailDataError>;
+
+impl RailDataError {
+    pub fn at_line(self, line: usize) -> Self {
+        self.at_record(line, "")
+    }
+
+    /** Wrap with the $line it was found on and the $record there, up to its first 80 characters */
+    pub fn at_record(self, line: usize, record: &str) -> Self {
+        let record = record.trim_end_matches(['\r', '\n']).chars().take(MAX_RECORD).collect();
+        RailDataError::AtLine { line, record, source: Box::new(self) }
+    }
+
+    /** Wrap with the $file it was found in, its kind going by the extension */
+    pub fn in_file(self, file: &str) -> Self {
+        RailDataError::InFile { file: file.to_string(), kind: FileKind::from_path(file), source: Box::new(self) }
+    }
+
+    /** The underlying error without any line or file context */
+    pub fn cause(&self) -> &RailDataError {
+        match self {
+            RailDataError::AtLine { source, .. } | RailDataError::InFile { source, .. } => source.cause(),
+            other => other
+        }
+    }
+
+    /** The line the error was found on, if known */
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            RailDataError::AtLine { line, .. } => Some(*line),
+            RailDataError::InFile { source, .. } => source.line(),
+            _ => None
+        }
+    }
+
+    /** The file the error was found in, if known */
+    pub fn file(&self) -> Option<&str> {
+        match self {
+            RailDataError::InFile { file, .. } => Some(file),
+            RailDataError::AtLine { source, .. } => source.file(),
+            _ => None
+        }
+    }
+
+    /** The kind of file the error was found in, if known */
+    pub fn file_kind(&self) -> Option<FileKind> {
+        match self {
+            RailDataError::InFile { kind, .. } => Some(*kind),
+            RailDataError::AtLine { source, .. } => source.file_kind(),
+            _ => None
+        }
+    }
+
+    /** The start of the record the error was found in, if known */
+    pub fn record(&self) -> Option<&str> {
+        match self {
+            RailDataError::AtLine { record, .. } if !record.is_empty() => Some(record),
+            RailDataError::AtLine { source, .. } | RailDataError::InFile { source, .. } => source.record(),
+            _ => None
+        }
+    }
+
+    /** The name of the field which couldn't be read, for errors about a field */
+    pub fn field(&self) -> Option<&str> {
+        match self.cause() {
+            RailDataError::RecordLength { field, .. }
+                | RailDataError::InvalidField { field, .. }
+                | RailDataError::InvalidTime { field, .. } => Some(field),
+            _ => None
+        }
+    }
+}
+
+impl From<RailDataError> for io::Error {
+    fn from(e: RailDataError) -> Self {
+        match e {
+            RailDataError::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context() {
+        let err = RailDataError::InvalidTime { field: "public_arrival".to_string(), value: "12x5".to_string() }
+            .at_line(12)
+            .in_file("RJTTF748.MCA");
+
+        assert_eq!(err.to_string(), "In RJTTF748.MCA: On line 12: Could not parse time field public_arrival '12x5'");
+        assert_eq!(err.line(), Some(12));
+        assert_eq!(err.file(), Some("RJTTF748.MCA"));
+        assert!(matches!(err.cause(), RailDataError::InvalidTime { .. }));
+        assert_eq!(err.file_kind(), Some(FileKind::Mca));
+        assert_eq!(err.field(), Some("public_arrival"));
+        assert_eq!(err.record(), None);
+
+        let err = RailDataError::RecordLength { field: "tiploc".to_string(), len: 5 }
+            .at_record(3, &format!("{}\r\n", "LIKNGX".repeat(20)))
+            .in_file("data/ttisf748.msn");
+        assert_eq!(err.file_kind(), Some(FileKind::Msn));
+        assert_eq!(err.record().map(|record| record.len()), Some(80));
+        assert!(err.record().unwrap().starts_with("LIKNGXLIKNGX"));
+        assert_eq!(err.field(), Some("tiploc"));
+
+        // Missing files can be told apart from bad records
+        let err = RailDataError::from(io::Error::from(io::ErrorKind::NotFound)).in_file("RJTTF748.FLF");
+        assert!(matches!(err.cause(), RailDataError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+        assert_eq!((err.line(), err.field(), err.file_kind()), (None, None, Some(FileKind::Flf)));
+    }
+}
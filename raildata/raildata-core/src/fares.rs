@@ -0,0 +1,383 @@
+/* Copyright James Lomax 2020 */
+
+// Fares from the RDG fares feed (the RJFAF files). Flows (F records in the
+// .FFL file) give the fares (T records) between two locations by a route, and
+// locations are named by NLC code, mapped to CRS codes by the .LOC file.
+// Flows are set by fare TOCs, mapped to the ATOC codes of train operators by
+// the .TOC file. Only what's needed to estimate walk-up prices is read.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::io::{BufRead, BufReader};
+
+use chrono::NaiveDate;
+
+use crate::error::{RailDataError, Result};
+use crate::record_parsing::{parse_date_or_invalid, parse_or_invalid};
+
+make_record_type!(FlowRecord,
+    (origin, 2, 4),
+    (destination, 6, 4),
+    (route, 10, 5),
+    (direction, 19, 1),
+    (end_date, 20, 8),
+    (start_date, 28, 8),
+    (toc, 36, 3),
+    (flow_id, 42, 7)
+);
+
+make_record_type!(FareRecord,
+    (flow_id, 2, 7),
+    (ticket, 9, 3),
+    (fare, 12, 8)
+);
+
+make_record_type!(FareTocRecord,
+    (fare_toc, 2, 3),
+    (toc, 5, 2)
+);
+
+make_record_type!(LocationRecord,
+    (end_date, 9, 8),
+    (nlc, 36, 4),
+    (crs, 56, 3)
+);
+
+// Ticket codes of walk-up fares usable on any train: Anytime (Day) Single and Return, standard class
+const WALK_UP_SINGLES: [&str; 2] = ["SDS", "SOS"];
+const WALK_UP_RETURNS: [&str; 2] = ["SDR", "SOR"];
+// Route code of flows by any permitted route, usable on any operator's trains
+const ANY_ROUTE: &str = "00000";
+
+// Records which aren't to be applied: comments, and deletions in change files
+fn skipped(line: &str) -> bool {
+    line.starts_with('/') || line.starts_with('D')
+}
+
+/** A fare for one kind of ticket, in pence */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fare {
+    pub ticket: String,
+    pub pence: u32
+}
+
+/** The fares from one station to another by one route, valid between its dates (inclusive) */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    // "00000" for any permitted route
+    pub route: String,
+    pub toc: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub fares: Vec<Fare>
+}
+
+impl Flow {
+    /** The cheapest fare for any of $tickets */
+    pub fn cheapest(&self, tickets: &[&str]) -> Option<u32> {
+        self.fares.iter().filter(|fare| tickets.contains(&fare.ticket.as_str())).map(|fare| fare.pence).min()
+    }
+
+    fn valid_on(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+}
+
+/** Estimated walk-up prices in pence, None where there's no such fare */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FareEstimate {
+    pub single: Option<u32>,
+    pub return_fare: Option<u32>
+}
+
+impl FareEstimate {
+    // The cheaper of each price of $self and $other
+    fn cheapest(self, other: FareEstimate) -> FareEstimate {
+        let min = |a: Option<u32>, b: Option<u32>| a.into_iter().chain(b).min();
+        FareEstimate { single: min(self.single, other.single), return_fare: min(self.return_fare, other.return_fare) }
+    }
+}
+
+/** Flows indexed by origin and destination CRS code */
+#[derive(Debug, Clone, Default)]
+pub struct FareTable {
+    flows: HashMap<(String, String), Vec<Flow>>,
+    // The ATOC code of each fare TOC, where the .TOC file gives one
+    operators: HashMap<String, String>
+}
+
+impl FareTable {
+    /**
+     * Load the .FFL and .LOC files sharing $file_prefix, e.g. "fares/RJFAF499", and
+     * the .TOC file if there is one. Without it, flows' fare TOCs are taken to be ATOC codes
+     */
+    pub fn load(file_prefix: &str) -> Result<Self> {
+        let open = |ext: &str| {
+            let path = format!("{}.{}", file_prefix, ext);
+            File::open(&path).map(BufReader::new).map_err(|e| RailDataError::from(e).in_file(&path))
+        };
+        let locations = Self::read_locations(&mut open("LOC")?).map_err(|e| e.in_file(&format!("{}.LOC", file_prefix)))?;
+        let mut table = Self::read_flows(&mut open("FFL")?, &locations).map_err(|e| e.in_file(&format!("{}.FFL", file_prefix)))?;
+        if Path::new(&format!("{}.TOC", file_prefix)).exists() {
+            table.operators = Self::read_operators(&mut open("TOC")?).map_err(|e| e.in_file(&format!("{}.TOC", file_prefix)))?;
+        }
+        Ok(table)
+    }
+
+    /** Read the ATOC code of each fare TOC from a .TOC file */
+    pub fn read_operators(reader: &mut dyn BufRead) -> Result<HashMap<String, String>> {
+        let mut operators = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if skipped(&line) || line.get(1..2) != Some("F") {
+                continue;
+            }
+            let r = FareTocRecord::read(&line).map_err(|e| e.at_record(index + 1, &line))?;
+            operators.insert(r.fare_toc.to_string(), r.toc.to_string());
+        }
+        Ok(operators)
+    }
+
+    /** $self with the ATOC code of each fare TOC from $operators, as from `read_operators` */
+    pub fn with_operators(self, operators: HashMap<String, String>) -> Self {
+        Self { operators, ..self }
+    }
+
+    /** Read the CRS code of each NLC code from a .LOC file. Locations without one, e.g. groups, are left out */
+    pub fn read_locations(reader: &mut dyn BufRead) -> Result<HashMap<String, String>> {
+        let mut locations = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if skipped(&line) || line.get(1..2) != Some("L") {
+                continue;
+            }
+            let r = LocationRecord::read(&line).map_err(|e| e.at_record(index + 1, &line))?;
+            // Of a location's records over time, the current one has the latest end date
+            if !r.crs.is_empty() && (r.end_date == "31122999" || !locations.contains_key(r.nlc)) {
+                locations.insert(r.nlc.to_string(), r.crs.to_string());
+            }
+        }
+        Ok(locations)
+    }
+
+    /** Read flows and their fares from a .FFL file, with $locations from `read_locations`. Flows between locations without a CRS code are left out */
+    pub fn read_flows(reader: &mut dyn BufRead, locations: &HashMap<String, String>) -> Result<Self> {
+        // Fares follow all the flows, so flows are kept by ID until they're complete
+        let mut by_id: HashMap<String, (String, String, bool, Flow)> = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if skipped(&line) {
+                continue;
+            }
+            let parsed = match line.get(1..2) {
+                Some("F") => FlowRecord::read(&line).and_then(|r| {
+                    let flow = Flow {
+                        route: r.route.to_string(),
+                        toc: r.toc.to_string(),
+                        start: parse_date_or_invalid(r.start_date, "%d%m%Y", "start_date")?,
+                        end: parse_date_or_invalid(r.end_date, "%d%m%Y", "end_date")?,
+                        fares: Vec::new()
+                    };
+                    if let (Some(origin), Some(destination)) = (locations.get(r.origin), locations.get(r.destination)) {
+                        by_id.insert(r.flow_id.to_string(), (origin.clone(), destination.clone(), r.direction == "R", flow));
+                    }
+                    Ok(())
+                }),
+                Some("T") => FareRecord::read(&line).and_then(|r| {
+                    let pence = parse_or_invalid(r.fare, "fare")?;
+                    if let Some((_, _, _, flow)) = by_id.get_mut(r.flow_id) {
+                        flow.fares.push(Fare { ticket: r.ticket.to_string(), pence });
+                    }
+                    Ok(())
+                }),
+                _ => Ok(())
+            };
+            parsed.map_err(|e| e.at_record(index + 1, &line))?;
+        }
+
+        let mut table = Self::default();
+        for (origin, destination, reversible, flow) in by_id.into_values() {
+            if reversible {
+                table.flows.entry((destination.clone(), origin.clone())).or_default().push(flow.clone());
+            }
+            table.flows.entry((origin, destination)).or_default().push(flow);
+        }
+        Ok(table)
+    }
+
+    /** The flows from $origin to $destination (CRS codes), by any route */
+    pub fn flows(&self, origin: &str, destination: &str) -> &[Flow] {
+        self.flows.get(&(origin.to_string(), destination.to_string())).map_or(&[], Vec::as_slice)
+    }
+
+    /** The flow from $origin to $destination by $route, e.g. "00000" for any permitted route */
+    pub fn flow(&self, origin: &str, destination: &str, route: &str) -> Option<&Flow> {
+        self.flows(origin, destination).iter().find(|flow| flow.route == route)
+    }
+
+    /**
+     * Estimated walk-up single and return prices from $origin to $destination: the
+     * cheapest Anytime fares of any route, valid on $date if given. None without a flow
+     */
+    pub fn estimate(&self, origin: &str, destination: &str, date: Option<NaiveDate>) -> Option<FareEstimate> {
+        self.estimate_by(origin, destination, date, |_| true)
+    }
+
+    /**
+     * As estimate, but only by flows usable on trains of each of $operators (ATOC codes).
+     * Flows by any permitted route are usable on any train, and flows by a particular
+     * route are taken to be only for trains of the operator setting them
+     */
+    pub fn estimate_on(&self, origin: &str, destination: &str, operators: &[&str], date: Option<NaiveDate>) -> Option<FareEstimate> {
+        self.estimate_by(origin, destination, date, |flow| {
+            let toc = self.operators.get(&flow.toc).unwrap_or(&flow.toc);
+            flow.route == ANY_ROUTE || operators.iter().all(|operator| operator == toc)
+        })
+    }
+
+    /**
+     * Estimated walk-up prices of travelling by $legs in turn, each a (origin, destination, operator)
+     * of CRS and ATOC codes: the cheaper of a through fare usable on every leg's operator and buying
+     * a fare for each leg. None if there's neither
+     */
+    pub fn estimate_legs(&self, legs: &[(&str, &str, &str)], date: Option<NaiveDate>) -> Option<FareEstimate> {
+        let (first, last) = (legs.first()?, legs.last()?);
+        let operators: Vec<&str> = legs.iter().map(|leg| leg.2).collect();
+        let through = self.estimate_on(first.0, last.1, &operators, date);
+
+        // Each leg's price, and only a total where every leg has one
+        let split = legs.iter()
+            .map(|(origin, destination, operator)| self.estimate_on(origin, destination, &[operator], date))
+            .collect::<Option<Vec<FareEstimate>>>()
+            .map(|estimates| FareEstimate {
+                single: estimates.iter().map(|estimate| estimate.single).sum(),
+                return_fare: estimates.iter().map(|estimate| estimate.return_fare).sum()
+            });
+        match (through, split.filter(|_| legs.len() > 1)) {
+            (Some(through), Some(split)) => Some(through.cheapest(split)),
+            (through, split) => through.or(split)
+        }
+    }
+
+    // Estimated prices from $origin to $destination by the flows valid on $date which are $usable
+    fn estimate_by(&self, origin: &str, destination: &str, date: Option<NaiveDate>, usable: impl Fn(&Flow) -> bool) -> Option<FareEstimate> {
+        let flows: Vec<&Flow> = self.flows(origin, destination).iter()
+            .filter(|flow| date.is_none_or(|date| flow.valid_on(date)) && usable(flow))
+            .collect();
+        if flows.is_empty() {
+            return None;
+        }
+        Some(FareEstimate {
+            single: flows.iter().filter_map(|flow| flow.cheapest(&WALK_UP_SINGLES)).min(),
+            return_fare: flows.iter().filter_map(|flow| flow.cheapest(&WALK_UP_RETURNS)).min()
+        })
+    }
+
+    /** Number of origin and destination pairs with flows */
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(nlc: &str, crs: &str, end: &str) -> String {
+        format!("RL{:7}{}{:8}{:8}{:3}{}{:16}{:3}", "7000000", end, "01012020", "01012020", "", nlc, "LOCATION", crs)
+    }
+
+    fn flow(origin: &str, destination: &str, route: &str, direction: char, id: &str) -> String {
+        format!("RF{}{}{}{:3}A{}3112299901012020ECX   {}", origin, destination, route, "000", direction, id)
+    }
+
+    fn fare(id: &str, ticket: &str, pence: u32) -> String {
+        format!("RT{}{}{:08}  ", id, ticket, pence)
+    }
+
+    #[test]
+    fn test_fares() {
+        let loc = [
+            "/!! Start of file".to_string(),
+            location("0433", "CBG", "31122999"),
+            location("6121", "KGX", "31122999"),
+            // An old record for Kings Cross, and a group without a CRS code
+            location("6121", "XXX", "31122019"),
+            location("1072", "", "31122999")
+        ].join("\n");
+        let locations = FareTable::read_locations(&mut loc.as_bytes()).unwrap();
+        assert_eq!(locations.get("6121").map(String::as_str), Some("KGX"));
+        assert!(!locations.contains_key("1072"));
+
+        let ffl = [
+            flow("0433", "6121", "00000", 'R', "0000001"),
+            flow("0433", "6121", "00700", 'S', "0000002"),
+            flow("0433", "1072", "00000", 'R', "0000003"),
+            fare("0000001", "SOS", 2800),
+            fare("0000001", "SOR", 3000),
+            fare("0000001", "CDS", 1500),
+            fare("0000002", "SDS", 2500),
+            fare("0000003", "SOS", 2900)
+        ].join("\n");
+        let fares = FareTable::read_flows(&mut ffl.as_bytes(), &locations).unwrap();
+        assert_eq!(fares.len(), 2);
+        assert_eq!(fares.flows("CBG", "KGX").len(), 2);
+        assert_eq!(fares.flow("CBG", "KGX", "00700").unwrap().fares, vec![Fare { ticket: "SDS".to_string(), pence: 2500 }]);
+
+        // The cheapest walk-up fare of any route, and only reversible flows the other way
+        assert_eq!(fares.estimate("CBG", "KGX", None), Some(FareEstimate { single: Some(2500), return_fare: Some(3000) }));
+        assert_eq!(fares.estimate("KGX", "CBG", None), Some(FareEstimate { single: Some(2800), return_fare: Some(3000) }));
+        assert_eq!(fares.estimate("CBG", "ELY", None), None);
+        assert_eq!(fares.estimate("CBG", "KGX", NaiveDate::from_ymd_opt(2019, 6, 1)), None);
+
+        let bad = fare("0000001", "SOS", 0).replace("00000000", "12.50   ");
+        let err = FareTable::read_flows(&mut bad.as_bytes(), &locations).unwrap_err();
+        assert_eq!(err.line(), Some(1));
+    }
+
+    #[test]
+    fn test_estimate_legs() {
+        let loc = [location("0433", "CBG", "31122999"), location("6121", "KGX", "31122999"), location("1555", "STP", "31122999")].join("\n");
+        let locations = FareTable::read_locations(&mut loc.as_bytes()).unwrap();
+        // A cheaper single from CBG to KGX only on ECX's trains, and through fares to STP
+        let ffl = [
+            flow("0433", "6121", "00000", 'R', "0000001"),
+            flow("0433", "6121", "00700", 'S', "0000002"),
+            flow("6121", "1555", "00000", 'S', "0000003"),
+            flow("0433", "1555", "00000", 'S', "0000004"),
+            fare("0000001", "SOS", 2800),
+            fare("0000001", "SOR", 3000),
+            fare("0000002", "SDS", 2500),
+            fare("0000003", "SOS", 300),
+            fare("0000004", "SOS", 3000)
+        ].join("\n");
+        let fares = FareTable::read_flows(&mut ffl.as_bytes(), &locations).unwrap();
+        let single = |legs: &[(&str, &str, &str)]| fares.estimate_legs(legs, None).and_then(|estimate| estimate.single);
+
+        // Without the .TOC file fare TOCs are compared as they are
+        assert_eq!(single(&[("CBG", "KGX", "ECX")]), Some(2500));
+        assert_eq!(single(&[("CBG", "KGX", "GR")]), Some(2800));
+
+        let operators = FareTable::read_operators(&mut "/!! Start of file\nRFECXGRLNER\nRTGRLNER".as_bytes()).unwrap();
+        assert_eq!(operators.get("ECX").map(String::as_str), Some("GR"));
+        let fares = fares.with_operators(operators);
+        let single = |legs: &[(&str, &str, &str)]| fares.estimate_legs(legs, None).and_then(|estimate| estimate.single);
+        assert_eq!(single(&[("CBG", "KGX", "GR")]), Some(2500));
+        assert_eq!(single(&[("CBG", "KGX", "GN")]), Some(2800));
+        assert_eq!(fares.estimate_on("CBG", "KGX", &["GR", "GN"], None).unwrap().single, Some(2800));
+
+        // Buying a ticket for each leg where that's cheaper than the through fare
+        assert_eq!(single(&[("CBG", "KGX", "GR"), ("KGX", "STP", "LO")]), Some(2800));
+        assert_eq!(single(&[("CBG", "KGX", "GN"), ("KGX", "STP", "LO")]), Some(3000));
+        // Returns only where every leg has one
+        assert_eq!(fares.estimate_legs(&[("CBG", "KGX", "GN"), ("KGX", "STP", "LO")], None).unwrap().return_fare, None);
+        assert_eq!(single(&[("KGX", "CBG", "GR"), ("CBG", "ELY", "GR")]), None);
+        assert_eq!(fares.estimate_legs(&[], None), None);
+    }
+}
@@ -0,0 +1,397 @@
+/** Copyright James Lomax 2020 */
+
+use std::io::BufRead;
+use chrono::NaiveDate;
+use regex::Regex;
+use crate::error::{RailDataError, Result};
+use crate::record_parsing::parse_date_or_invalid;
+use crate::stations::{StationId, StationList};
+use crate::timetable::{Calendar, RailTime, Stp};
+use crate::validate::{IssueSink, ParseIssue, ParseIssueKind};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FixedLinkKind {
+    Walk,
+    Tube,
+    Metro,
+    Bus,
+    Ferry,
+    Transfer
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedLink {
+    pub a: StationId,
+    pub b: StationId,
+    pub time: u32,
+    pub kind: FixedLinkKind,
+    // Only from a to b. ALF files list each direction separately, FLF links go both ways
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub one_way: bool,
+    // None if the link can always be used
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub availability: Option<LinkAvailability>
+}
+
+impl FixedLink {
+    /** A link both ways which can always be used, as in FLF files */
+    pub fn simple(a: StationId, b: StationId, time: u32, kind: FixedLinkKind) -> Self {
+        Self { a, b, time, kind, one_way: false, availability: None }
+    }
+}
+
+/** When an ALF link can be used, e.g. a bus which only runs during the day */
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkAvailability {
+    // The times of day it can be set off on, inclusive. An end before the start is past midnight
+    pub start: RailTime,
+    pub end: RailTime,
+    pub calendar: Calendar
+}
+
+impl LinkAvailability {
+    /** Seconds to wait from $t until the link can next be set off on, ignoring the days it runs */
+    pub fn wait_from(&self, t: RailTime) -> u32 {
+        if self.open_at(t) { 0 } else { t.timetil(&self.start) }
+    }
+
+    /** Seconds back from $t to the latest time at or before it the link can be set off on, ignoring the days it runs */
+    pub fn back_from(&self, t: RailTime) -> u32 {
+        if self.open_at(t) { 0 } else { self.end.timetil(&t) }
+    }
+
+    fn open_at(&self, t: RailTime) -> bool {
+        if self.start.secs() <= self.end.secs() {
+            self.start.secs() <= t.secs() && t.secs() <= self.end.secs()
+        } else {
+            t.secs() >= self.start.secs() || t.secs() <= self.end.secs()
+        }
+    }
+}
+
+fn kind_from_name(name: &str) -> Option<FixedLinkKind> {
+    match name {
+        "WALK" => Some(FixedLinkKind::Walk),
+        "TUBE" => Some(FixedLinkKind::Tube),
+        "METRO" => Some(FixedLinkKind::Metro),
+        "BUS" => Some(FixedLinkKind::Bus),
+        "FERRY" => Some(FixedLinkKind::Ferry),
+        "TRANSFER" => Some(FixedLinkKind::Transfer),
+        _ => None
+    }
+}
+
+// Minutes as seconds, as an error if they'd overflow
+fn minutes(minstr: &str) -> Result<u32> {
+    match minstr.parse::<u32>().ok().filter(|m| m.checked_mul(60).is_some()) {
+        Some(mins) => Ok(mins*60),
+        None => Err(RailDataError::InvalidField { field: "minutes".to_string(), value: minstr.to_string() })
+    }
+}
+
+fn station_or_err(stations: &StationList, crs: &str, line: usize, record: &str, sink: &mut IssueSink) -> Result<Option<StationId>> {
+    if let Some(stat) = stations.get_by_crs(crs) {
+        Ok(Some(stat.id))
+    } else {
+        let err = RailDataError::UnknownStation(format!("CRS {}", crs));
+        sink.report(line, record, ParseIssueKind::UnknownStation(crs.to_string()), err)?;
+        Ok(None)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub fn parse_fixed_links(stations: &StationList, reader: &mut dyn BufRead) -> Result<Vec<FixedLink>> {
+    parse_links(stations, reader, &mut IssueSink::strict())
+}
+
+/** Parse fixed links, collecting tolerated issues such as unknown link kinds in $warnings */
+pub fn parse_fixed_links_with_warnings(stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<ParseIssue>) -> Result<Vec<FixedLink>> {
+    parse_links(stations, reader, &mut IssueSink::strict_with_warnings(warnings))
+}
+
+/** Parse fixed links, skipping links to unknown stations and collecting them in $issues */
+pub fn parse_fixed_links_lenient(stations: &StationList, reader: &mut dyn BufRead, issues: &mut Vec<ParseIssue>) -> Result<Vec<FixedLink>> {
+    parse_links(stations, reader, &mut IssueSink::lenient(issues))
+}
+
+fn parse_links(stations: &StationList, reader: &mut dyn BufRead, sink: &mut IssueSink) -> Result<Vec<FixedLink>> {
+    let pattern = Regex::new("^ADDITIONAL LINK: ([A-Z]+) BETWEEN ([A-Z]{3}) AND ([A-Z]{3}) IN +([0-9]+) MINUTES *$").unwrap();
+
+    let mut links = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_num = index + 1;
+        let line = line?;
+
+        if let Some(caps) = pattern.captures(&line) {
+            assert_eq!(caps.len(), 5);
+
+            let kind = match kind_from_name(caps.get(1).unwrap().as_str()) {
+                Some(kind) => kind,
+                None => {
+                    let other = caps.get(1).unwrap().as_str();
+                    sink.note(line_num, ParseIssueKind::UnknownLinkKind(other.to_string()), format!("Unknown link kind {}", other));
+                    continue;
+                }
+            };
+
+            let a = station_or_err(stations, caps.get(2).unwrap().as_str(), line_num, &line, sink)?;
+            let b = station_or_err(stations, caps.get(3).unwrap().as_str(), line_num, &line, sink)?;
+            let (a, b) = match (a, b) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue
+            };
+
+            // Matches [0-9]+ but may still overflow
+            let time = match minutes(caps.get(4).unwrap().as_str()) {
+                Ok(time) => time,
+                Err(err) => {
+                    sink.report(line_num, &line, ParseIssueKind::Malformed, err)?;
+                    continue;
+                }
+            };
+
+            links.push(FixedLink::simple(a, b, time, kind));
+        }
+    }
+
+    tracing::debug!(links = links.len(), "Read FLF file");
+    Ok(links)
+}
+
+#[tracing::instrument(skip_all)]
+pub fn parse_additional_links(stations: &StationList, reader: &mut dyn BufRead) -> Result<Vec<FixedLink>> {
+    parse_alf(stations, reader, &mut IssueSink::strict())
+}
+
+/** Parse an ALF file, collecting tolerated issues such as unknown link kinds in $warnings */
+pub fn parse_additional_links_with_warnings(stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<ParseIssue>) -> Result<Vec<FixedLink>> {
+    parse_alf(stations, reader, &mut IssueSink::strict_with_warnings(warnings))
+}
+
+/** Parse an ALF file, skipping malformed links and links to unknown stations and collecting them in $issues */
+pub fn parse_additional_links_lenient(stations: &StationList, reader: &mut dyn BufRead, issues: &mut Vec<ParseIssue>) -> Result<Vec<FixedLink>> {
+    parse_alf(stations, reader, &mut IssueSink::lenient(issues))
+}
+
+// The fields of an ALF line, before looking up the stations
+struct AlfLine<'a> {
+    mode: &'a str,
+    origin: &'a str,
+    destination: &'a str,
+    time: u32,
+    availability: Option<LinkAvailability>
+}
+
+fn alf_time(s: &str, fieldname: &str) -> Result<RailTime> {
+    RailTime::from_24h(s).filter(|_| s.len() == 4)
+        .ok_or_else(|| RailDataError::InvalidTime { field: fieldname.to_string(), value: s.to_string() })
+}
+
+/**
+ * Split an ALF line such as "M=BUS,O=ABC,D=DEF,T=10,S=0600,E=2300,P=4,F=01/01/2020,U=31/12/2020,R=1111100"
+ * into its fields. M, O, D and T are required, the rest restrict when the link can be used.
+ */
+fn parse_alf_line(line: &str) -> Result<AlfLine<'_>> {
+    let (mut mode, mut origin, mut destination, mut time) = (None, None, None, None);
+    let (mut start, mut end, mut from, mut until, mut days) = (None, None, None, None, None);
+    for field in line.split(',') {
+        let (key, value) = match field.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(RailDataError::InvalidField { field: "alf_field".to_string(), value: field.to_string() })
+        };
+        match key {
+            "M" => mode = Some(value),
+            "O" => origin = Some(value),
+            "D" => destination = Some(value),
+            "T" => time = Some(minutes(value)?),
+            "S" => start = Some(alf_time(value, "start_time")?),
+            "E" => end = Some(alf_time(value, "end_time")?),
+            "F" => from = Some(parse_date_or_invalid(value, "%d/%m/%Y", "start_date")?),
+            "U" => until = Some(parse_date_or_invalid(value, "%d/%m/%Y", "end_date")?),
+            "R" => {
+                let invalid = || RailDataError::InvalidField { field: "days_run".to_string(), value: value.to_string() };
+                if value.len() != 7 {
+                    return Err(invalid());
+                }
+                let mut days_run = [false; 7];
+                for (day, c) in days_run.iter_mut().zip(value.chars()) {
+                    *day = match c {
+                        '1' => true,
+                        '0' => false,
+                        _ => return Err(invalid())
+                    };
+                }
+                days = Some(days_run);
+            }
+            // Priority, and anything newer, doesn't affect routing
+            _ => {}
+        }
+    }
+
+    let missing = |field: &str| RailDataError::InvalidField { field: field.to_string(), value: line.to_string() };
+    let restricted = start.is_some() || end.is_some() || from.is_some() || until.is_some() || days.is_some();
+    Ok(AlfLine {
+        mode: mode.ok_or_else(|| missing("mode"))?,
+        origin: origin.ok_or_else(|| missing("origin"))?,
+        destination: destination.ok_or_else(|| missing("destination"))?,
+        time: time.ok_or_else(|| missing("time"))?,
+        availability: restricted.then(|| LinkAvailability {
+            start: start.unwrap_or(RailTime::new(0, 0)),
+            end: end.unwrap_or(RailTime::new(23, 59)),
+            calendar: Calendar {
+                runs_from: from.unwrap_or(NaiveDate::MIN),
+                runs_to: until.unwrap_or(NaiveDate::MAX),
+                days_run: days.unwrap_or([true; 7]),
+                stp: Stp::Permanent
+            }
+        })
+    })
+}
+
+fn parse_alf(stations: &StationList, reader: &mut dyn BufRead, sink: &mut IssueSink) -> Result<Vec<FixedLink>> {
+    let mut links = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_num = index + 1;
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("/!!") {
+            continue;
+        }
+
+        let alf = match parse_alf_line(line) {
+            Ok(alf) => alf,
+            Err(err) => {
+                sink.report(line_num, line, ParseIssueKind::Malformed, err)?;
+                continue;
+            }
+        };
+
+        let kind = match kind_from_name(alf.mode) {
+            Some(kind) => kind,
+            None => {
+                sink.note(line_num, ParseIssueKind::UnknownLinkKind(alf.mode.to_string()), format!("Unknown link kind {}", alf.mode));
+                continue;
+            }
+        };
+
+        let a = station_or_err(stations, alf.origin, line_num, line, sink)?;
+        let b = station_or_err(stations, alf.destination, line_num, line, sink)?;
+        if let (Some(a), Some(b)) = (a, b) {
+            links.push(FixedLink {
+                a,
+                b,
+                time: alf.time,
+                kind,
+                one_way: true,
+                availability: alf.availability
+            });
+        }
+    }
+
+    tracing::debug!(links = links.len(), "Read ALF file");
+    Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use proptest::prelude::*;
+    use crate::stations::Station;
+
+    #[test]
+    fn test_fixed_links() {
+        let example = "/!! Begin
+ADDITIONAL LINK: FERRY BETWEEN ABC AND DEF IN  25 MINUTES  
+ADDITIONAL LINK: TUBE BETWEEN DEF AND XYZ IN  45 MINUTES    ";
+
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "ABC"),
+            Station::simple("KINGSX", "London Kings Cross", "DEF"),
+            Station::simple("FOO", "FooBar", "XYZ")
+        ]);
+
+        let mut reader = io::Cursor::new(&example);
+        let links = parse_fixed_links(&stations, &mut reader).unwrap();
+
+        assert_eq!(links, vec![
+            FixedLink::simple(0, 1, 25*60, FixedLinkKind::Ferry),
+            FixedLink::simple(1, 2, 45*60, FixedLinkKind::Tube),
+        ]);
+    }
+
+    #[test]
+    fn test_additional_links() {
+        let example = "M=WALK,O=ABC,D=DEF,T=5,S=0000,E=2359,P=4,F=01/01/2020,U=31/12/2020,R=1111111
+M=BUS,O=DEF,D=ABC,T=20,S=0630,E=2300,P=4,F=01/06/2020,U=30/06/2020,R=1111100
+
+M=HOVERCRAFT,O=ABC,D=DEF,T=10
+M=TUBE,O=DEF,D=ABC,T=15";
+
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "ABC"),
+            Station::simple("KINGSX", "London Kings Cross", "DEF")
+        ]);
+
+        let mut warnings = Vec::new();
+        let links = parse_additional_links_with_warnings(&stations, &mut io::Cursor::new(&example), &mut warnings).unwrap();
+        assert_eq!(links.len(), 3);
+        assert_eq!(warnings[0].kind, ParseIssueKind::UnknownLinkKind("HOVERCRAFT".to_string()));
+
+        assert_eq!((links[0].a, links[0].b, links[0].time, links[0].kind, links[0].one_way), (0, 1, 5*60, FixedLinkKind::Walk, true));
+        let bus = links[1].availability.as_ref().unwrap();
+        assert_eq!((bus.start, bus.end), (RailTime::new(6, 30), RailTime::new(23, 0)));
+        assert!(bus.calendar.runs_on(NaiveDate::from_ymd_opt(2020, 6, 5).unwrap()));
+        assert!(!bus.calendar.runs_on(NaiveDate::from_ymd_opt(2020, 6, 6).unwrap()));
+        assert!(!bus.calendar.runs_on(NaiveDate::from_ymd_opt(2020, 7, 1).unwrap()));
+        assert_eq!(bus.wait_from(RailTime::new(6, 0)), 30*60);
+        assert_eq!(bus.wait_from(RailTime::new(23, 30)), 7*60*60);
+        assert_eq!(bus.back_from(RailTime::new(6, 0)), 7*60*60);
+        assert_eq!((bus.back_from(RailTime::new(12, 0)), bus.back_from(RailTime::new(23, 30))), (0, 30*60));
+        assert_eq!(links[2], FixedLink { one_way: true, ..FixedLink::simple(1, 0, 15*60, FixedLinkKind::Tube) });
+
+        // Missing the time, or a bad day of the week
+        let bad = "M=WALK,O=ABC,D=DEF\nM=WALK,O=ABC,D=DEF,T=5,R=11x1111\n";
+        assert!(parse_additional_links(&stations, &mut io::Cursor::new(&bad)).is_err());
+        let mut issues = Vec::new();
+        assert!(parse_additional_links_lenient(&stations, &mut io::Cursor::new(&bad), &mut issues).unwrap().is_empty());
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn test_fixed_link_overflow() {
+        let example = "ADDITIONAL LINK: WALK BETWEEN ABC AND DEF IN 99999999999 MINUTES\n";
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "ABC"),
+            Station::simple("KINGSX", "London Kings Cross", "DEF")
+        ]);
+
+        assert!(parse_fixed_links(&stations, &mut io::Cursor::new(&example)).is_err());
+
+        let mut issues = Vec::new();
+        let links = parse_fixed_links_lenient(&stations, &mut io::Cursor::new(&example), &mut issues).unwrap();
+        assert!(links.is_empty());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, ParseIssueKind::Malformed);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_flf_lines(lines in proptest::collection::vec("ADDITIONAL LINK: (WALK|BUS|HOVERCRAFT) BETWEEN [A-C]{3} AND [A-C]{3} IN +[0-9]{1,12} MINUTES *|\\PC{0,80}", 0..10)) {
+            let stations = StationList::new(vec![
+                Station::simple("AAA", "A", "AAA"),
+                Station::simple("BBB", "B", "BBB")
+            ]);
+            let flf = lines.join("\n");
+
+            let mut issues = Vec::new();
+            let links = parse_fixed_links_lenient(&stations, &mut io::Cursor::new(&flf), &mut issues).unwrap();
+            prop_assert!(links.iter().all(|link| link.a < 2 && link.b < 2));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+/** Copyright James Lomax 2020 */
+
+/** Latitude and longitude in degrees on the WGS84 datum, as used by GPS and web maps */
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64
+}
+
+// Airy 1830 ellipsoid and National Grid projection constants
+const AIRY_A: f64 = 6377563.396;
+const AIRY_B: f64 = 6356256.909;
+const NG_F0: f64 = 0.9996012717;
+const NG_LAT0: f64 = 49.0;
+const NG_LON0: f64 = -2.0;
+const NG_E0: f64 = 400000.0;
+const NG_N0: f64 = -100000.0;
+
+// WGS84 ellipsoid
+const WGS84_A: f64 = 6378137.0;
+const WGS84_B: f64 = 6356752.3142;
+
+// Helmert transform from OSGB36 to WGS84 (translation in metres, scale in ppm, rotation in arc seconds)
+const HELMERT_T: (f64, f64, f64) = (446.448, -125.157, 542.060);
+const HELMERT_S: f64 = -20.4894;
+const HELMERT_R: (f64, f64, f64) = (0.1502, 0.2470, 0.8421);
+
+// Distance north along the central meridian of the National Grid from its true origin to $lat (radians)
+fn meridional_arc(lat: f64) -> f64 {
+    let (b, f0) = (AIRY_B, NG_F0);
+    let lat0 = NG_LAT0.to_radians();
+    let n = (AIRY_A - b) / (AIRY_A + b);
+    let (n2, n3) = (n * n, n * n * n);
+    let ma = (1.0 + n + 1.25 * n2 + 1.25 * n3) * (lat - lat0);
+    let mb = (3.0 * n + 3.0 * n2 + 21.0 / 8.0 * n3) * (lat - lat0).sin() * (lat + lat0).cos();
+    let mc = (15.0 / 8.0 * n2 + 15.0 / 8.0 * n3) * (2.0 * (lat - lat0)).sin() * (2.0 * (lat + lat0)).cos();
+    let md = 35.0 / 24.0 * n3 * (3.0 * (lat - lat0)).sin() * (3.0 * (lat + lat0)).cos();
+    b * f0 * (ma - mb + mc - md)
+}
+
+/** Transverse mercator projection of a latitude/longitude (radians) on the Airy ellipsoid onto the National Grid */
+fn airy_to_grid(lat: f64, lon: f64) -> (f64, f64) {
+    let (a, b, f0) = (AIRY_A, AIRY_B, NG_F0);
+    let e2 = 1.0 - (b * b) / (a * a);
+    let (sin_lat, cos_lat) = lat.sin_cos();
+    let nu = a * f0 / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    let rho = a * f0 * (1.0 - e2) / (1.0 - e2 * sin_lat * sin_lat).powf(1.5);
+    let eta2 = nu / rho - 1.0;
+    let tan2 = lat.tan().powi(2);
+    let tan4 = tan2 * tan2;
+
+    let i = meridional_arc(lat) + NG_N0;
+    let ii = nu / 2.0 * sin_lat * cos_lat;
+    let iii = nu / 24.0 * sin_lat * cos_lat.powi(3) * (5.0 - tan2 + 9.0 * eta2);
+    let iiia = nu / 720.0 * sin_lat * cos_lat.powi(5) * (61.0 - 58.0 * tan2 + tan4);
+    let iv = nu * cos_lat;
+    let v = nu / 6.0 * cos_lat.powi(3) * (nu / rho - tan2);
+    let vi = nu / 120.0 * cos_lat.powi(5) * (5.0 - 18.0 * tan2 + tan4 + 14.0 * eta2 - 58.0 * tan2 * eta2);
+
+    let dl = lon - NG_LON0.to_radians();
+    let north = i + ii * dl.powi(2) + iii * dl.powi(4) + iiia * dl.powi(6);
+    let east = NG_E0 + iv * dl + v * dl.powi(3) + vi * dl.powi(5);
+    (east, north)
+}
+
+/** Inverse transverse mercator projection of a National Grid reference onto the Airy ellipsoid */
+fn grid_to_airy(east: f64, north: f64) -> (f64, f64) {
+    let (a, b, f0) = (AIRY_A, AIRY_B, NG_F0);
+    let lat0 = NG_LAT0.to_radians();
+    let lon0 = NG_LON0.to_radians();
+    let e2 = 1.0 - (b * b) / (a * a);
+
+    // Iterate to find the latitude giving the meridional arc for this northing
+    let mut lat = lat0;
+    let mut m = 0.0;
+    loop {
+        lat += (north - NG_N0 - m) / (a * f0);
+        m = meridional_arc(lat);
+        if (north - NG_N0 - m).abs() < 0.00001 {
+            break;
+        }
+    }
+
+    let (sin_lat, cos_lat) = lat.sin_cos();
+    let nu = a * f0 / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    let rho = a * f0 * (1.0 - e2) / (1.0 - e2 * sin_lat * sin_lat).powf(1.5);
+    let eta2 = nu / rho - 1.0;
+
+    let tan = lat.tan();
+    let (tan2, tan4, tan6) = (tan * tan, tan.powi(4), tan.powi(6));
+    let sec = 1.0 / cos_lat;
+
+    let vii = tan / (2.0 * rho * nu);
+    let viii = tan / (24.0 * rho * nu.powi(3)) * (5.0 + 3.0 * tan2 + eta2 - 9.0 * tan2 * eta2);
+    let ix = tan / (720.0 * rho * nu.powi(5)) * (61.0 + 90.0 * tan2 + 45.0 * tan4);
+    let x = sec / nu;
+    let xi = sec / (6.0 * nu.powi(3)) * (nu / rho + 2.0 * tan2);
+    let xii = sec / (120.0 * nu.powi(5)) * (5.0 + 28.0 * tan2 + 24.0 * tan4);
+    let xiia = sec / (5040.0 * nu.powi(7)) * (61.0 + 662.0 * tan2 + 1320.0 * tan4 + 720.0 * tan6);
+
+    let de = east - NG_E0;
+    let lat = lat - vii * de.powi(2) + viii * de.powi(4) - ix * de.powi(6);
+    let lon = lon0 + x * de - xi * de.powi(3) + xii * de.powi(5) - xiia * de.powi(7);
+    (lat, lon)
+}
+
+fn to_cartesian(lat: f64, lon: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let nu = a / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    (nu * lat.cos() * lon.cos(), nu * lat.cos() * lon.sin(), (1.0 - e2) * nu * lat.sin())
+}
+
+fn from_cartesian(x: f64, y: f64, z: f64, a: f64, b: f64) -> (f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let p = (x * x + y * y).sqrt();
+    let mut lat = z.atan2(p * (1.0 - e2));
+    for _ in 0..10 {
+        let nu = a / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        lat = (z + e2 * nu * lat.sin()).atan2(p);
+    }
+    (lat, y.atan2(x))
+}
+
+/** Convert an OSGB36 National Grid easting/northing (in metres) to WGS84 latitude/longitude */
+pub fn osgb36_to_wgs84(east: f64, north: f64) -> LatLon {
+    let (lat, lon) = grid_to_airy(east, north);
+    let (x, y, z) = helmert(to_cartesian(lat, lon, AIRY_A, AIRY_B), 1.0);
+    let (lat, lon) = from_cartesian(x, y, z, WGS84_A, WGS84_B);
+    LatLon {
+        lat: lat.to_degrees(),
+        lon: lon.to_degrees()
+    }
+}
+
+/** Convert WGS84 latitude/longitude to an OSGB36 National Grid easting/northing (in metres), accurate to a few metres */
+pub fn wgs84_to_osgb36(ll: LatLon) -> (f64, f64) {
+    let (x, y, z) = helmert(to_cartesian(ll.lat.to_radians(), ll.lon.to_radians(), WGS84_A, WGS84_B), -1.0);
+    let (lat, lon) = from_cartesian(x, y, z, AIRY_A, AIRY_B);
+    airy_to_grid(lat, lon)
+}
+
+// The Helmert transform from OSGB36 to WGS84 cartesian coordinates, or approximately back again with $sign -1
+fn helmert((x, y, z): (f64, f64, f64), sign: f64) -> (f64, f64, f64) {
+    let s = sign * HELMERT_S * 1e-6;
+    let arcsec = |v: f64| sign * (v / 3600.0).to_radians();
+    let (rx, ry, rz) = (arcsec(HELMERT_R.0), arcsec(HELMERT_R.1), arcsec(HELMERT_R.2));
+    let (tx, ty, tz) = (sign * HELMERT_T.0, sign * HELMERT_T.1, sign * HELMERT_T.2);
+
+    (
+        tx + (1.0 + s) * x - rz * y + ry * z,
+        ty + rz * x + (1.0 + s) * y - rx * z,
+        tz - ry * x + rx * y + (1.0 + s) * z
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_to_airy() {
+        // Worked example from the OS "guide to coordinate systems in Great Britain"
+        let (lat, lon) = grid_to_airy(651409.903, 313177.270);
+        assert!((lat.to_degrees() - 52.657570).abs() < 1e-6);
+        assert!((lon.to_degrees() - 1.717922).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_airy_to_grid() {
+        // The inverse of the worked example
+        let (east, north) = airy_to_grid(52.657570_f64.to_radians(), 1.717922_f64.to_radians());
+        assert!((east - 651409.903).abs() < 0.1, "{}", east);
+        assert!((north - 313177.270).abs() < 0.1, "{}", north);
+    }
+
+    #[test]
+    fn test_wgs84_to_osgb36() {
+        for (east, north) in [(530300.0, 183000.0), (394200.0, 805800.0), (146000.0, 32000.0)] {
+            let (e, n) = wgs84_to_osgb36(osgb36_to_wgs84(east, north));
+            assert!((e - east).abs() < 1.0 && (n - north).abs() < 1.0, "{} {} -> {} {}", east, north, e, n);
+        }
+    }
+
+    #[test]
+    fn test_osgb36_to_wgs84() {
+        // London Kings Cross, TQ 303 830
+        let ll = osgb36_to_wgs84(530300.0, 183000.0);
+        assert!((ll.lat - 51.5309).abs() < 0.0001);
+        assert!((ll.lon - -0.1229).abs() < 0.0001);
+
+        // Aberdeen, NJ 942 058
+        let ll = osgb36_to_wgs84(394200.0, 805800.0);
+        assert!((ll.lat - 57.1431).abs() < 0.0001);
+        assert!((ll.lon - -2.0975).abs() < 0.0001);
+    }
+}
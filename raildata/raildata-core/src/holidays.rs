@@ -0,0 +1,191 @@
+/* Copyright James Lomax 2020 */
+
+// Bank holidays, which schedules can be marked as not running on. Those of
+// England and Wales and of Scotland are built in, following the usual rules
+// with substitute days for those at a weekend, and one-off changes such as a
+// jubilee can be added or a moved holiday removed.
+
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+// Years the built-in holidays are worked out for
+const FIRST_YEAR: i32 = 1990;
+const LAST_YEAR: i32 = 2060;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    EnglandWales,
+    Scotland
+}
+
+/** The bank holidays a schedule doesn't run on, from the bank holiday running field of its BS record */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BankHolidayRunning {
+    #[default]
+    Runs,
+    // X: not on bank holiday Mondays (in England and Wales)
+    NotBankHolidayMondays,
+    // G: not on Glasgow bank holidays, taken to be Scotland's
+    NotGlasgowBankHolidays
+}
+
+impl BankHolidayRunning {
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "X" => BankHolidayRunning::NotBankHolidayMondays,
+            "G" => BankHolidayRunning::NotGlasgowBankHolidays,
+            _ => BankHolidayRunning::Runs
+        }
+    }
+
+    /** Whether a schedule with this marking doesn't run on $date, going by $holidays */
+    pub fn excludes(&self, holidays: &BankHolidays, date: NaiveDate) -> bool {
+        match self {
+            BankHolidayRunning::Runs => false,
+            BankHolidayRunning::NotBankHolidayMondays => date.weekday() == Weekday::Mon && holidays.is_holiday(Region::EnglandWales, date),
+            BankHolidayRunning::NotGlasgowBankHolidays => holidays.is_holiday(Region::Scotland, date)
+        }
+    }
+}
+
+/** The built-in bank holidays from 1990 to 2060 with any changes made to them */
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BankHolidays {
+    dates: BTreeSet<(Region, NaiveDate)>
+}
+
+impl Default for BankHolidays {
+    fn default() -> Self {
+        let mut dates = BTreeSet::new();
+        for year in FIRST_YEAR..=LAST_YEAR {
+            dates.extend(england_wales(year).into_iter().map(|date| (Region::EnglandWales, date)));
+            dates.extend(scotland(year).into_iter().map(|date| (Region::Scotland, date)));
+        }
+        Self { dates }
+    }
+}
+
+impl BankHolidays {
+    pub fn is_holiday(&self, region: Region, date: NaiveDate) -> bool {
+        self.dates.contains(&(region, date))
+    }
+
+    /** Add a one-off holiday in $region */
+    pub fn add(&mut self, region: Region, date: NaiveDate) {
+        self.dates.insert((region, date));
+    }
+
+    /** Remove a holiday in $region, e.g. one moved for a special occasion */
+    pub fn remove(&mut self, region: Region, date: NaiveDate) {
+        self.dates.remove(&(region, date));
+    }
+
+    /** The holidays in $region in $year, in date order */
+    pub fn in_year(&self, region: Region, year: i32) -> Vec<NaiveDate> {
+        self.dates.iter().filter(|(r, date)| *r == region && date.year() == year).map(|(_, date)| *date).collect()
+    }
+}
+
+// Easter Sunday, by the anonymous Gregorian algorithm
+fn easter(year: i32) -> NaiveDate {
+    let (a, b, c) = (year % 19, year / 100, year % 100);
+    let g = (b - (b + 8)/25 + 1) / 3;
+    let h = (19*a + b - b/4 - g + 15) % 30;
+    let l = (32 + 2*(b % 4) + 2*(c / 4) - h - (c % 4)) % 7;
+    let m = (a + 11*h + 22*l) / 451;
+    let n = h + l - 7*m + 114;
+    NaiveDate::from_ymd_opt(year, (n / 31) as u32, (n % 31 + 1) as u32).unwrap()
+}
+
+fn first_monday(year: i32, month: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Mon, 1).unwrap()
+}
+
+fn last_monday(year: i32, month: u32) -> NaiveDate {
+    let last = NaiveDate::from_ymd_opt(year, month + 1, 1).unwrap() - Duration::days(1);
+    last - Duration::days(last.weekday().num_days_from_monday() as i64)
+}
+
+// $fixed (month, day) dates with those at a weekend moved to the next weekday which isn't already a holiday, and $others
+fn with_substitutes(year: i32, fixed: &[(u32, u32)], others: Vec<NaiveDate>) -> Vec<NaiveDate> {
+    let fixed: Vec<NaiveDate> = fixed.iter().map(|(month, day)| NaiveDate::from_ymd_opt(year, *month, *day).unwrap()).collect();
+    let weekend = |date: &NaiveDate| date.weekday().num_days_from_monday() >= 5;
+    let mut dates: BTreeSet<NaiveDate> = others.into_iter().chain(fixed.iter().copied().filter(|date| !weekend(date))).collect();
+    for date in fixed.iter().filter(|date| weekend(date)) {
+        let mut substitute = *date;
+        while weekend(&substitute) || dates.contains(&substitute) {
+            substitute += Duration::days(1);
+        }
+        dates.insert(substitute);
+    }
+    dates.into_iter().collect()
+}
+
+fn england_wales(year: i32) -> Vec<NaiveDate> {
+    let easter = easter(year);
+    let others = vec![
+        easter - Duration::days(2),
+        easter + Duration::days(1),
+        first_monday(year, 5),
+        last_monday(year, 5),
+        last_monday(year, 8)
+    ];
+    with_substitutes(year, &[(1, 1), (12, 25), (12, 26)], others)
+}
+
+fn scotland(year: i32) -> Vec<NaiveDate> {
+    let others = vec![
+        easter(year) - Duration::days(2),
+        first_monday(year, 5),
+        last_monday(year, 5),
+        first_monday(year, 8)
+    ];
+    with_substitutes(year, &[(1, 1), (1, 2), (11, 30), (12, 25), (12, 26)], others)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_bank_holidays() {
+        assert_eq!(easter(2024), ymd(2024, 3, 31));
+        assert_eq!(easter(2025), ymd(2025, 4, 20));
+
+        let holidays = BankHolidays::default();
+        // Christmas on a Sunday is made up for on the Tuesday, after Boxing Day
+        assert_eq!(holidays.in_year(Region::EnglandWales, 2022), vec![
+            ymd(2022, 1, 3), ymd(2022, 4, 15), ymd(2022, 4, 18), ymd(2022, 5, 2), ymd(2022, 5, 30),
+            ymd(2022, 8, 29), ymd(2022, 12, 26), ymd(2022, 12, 27)
+        ]);
+        // New Year on a Saturday gives the Monday and Tuesday in Scotland
+        assert_eq!(holidays.in_year(Region::Scotland, 2022), vec![
+            ymd(2022, 1, 3), ymd(2022, 1, 4), ymd(2022, 4, 15), ymd(2022, 5, 2), ymd(2022, 5, 30),
+            ymd(2022, 8, 1), ymd(2022, 11, 30), ymd(2022, 12, 26), ymd(2022, 12, 27)
+        ]);
+        // St Andrew's Day on a Saturday is made up for on the Monday
+        assert!(holidays.is_holiday(Region::Scotland, ymd(2024, 12, 2)));
+
+        // The spring holiday of 2022 was moved for the jubilee
+        let mut holidays = BankHolidays::default();
+        holidays.remove(Region::EnglandWales, ymd(2022, 5, 30));
+        holidays.add(Region::EnglandWales, ymd(2022, 6, 2));
+        holidays.add(Region::EnglandWales, ymd(2022, 6, 3));
+        let bank_holiday_mondays = BankHolidayRunning::from_code("X");
+        assert!(bank_holiday_mondays.excludes(&holidays, ymd(2022, 8, 29)));
+        assert!(!bank_holiday_mondays.excludes(&holidays, ymd(2022, 5, 30)));
+        // Only Mondays, and not in Scotland
+        assert!(!bank_holiday_mondays.excludes(&holidays, ymd(2022, 6, 3)));
+        assert!(!bank_holiday_mondays.excludes(&holidays, ymd(2022, 8, 1)));
+        assert!(BankHolidayRunning::from_code("G").excludes(&holidays, ymd(2022, 8, 1)));
+        assert!(!BankHolidayRunning::from_code("").excludes(&holidays, ymd(2022, 8, 29)));
+    }
+}
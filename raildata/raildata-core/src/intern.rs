@@ -0,0 +1,69 @@
+/* Copyright James Lomax 2020 */
+
+// Interned strings, for the short codes every service repeats such as its
+// operator and train category. Each distinct value is allocated once and kept
+// for the life of the process, so services share it rather than each holding
+// a copy. Only for values drawn from a small set, as they're never freed.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+/** A string from `intern` */
+pub type Interned = &'static str;
+
+static INTERNED: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+
+/** The shared copy of $s */
+pub fn intern(s: &str) -> Interned {
+    if s.is_empty() {
+        return "";
+    }
+    let mut interned = INTERNED.get_or_init(Default::default).lock().unwrap();
+    match interned.get(s) {
+        Some(shared) => shared,
+        None => {
+            let shared: &'static str = Box::leak(s.into());
+            interned.insert(shared);
+            shared
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+struct InternVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for InternVisitor {
+    type Value = Interned;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(intern(v))
+    }
+}
+
+/** Deserialize an interned string, for fields with `deserialize_with` */
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Interned, D::Error> {
+    deserializer.deserialize_str(InternVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        // Equal strings from different allocations share one copy
+        let code = String::from("GN");
+        let a = intern(&code);
+        let b = intern(&code.clone());
+        assert_eq!(a, "GN");
+        assert!(std::ptr::eq(a, b));
+        assert!(!std::ptr::eq(a, intern("GR")));
+        assert_eq!(intern(""), "");
+    }
+}
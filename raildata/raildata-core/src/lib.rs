@@ -0,0 +1,27 @@
+#![allow(dead_code)]
+/** Copyright James Lomax 2020 */
+
+#[macro_use]
+mod record_parsing;
+pub mod error;
+pub mod stations;
+pub mod timetable;
+pub mod fixed_links;
+pub mod validate;
+pub mod diff;
+pub mod intern;
+pub mod fares;
+pub mod holidays;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(feature = "darwin")]
+pub mod darwin;
+
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub use record_parsing::extract_record_field;
+
+pub use error::{FileKind, RailDataError};
+pub use stations::{Station, StationList, StationGroup, StationId, LookupOptions, LookupOrder};
+pub use fixed_links::FixedLinkKind;
+pub use timetable::{Timetable, ServiceIter, Departure, RailTime, ServiceTime, Reservations, Accommodation, Platform, ServiceMode, StopActivity, Calendar, Stp, Service, ServiceId, Association, AssociationKind};
@@ -0,0 +1,76 @@
+/** Copyright James Lomax 2020 */
+
+use crate::error::{RailDataError, Result};
+
+pub fn extract_record_field<'a>(fieldname: &str, rec: &'a str, offset: usize, len: usize) -> Result<&'a str> {
+    if offset + len > rec.len() {
+        return Err(RailDataError::RecordLength { field: fieldname.to_string(), len: rec.len() });
+    }
+    match rec.get(offset..offset+len) {
+        Some(field) => Ok(field.trim()),
+        // The field boundary splits a multi-byte character
+        None => Err(RailDataError::InvalidField {
+            field: fieldname.to_string(),
+            value: String::from_utf8_lossy(&rec.as_bytes()[offset..offset+len]).to_string()
+        })
+    }
+}
+
+macro_rules! make_record_type {
+    ($T:ident, $( ($name:ident, $offset:expr, $len:expr) ),*) => {
+        struct $T<'a> {
+            $($name: &'a str,)*
+        }
+
+        impl<'a> $T<'a> {
+            fn read(rec: &'a str) -> crate::error::Result<Self> {
+                Ok(Self {
+                    $($name: crate::record_parsing::extract_record_field(stringify!($name), rec, $offset, $len)?,)*
+                })
+            }
+        }
+    }
+}
+
+pub fn parse_or_invalid<T>(s: &str, fieldname: &str) -> Result<T> 
+    where T : std::str::FromStr
+{
+    match s.parse::<T>() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(RailDataError::InvalidField { field: fieldname.to_string(), value: s.to_string() })
+    }
+}
+
+/** Parse a CIF date field, e.g. format "%d%m%y" for the header and "%y%m%d" for schedules */
+pub fn parse_date_or_invalid(s: &str, format: &str, fieldname: &str) -> Result<chrono::NaiveDate> {
+    match chrono::NaiveDate::parse_from_str(s, format) {
+        Ok(d) => Ok(d),
+        Err(_) => Err(RailDataError::InvalidField { field: fieldname.to_string(), value: s.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_extract_record_field() {
+        assert_eq!(extract_record_field("a", "AB  CD", 2, 4).unwrap(), "CD");
+        assert!(matches!(extract_record_field("a", "AB", 1, 2), Err(RailDataError::RecordLength { .. })));
+        // Splitting the two byte \u{e9}
+        assert!(matches!(extract_record_field("a", "A\u{e9}B", 0, 2), Err(RailDataError::InvalidField { .. })));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_extract_record_field(rec in "\\PC{0,40}", offset in 0usize..48, len in 0usize..16) {
+            match extract_record_field("a", &rec, offset, len) {
+                Ok(field) => prop_assert!(rec.contains(field)),
+                Err(RailDataError::RecordLength { .. }) => prop_assert!(offset + len > rec.len()),
+                Err(RailDataError::InvalidField { .. }) => prop_assert!(!rec.is_ascii()),
+                Err(e) => prop_assert!(false, "unexpected error {}", e)
+            }
+        }
+    }
+}
@@ -0,0 +1,854 @@
+/** Copyright James Lomax 2020 */
+
+use std::collections::HashMap;
+
+use std::io::BufRead;
+use crate::error::{RailDataError, Result};
+use crate::validate::{IssueSink, ParseIssue, ParseIssueKind};
+
+use crate::record_parsing::parse_or_invalid;
+#[cfg(feature = "geo")]
+use crate::geo::{osgb36_to_wgs84, LatLon};
+
+pub type StationId = usize;
+
+#[derive(std::fmt::Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Station {
+    pub id: StationId,
+    pub tiplocs: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(rename = "crs"))]
+    pub crs_code: String,
+    pub names: Vec<String>,
+    pub min_change_time: u32,
+    // National Grid reference in units of 100m, 0 for both if the MSN doesn't give one
+    pub gref_east: i32,
+    pub gref_north: i32
+}
+
+make_record_type!(
+    MsnStationRecord,
+    (name, 5, 26),
+    (cate_interchange, 35, 1),
+    (tiploc, 36, 7),
+    (crs_ref, 43, 3),
+    (crs, 49, 3),
+    (os_gref_east, 52, 5),
+    (os_gref_north, 58, 5),
+    (min_change_time, 63, 2)
+);
+
+// MSN grid references are 5 digits in units of 100m, offset so eastings always
+// start with a 1 and northings with a 6. All zeros means there isn't one.
+const EAST_OFFSET: i32 = 10000;
+const NORTH_OFFSET: i32 = 60000;
+
+fn decode_gref(field: &str, offset: i32, fieldname: &str) -> Result<i32> {
+    match parse_or_invalid::<i32>(field, fieldname)? {
+        0 => Ok(0),
+        v if v >= offset => Ok(v - offset),
+        _ => Err(RailDataError::InvalidField { field: fieldname.to_string(), value: field.to_string() })
+    }
+}
+
+make_record_type!(
+    MsnAliasRecord,
+    (name, 5, 26),
+    (alias, 36, 26)
+);
+
+impl Station {
+    /** Station with a single name and TIPLOC, for test fixtures */
+    #[doc(hidden)]
+    pub fn simple(tiploc: &str, name: &str, crs: &str) -> Self {
+        Self {
+            id: 0,
+            tiplocs: vec![tiploc.to_string()],
+            crs_code: crs.to_string(),
+            names: vec![name.to_string()],
+            min_change_time: 0,
+            gref_east: 0,
+            gref_north: 0
+        }
+    }
+
+    pub(crate) fn from_msn_a_record(line: &str) -> Result<Self> {
+        let record = MsnStationRecord::read(line)?;
+        
+        return Ok(Self {
+            id: 0,
+            tiplocs: vec![record.tiploc.to_string()],
+            crs_code: record.crs.to_string(),
+            names: vec![record.name.to_string()],
+            min_change_time: parse_or_invalid(record.min_change_time, "min_change_time")?,
+            gref_east: decode_gref(record.os_gref_east, EAST_OFFSET, "os_gref_east")?,
+            gref_north: decode_gref(record.os_gref_north, NORTH_OFFSET, "os_gref_north")?
+        });
+    }
+
+    /** National Grid easting and northing in metres, or None if the station has no grid reference */
+    pub fn grid_ref(&self) -> Option<(f64, f64)> {
+        if self.gref_east == 0 && self.gref_north == 0 {
+            return None;
+        }
+        Some((self.gref_east as f64 * 100.0, self.gref_north as f64 * 100.0))
+    }
+
+    /** WGS84 latitude and longitude for web maps, or None if the station has no grid reference */
+    #[cfg(feature = "geo")]
+    pub fn lat_lon(&self) -> Option<LatLon> {
+        self.grid_ref().map(|(east, north)| osgb36_to_wgs84(east, north))
+    }
+
+    pub(crate) fn update_from_other(&mut self, other: &Self) {
+        // They should be essentially the same if they have the same CRS
+        assert_eq!(self.crs_code, other.crs_code);
+        // Some details are discarded
+
+        // Union of names
+        for name in &other.names {
+            if !self.names.contains(name) {
+                self.names.push(name.clone());
+            }
+        }
+
+        // But the TIPLOC will be different
+        self.tiplocs.append(&mut other.tiplocs.clone());
+    }
+}
+
+/** Stations a journey can start or end at any of, e.g. the London terminals */
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationGroup {
+    pub code: String,
+    pub name: String,
+    pub members: Vec<StationId>
+}
+
+/** How `StationList::lookup` orders its results */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LookupOrder {
+    // The exact CRS match, then as name_search
+    #[default]
+    Score,
+    // By main name, then ID
+    Name
+}
+
+/** Filters, order and paging for `StationList::lookup`. The default keeps every match */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupOptions {
+    pub order: LookupOrder,
+    // Leave out stations without a CRS code
+    pub crs_only: bool,
+    // Only stations located within the box between these south-west and north-east corners
+    #[cfg(feature = "geo")]
+    pub within: Option<(LatLon, LatLon)>,
+    pub offset: usize,
+    // Every match after offset if None
+    pub limit: Option<usize>
+}
+
+impl LookupOptions {
+    fn keeps(&self, station: &Station) -> bool {
+        if self.crs_only && station.crs_code.trim().is_empty() {
+            return false;
+        }
+        #[cfg(feature = "geo")]
+        if let Some((sw, ne)) = self.within {
+            return station.lat_lon().is_some_and(|ll| sw.lat <= ll.lat && ll.lat <= ne.lat && sw.lon <= ll.lon && ll.lon <= ne.lon);
+        }
+        true
+    }
+}
+
+pub struct StationList {
+    // Map of stations by TIPLOC
+    stations: Vec<Station>,
+
+    // Groups by code. These aren't in the MSN, so are added after loading and aren't serialized
+    groups: HashMap<String, StationGroup>,
+    
+    // Map of IDs by TIPLOC
+    by_tiploc: HashMap<String, StationId>,
+
+    // Map of ID by name (including any Aliases)
+    by_name: HashMap<String, StationId>,
+
+    // Map of IDs by CRS code
+    by_crs: HashMap<String, StationId>,
+
+    // Stations with a grid reference by grid square, for nearest searches
+    grid: GridIndex
+}
+
+fn insert_for(map: &mut HashMap<String, StationId>, names: &Vec<String>, station: StationId) {
+    for name in names {
+        map.insert(name.clone(), station);
+    }
+}
+
+impl StationList {
+    pub fn new(statlist: Vec<Station>) -> Self {
+        let mut stations = Self {
+            stations: statlist,
+            groups: HashMap::new(),
+            by_tiploc: HashMap::new(),
+            by_name: HashMap::new(),
+            by_crs: HashMap::new(),
+            grid: GridIndex::default()
+        };
+        
+        // Populate the lookup tables
+        for (i, station) in stations.stations.iter_mut().enumerate() {
+            insert_for(&mut stations.by_tiploc, &station.tiplocs, i);
+            insert_for(&mut stations.by_name, &station.names, i);
+            stations.by_crs.insert(station.crs_code.clone(), i as StationId);
+            station.id = i as StationId;
+        }
+        stations.grid = GridIndex::new(&stations.stations);
+
+        return stations;
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn read_msn_file(reader: &mut dyn BufRead) -> Result<Self> {
+        Self::read_msn(reader, &mut IssueSink::strict())
+    }
+
+    /** Read an MSN file, collecting tolerated issues such as records merged by CRS in $warnings */
+    pub fn read_msn_file_with_warnings(reader: &mut dyn BufRead, warnings: &mut Vec<ParseIssue>) -> Result<Self> {
+        Self::read_msn(reader, &mut IssueSink::strict_with_warnings(warnings))
+    }
+
+    /** Read an MSN file, skipping bad records and collecting them in $issues */
+    pub fn read_msn_file_lenient(reader: &mut dyn BufRead, issues: &mut Vec<ParseIssue>) -> Result<Self> {
+        Self::read_msn(reader, &mut IssueSink::lenient(issues))
+    }
+
+    fn read_msn(reader: &mut dyn BufRead, sink: &mut IssueSink) -> Result<Self> {
+        let mut stations = Self {
+            stations: Vec::new(),
+            groups: HashMap::new(),
+            by_tiploc: HashMap::new(),
+            by_name: HashMap::new(),
+            by_crs: HashMap::new(),
+            grid: GridIndex::default()
+        };
+
+        // Iterate over file and populate stations map
+        let mut a_rec_head = true;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_num = index + 1;
+
+            match line.chars().nth(0) {
+                Some('A') => {
+                    // Station record
+                    if a_rec_head {
+                        // Skip first line
+                        a_rec_head = false;
+                    } else {
+                        let mut s = match Station::from_msn_a_record(&line) {
+                            Ok(s) => s,
+                            Err(e) => {
+                                sink.report(line_num, &line, ParseIssueKind::Malformed, e)?;
+                                continue;
+                            }
+                        };
+                        let crs = s.crs_code.clone();
+                        let tiplocs = s.tiplocs.clone();
+
+                        // Check if we've already got a station with this CRS
+                        if let Some(station) = stations.by_crs.get_mut(&crs).cloned() {
+                            sink.note(line_num, ParseIssueKind::MergedStation(crs.clone()),
+                                format!("Merged TIPLOC {} into station {}", tiplocs.join(","), crs));
+                            stations.stations[station].update_from_other(&s);
+                            insert_for(&mut stations.by_tiploc, &tiplocs, station);
+                            insert_for(&mut stations.by_name, &s.names, station);
+                        } else {
+                            // New ID is the current length (next index)
+                            let next_id = stations.stations.len() as StationId;
+                            s.id = next_id;
+                            let names = s.names.clone();
+                            stations.stations.push(s);
+                            stations.by_crs.insert(crs, next_id);
+                            insert_for(&mut stations.by_name, &names, next_id);
+                            insert_for(&mut stations.by_tiploc, &tiplocs, next_id);
+                        }
+                    }
+                }
+                Some('L') => {
+                    // Alias record
+                    let r = match MsnAliasRecord::read(&line) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            sink.report(line_num, &line, ParseIssueKind::Malformed, e)?;
+                            continue;
+                        }
+                    };
+                    
+                    let maybe_id = stations.by_name.get(r.name).cloned();
+                    if let Some(id) = maybe_id {
+                        stations.by_name.insert(r.alias.to_string(), id);
+                        stations.stations[id as usize].names.push(r.alias.to_string());
+                    } else {
+                        let err = RailDataError::UnknownStation(r.name.to_string());
+                        sink.report(line_num, &line, ParseIssueKind::UnknownStation(r.name.to_string()), err)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        stations.grid = GridIndex::new(&stations.stations);
+        tracing::debug!(stations = stations.count(), "Read MSN file");
+        return Ok(stations);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<Station> {
+        self.stations.iter()
+    }
+
+    pub fn get(&self, id: StationId) -> Option<&Station> {
+        self.stations.get(id as usize)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Station> {
+        match self.by_name.get(name).cloned() {
+            Some(id) => self.get(id),
+            None => None
+        }
+    }
+
+    pub fn get_by_tiploc(&self, name: &str) -> Option<&Station> {
+        match self.by_tiploc.get(name).cloned() {
+            Some(id) => self.get(id),
+            None => None
+        }
+    }
+
+    pub fn get_by_crs(&self, name: &str) -> Option<&Station> {
+        match self.by_crs.get(name).cloned() {
+            Some(id) => self.get(id),
+            None => None
+        }
+    }
+
+    /**
+     * Add a group of the stations with CRS codes $members, replacing any group with
+     * the same $code. Fails if a member isn't a known station.
+     */
+    pub fn add_group(&mut self, code: &str, name: &str, members: &[String]) -> Result<()> {
+        let members = members.iter()
+            .map(|crs| self.by_crs.get(crs).copied().ok_or_else(|| RailDataError::UnknownStation(crs.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        self.groups.insert(code.to_string(), StationGroup { code: code.to_string(), name: name.to_string(), members });
+        Ok(())
+    }
+
+    /** Set the minimum change time, in minutes, of the station with CRS $code. Fails if there's no such station */
+    pub fn set_min_change_time(&mut self, code: &str, mins: u32) -> Result<StationId> {
+        let id = *self.by_crs.get(code).ok_or_else(|| RailDataError::UnknownStation(code.to_string()))?;
+        self.stations[id].min_change_time = mins;
+        Ok(id)
+    }
+
+    pub fn get_group(&self, code: &str) -> Option<&StationGroup> {
+        self.groups.get(code)
+    }
+
+    /** Groups ordered by code */
+    pub fn groups(&self) -> Vec<&StationGroup> {
+        let mut groups: Vec<&StationGroup> = self.groups.values().collect();
+        groups.sort_by(|a, b| a.code.cmp(&b.code));
+        groups
+    }
+
+    /** The station with CRS $code, or the members of the group with that code if there's no such station */
+    pub fn resolve(&self, code: &str) -> Option<Vec<StationId>> {
+        match self.by_crs.get(code) {
+            Some(id) => Some(vec![*id]),
+            None => self.get_group(code).map(|group| group.members.clone())
+        }
+    }
+
+    /**
+     * Stations with a name like $query, best match first with its score. Case and
+     * punctuation are ignored, so "kings cross" finds "LONDON KINGS CROSS". Exact
+     * names score highest, then names starting with the query, names containing
+     * it, names with every word close to one of the query's, and finally names
+     * sharing enough trigrams with it. Ties are in name order.
+     */
+    pub fn name_search(&self, query: &str) -> Vec<(StationId, u32)> {
+        let query = normalise_name(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let query_words: Vec<&str> = query.split(' ').collect();
+        let query_trigrams = trigrams(&query);
+
+        // The best score for each station over its names, and the name it was for
+        let mut best: HashMap<StationId, (u32, &str)> = HashMap::new();
+        for (name, id) in self.by_name.iter() {
+            let score = match name_score(&normalise_name(name), &query, &query_words, &query_trigrams) {
+                Some(score) => score,
+                None => continue
+            };
+            let entry = best.entry(*id).or_insert((score, name));
+            if (score, std::cmp::Reverse(name.as_str())) > (entry.0, std::cmp::Reverse(entry.1)) {
+                *entry = (score, name);
+            }
+        }
+
+        let mut ranked: Vec<(StationId, u32, &str)> = best.into_iter().map(|(id, (score, name))| (id, score, name)).collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(b.2)).then(a.0.cmp(&b.0)));
+        ranked.into_iter().map(|(id, score, _)| (id, score)).collect()
+    }
+
+    /**
+     * A page of the stations for $query, as given by the server's lookups: the
+     * station with that CRS code (if any) then `name_search` matches, filtered,
+     * ordered and paged by $options. Also gives the number of matches over all pages.
+     */
+    pub fn lookup(&self, query: &str, options: &LookupOptions) -> (Vec<StationId>, usize) {
+        let crs = self.by_crs.get(&query.trim().to_uppercase()).copied();
+        let mut found: Vec<StationId> = crs.into_iter().collect();
+        found.extend(self.name_search(query).into_iter().map(|(id, _)| id).filter(|id| Some(*id) != crs));
+        found.retain(|id| options.keeps(&self.stations[*id]));
+        if options.order == LookupOrder::Name {
+            found.sort_by(|a, b| self.stations[*a].names[0].cmp(&self.stations[*b].names[0]).then(a.cmp(b)));
+        }
+
+        let total = found.len();
+        let page = found.into_iter().skip(options.offset).take(options.limit.unwrap_or(usize::MAX)).collect();
+        (page, total)
+    }
+
+    /**
+     * The $n stations closest to the National Grid reference $east, $north (in
+     * metres), closest first with their distances in metres. Stations without a
+     * grid reference are never included.
+     */
+    pub fn nearest(&self, n: usize, east: f64, north: f64) -> Vec<(StationId, f64)> {
+        self.grid.nearest(&self.stations, n, east, north)
+    }
+
+    pub fn count(&self) -> usize {
+        self.stations.len()
+    }
+}
+
+// Width of the squares stations are bucketed by, in metres
+const GRID_SQUARE: f64 = 10_000.0;
+
+#[derive(Default)]
+struct GridIndex {
+    squares: HashMap<(i32, i32), Vec<StationId>>,
+    // Lowest and highest square (x, y) with any stations in
+    min: (i32, i32),
+    max: (i32, i32)
+}
+
+fn grid_square(east: f64, north: f64) -> (i32, i32) {
+    ((east / GRID_SQUARE).floor() as i32, (north / GRID_SQUARE).floor() as i32)
+}
+
+impl GridIndex {
+    fn new(stations: &[Station]) -> Self {
+        let mut grid = GridIndex { squares: HashMap::new(), min: (i32::MAX, i32::MAX), max: (i32::MIN, i32::MIN) };
+        for station in stations {
+            if let Some((east, north)) = station.grid_ref() {
+                let square = grid_square(east, north);
+                grid.squares.entry(square).or_default().push(station.id);
+                grid.min = (grid.min.0.min(square.0), grid.min.1.min(square.1));
+                grid.max = (grid.max.0.max(square.0), grid.max.1.max(square.1));
+            }
+        }
+        grid
+    }
+
+    // Searches rings of squares out from the one containing the point until no unseen station can be closer than the nth found
+    fn nearest(&self, stations: &[Station], n: usize, east: f64, north: f64) -> Vec<(StationId, f64)> {
+        if n == 0 || self.squares.is_empty() {
+            return Vec::new();
+        }
+        let (cx, cy) = grid_square(east, north);
+        // The rings before and after which there are no stations
+        let first_ring = [0, cx - self.max.0, self.min.0 - cx, cy - self.max.1, self.min.1 - cy].iter().copied().max().unwrap();
+        let last_ring = [cx - self.min.0, self.max.0 - cx, cy - self.min.1, self.max.1 - cy].iter().map(|d| d.abs()).max().unwrap();
+
+        let mut found: Vec<(StationId, f64)> = Vec::new();
+        for ring in first_ring..=last_ring {
+            let mut visit = |x: i32, y: i32| {
+                for id in self.squares.get(&(x, y)).into_iter().flatten() {
+                    let (e, n) = stations[*id].grid_ref().unwrap();
+                    found.push((*id, (e - east).hypot(n - north)));
+                }
+            };
+            if ring == 0 {
+                visit(cx, cy);
+            }
+            for dx in -ring..=ring {
+                if ring > 0 {
+                    visit(cx + dx, cy - ring);
+                    visit(cx + dx, cy + ring);
+                }
+            }
+            for dy in (1 - ring)..ring {
+                visit(cx - ring, cy + dy);
+                visit(cx + ring, cy + dy);
+            }
+
+            found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            // Stations in later rings are at least this far away
+            if found.len() >= n && found[n - 1].1 <= ring as f64 * GRID_SQUARE {
+                break;
+            }
+        }
+        found.truncate(n);
+        found
+    }
+}
+
+// Upper case alphanumeric words separated by single spaces, so "King's  Cross" is "KINGS CROSS"
+fn normalise_name(name: &str) -> String {
+    let mut normalised = String::with_capacity(name.len());
+    for word in name.split(|c: char| c.is_whitespace() || c == '-' || c == '/' || c == '(' || c == ')') {
+        let word: String = word.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect();
+        if !word.is_empty() {
+            if !normalised.is_empty() {
+                normalised.push(' ');
+            }
+            normalised.push_str(&word);
+        }
+    }
+    normalised
+}
+
+// Sets of three characters in $s padded with spaces, sorted without repeats
+fn trigrams(s: &str) -> Vec<[u8; 3]> {
+    let padded: Vec<u8> = format!("  {} ", s).into_bytes();
+    let mut grams: Vec<[u8; 3]> = padded.windows(3).map(|w| [w[0], w[1], w[2]]).collect();
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+// Jaccard similarity of two sorted trigram sets, from 0 to 1
+fn trigram_similarity(a: &[[u8; 3]], b: &[[u8; 3]]) -> f32 {
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    shared as f32 / (a.len() + b.len() - shared) as f32
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.bytes().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Scores for the kinds of match, less a little for each character of the name not matched
+const EXACT_SCORE: u32 = 1000;
+const PREFIX_SCORE: u32 = 900;
+const WORD_PREFIX_SCORE: u32 = 800;
+const CONTAINS_SCORE: u32 = 700;
+const WORDS_SCORE: u32 = 600;
+const TRIGRAM_SCORE: u32 = 500;
+// Names less alike than this aren't matches at all
+const MIN_TRIGRAM_SIMILARITY: f32 = 0.3;
+
+// How well the normalised $name matches $query (already split into $words and $grams), None if not at all
+fn name_score(name: &str, query: &str, words: &[&str], grams: &[[u8; 3]]) -> Option<u32> {
+    let unmatched = (name.len().saturating_sub(query.len()) as u32).min(99);
+    if name == query {
+        return Some(EXACT_SCORE);
+    }
+    if name.starts_with(query) {
+        return Some(PREFIX_SCORE - unmatched);
+    }
+    if name.contains(&format!(" {}", query)) {
+        return Some(WORD_PREFIX_SCORE - unmatched);
+    }
+    if name.contains(query) {
+        return Some(CONTAINS_SCORE - unmatched);
+    }
+
+    // Every query word starting a word of the name or a typo or two away from one
+    let name_words: Vec<&str> = name.split(' ').collect();
+    let mut typos = 0;
+    for word in words {
+        let allowed = if word.len() >= 6 { 2 } else if word.len() >= 3 { 1 } else { 0 };
+        let closest = name_words.iter()
+            .map(|name_word| if name_word.starts_with(word) { 0 } else { levenshtein(word, name_word) })
+            .min()?;
+        if closest > allowed {
+            typos = usize::MAX;
+            break;
+        }
+        typos += closest;
+    }
+    if typos != usize::MAX {
+        return Some(WORDS_SCORE - (typos as u32 * 10 + unmatched).min(99));
+    }
+
+    let similarity = trigram_similarity(&trigrams(name), grams);
+    if similarity >= MIN_TRIGRAM_SIMILARITY {
+        return Some(TRIGRAM_SCORE - ((1.0 - similarity) * 99.0) as u32);
+    }
+    None
+}
+
+// Serialized as the list of stations, the lookups are rebuilt from their codes and names
+#[cfg(feature = "serde")]
+impl serde::Serialize for StationList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.stations.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StationList {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Vec::<Station>::deserialize(deserializer).map(StationList::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_station_msn_parse() {
+        let rec1 = "A    ABBEY WOOD MTR                9ABWDXR ABX   ABW15473 61790 4";
+        let s = Station::from_msn_a_record(rec1).unwrap();
+        assert_eq!(s.tiplocs, vec!["ABWDXR"]);
+        assert_eq!(s.crs_code, "ABW");
+        assert_eq!(s.names, vec!["ABBEY WOOD MTR"]);
+        assert_eq!(s.min_change_time, 4);
+        assert_eq!(s.gref_east, 5473);
+        assert_eq!(s.gref_north, 1790);
+
+        let rec2 = "A    ABBEY WOOD MTR                9ABWDXR ABX   ABW15473 617";
+        let s = Station::from_msn_a_record(rec2);
+        s.expect_err("Record too short!");
+    }
+
+    #[test]
+    fn test_grid_ref() {
+        let rec = "A    ABERDEEN                      2ABRDEENABD   ABD13942 68058 5";
+        let s = Station::from_msn_a_record(rec).unwrap();
+        assert_eq!(s.grid_ref(), Some((394200.0, 805800.0)));
+        #[cfg(feature = "geo")]
+        {
+            let ll = s.lat_lon().unwrap();
+            assert!((ll.lat - 57.143).abs() < 0.01 && (ll.lon + 2.098).abs() < 0.01, "{:?}", ll);
+        }
+
+        let rec = "A    NOWHERE                       0NOWHEREXNW   XNW00000 00000 5";
+        let s = Station::from_msn_a_record(rec).unwrap();
+        assert_eq!(s.grid_ref(), None);
+
+        let rec = "A    NOWHERE                       0NOWHEREXNW   XNW05473 61790 5";
+        Station::from_msn_a_record(rec).expect_err("Easting without its leading 1");
+    }
+
+    #[test]
+    fn test_stations_read() {
+        let msn = "/!! Start of file...
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    ABBEY WOOD                    0ABWD   ABW   ABW15473 61790 4                         
+A    ABERDARE                      0ABDARE ABA   ABA13004 62027 3                 
+A    ABERDEEN                      2ABRDEENABD   ABD13942 68058 5                         
+A    CAMBRIDGE NORTH               2CAMBNTHCMB   CMB15475 62607 5                 
+A    CAMBRIDGE NORTH Stand         9CMBNTSTCMB   CMB15475 62607 5                 
+L    ABERDARE                       ABAHDAR                                       
+";
+        
+        let mut msn_read = io::Cursor::new(&msn);
+        let stations = StationList::read_msn_file(&mut msn_read).unwrap();
+
+        let abdare1 = stations.get_by_tiploc("ABDARE")
+            .expect("Expected station with TIPLOC ABDARE");
+        assert_eq!(abdare1.names, vec!["ABERDARE", "ABAHDAR"]);
+        assert_eq!(abdare1.gref_north, 2027);
+
+        let abdare2 = stations.get_by_name("ABAHDAR")
+            .expect("Expected station with name ABAHDAR");
+        assert_eq!(abdare1.names, vec!["ABERDARE", "ABAHDAR"]);
+        assert_eq!(abdare2.gref_north, 2027);
+
+        let abdare3 = stations.get_by_name("ABERDARE")
+            .expect("Expected station with name ABERDARE");
+        assert_eq!(abdare1.names, vec!["ABERDARE", "ABAHDAR"]);
+        assert_eq!(abdare3.gref_north, 2027);
+
+        let camnorth_id = stations.get_by_crs("CMB")
+            .expect("Expected station with name CAMBRIDGE NORTH")
+            .id;
+        let camnorth = stations.get(camnorth_id).unwrap();
+        assert_eq!(camnorth.names, vec!["CAMBRIDGE NORTH", "CAMBRIDGE NORTH Stand"]);
+        assert_eq!(camnorth.tiplocs, vec!["CAMBNTH", "CMBNTST"]);
+        assert_eq!(camnorth.crs_code, "CMB");
+
+        let mut warnings = Vec::new();
+        StationList::read_msn_file_with_warnings(&mut io::Cursor::new(&msn), &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 7);
+        assert_eq!(warnings[0].kind, ParseIssueKind::MergedStation("CMB".to_string()));
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut stations = StationList::new(vec![
+            Station::simple("MNCRPIC", "MANCHESTER PICCADILLY", "MAN"),
+            Station::simple("MNCROXR", "MANCHESTER OXFORD ROAD", "MCO"),
+            Station::simple("MNCRVIC", "MANCHESTER VICTORIA", "MCV")
+        ]);
+        stations.add_group("MCR", "Manchester", &["MAN".to_string(), "MCV".to_string()]).unwrap();
+        assert_eq!(stations.resolve("MCR"), Some(vec![0, 2]));
+        assert_eq!(stations.resolve("MCO"), Some(vec![1]));
+        assert_eq!(stations.resolve("XXX"), None);
+        assert!(matches!(stations.add_group("ZZZ", "Nowhere", &["XXX".to_string()]), Err(RailDataError::UnknownStation(_))));
+        assert_eq!(stations.groups().len(), 1);
+
+        // Groups are config, not stations, so aren't kept with them
+        #[cfg(feature = "serde")]
+        {
+            let stations: StationList = serde_json::from_str(&serde_json::to_string(&stations).unwrap()).unwrap();
+            assert_eq!(stations.resolve("MCR"), None);
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        let station = |tiploc: &str, crs: &str, east: i32, north: i32| Station { gref_east: east, gref_north: north, ..Station::simple(tiploc, tiploc, crs) };
+        let stations = StationList::new(vec![
+            station("KNGX", "KGX", 5303, 1830),
+            station("STPX", "STP", 5300, 1829),
+            station("CAMBDGE", "CBG", 5462, 2573),
+            station("ABRDEEN", "ABD", 3942, 8058),
+            station("NOWHERE", "XNW", 0, 0)
+        ]);
+
+        let nearest = |n, east, north| -> Vec<StationId> { stations.nearest(n, east, north).into_iter().map(|(id, _)| id).collect() };
+        assert_eq!(nearest(2, 530250.0, 183000.0), vec![0, 1]);
+        assert_eq!(nearest(3, 540000.0, 250000.0), vec![2, 0, 1]);
+        assert_eq!(nearest(10, 394000.0, 800000.0), vec![3, 2, 0, 1]);
+        // Far outside the grid squares with stations
+        assert_eq!(nearest(1, 9_000_000.0, -50_000.0), vec![2]);
+        assert!(nearest(0, 530250.0, 183000.0).is_empty());
+
+        let (_, distance) = stations.nearest(1, 530300.0, 183100.0)[0];
+        assert!((distance - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_name_search_ordered() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBNTH", "CAMBRIDGE NORTH", "CMB"),
+            Station::simple("KNGX", "KINGS CROSS", "KGX"),
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("CAMBHTH", "CAMBRIDGE HEATH", "CBH"),
+        ]);
+
+        let rs: Vec<StationId> = stations.name_search("CAMBRIDGE").into_iter().map(|(id, _)| id).collect();
+        assert_eq!(rs, vec![2, 3, 0]);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut stations = vec![
+            Station::simple("CAMBNTH", "CAMBRIDGE NORTH", "CMB"),
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("CAMBHTH", "CAMBRIDGE HEATH", "CBH"),
+            Station::simple("CAMBJN", "CAMBRIDGE JUNCTION", ""),
+            Station::simple("CMBRNE", "CAMBORNE", "CBN"),
+            Station::simple("BRKCAMB", "BROOK CAMBRIDGE", "BKC")
+        ];
+        stations[1].gref_east = 5462;
+        stations[1].gref_north = 2573;
+        stations[2].gref_east = 5349;
+        stations[2].gref_north = 1833;
+        let stations = StationList::new(stations);
+
+        let all = stations.lookup("cambridge", &LookupOptions::default());
+        assert_eq!(all, (vec![1, 2, 0, 3, 5], 5));
+        // The CRS match comes first however it scores on name
+        assert_eq!(stations.lookup("cbn", &LookupOptions::default()).0[0], 4);
+
+        let by_name = LookupOptions { order: LookupOrder::Name, ..Default::default() };
+        assert_eq!(stations.lookup("cambridge", &by_name).0, vec![5, 1, 2, 3, 0]);
+        let page = LookupOptions { order: LookupOrder::Name, offset: 1, limit: Some(2), ..Default::default() };
+        assert_eq!(stations.lookup("cambridge", &page), (vec![1, 2], 5));
+        let past_end = LookupOptions { offset: 10, ..Default::default() };
+        assert_eq!(stations.lookup("cambridge", &past_end), (vec![], 5));
+
+        let crs_only = LookupOptions { crs_only: true, ..Default::default() };
+        assert_eq!(stations.lookup("cambridge", &crs_only), (vec![1, 2, 0, 5], 4));
+        #[cfg(feature = "geo")]
+        {
+            // Around Cambridge, leaving out Cambridge Heath in London and those without a grid reference
+            let within = LookupOptions { within: Some((LatLon { lat: 52.0, lon: 0.0 }, LatLon { lat: 52.5, lon: 0.5 })), ..Default::default() };
+            assert_eq!(stations.lookup("cambridge", &within), (vec![1], 1));
+        }
+    }
+
+    #[test]
+    fn test_name_search_fuzzy() {
+        let stations = StationList::new(vec![
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("KGSLYNN", "KINGS LYNN", "KLN"),
+            Station::simple("MNCRPIC", "MANCHESTER PICCADILLY", "MAN"),
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+        ]);
+        let ids = |query: &str| -> Vec<StationId> { stations.name_search(query).into_iter().map(|(id, _)| id).collect() };
+
+        // Kings Lynn is like it too, but not as like
+        assert_eq!(ids("kings cross"), vec![0, 1]);
+        assert_eq!(ids("King's"), vec![1, 0]);
+        assert_eq!(ids("manchester picadilly"), vec![2]);
+        assert_eq!(ids("cambrige"), vec![3]);
+        assert_eq!(ids("  "), Vec::<StationId>::new());
+        assert!(ids("xyzzy").is_empty());
+
+        let scores: Vec<u32> = stations.name_search("CAMBRIDGE").into_iter().map(|(_, score)| score).collect();
+        assert_eq!(scores, vec![EXACT_SCORE]);
+        assert_eq!(levenshtein("KITTEN", "SITTING"), 3);
+        assert_eq!(normalise_name(" St. Pancras  (Intl)"), "ST PANCRAS INTL");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_msn_lines(lines in proptest::collection::vec("(A|L|/)[ A-Z0-9]{0,80}|\\PC{0,80}", 0..20)) {
+            let msn = lines.join("\n");
+            let mut issues = Vec::new();
+            let stations = StationList::read_msn_file_lenient(&mut io::Cursor::new(&msn), &mut issues).unwrap();
+            for (id, station) in stations.iter().enumerate() {
+                prop_assert_eq!(station.id, id);
+                prop_assert_eq!(stations.get_by_crs(&station.crs_code).map(|s| s.id), Some(id));
+            }
+        }
+    }
+}
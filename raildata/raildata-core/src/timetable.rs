@@ -0,0 +1,1725 @@
+/** Copyright James Lomax 2020 */
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::OnceLock;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::error::{RailDataError, Result};
+pub use crate::holidays::BankHolidayRunning;
+use crate::intern::{intern, Interned};
+use crate::stations::{StationId, StationList};
+use crate::record_parsing::parse_date_or_invalid;
+use crate::validate::{IssueSink, ParseIssue, ParseIssueKind};
+
+pub type ServiceId = u32;
+
+// RailTime is represented by seconds since 00:00am. (TODO: 3am?)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RailTime {
+    secs: u32
+}
+
+impl RailTime {
+    pub fn new(hours: u32, mins: u32) -> Self {
+        Self {
+            secs: (hours*60*60 + mins*60) % (24*60*60)
+        }
+    }
+
+    /** Parse "HHMM", optionally followed by one more character (e.g. the CIF "H" half minute, ignored) */
+    pub fn from_24h(timestr: &str) -> Option<Self> {
+        let digits = timestr.get(0..4)?;
+        if timestr[4..].chars().count() > 1 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let hrs = digits[0..2].parse::<u32>().ok()?;
+        let mns = digits[2..4].parse::<u32>().ok()?;
+        if hrs >= 24 || mns >= 60 {
+            return None;
+        }
+
+        Some(Self {
+            secs: hrs*60*60 + mns*60
+        })
+    }
+
+    /** Seconds since midnight */
+    pub fn secs(&self) -> u32 {
+        self.secs
+    }
+
+    pub fn to_24h(&self) -> String {
+        let a = self.secs % (60*60);
+        let hrs = (self.secs - a) / (60*60);
+        let b = a % 60;
+        let mins = (a - b) / 60;
+        return format!("{:02}{:02}", hrs, mins);
+    }
+
+    /**
+     * Returns the number of seconds until the $other time,
+     * if it is in the past, then it will wrap around, assuming its
+     * in the next day
+     */
+    pub fn timetil(&self, other: &RailTime) -> u32 {
+        if self.secs > other.secs {
+            return other.secs + 24*60*60 - self.secs;
+        } else {
+            return other.secs - self.secs;
+        }
+    }
+
+    pub fn add(&self, secs: u32) -> Self {
+        Self {
+            secs: (self.secs + secs) % (24*60*60)
+        }
+    }
+
+    /** This time on $date */
+    pub fn on(&self, date: NaiveDate) -> NaiveDateTime {
+        date.and_time(NaiveTime::from_num_seconds_from_midnight_opt(self.secs, 0).unwrap())
+    }
+
+    pub fn sub(&self, secs: u32) -> Self {
+        let s = if secs > self.secs {
+            self.secs + 24*60*60 - secs
+        } else {
+            self.secs - secs
+        };
+
+        Self {
+            secs: s
+        }
+    }
+}
+
+const DAY: u32 = 24*60*60;
+
+/**
+ * A time in a service's schedule, counting the midnights passed since it left
+ * its origin, so times keep increasing along an overnight service's stops.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceTime {
+    // Seconds since midnight on the day the service started
+    secs: u32
+}
+
+impl ServiceTime {
+    /** $time on the $day'th day after the service started */
+    pub fn new(day: u32, time: RailTime) -> Self {
+        Self {
+            secs: day*DAY + time.secs
+        }
+    }
+
+    /** Parse "HHMM" as for `RailTime::from_24h`, with hours from 24 on for later days as in GTFS */
+    pub fn from_24h(timestr: &str) -> Option<Self> {
+        let hours = timestr.get(0..2).filter(|hours| hours.bytes().all(|b| b.is_ascii_digit()))?;
+        let hours = hours.parse::<u32>().ok()?;
+        let time = RailTime::from_24h(&format!("{:02}{}", hours % 24, &timestr[2..]))?;
+        Some(Self::new(hours / 24, time))
+    }
+
+    /** The time of day */
+    pub fn time(&self) -> RailTime {
+        RailTime {
+            secs: self.secs % DAY
+        }
+    }
+
+    /** Days since the service started, 1 after its first midnight */
+    pub fn day(&self) -> u32 {
+        self.secs / DAY
+    }
+
+    /** Seconds since midnight on the day the service started */
+    pub fn secs(&self) -> u32 {
+        self.secs
+    }
+
+    /** The time of day as "HHMM" */
+    pub fn to_24h(&self) -> String {
+        self.time().to_24h()
+    }
+
+    /** Seconds from $earlier until this time, 0 if $earlier is later */
+    pub fn since(&self, earlier: &ServiceTime) -> u32 {
+        self.secs.saturating_sub(earlier.secs)
+    }
+
+    /** The first time it's $time at or after this time, the next day if $time is earlier in the day */
+    pub fn next(&self, time: RailTime) -> Self {
+        self.add(self.time().timetil(&time))
+    }
+
+    pub fn add(&self, secs: u32) -> Self {
+        Self {
+            secs: self.secs + secs
+        }
+    }
+}
+
+impl From<RailTime> for ServiceTime {
+    fn from(time: RailTime) -> Self {
+        Self::new(0, time)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stop {
+    pub station: StationId,
+    // Arrival and departure time are "public" if the record exists, scheduled otherwise.
+    // First/last stops use the same time for arrival and departure. Times after
+    // midnight are on later days of the service
+    pub arrival: ServiceTime,
+    pub departure: ServiceTime,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub platform: Option<Platform>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub activity: StopActivity
+}
+
+impl Stop {
+    /** Stop with HHMM times, for test fixtures. Hours from 24 on are after midnight */
+    #[doc(hidden)]
+    pub fn simple(station: StationId, arrival: &str, departure: &str) -> Self {
+        Self {
+            station: station,
+            arrival: ServiceTime::from_24h(arrival).unwrap(),
+            departure: ServiceTime::from_24h(departure).unwrap(),
+            platform: None,
+            activity: StopActivity::Normal
+        }
+    }
+}
+
+/** Whether passengers can both board and alight at a stop, from the location's activity codes */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StopActivity {
+    #[default]
+    Normal,
+    // "U", passengers can only board
+    PickUpOnly,
+    // "D", passengers can only alight
+    SetDownOnly
+}
+
+impl StopActivity {
+    /** From a CIF activity field of up to six two character codes */
+    pub(crate) fn from_codes(field: &str) -> Self {
+        let codes = field.as_bytes().chunks(2).map(|code| std::str::from_utf8(code).unwrap_or_default().trim());
+        for code in codes {
+            match code {
+                "U" => return StopActivity::PickUpOnly,
+                "D" => return StopActivity::SetDownOnly,
+                _ => {}
+            }
+        }
+        StopActivity::Normal
+    }
+
+    pub fn can_board(&self) -> bool {
+        *self != StopActivity::SetDownOnly
+    }
+
+    pub fn can_alight(&self) -> bool {
+        *self != StopActivity::PickUpOnly
+    }
+}
+
+/**
+ * A platform as published, e.g. "9" or "10A". CIF allows up to three characters,
+ * so it's kept inline and copied onto graph links without allocating.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Platform {
+    len: u8,
+    chars: [u8; 3]
+}
+
+impl Platform {
+    /** None if $s is blank or longer than three bytes */
+    pub fn new(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.is_empty() || s.len() > 3 {
+            return None;
+        }
+        let mut chars = [0; 3];
+        chars[..s.len()].copy_from_slice(s.as_bytes());
+        Some(Self { len: s.len() as u8, chars })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from a whole &str
+        std::str::from_utf8(&self.chars[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+// Serialized as the string
+#[cfg(feature = "serde")]
+impl serde::Serialize for Platform {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Platform {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Platform::new(&s).ok_or_else(|| serde::de::Error::custom(format!("Invalid platform {:?}", s)))
+    }
+}
+
+/** Reservation policy from the schedule record */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reservations {
+    #[default]
+    None,
+    // Seat reservations possible from any station
+    Possible,
+    Recommended,
+    Compulsory,
+    // Bicycles must be booked
+    BicyclesEssential
+}
+
+impl Reservations {
+    /** From the CIF reservations code, anything unrecognised meaning none */
+    pub(crate) fn from_code(code: &str) -> Self {
+        match code {
+            "S" => Reservations::Possible,
+            "R" => Reservations::Recommended,
+            "A" => Reservations::Compulsory,
+            "E" => Reservations::BicyclesEssential,
+            _ => Reservations::None
+        }
+    }
+}
+
+/** Classes of seats or of sleeper berths on a service, from the schedule record */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Accommodation {
+    #[default]
+    None,
+    FirstAndStandard,
+    FirstOnly,
+    StandardOnly
+}
+
+impl Accommodation {
+    /** From the CIF seating class, blank meaning first and standard as for "B" */
+    pub(crate) fn from_seating_code(code: &str) -> Self {
+        match code {
+            "S" => Accommodation::StandardOnly,
+            _ => Accommodation::FirstAndStandard
+        }
+    }
+
+    /** From the CIF sleepers code, blank meaning none */
+    pub(crate) fn from_sleepers_code(code: &str) -> Self {
+        match code {
+            "B" => Accommodation::FirstAndStandard,
+            "F" => Accommodation::FirstOnly,
+            "S" => Accommodation::StandardOnly,
+            _ => Accommodation::None
+        }
+    }
+}
+
+/** How a service travels, classified from its train category */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ServiceMode {
+    Rail,
+    // Bus services and rail replacement buses
+    Bus,
+    Ship
+}
+
+impl ServiceMode {
+    /** From a CIF train category */
+    pub fn from_category(category: &str) -> Self {
+        match category {
+            "BR" | "BS" => ServiceMode::Bus,
+            "SS" => ServiceMode::Ship,
+            _ => ServiceMode::Rail
+        }
+    }
+}
+
+/**
+ * Short term planning indicator of a schedule, in order of precedence. Where schedules for
+ * the same train UID run on the same date, the first in this order applies that day.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stp {
+    // The train doesn't run, and the schedule has no stops
+    Cancellation,
+    // A one-off train without a permanent schedule
+    New,
+    // Replaces the permanent schedule on its dates
+    Overlay,
+    #[default]
+    Permanent
+}
+
+impl Stp {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "C" => Stp::Cancellation,
+            "N" => Stp::New,
+            "O" => Stp::Overlay,
+            _ => Stp::Permanent
+        }
+    }
+}
+
+/** The dates a service runs, from the BS record */
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Calendar {
+    // Inclusive
+    pub runs_from: NaiveDate,
+    pub runs_to: NaiveDate,
+    // Monday first
+    pub days_run: [bool; 7],
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub stp: Stp
+}
+
+impl Calendar {
+    /** Whether the schedule applies on $date, before considering others for the train UID */
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        self.runs_from <= date && date <= self.runs_to && self.days_run[date.weekday().num_days_from_monday() as usize]
+    }
+
+    /** Whether the date ranges overlap, whatever the days run */
+    pub fn overlaps(&self, other: &Calendar) -> bool {
+        self.runs_from <= other.runs_to && other.runs_from <= self.runs_to
+    }
+
+    // From the date, days run and STP fields shared by BS and AA records
+    fn parse(runs_from: &str, runs_to: &str, days: &str, stp: &str) -> Result<Self> {
+        let mut days_run = [false; 7];
+        for (day, c) in days_run.iter_mut().zip(days.chars()) {
+            *day = match c {
+                '1' => true,
+                '0' => false,
+                _ => return Err(RailDataError::InvalidField { field: "days_run".to_string(), value: days.to_string() })
+            };
+        }
+        Ok(Self {
+            runs_from: parse_date_or_invalid(runs_from, "%y%m%d", "date_runs_from")?,
+            runs_to: parse_date_or_invalid(runs_to, "%y%m%d", "date_runs_to")?,
+            days_run,
+            stp: Stp::from_code(stp)
+        })
+    }
+
+    // Deletions only give the start date and STP indicator, which with the train UID identify the schedule
+    fn deletion(runs_from: &str, stp: &str) -> Result<Self> {
+        let runs_from = parse_date_or_invalid(runs_from, "%y%m%d", "date_runs_from")?;
+        Ok(Self { runs_from, runs_to: runs_from, days_run: [false; 7], stp: Stp::from_code(stp) })
+    }
+
+    fn from_bs_record(r: &McaScheduleRecord) -> Result<Self> {
+        Self::parse(r.date_runs_from, r.date_runs_to, r.days_run, r.stp_indicator)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Service {
+    pub id: ServiceId,
+    pub train_uid: String,
+    pub stops: Vec<Stop>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reservations: Reservations,
+    // ATOC code of the train operating company from the BX record, e.g. "GN", empty if unknown.
+    // These codes are interned (see crate::intern), as there are few of them and every service has one
+    #[cfg_attr(feature = "serde", serde(default, deserialize_with = "crate::intern::deserialize"))]
+    pub operator: Interned,
+    // CIF train category, e.g. "OO" for ordinary passenger or "BR" for a replacement bus, and power type, e.g. "EMU"
+    #[cfg_attr(feature = "serde", serde(default, deserialize_with = "crate::intern::deserialize"))]
+    pub category: Interned,
+    #[cfg_attr(feature = "serde", serde(default, deserialize_with = "crate::intern::deserialize"))]
+    pub power_type: Interned,
+    // Classes of seats and of sleeper berths. Schedules always have seats, but services from elsewhere may not
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seating: Accommodation,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sleepers: Accommodation,
+    // Bank holidays it doesn't run on, when the calendar would otherwise have it running
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bank_holidays: BankHolidayRunning,
+    // None if the service runs every day, e.g. in a generated timetable
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub calendar: Option<Calendar>
+}
+
+impl Service {
+    /** Service running every day with $stops and nothing else known about it, for test fixtures */
+    #[doc(hidden)]
+    pub fn simple(id: ServiceId, train_uid: &str, stops: Vec<Stop>) -> Self {
+        Self {
+            id,
+            train_uid: train_uid.to_string(),
+            stops,
+            reservations: Reservations::None,
+            operator: "",
+            category: "",
+            power_type: "",
+            seating: Accommodation::FirstAndStandard,
+            sleepers: Accommodation::None,
+            bank_holidays: BankHolidayRunning::Runs,
+            calendar: None
+        }
+    }
+
+    /** Whether the schedule applies on $date, before considering others for the train UID (see `Timetable::services_on`) */
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        self.calendar.as_ref().is_none_or(|calendar| calendar.runs_on(date))
+    }
+
+    pub fn mode(&self) -> ServiceMode {
+        ServiceMode::from_category(self.category)
+    }
+
+    /** Whether passengers can travel seated. Buses and ships are taken to be, as they don't give a seating class */
+    pub fn is_seated(&self) -> bool {
+        self.seating != Accommodation::None || self.mode() != ServiceMode::Rail
+    }
+
+    pub fn is_sleeper(&self) -> bool {
+        self.sleepers != Accommodation::None
+    }
+
+    /** Whether this is an STP cancellation of the train's other schedules */
+    pub fn is_cancellation(&self) -> bool {
+        self.calendar.as_ref().is_some_and(|calendar| calendar.stp == Stp::Cancellation)
+    }
+
+    fn stp(&self) -> Stp {
+        self.calendar.as_ref().map_or(Stp::Permanent, |calendar| calendar.stp)
+    }
+}
+
+/**
+ * For each of $services, the calendars of other schedules for the same train UID
+ * which take precedence over it, so it doesn't run on their dates
+ */
+pub fn superseding_calendars<'a>(services: &[&'a Service]) -> Vec<Vec<&'a Calendar>> {
+    let mut by_uid: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, service) in services.iter().enumerate() {
+        by_uid.entry(&service.train_uid).or_default().push(i);
+    }
+
+    let mut superseding = vec![Vec::new(); services.len()];
+    for variants in by_uid.values().filter(|variants| variants.len() > 1) {
+        for &i in variants {
+            for &j in variants {
+                let calendar = match &services[j].calendar {
+                    Some(calendar) if calendar.stp < services[i].stp() => calendar,
+                    _ => continue
+                };
+                if services[i].calendar.as_ref().is_none_or(|own| own.overlaps(calendar)) {
+                    superseding[i].push(calendar);
+                }
+            }
+        }
+    }
+    superseding
+}
+
+/** How two trains are associated at a location, from the AA record's category */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AssociationKind {
+    // The associated train joins the main train
+    Join,
+    // The associated train divides from the main train
+    Divide,
+    // The associated train is formed from the main train's stock after it arrives
+    Next
+}
+
+/**
+ * Two trains associated at a station (an AA record), e.g. a portion which divides
+ * from the main train. Passengers stay aboard through a join or divide.
+ */
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Association {
+    pub main_uid: String,
+    pub assoc_uid: String,
+    pub kind: AssociationKind,
+    pub location: StationId,
+    // Days after the main train's call at $location that the associated train calls: -1, 0 or 1
+    pub day_offset: i8,
+    // False for associations only of interest to operations
+    pub passenger: bool,
+    pub calendar: Calendar
+}
+
+impl Association {
+    /** The trains passengers are on before and after the location, for a join or divide */
+    pub fn through(&self) -> Option<(&str, &str)> {
+        match self.kind {
+            AssociationKind::Join => Some((&self.assoc_uid, &self.main_uid)),
+            AssociationKind::Divide => Some((&self.main_uid, &self.assoc_uid)),
+            AssociationKind::Next => None
+        }
+    }
+}
+
+make_record_type!(
+    McaAssociationRecord,
+    (transaction_type, 2, 1),
+    (main_uid, 3, 6),
+    (assoc_uid, 9, 6),
+    (date_from, 15, 6),
+    (date_to, 21, 6),
+    (days, 27, 7),
+    (category, 34, 2),
+    (date_indicator, 36, 1),
+    (location, 37, 7),
+    (assoc_type, 47, 1),
+    (stp_indicator, 79, 1)
+);
+
+make_record_type!(
+    McaExtraRecord,
+    (uic_code, 6, 5),
+    (atoc_code, 11, 2)
+);
+
+// The association, or None if it's at a TIPLOC which isn't a station. Deletions only have the fields identifying it
+fn read_association(stations: &StationList, line: &str) -> Result<Option<(Transaction, Association)>> {
+    let r = McaAssociationRecord::read(line)?;
+    let transaction = Transaction::from_code(r.transaction_type);
+    if transaction == Transaction::Delete {
+        let calendar = Calendar::deletion(r.date_from, r.stp_indicator)?;
+        return Ok(stations.get_by_tiploc(r.location).map(|station| (transaction, Association {
+            main_uid: r.main_uid.to_string(),
+            assoc_uid: r.assoc_uid.to_string(),
+            kind: AssociationKind::Next,
+            location: station.id,
+            day_offset: 0,
+            passenger: false,
+            calendar
+        })));
+    }
+    let kind = match r.category {
+        "JJ" => AssociationKind::Join,
+        "VV" => AssociationKind::Divide,
+        "NP" => AssociationKind::Next,
+        _ => return Err(RailDataError::InvalidField { field: "category".to_string(), value: r.category.to_string() })
+    };
+    let day_offset = match r.date_indicator {
+        "N" => 1,
+        "P" => -1,
+        _ => 0
+    };
+    let calendar = Calendar::parse(r.date_from, r.date_to, r.days, r.stp_indicator)?;
+    Ok(stations.get_by_tiploc(r.location).map(|station| (transaction, Association {
+        main_uid: r.main_uid.to_string(),
+        assoc_uid: r.assoc_uid.to_string(),
+        kind,
+        location: station.id,
+        day_offset,
+        passenger: r.assoc_type != "O",
+        calendar
+    })))
+}
+
+/** What a record in an update extract does to the timetable. Full extracts are all new records */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transaction {
+    New,
+    Delete,
+    // Replaces the schedule or association with the same key
+    Revise
+}
+
+impl Transaction {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "D" => Transaction::Delete,
+            "R" => Transaction::Revise,
+            _ => Transaction::New
+        }
+    }
+}
+
+// An extract's header and each of its schedules and associations with what it does to the timetable
+type Transactions = (Option<TimetableHeader>, Vec<(Transaction, Service)>, Vec<(Transaction, Association)>);
+
+make_record_type!(
+    McaHeaderRecord,
+    (mainframe_identity, 2, 20),
+    (extract_date, 22, 6),
+    (current_file_ref, 32, 7),
+    (last_file_ref, 39, 7),
+    (update_indicator, 46, 1),
+    (start_date, 48, 6),
+    (end_date, 54, 6)
+);
+
+/** Details of the extract from the HD record at the start of the MCA file */
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimetableHeader {
+    pub mainframe_identity: String,
+    pub file_ref: String,
+    // The extract an update follows on from
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_file_ref: String,
+    pub extracted: NaiveDate,
+    // True for an update extract, false for a full extract
+    pub update: bool,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate
+}
+
+impl TimetableHeader {
+    pub(crate) fn from_hd_record(line: &str) -> Result<Self> {
+        let r = McaHeaderRecord::read(line)?;
+        Ok(Self {
+            mainframe_identity: r.mainframe_identity.to_string(),
+            file_ref: r.current_file_ref.to_string(),
+            last_file_ref: r.last_file_ref.trim().to_string(),
+            extracted: parse_date_or_invalid(r.extract_date, "%d%m%y", "extract_date")?,
+            update: r.update_indicator == "U",
+            start_date: parse_date_or_invalid(r.start_date, "%d%m%y", "start_date")?,
+            end_date: parse_date_or_invalid(r.end_date, "%d%m%y", "end_date")?
+        })
+    }
+}
+
+// There's more but these are the ones I'm probably interested in...
+make_record_type!(
+    McaScheduleRecord,
+    (transaction_type, 2, 1),
+    (train_uid, 3, 6),
+    (date_runs_from, 9, 6),
+    (date_runs_to, 15, 6),
+    (days_run, 21, 7),
+    (bank_holiday_running, 28, 1),
+    (train_category, 30, 2),
+    (power_type, 50, 3),
+    (seating_class, 66, 1),
+    (sleepers, 67, 1),
+    (reservations, 68, 1),
+    (stp_indicator, 79, 1)
+);
+
+make_record_type!(
+    McaOriginStationRecord,
+    (tiploc, 2, 7),
+    (sched_departure, 10, 5),
+    (public_departure, 15, 4),
+    (platform, 19, 3)
+);
+
+make_record_type!(
+    McaIntermediateStationRecord,
+    (tiploc, 2, 7),
+    (tiploc_suffix, 9, 1),
+    (scheduled_arrival, 10, 5),
+    (scheduled_departure, 15, 5),
+    (scheduled_pass, 20, 5),
+    (public_arrival, 25, 4),
+    (public_departure, 29, 4),
+    (platform, 33, 3)
+);
+
+make_record_type!(
+    McaTerminalStationRecord,
+    (tiploc, 2, 7),
+    (tiploc_suffix, 9, 1),
+    (scheduled_arrival, 10, 5),
+    (public_arrival, 15, 4),
+    (platform, 19, 3)
+);
+
+// A location record (LO, LI or LT) with the public times the train calls at it
+struct Location<'a> {
+    tiploc: &'a str,
+    pass: bool,
+    arrival: &'a str,
+    departure: &'a str,
+    platform: &'a str,
+    activity: StopActivity
+}
+
+// The activity field is read leniently as it's near the end of the record, which may have had trailing spaces trimmed
+fn read_activity(line: &str, offset: usize) -> StopActivity {
+    let field = line.get(offset..line.len().min(offset + 12)).unwrap_or_default();
+    StopActivity::from_codes(field)
+}
+
+fn read_location(line: &str) -> Result<Location<'_>> {
+    match line.get(0..2).unwrap_or_default() {
+        "LO" => {
+            let r = McaOriginStationRecord::read(line)?;
+            Ok(Location {
+                tiploc: r.tiploc,
+                pass: false,
+                arrival: r.public_departure,
+                departure: r.public_departure,
+                platform: r.platform,
+                activity: read_activity(line, 29)
+            })
+        }
+        "LI" => {
+            let r = McaIntermediateStationRecord::read(line)?;
+            let pass = RailTime::from_24h(r.scheduled_pass).is_some();
+            Ok(Location {
+                tiploc: r.tiploc,
+                pass,
+                arrival: r.public_arrival,
+                departure: r.public_departure,
+                platform: r.platform,
+                activity: read_activity(line, 42)
+            })
+        }
+        _ => {
+            let r = McaTerminalStationRecord::read(line)?;
+            Ok(Location {
+                tiploc: r.tiploc,
+                pass: false,
+                arrival: r.public_arrival,
+                departure: r.public_arrival,
+                platform: r.platform,
+                activity: read_activity(line, 25)
+            })
+        }
+    }
+}
+
+fn parse_time_field(s: &str, fieldname: &str) -> Result<RailTime> {
+    match RailTime::from_24h(s) {
+        Some(t) => Ok(t),
+        None => Err(RailDataError::InvalidTime { field: fieldname.to_string(), value: s.to_string() })
+    }
+}
+
+fn is_public_time(s: &str) -> bool {
+    !s.is_empty() && s != "0000"
+}
+
+impl Service {
+    /** Reads the next service from $reader. Line numbers in errors are relative to the start of the entry. */
+    pub(crate) fn read_service_entry(stations: &StationList, reader: &mut dyn BufRead) -> Result<Option<Service>> {
+        let mut line_num = 0;
+        let entry = Self::read_entry(stations, reader, &mut line_num, &mut Vec::new(), &mut IssueSink::strict())?;
+        Ok(entry.map(|(_, service)| service))
+    }
+
+    // Associations found before the service are added to $associations
+    fn read_entry(stations: &StationList, reader: &mut dyn BufRead, line_num: &mut usize, associations: &mut Vec<(Transaction, Association)>,
+            sink: &mut IssueSink) -> Result<Option<(Transaction, Service)>> {
+        let mut service = Service {
+            id: 0,
+            train_uid: String::new(),
+            stops: Vec::new(),
+            reservations: Reservations::None,
+            operator: "",
+            category: "",
+            power_type: "",
+            seating: Accommodation::None,
+            sleepers: Accommodation::None,
+            bank_holidays: BankHolidayRunning::Runs,
+            calendar: None
+        };
+
+        let mut has_record = false;
+        let mut transaction = Transaction::New;
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? > 2 {
+                *line_num += 1;
+                match line.get(0..2).unwrap_or_default() {
+                    "BS" => {
+                        has_record = true;
+                        let record = McaScheduleRecord::read(&line).and_then(|r| {
+                            let transaction = Transaction::from_code(r.transaction_type);
+                            let calendar = if transaction == Transaction::Delete {
+                                Calendar::deletion(r.date_runs_from, r.stp_indicator)
+                            } else {
+                                Calendar::from_bs_record(&r)
+                            };
+                            calendar.map(|c| (r, transaction, c))
+                        });
+                        match record {
+                            Ok((r, record_transaction, calendar)) => {
+                                transaction = record_transaction;
+                                service.train_uid = r.train_uid.to_string();
+                                service.reservations = Reservations::from_code(r.reservations);
+                                service.seating = Accommodation::from_seating_code(r.seating_class);
+                                service.sleepers = Accommodation::from_sleepers_code(r.sleepers);
+                                service.category = intern(r.train_category);
+                                service.power_type = intern(r.power_type);
+                                service.bank_holidays = BankHolidayRunning::from_code(r.bank_holiday_running);
+                                service.calendar = Some(calendar);
+                                // Cancellations and deletions are just the BS record
+                                if service.is_cancellation() || transaction == Transaction::Delete {
+                                    return Ok(Some((transaction, service)));
+                                }
+                            }
+                            Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e)?
+                        }
+                    }
+                    "BX" => {
+                        match McaExtraRecord::read(&line) {
+                            Ok(r) => service.operator = intern(r.atoc_code),
+                            Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e)?
+                        }
+                    }
+                    "AA" if !has_record => {
+                        match read_association(stations, &line) {
+                            Ok(Some(association)) => associations.push(association),
+                            Ok(None) => {}
+                            Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e)?
+                        }
+                    }
+                    "LO" | "LI" | "LT" => {
+                        match read_location(&line) {
+                            Ok(loc) => {
+                                if loc.pass || (loc.arrival.is_empty() && loc.departure.is_empty()) {
+                                    // Skip, we dont record passes or calls without public times
+                                } else if let Some(station) = stations.get_by_tiploc(loc.tiploc) {
+                                    // Set down or pick up only calls have just one public time, use it for both
+                                    let arrival = if loc.arrival.is_empty() { loc.departure } else { loc.arrival };
+                                    let departure = if loc.departure.is_empty() { loc.arrival } else { loc.departure };
+                                    let times = parse_time_field(arrival, "public_arrival").and_then(|arrival| {
+                                        parse_time_field(departure, "public_departure").map(|departure| (arrival, departure))
+                                    });
+                                    match times {
+                                        Ok((arrival, departure)) => {
+                                            // Times are of day, so one earlier than the previous call's is after midnight
+                                            let previous = service.stops.last().map_or_else(ServiceTime::default, |stop| stop.departure);
+                                            let arrival = previous.next(arrival);
+                                            service.stops.push(Stop {
+                                                station: station.id,
+                                                arrival,
+                                                departure: arrival.next(departure),
+                                                platform: Platform::new(loc.platform),
+                                                activity: loc.activity
+                                            });
+                                        }
+                                        Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e)?
+                                    }
+                                } else if is_public_time(loc.arrival) || is_public_time(loc.departure) {
+                                    let msg = format!("Service {} calls at unknown TIPLOC {}", service.train_uid, loc.tiploc);
+                                    sink.note(*line_num, ParseIssueKind::UnknownStation(loc.tiploc.to_string()), msg);
+                                }
+                            }
+                            Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e)?
+                        }
+
+                        if line.starts_with("LT") {
+                            return Ok(Some((transaction, service)));
+                        }
+                    }
+                    _ => {}
+                }
+            } else {
+                if has_record {
+                    sink.report(*line_num, &line, ParseIssueKind::Malformed, RailDataError::TruncatedService)?;
+                }
+                return Ok(None);
+            }
+        }
+    }
+}
+
+
+/** A service calling at a station, from `Timetable::departures` */
+#[derive(Debug, Clone, Copy)]
+pub struct Departure<'a> {
+    pub service: &'a Service,
+    pub stop: &'a Stop,
+    // Where the service ends
+    pub destination: StationId
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timetable {
+    pub header: Option<TimetableHeader>,
+    // Private, so the indexes below are cleared whenever the services change
+    services: Vec<Service>,
+    pub associations: Vec<Association>,
+    // For each station, the (service index, stop index) of each call passengers can board at, by departure time.
+    // Built on first use, and cleared by services_mut and updates
+    #[cfg_attr(feature = "serde", serde(skip))]
+    calls: OnceLock<Vec<Vec<(u32, u32)>>>,
+    // The service indexes of each train UID's schedules, built and cleared as calls
+    #[cfg_attr(feature = "serde", serde(skip))]
+    by_uid: OnceLock<HashMap<String, Vec<u32>>>
+}
+
+impl Timetable {
+    pub fn new(header: Option<TimetableHeader>, services: Vec<Service>, associations: Vec<Association>) -> Self {
+        Self { header, services, associations, calls: OnceLock::new(), by_uid: OnceLock::new() }
+    }
+
+    /** The services, indexed by service ID */
+    pub fn services(&self) -> &[Service] {
+        &self.services
+    }
+
+    /** The services to change, clearing the indexes built over them */
+    pub fn services_mut(&mut self) -> &mut Vec<Service> {
+        self.calls = OnceLock::new();
+        self.by_uid = OnceLock::new();
+        &mut self.services
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn read_mca_file(stations: &StationList, reader: &mut dyn BufRead) -> Result<Self> {
+        Self::read_mca(ServiceIter::new(stations, reader)?)
+    }
+
+    /** Read an MCA file, collecting tolerated issues such as calls at unknown TIPLOCs in $warnings */
+    pub fn read_mca_file_with_warnings(stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<ParseIssue>) -> Result<Self> {
+        Self::read_mca(ServiceIter::with_warnings(stations, reader, warnings)?)
+    }
+
+    /** Read an MCA file, skipping bad records and collecting them (and calls at unknown TIPLOCs) in $issues */
+    pub fn read_mca_file_lenient(stations: &StationList, reader: &mut dyn BufRead, issues: &mut Vec<ParseIssue>) -> Result<Self> {
+        Self::read_mca(ServiceIter::lenient(stations, reader, issues)?)
+    }
+
+    /** The services running on $date, taking the schedule with the highest STP precedence for each train */
+    pub fn services_on(&self, date: NaiveDate) -> Vec<&Service> {
+        let services: Vec<&Service> = self.services.iter().collect();
+        let superseding = superseding_calendars(&services);
+        services.into_iter().zip(superseding)
+            .filter(|(service, superseding)| {
+                !service.is_cancellation() && service.runs_on(date) && !superseding.iter().any(|calendar| calendar.runs_on(date))
+            })
+            .map(|(service, _)| service)
+            .collect()
+    }
+
+    /**
+     * Services leaving $station within $window seconds of $from, in departure order,
+     * whatever days they run. Calls where passengers can't board, including the end
+     * of each service, are left out. The index this uses is built on the first call,
+     * and rebuilt after the services are changed through `services_mut`.
+     */
+    pub fn departures(&self, station: StationId, from: RailTime, window: u32) -> Vec<Departure<'_>> {
+        let calls = match self.calls.get_or_init(|| self.index_calls()).get(station) {
+            Some(calls) => calls,
+            None => return Vec::new()
+        };
+        let departure = |&(service, stop): &(u32, u32)| self.services[service as usize].stops[stop as usize].departure.time();
+
+        // From the first call at or after $from, round past midnight if need be
+        let first = calls.partition_point(|call| departure(call).secs() < from.secs());
+        calls[first..].iter().chain(&calls[..first])
+            .take_while(|call| from.timetil(&departure(call)) < window)
+            .map(|&(service, stop)| {
+                let service = &self.services[service as usize];
+                Departure { service, stop: &service.stops[stop as usize], destination: service.stops.last().unwrap().station }
+            })
+            .collect()
+    }
+
+    /**
+     * Every schedule of the train $uid in timetable order, e.g. its permanent schedule
+     * with the overlays and cancellations of it, whatever days they run. As for
+     * departures, the index this uses is built on the first call and rebuilt after
+     * `services_mut`.
+     */
+    pub fn get_by_uid(&self, uid: &str) -> Vec<&Service> {
+        let by_uid = self.by_uid.get_or_init(|| {
+            let mut by_uid: HashMap<String, Vec<u32>> = HashMap::new();
+            for (i, service) in self.services.iter().enumerate() {
+                by_uid.entry(service.train_uid.clone()).or_default().push(i as u32);
+            }
+            by_uid
+        });
+        by_uid.get(uid).map_or(Vec::new(), |services| services.iter().map(|&i| &self.services[i as usize]).collect())
+    }
+
+    fn index_calls(&self) -> Vec<Vec<(u32, u32)>> {
+        let mut calls: Vec<Vec<(u32, u32)>> = Vec::new();
+        for (i, service) in self.services.iter().enumerate() {
+            let stops = &service.stops[..service.stops.len().saturating_sub(1)];
+            for (j, stop) in stops.iter().enumerate().filter(|(_, stop)| stop.activity.can_board()) {
+                if calls.len() <= stop.station {
+                    calls.resize(stop.station + 1, Vec::new());
+                }
+                calls[stop.station].push((i as u32, j as u32));
+            }
+        }
+        for station in &mut calls {
+            station.sort_by_key(|&(service, stop)| (self.services[service as usize].stops[stop as usize].departure.time().secs(), service));
+        }
+        calls
+    }
+
+    /**
+     * Apply a CIF update extract from $reader, which must follow on from this timetable's
+     * extract if it has a header. Schedules and associations are new, deleted or revised
+     * by train UID, start date and STP indicator. Services are renumbered, so the travel
+     * graph has to be rebuilt after.
+     */
+    pub fn apply_update_file(&mut self, stations: &StationList, reader: &mut dyn BufRead) -> Result<UpdateCounts> {
+        self.apply_update(stations, reader, &mut IssueSink::strict())
+    }
+
+    /** Apply an update extract as `apply_update_file`, collecting tolerated issues in $warnings */
+    pub fn apply_update_file_with_warnings(&mut self, stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<ParseIssue>) -> Result<UpdateCounts> {
+        self.apply_update(stations, reader, &mut IssueSink::strict_with_warnings(warnings))
+    }
+
+    fn read_header(reader: &mut dyn BufRead, line_num: &mut usize, sink: &mut IssueSink) -> Result<Option<TimetableHeader>> {
+        if !reader.fill_buf()?.starts_with(b"HD") {
+            return Ok(None);
+        }
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        *line_num += 1;
+        match TimetableHeader::from_hd_record(&line) {
+            Ok(header) => Ok(Some(header)),
+            Err(e) => sink.report(*line_num, &line, ParseIssueKind::Malformed, e).map(|_| None)
+        }
+    }
+
+    // Read the header and each service and association with what it does to the timetable
+    fn read_transactions(stations: &StationList, reader: &mut dyn BufRead, sink: &mut IssueSink) -> Result<Transactions> {
+        let mut line_num = 0;
+        let header = Self::read_header(reader, &mut line_num, sink)?;
+        let mut services = Vec::new();
+        let mut associations = Vec::new();
+        while let Some(entry) = Service::read_entry(stations, reader, &mut line_num, &mut associations, sink)? {
+            services.push(entry);
+        }
+        Ok((header, services, associations))
+    }
+
+    fn read_mca<R: BufRead>(mut services: ServiceIter<R>) -> Result<Self> {
+        let read = services.by_ref().collect::<Result<Vec<Service>>>()?;
+        let header = services.header.take();
+        let timetable = Timetable::new(header, read, services.into_associations());
+
+        tracing::debug!(services = timetable.services.len(), associations = timetable.associations.len(), "Read MCA file");
+        Ok(timetable)
+    }
+
+    fn apply_update(&mut self, stations: &StationList, reader: &mut dyn BufRead, sink: &mut IssueSink) -> Result<UpdateCounts> {
+        let (header, services, associations) = Self::read_transactions(stations, reader, sink)?;
+        let header = header.ok_or(RailDataError::MissingHeader)?;
+        if !header.update {
+            return Err(RailDataError::InvalidField { field: "update_indicator".to_string(), value: "F".to_string() });
+        }
+        if let Some(current) = &mut self.header {
+            if header.last_file_ref != current.file_ref {
+                return Err(RailDataError::UpdateSequence { expected: current.file_ref.clone(), found: header.last_file_ref });
+            }
+            current.file_ref = header.file_ref;
+            current.last_file_ref = header.last_file_ref;
+            current.extracted = header.extracted;
+            current.end_date = current.end_date.max(header.end_date);
+        }
+
+        let mut counts = UpdateCounts::default();
+        let schedule_key = |s: &Service| (s.train_uid.clone(), s.calendar.as_ref().map(|c| (c.runs_from, c.stp)));
+        let existing = std::mem::take(&mut self.services).into_iter().map(Some).collect();
+        let mut updated = apply_transactions(existing, services, schedule_key, &mut counts);
+        let association_key = |a: &Association| (a.main_uid.clone(), a.assoc_uid.clone(), a.location, a.calendar.runs_from, a.calendar.stp);
+        let existing = std::mem::take(&mut self.associations).into_iter().map(Some).collect();
+        self.associations = apply_transactions(existing, associations, association_key, &mut counts);
+
+        for (i, service) in updated.iter_mut().enumerate() {
+            service.id = i as ServiceId;
+        }
+        self.services = updated;
+        self.calls = OnceLock::new();
+        self.by_uid = OnceLock::new();
+        tracing::info!(?counts, services = self.services.len(), "Applied timetable update");
+        Ok(counts)
+    }
+}
+
+/**
+ * Services read one at a time from an MCA file, so a timetable can be filtered,
+ * counted or built into a graph without holding all of it. IDs count up from 0 in
+ * file order, as for `Timetable::read_mca_file`. An error ends the iteration.
+ */
+pub struct ServiceIter<'a, R> {
+    stations: &'a StationList,
+    reader: R,
+    sink: IssueSink<'a>,
+    header: Option<TimetableHeader>,
+    // Read along the way, as associations come between services
+    associations: Vec<(Transaction, Association)>,
+    line_num: usize,
+    next_id: ServiceId,
+    done: bool
+}
+
+impl<'a, R: BufRead> ServiceIter<'a, R> {
+    /** Start reading services from $reader, after the header if there is one */
+    pub fn new(stations: &'a StationList, reader: R) -> Result<Self> {
+        Self::start(stations, reader, IssueSink::strict())
+    }
+
+    /** As `new`, collecting tolerated issues such as calls at unknown TIPLOCs in $warnings */
+    pub fn with_warnings(stations: &'a StationList, reader: R, warnings: &'a mut Vec<ParseIssue>) -> Result<Self> {
+        Self::start(stations, reader, IssueSink::strict_with_warnings(warnings))
+    }
+
+    /** As `new`, skipping bad records and collecting them (and calls at unknown TIPLOCs) in $issues */
+    pub fn lenient(stations: &'a StationList, reader: R, issues: &'a mut Vec<ParseIssue>) -> Result<Self> {
+        Self::start(stations, reader, IssueSink::lenient(issues))
+    }
+
+    fn start(stations: &'a StationList, mut reader: R, mut sink: IssueSink<'a>) -> Result<Self> {
+        let mut line_num = 0;
+        let header = Timetable::read_header(&mut reader, &mut line_num, &mut sink)?;
+        Ok(Self { stations, reader, sink, header, associations: Vec::new(), line_num, next_id: 0, done: false })
+    }
+
+    pub fn header(&self) -> Option<&TimetableHeader> {
+        self.header.as_ref()
+    }
+
+    /** The associations read so far, all of them once the services have been */
+    pub fn into_associations(self) -> Vec<Association> {
+        self.associations.into_iter().map(|(_, association)| association).collect()
+    }
+}
+
+impl<R: BufRead> Iterator for ServiceIter<'_, R> {
+    type Item = Result<Service>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match Service::read_entry(self.stations, &mut self.reader, &mut self.line_num, &mut self.associations, &mut self.sink) {
+            Ok(Some((_, mut service))) => {
+                service.id = self.next_id;
+                self.next_id += 1;
+                Some(Ok(service))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/** Records applied from an update extract. Unmatched deletions are skipped and unmatched revisions added */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateCounts {
+    pub created: usize,
+    pub deleted: usize,
+    pub revised: usize,
+    pub unmatched: usize
+}
+
+// Apply $transactions to $existing by the key of each record, keeping the order of those left
+fn apply_transactions<T, K: Eq + std::hash::Hash>(mut existing: Vec<Option<T>>, transactions: Vec<(Transaction, T)>, key: impl Fn(&T) -> K,
+        counts: &mut UpdateCounts) -> Vec<T> {
+    let mut index: HashMap<K, usize> = existing.iter().enumerate()
+        .filter_map(|(i, record)| record.as_ref().map(|record| (key(record), i)))
+        .collect();
+    for (transaction, record) in transactions {
+        let k = key(&record);
+        match (transaction, index.get(&k).copied()) {
+            (Transaction::Revise, Some(i)) => {
+                existing[i] = Some(record);
+                counts.revised += 1;
+            }
+            (Transaction::Delete, Some(i)) => {
+                existing[i] = None;
+                index.remove(&k);
+                counts.deleted += 1;
+            }
+            (Transaction::Delete, None) => counts.unmatched += 1,
+            (transaction, _) => {
+                if transaction == Transaction::Revise {
+                    counts.unmatched += 1;
+                } else {
+                    counts.created += 1;
+                }
+                index.insert(k, existing.len());
+                existing.push(Some(record));
+            }
+        }
+    }
+    existing.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use proptest::prelude::*;
+    use crate::stations::Station;
+
+    #[test]
+    fn test_railtime() {
+        assert_eq!(RailTime::from_24h("0025"), Some(RailTime { secs: 25*60 }));
+        assert_eq!(RailTime::from_24h("2359"), Some(RailTime { secs: 23*60*60+59*60 }));
+        assert_eq!(RailTime::from_24h("1136H"), Some(RailTime { secs: 11*60*60+36*60 }));
+        for bad in &["", "123", "2400", "1260", "12345H", "+123", "\u{661}\u{662}\u{663}\u{664}", "1\u{e9}00"] {
+            assert_eq!(RailTime::from_24h(bad), None, "{:?}", bad);
+        }
+
+        let t1 = RailTime::from_24h("1325").unwrap();
+        let t2 = RailTime::from_24h("1412").unwrap();
+        assert_eq!(t1.timetil(&t2), 47*60);
+        
+        let t1 = RailTime::from_24h("2355").unwrap();
+        let t2 = RailTime::from_24h("0020").unwrap();
+        assert_eq!(t1.timetil(&t2), 25*60);
+    }
+
+    #[test]
+    fn test_service_time() {
+        let t = ServiceTime::from_24h("2405").unwrap();
+        assert_eq!((t.day(), t.to_24h(), t.secs()), (1, "0005".to_string(), 24*60*60 + 5*60));
+        assert_eq!(ServiceTime::from_24h("2359"), Some(ServiceTime::new(0, RailTime::new(23, 59))));
+        for bad in &["", "2460", "+123", "0x00"] {
+            assert_eq!(ServiceTime::from_24h(bad), None, "{:?}", bad);
+        }
+
+        // Times earlier in the day are after midnight
+        let late = ServiceTime::from_24h("2350").unwrap();
+        assert_eq!(late.next(RailTime::new(23, 55)), ServiceTime::from_24h("2355").unwrap());
+        assert_eq!(late.next(RailTime::new(0, 20)), t.add(15*60));
+        assert_eq!(t.since(&late), 15*60);
+        assert_eq!(late.since(&t), 0);
+
+        // An overnight service's calls after midnight are on its second day
+        let mca_file = "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821                    
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+LOKLYNN   2345 23451         TB                                                 
+LTKNGX    0135 01356     TF                                                     
+";
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let stations = StationList::read_msn_file(&mut io::Cursor::new(&msn_file)).unwrap();
+        let service = ServiceIter::new(&stations, io::Cursor::new(&mca_file)).unwrap().next().unwrap().unwrap();
+        let arrival = service.stops[1].arrival;
+        assert_eq!((arrival.day(), arrival.to_24h()), (1, "0135".to_string()));
+        assert_eq!(arrival.since(&service.stops[0].departure), 110*60);
+    }
+
+    #[test]
+    fn test_service_parse() {
+        let mca_file = "/!! Comment line!
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+BX         GNYGN161701                                                          
+LOKLYNN   1045 10451         TB                                                 
+LIWATLGTN 1052 1052H     105210521        T                                     
+CRCAMBDGE XX1T25    121725000 EMU365 100D     B                    GN161703     
+LICAMBDGE 1136H1144H     113711448        T -U                                  
+LISTEVNGE           1211H000000002                      1                       
+LTKNGX    1235 12356     TF                                                     
+";
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    WATLINGTON                    0WATLGTNWTG   WTG15612 63110 5                 
+A    CAMBRIDGE                     2CAMBDGECBG   CBG15462 62573 5                 
+A    STEVENAGE                     2STEVNGESVG   SVG15235 62238 4                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        
+        let mut msn_read = io::Cursor::new(&msn_file);
+        let stations = StationList::read_msn_file(&mut msn_read).unwrap();
+
+        let mut mca_read = io::Cursor::new(&mca_file);
+        
+        let service = Service::read_service_entry(&stations, &mut mca_read).unwrap().unwrap();
+        println!("service: {:?}", service);
+        assert_eq!(service.train_uid, "L22108");
+        assert_eq!(service.stops.len(), 4);
+        assert_eq!(service.stops.get(2).unwrap().station, stations.get_by_name("CAMBRIDGE").unwrap().id);
+        assert_eq!(service.stops.get(2).unwrap().departure.to_24h(), "1144");
+        assert_eq!(service.reservations, Reservations::None);
+        assert_eq!((service.seating, service.sleepers), (Accommodation::FirstAndStandard, Accommodation::None));
+        assert!(service.is_seated() && !service.is_sleeper());
+        assert_eq!(service.operator, "GN");
+        assert_eq!((service.category, service.power_type), ("XX", "EMU"));
+        assert_eq!(service.mode(), ServiceMode::Rail);
+        let platforms: Vec<Option<&str>> = service.stops.iter().map(|stop| stop.platform.as_ref().map(Platform::as_str)).collect();
+        assert_eq!(platforms, vec![Some("1"), Some("1"), Some("8"), Some("6")]);
+        assert!(service.stops.iter().all(|stop| stop.activity == StopActivity::Normal));
+
+        // Calls without public times are skipped and set-down only calls use the arrival for both
+        let mca_file = mca_file
+            .replace("1052H     105210521", "1052H             1")
+            .replace("1144H     113711448        T -U", "1144H     1137    8        D   ")
+            .replace("     B            P", "      SE          P")
+            .replace("0 PXX1T25", "0 PBR1T25");
+        let mut mca_read = io::Cursor::new(&mca_file);
+        let service = Service::read_service_entry(&stations, &mut mca_read).unwrap().unwrap();
+        assert_eq!(service.stops.len(), 3);
+        assert_eq!(service.stops[1].station, stations.get_by_name("CAMBRIDGE").unwrap().id);
+        assert_eq!(service.reservations, Reservations::BicyclesEssential);
+        // A blank seating class is first and standard, but blank sleepers are none
+        assert_eq!((service.seating, service.sleepers), (Accommodation::FirstAndStandard, Accommodation::StandardOnly));
+        assert!(service.is_sleeper());
+        assert_eq!(service.mode(), ServiceMode::Bus);
+        let mca_file = mca_file.replace("      SE", "     S E");
+        let service = Service::read_service_entry(&stations, &mut io::Cursor::new(&mca_file)).unwrap().unwrap();
+        assert_eq!((service.seating, service.sleepers), (Accommodation::StandardOnly, Accommodation::None));
+        assert_eq!(service.stops[1].arrival, service.stops[1].departure);
+        assert_eq!(service.stops[1].activity, StopActivity::SetDownOnly);
+        assert_eq!(service.stops[1].departure.to_24h(), "1137");
+    }
+
+    #[test]
+    fn test_timetable() {
+        let mca_file = "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821                    
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+BX         GNYGN161701                                                          
+LOKLYNN   1045 10451         TB                                                 
+LTKNGX    1235 12356     TF                                                     
+BSNL221192005232012120000010 PXX1T30    121725000 EMU365 100D     B            P
+BX         GNYGN162200                                                          
+LOKNGX    1242 12429  B      TB                                                 
+LTKLYNN   1431 14311     TF                                                     
+";
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+
+        let mut msn_read = io::Cursor::new(&msn_file);
+        let stations = StationList::read_msn_file(&mut msn_read).unwrap();
+
+        let mut mca_read = io::Cursor::new(&mca_file);
+
+        let timetable = Timetable::read_mca_file(&stations, &mut mca_read).unwrap();
+        let header = timetable.header.unwrap();
+        assert_eq!(header.file_ref, "DFROC1A");
+        assert_eq!(header.extracted, NaiveDate::from_ymd_opt(2020, 8, 25).unwrap());
+        assert!(!header.update);
+        assert_eq!(header.start_date, NaiveDate::from_ymd_opt(2020, 8, 25).unwrap());
+        assert_eq!(header.end_date, NaiveDate::from_ymd_opt(2021, 8, 25).unwrap());
+        assert_eq!(timetable.services.len(), 2);
+        assert_eq!(timetable.services[1].train_uid, "L22119");
+        assert_eq!(timetable.services[1].stops.len(), 2);
+
+        // Saturdays from 23 May to 12 December 2020
+        let calendar = timetable.services[1].calendar.clone().unwrap();
+        assert_eq!(calendar.runs_from, NaiveDate::from_ymd_opt(2020, 5, 23).unwrap());
+        assert_eq!(calendar.runs_to, NaiveDate::from_ymd_opt(2020, 12, 12).unwrap());
+        assert!(timetable.services[1].runs_on(NaiveDate::from_ymd_opt(2020, 9, 12).unwrap()));
+        assert!(!timetable.services[1].runs_on(NaiveDate::from_ymd_opt(2020, 9, 14).unwrap()));
+        assert!(!timetable.services[1].runs_on(NaiveDate::from_ymd_opt(2020, 12, 19).unwrap()));
+    }
+
+    #[test]
+    fn test_service_iter() {
+        let mca_file = "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821                    
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+LOKLYNN   1045 10451         TB                                                 
+LTKNGX    1235 12356     TF                                                     
+AANL22108L221192005232012120000010VVSKNGX    TP                                P
+BSNL221192005232012120000010 PXX1T30    121725000 EMU365 100D     B            P
+LOKNGX    1242 12429  B      TB                                                 
+LTKLYNN   1431 14311     TF                                                     
+";
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let stations = StationList::read_msn_file(&mut io::Cursor::new(&msn_file)).unwrap();
+
+        let mut services = ServiceIter::new(&stations, io::Cursor::new(&mca_file)).unwrap();
+        assert_eq!(services.header().unwrap().file_ref, "DFROC1A");
+        let first = services.next().unwrap().unwrap();
+        assert_eq!((first.id, first.train_uid.as_str()), (0, "L22108"));
+        let rest: Vec<(ServiceId, String)> = services.by_ref().map(|service| service.map(|s| (s.id, s.train_uid))).collect::<Result<_>>().unwrap();
+        assert_eq!(rest, vec![(1, "L22119".to_string())]);
+        assert_eq!(services.into_associations().len(), 1);
+
+        // Counting the services from Kings Lynn without keeping them
+        let from_lynn = ServiceIter::new(&stations, io::Cursor::new(&mca_file)).unwrap()
+            .filter(|service| service.as_ref().map_or(true, |s| s.stops[0].station == 0))
+            .count();
+        assert_eq!(from_lynn, 1);
+
+        // The first error is the last item
+        let bad = mca_file.replace("LOKNGX    1242 1242", "LOKNGX    1242 12X2");
+        let read: Vec<bool> = ServiceIter::new(&stations, io::Cursor::new(&bad)).unwrap().map(|service| service.is_ok()).collect();
+        assert_eq!(read, vec![true, false]);
+        let mut issues = Vec::new();
+        assert_eq!(ServiceIter::lenient(&stations, io::Cursor::new(&bad), &mut issues).unwrap().count(), 2);
+        assert_eq!(issues.len(), 1);
+    }
+
+    #[test]
+    fn test_departures() {
+        let service = |id, stops| Service::simple(id, &format!("C0000{}", id), stops);
+        let set_down = Stop { activity: StopActivity::SetDownOnly, ..Stop::simple(1, "2350", "2351") };
+        let timetable = Timetable::new(None, vec![
+            service(0, vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0930", "0932"), Stop::simple(2, "1000", "1000")]),
+            service(1, vec![Stop::simple(0, "2330", "2330"), set_down, Stop::simple(2, "2410", "2410")]),
+            service(2, vec![Stop::simple(2, "0020", "0025"), Stop::simple(1, "0050", "0050")]),
+            service(3, vec![Stop::simple(1, "0850", "0850"), Stop::simple(0, "0920", "0920")])
+        ], Vec::new());
+
+        let board = |station, from, window| -> Vec<(ServiceId, String, StationId)> {
+            timetable.departures(station, from, window).iter().map(|d| (d.service.id, d.stop.departure.to_24h(), d.destination)).collect()
+        };
+        assert_eq!(board(1, RailTime::new(8, 0), 2*60*60), vec![(3, "0850".to_string(), 0), (0, "0932".to_string(), 2)]);
+        assert_eq!(board(1, RailTime::new(8, 55), 30*60), Vec::new());
+        // Round midnight, without the set down only call or ends of services
+        assert_eq!(board(2, RailTime::new(23, 0), 2*60*60), vec![(2, "0025".to_string(), 1)]);
+        assert_eq!(board(1, RailTime::new(23, 0), 2*60*60), Vec::new());
+        assert_eq!(board(0, RailTime::new(0, 0), 24*60*60).len(), 2);
+        assert_eq!(board(7, RailTime::new(0, 0), 60), Vec::new());
+
+        // Changing the services rebuilds the index
+        let mut timetable = timetable;
+        timetable.services_mut().push(service(4, vec![Stop::simple(1, "0900", "0900"), Stop::simple(2, "0930", "0930")]));
+        let ids: Vec<ServiceId> = timetable.departures(1, RailTime::new(8, 0), 2*60*60).iter().map(|d| d.service.id).collect();
+        assert_eq!(ids, vec![3, 4, 0]);
+    }
+
+    #[test]
+    fn test_stp() {
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let stations = StationList::read_msn_file(&mut io::Cursor::new(&msn_file)).unwrap();
+
+        // Saturdays, overlaid with a later train on 12 September and cancelled on 19 September
+        let bs = |dates: &str, stp: &str| format!("{:<79}{}", format!("BSNL22108{}0000010 PXX1T25    121725000 EMU365 100D     B", dates), stp);
+        let mca_file = [
+            bs("200523201212", "P"), "LOKLYNN   1045 10451         TB".to_string(), "LTKNGX    1235 12356     TF".to_string(),
+            bs("200912200912", "O"), "LOKLYNN   1100 11001         TB".to_string(), "LTKNGX    1250 12506     TF".to_string(),
+            bs("200919200919", "C"),
+            bs("200523201212", "P").replace("L22108", "L22119"), "LOKNGX    1242 12429  B      TB".to_string(), "LTKLYNN   1431 14311     TF".to_string()
+        ].join("\n");
+        let timetable = Timetable::read_mca_file(&stations, &mut io::Cursor::new(&mca_file)).unwrap();
+        assert_eq!(timetable.services.len(), 4);
+        assert!(timetable.services[2].is_cancellation());
+        assert!(timetable.services[2].stops.is_empty());
+        assert_eq!(timetable.services[3].train_uid, "L22119");
+        let schedules: Vec<ServiceId> = timetable.get_by_uid("L22108").iter().map(|s| s.id).collect();
+        assert_eq!(schedules, vec![0, 1, 2]);
+        assert!(timetable.get_by_uid("L22999").is_empty());
+
+        let departures = |date: u32| -> Vec<String> {
+            timetable.services_on(NaiveDate::from_ymd_opt(2020, 9, date).unwrap()).iter()
+                .map(|service| format!("{} {}", service.train_uid, service.stops[0].departure.to_24h()))
+                .collect()
+        };
+        assert_eq!(departures(5), vec!["L22108 1045", "L22119 1242"]);
+        assert_eq!(departures(12), vec!["L22108 1100", "L22119 1242"]);
+        assert_eq!(departures(19), vec!["L22119 1242"]);
+        assert!(departures(14).is_empty());
+
+        // Changing the services rebuilds the index
+        let mut timetable = timetable;
+        timetable.services_mut()[3].train_uid = "L22108".to_string();
+        let schedules: Vec<ServiceId> = timetable.get_by_uid("L22108").iter().map(|s| s.id).collect();
+        assert_eq!(schedules, vec![0, 1, 2, 3]);
+        assert!(timetable.get_by_uid("L22119").is_empty());
+    }
+
+    #[test]
+    fn test_update() {
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let stations = StationList::read_msn_file(&mut io::Cursor::new(&msn_file)).unwrap();
+
+        let bs = |transaction: &str, uid: &str| format!("{:<79}P", format!("BS{}{}2005232012120000010 PXX1T25    121725000 EMU365 100D     B", transaction, uid));
+        let aa = |transaction: &str, assoc: &str| format!("{:<79}P", format!("AA{}L22108{}2005232012120000010VVSKLYNN     TP", transaction, assoc));
+        let full = [
+            "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821".to_string(),
+            aa("N", "L22119"),
+            bs("N", "L22108"), "LOKLYNN   1045 10451         TB".to_string(), "LTKNGX    1235 12356     TF".to_string(),
+            bs("N", "L22119"), "LOKNGX    1242 12429  B      TB".to_string(), "LTKLYNN   1431 14311     TF".to_string(),
+            bs("N", "L22120"), "LOKNGX    1342 13429  B      TB".to_string(), "LTKLYNN   1531 15311     TF".to_string()
+        ].join("\n");
+        let mut timetable = Timetable::read_mca_file(&stations, &mut io::Cursor::new(&full)).unwrap();
+        assert_eq!(timetable.associations.len(), 1);
+        assert_eq!(timetable.get_by_uid("L22119").len(), 1);
+
+        // The next day's update retimes L22108, deletes L22119 and its association and adds L22130
+        let update = |last_ref: &str| [
+            format!("HDTPS.UDFROC1.PD2008262608201805DFROC1B{}UA260820250821", last_ref),
+            bs("R", "L22108"), "LOKLYNN   1050 10501         TB".to_string(), "LTKNGX    1240 12406     TF".to_string(),
+            format!("{:<79}P", "BSDL22119200523"),
+            format!("{:<79}P", "AADL22108L22119200523                KLYNN"),
+            bs("N", "L22130"), "LOKNGX    1442 14429  B      TB".to_string(), "LTKLYNN   1631 16311     TF".to_string(),
+            format!("{:<79}P", "BSDL22999200523")
+        ].join("\n");
+        assert!(matches!(timetable.apply_update_file(&stations, &mut io::Cursor::new(&update("DFROC1Z"))), Err(RailDataError::UpdateSequence { .. })));
+        assert!(timetable.apply_update_file(&stations, &mut io::Cursor::new(&full)).is_err());
+
+        let counts = timetable.apply_update_file(&stations, &mut io::Cursor::new(&update("DFROC1A"))).unwrap();
+        assert_eq!(counts, UpdateCounts { created: 1, deleted: 2, revised: 1, unmatched: 1 });
+        let services: Vec<(ServiceId, &str, String)> = timetable.services.iter()
+            .map(|s| (s.id, s.train_uid.as_str(), s.stops[0].departure.to_24h()))
+            .collect();
+        assert_eq!(services, vec![
+            (0, "L22108", "1050".to_string()),
+            (1, "L22120", "1342".to_string()),
+            (2, "L22130", "1442".to_string())
+        ]);
+        assert!(timetable.associations.is_empty());
+        assert_eq!(timetable.header.as_ref().unwrap().file_ref, "DFROC1B");
+        // Renumbered services are found by UID
+        assert!(timetable.get_by_uid("L22119").is_empty());
+        assert_eq!(timetable.get_by_uid("L22130")[0].id, 2);
+    }
+
+    #[test]
+    fn test_associations() {
+        let msn_file = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    CAMBRIDGE                     2CAMBDGECBG   CBG15462 62573 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let stations = StationList::read_msn_file(&mut io::Cursor::new(&msn_file)).unwrap();
+
+        let aa = |uids: &str, category: &str, location: &str| format!("{:<79}P", format!("AAN{}2005232012120000010{}S{:<7}  TP", uids, category, location));
+        let mca_file = [
+            "TIACAMBDGE00000000CCAMBRIDGE                   00000   0                          ".to_string(),
+            aa("L22108L22119", "VV", "CAMBDGE"),
+            aa("L22108L22120", "NP", "BOUNDSG"),
+            format!("{:<79}P", "BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B"),
+            "LOKNGX    1045 10451         TB".to_string(), "LTCAMBDGE 1135 11356     TF".to_string()
+        ].join("\n");
+        let timetable = Timetable::read_mca_file(&stations, &mut io::Cursor::new(&mca_file)).unwrap();
+        assert_eq!(timetable.services.len(), 1);
+
+        // The next working is at a depot which isn't a station
+        assert_eq!(timetable.associations.len(), 1);
+        let association = &timetable.associations[0];
+        assert_eq!(association.kind, AssociationKind::Divide);
+        assert_eq!(association.location, stations.get_by_name("CAMBRIDGE").unwrap().id);
+        assert_eq!(association.day_offset, 0);
+        assert!(association.passenger);
+        assert!(association.calendar.runs_on(NaiveDate::from_ymd_opt(2020, 9, 12).unwrap()));
+        assert_eq!(association.through(), Some(("L22108", "L22119")));
+
+        let bad = mca_file.replace("VVS", "XXS");
+        let err = Timetable::read_mca_file(&stations, &mut io::Cursor::new(&bad)).err().unwrap();
+        assert!(matches!(err, RailDataError::AtLine { line: 2, .. }));
+        assert!(err.record().unwrap().starts_with("AANL22108L22119"));
+        assert_eq!(err.field(), Some("category"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_service_serde() {
+        let service = Service {
+            reservations: Reservations::Compulsory,
+            operator: "GN",
+            category: "OO",
+            power_type: "EMU",
+            ..Service::simple(3, "C12345", vec![Stop::simple(0, "0930", "0930"), Stop::simple(1, "1005", "1007")])
+        };
+
+        let json = serde_json::to_string(&service).unwrap();
+        assert_eq!(json, r#"{"id":3,"train_uid":"C12345","stops":[{"station":0,"arrival":{"secs":34200},"departure":{"secs":34200},"platform":null,"activity":"Normal"},{"station":1,"arrival":{"secs":36300},"departure":{"secs":36420},"platform":null,"activity":"Normal"}],"reservations":"Compulsory","operator":"GN","category":"OO","power_type":"EMU","seating":"FirstAndStandard","sleepers":"None","bank_holidays":"Runs","calendar":null}"#);
+
+        let back: Service = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.stops[1].departure, ServiceTime::from_24h("1007").unwrap());
+
+        // Stops saved before platforms and activities were kept have none
+        let old = json.replace(r#","platform":null,"activity":"Normal""#, "");
+        let back: Service = serde_json::from_str(&old).unwrap();
+        assert_eq!(back.stops[0].platform, None);
+        assert_eq!(back.stops[0].activity, StopActivity::Normal);
+        // And services saved before bank holiday running was kept run on them
+        let old = json.replace(r#""bank_holidays":"Runs","#, "");
+        let back: Service = serde_json::from_str(&old).unwrap();
+        assert_eq!(back.bank_holidays, BankHolidayRunning::Runs);
+    }
+
+    // Lines shaped like MCA records, with the odd arbitrary one
+    fn mca_line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            8 => "(HD|AA|BS|BX|LO|LI|LT|CR)[ A-Z0-9H]{0,80}",
+            1 => "\\PC{0,80}"
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_railtime_roundtrip(hrs in 0u32..24, mins in 0u32..60, suffix in "[ H]?") {
+            let t = RailTime::from_24h(&format!("{:02}{:02}{}", hrs, mins, suffix));
+            prop_assert_eq!(t, Some(RailTime::new(hrs, mins)));
+            prop_assert_eq!(t.unwrap().to_24h(), format!("{:02}{:02}", hrs, mins));
+        }
+
+        #[test]
+        fn prop_railtime_any(s in "\\PC{0,6}") {
+            if let Some(t) = RailTime::from_24h(&s) {
+                prop_assert!(t.secs() < 24*60*60);
+            }
+        }
+
+        #[test]
+        fn prop_mca_lines(lines in proptest::collection::vec(mca_line(), 0..20)) {
+            let stations = StationList::new(vec![
+                Station::simple("KLYNN", "KINGS LYNN", "KLN"),
+                Station::simple("CAMBDGE", "CAMBRIDGE", "CBG")
+            ]);
+            let mca = lines.join("\n");
+
+            let mut issues = Vec::new();
+            let timetable = Timetable::read_mca_file_lenient(&stations, &mut io::Cursor::new(&mca), &mut issues).unwrap();
+            if Timetable::read_mca_file(&stations, &mut io::Cursor::new(&mca)).is_ok() {
+                prop_assert!(issues.iter().all(|issue| issue.kind != ParseIssueKind::Malformed));
+            }
+            prop_assert!(timetable.services.iter().flat_map(|s| &s.stops).all(|stop| stop.station < stations.count()));
+        }
+    }
+}
@@ -0,0 +1,353 @@
+/** Copyright James Lomax 2020 */
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::fixed_links;
+use crate::fixed_links::{FixedLink, FixedLinkKind};
+use crate::stations::{StationId, StationList};
+use crate::timetable::Timetable;
+use crate::error::{RailDataError, Result};
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseIssueKind {
+    // The record is truncated or has a field which can't be parsed
+    Malformed,
+    // The record refers to a station (TIPLOC or CRS) which isn't in the station list
+    UnknownStation(String),
+    // A station record merged into an earlier one with the same CRS
+    MergedStation(String),
+    // A fixed link of a kind we don't know, e.g. HOVERCRAFT
+    UnknownLinkKind(String)
+}
+
+/** A problem with a single record found while parsing a file */
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseIssue {
+    pub line: usize,
+    pub kind: ParseIssueKind,
+    pub message: String
+}
+
+/**
+ * Destination for parse issues. In strict mode (the default for loading)
+ * reported issues become errors, in lenient mode they are collected and the
+ * offending record is skipped. Tolerated issues (notes) are collected in
+ * either mode if there's somewhere to put them.
+ */
+pub(crate) struct IssueSink<'a> {
+    strict: bool,
+    issues: Option<&'a mut Vec<ParseIssue>>
+}
+
+impl<'a> IssueSink<'a> {
+    pub fn strict() -> Self {
+        Self { strict: true, issues: None }
+    }
+
+    /** Strict, collecting the tolerated issues in $warnings */
+    pub fn strict_with_warnings(warnings: &'a mut Vec<ParseIssue>) -> Self {
+        Self { strict: true, issues: Some(warnings) }
+    }
+
+    pub fn lenient(issues: &'a mut Vec<ParseIssue>) -> Self {
+        Self { strict: false, issues: Some(issues) }
+    }
+
+    /** Report an issue with the $record on $line, which is an error in strict mode */
+    pub fn report(&mut self, line: usize, record: &str, kind: ParseIssueKind, err: RailDataError) -> Result<()> {
+        match &mut self.issues {
+            Some(issues) if !self.strict => {
+                issues.push(ParseIssue { line, kind, message: err.to_string() });
+                Ok(())
+            }
+            _ => Err(err.at_record(line, record))
+        }
+    }
+
+    /** Note an issue which is tolerated (and dropped) in strict mode */
+    pub fn note(&mut self, line: usize, kind: ParseIssueKind, message: String) {
+        match &mut self.issues {
+            Some(issues) => issues.push(ParseIssue { line, kind, message }),
+            None => tracing::debug!(line, "Ignoring: {}", message)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuplicateFixedLink {
+    pub a: String,
+    pub b: String,
+    pub kind: FixedLinkKind,
+    pub count: usize
+}
+
+/** Data-quality report for a timetable extract. Stations are given by CRS and services by train UID */
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    // Malformed records per file (these are errors)
+    pub msn_issues: Vec<ParseIssue>,
+    pub flf_issues: Vec<ParseIssue>,
+    pub mca_issues: Vec<ParseIssue>,
+    // TIPLOCs with public calls which don't resolve to a station, with the number of calls
+    pub unknown_tiplocs: Vec<(String, usize)>,
+    // Services left with fewer than two stops, e.g. as none of their TIPLOCs resolved
+    pub empty_services: Vec<String>,
+    // Services with a stop departing before it arrives or arriving before the one before departs
+    pub non_monotonic_services: Vec<String>,
+    // Stations without any services or fixed links
+    pub disconnected_stations: Vec<String>,
+    // Stations which can't be reached from the hub checked from, if any, besides disconnected ones
+    pub unreachable_stations: Vec<String>,
+    pub duplicate_fixed_links: Vec<DuplicateFixedLink>,
+    // Stations with a fixed link to themselves
+    pub self_fixed_links: Vec<String>,
+    // Fixed link kinds we don't know, with the number of links left out
+    pub unknown_link_kinds: Vec<(String, usize)>
+}
+
+impl Report {
+    pub fn error_count(&self) -> usize {
+        self.msn_issues.len() + self.flf_issues.len() + self.mca_issues.len() + self.empty_services.len() + self.non_monotonic_services.len()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.unknown_tiplocs.len() + self.disconnected_stations.len() + self.unreachable_stations.len() + self.duplicate_fixed_links.len()
+            + self.self_fixed_links.len() + self.unknown_link_kinds.len()
+    }
+}
+
+// Split off the issues which are warnings, counting them by TIPLOC or link kind. Merged stations are normal
+fn split_warnings(issues: Vec<ParseIssue>, unknown_tiplocs: bool, unknown: &mut BTreeMap<String, usize>) -> Vec<ParseIssue> {
+    let mut errors = Vec::new();
+    for issue in issues {
+        match issue.kind {
+            ParseIssueKind::UnknownStation(tiploc) if unknown_tiplocs => *unknown.entry(tiploc).or_insert(0) += 1,
+            ParseIssueKind::UnknownLinkKind(kind) => *unknown.entry(kind).or_insert(0) += 1,
+            ParseIssueKind::MergedStation(_) => {}
+            _ => errors.push(issue)
+        }
+    }
+    errors
+}
+
+/**
+ * Parse the MSN, FLF and MCA files sharing $file_prefix in lenient mode and
+ * check the result for problems, with reachability from the station with CRS
+ * code $hub if given. Only failing to read the files or find the hub is an error.
+ */
+pub fn validate_files(file_prefix: &str, hub: Option<&str>) -> Result<Report> {
+    let msnfile = File::open(format!("{}.MSN", file_prefix))?;
+    let flffile = File::open(format!("{}.FLF", file_prefix))?;
+    let mcafile = File::open(format!("{}.MCA", file_prefix))?;
+
+    validate(
+        &mut BufReader::new(msnfile),
+        &mut BufReader::new(flffile),
+        &mut BufReader::with_capacity(1024*1024, mcafile),
+        hub
+    )
+}
+
+#[tracing::instrument(skip_all)]
+pub fn validate(msn: &mut dyn BufRead, flf: &mut dyn BufRead, mca: &mut dyn BufRead, hub: Option<&str>) -> Result<Report> {
+    let mut msn_issues = Vec::new();
+    let stations = StationList::read_msn_file_lenient(msn, &mut msn_issues)?;
+    let hub = hub.map(|crs| stations.get_by_crs(crs).map(|station| station.id)
+        .ok_or_else(|| RailDataError::InvalidField { field: "hub".to_string(), value: crs.to_string() })).transpose()?;
+
+    let mut flf_issues = Vec::new();
+    let fixedlinks = fixed_links::parse_fixed_links_lenient(&stations, flf, &mut flf_issues)?;
+
+    let mut mca_issues = Vec::new();
+    let timetable = Timetable::read_mca_file_lenient(&stations, mca, &mut mca_issues)?;
+
+    let mut report = check(&stations, &fixedlinks, &timetable, hub);
+    report.msn_issues = split_warnings(msn_issues, false, &mut BTreeMap::new());
+    let mut unknown = BTreeMap::new();
+    report.flf_issues = split_warnings(flf_issues, false, &mut unknown);
+    report.unknown_link_kinds = unknown.into_iter().collect();
+    let mut unknown = BTreeMap::new();
+    report.mca_issues = split_warnings(mca_issues, true, &mut unknown);
+    report.unknown_tiplocs = unknown.into_iter().collect();
+    Ok(report)
+}
+
+/**
+ * Check loaded data for problems, with reachability from $hub if given. Problems
+ * with the records themselves are only found when parsing, see `validate`
+ */
+pub fn check(stations: &StationList, fixedlinks: &[FixedLink], timetable: &Timetable, hub: Option<StationId>) -> Report {
+    let mut report = Report::default();
+
+    // Find services without a journey or going back in time, which stations are served and where each service goes next
+    let mut connected = HashSet::new();
+    let mut next: Vec<Vec<StationId>> = vec![Vec::new(); stations.count()];
+    for service in timetable.services().iter().filter(|service| !service.is_cancellation()) {
+        if service.stops.len() < 2 {
+            report.empty_services.push(service.train_uid.clone());
+            continue;
+        }
+        connected.extend(service.stops.iter().map(|stop| stop.station));
+        for (stop, following) in service.stops.iter().zip(&service.stops[1..]) {
+            next[stop.station].push(following.station);
+        }
+        let backwards = service.stops.iter().any(|stop| stop.departure < stop.arrival)
+            || service.stops.windows(2).any(|stops| stops[1].arrival < stops[0].departure);
+        if backwards {
+            report.non_monotonic_services.push(service.train_uid.clone());
+        }
+    }
+
+    // Fixed links are duplicates if they join the same stations in the same direction with the same kind.
+    // The FLF lists each link once each way, so a link and its reverse aren't duplicates
+    let mut link_counts = BTreeMap::new();
+    for link in fixedlinks {
+        connected.insert(link.a);
+        connected.insert(link.b);
+        next[link.a].push(link.b);
+        if !link.one_way {
+            next[link.b].push(link.a);
+        }
+        link_counts.entry((link.a, link.b, format!("{:?}", link.kind))).or_insert((link.kind, 0)).1 += 1;
+    }
+
+    let crs = |id| stations.get(id).unwrap().crs_code.clone();
+
+    for ((a, b, _), (kind, count)) in link_counts {
+        if a == b {
+            report.self_fixed_links.push(crs(a));
+        }
+        if count > 1 {
+            report.duplicate_fixed_links.push(DuplicateFixedLink { a: crs(a), b: crs(b), kind, count });
+        }
+    }
+    report.self_fixed_links.dedup();
+
+    for station in stations.iter() {
+        if !connected.contains(&station.id) {
+            report.disconnected_stations.push(station.crs_code.clone());
+        }
+    }
+
+    // Whatever the times, which stations there's any way to get to from the hub
+    if let Some(hub) = hub {
+        let mut reached = vec![false; stations.count()];
+        reached[hub] = true;
+        let mut queue = vec![hub];
+        while let Some(id) = queue.pop() {
+            for &to in &next[id] {
+                if !reached[to] {
+                    reached[to] = true;
+                    queue.push(to);
+                }
+            }
+        }
+        report.unreachable_stations = stations.iter()
+            .filter(|station| !reached[station.id] && connected.contains(&station.id))
+            .map(|station| station.crs_code.clone())
+            .collect();
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use crate::stations::Station;
+    use crate::timetable::{Service, Stop};
+
+    #[test]
+    fn test_validate() {
+        let msn = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    CAMBRIDGE                     2CAMBDGECBG   CBG15462 62573 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+A    LONDON ST PANCRAS             3STPX   STP   STP15301 6183115                 
+A    ELY                           2ELYY   ELY   ELY15543 6280 
+";
+        let flf = "ADDITIONAL LINK: WALK BETWEEN KGX AND STP IN   5 MINUTES
+ADDITIONAL LINK: WALK BETWEEN STP AND KGX IN   5 MINUTES
+ADDITIONAL LINK: WALK BETWEEN KGX AND STP IN  10 MINUTES
+ADDITIONAL LINK: WALK BETWEEN KGX AND XXX IN   5 MINUTES
+ADDITIONAL LINK: HOVERCRAFT BETWEEN KGX AND STP IN   5 MINUTES
+";
+        let mca = "/!! Comment line!
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+LOKLYNN   1045 10451         TB                                                 
+LIWATLGTN 1052 1052H     105210521        T                                     
+LTKNGX    1235 12356     TF                                                     
+BSNL221192005232012120000010 PXX1T30    121725000 EMU365 100D     B            P
+LOKNGX    1242 12429  B      TB                                                 
+LTKLY
+";
+
+        let report = validate(
+            &mut io::Cursor::new(&msn),
+            &mut io::Cursor::new(&flf),
+            &mut io::Cursor::new(&mca),
+            None
+        ).unwrap();
+
+        assert_eq!(report.msn_issues.len(), 1);
+        assert_eq!(report.msn_issues[0].line, 7);
+        assert_eq!(report.flf_issues.len(), 1);
+        assert_eq!(report.flf_issues[0].kind, ParseIssueKind::UnknownStation("XXX".to_string()));
+        assert_eq!(report.mca_issues.len(), 1);
+        assert_eq!(report.mca_issues[0].line, 8);
+        assert_eq!(report.unknown_tiplocs, vec![("WATLGTN".to_string(), 1)]);
+        assert_eq!(report.empty_services, vec!["L22119"]);
+        assert_eq!(report.disconnected_stations, vec!["CBG"]);
+        // Listing the walk each way is normal, it's listing KGX to STP again that's a duplicate
+        assert_eq!(report.duplicate_fixed_links, vec![DuplicateFixedLink {
+            a: "KGX".to_string(),
+            b: "STP".to_string(),
+            kind: FixedLinkKind::Walk,
+            count: 2
+        }]);
+        assert_eq!(report.unknown_link_kinds, vec![("HOVERCRAFT".to_string(), 1)]);
+        assert_eq!(report.error_count(), 4);
+        assert_eq!(report.warning_count(), 4);
+
+        // The only service runs towards KGX, so there's no way back to KLN from there
+        let from_hub = |hub| validate(&mut io::Cursor::new(&msn), &mut io::Cursor::new(&flf), &mut io::Cursor::new(&mca), Some(hub));
+        assert!(from_hub("KLN").unwrap().unreachable_stations.is_empty());
+        assert_eq!(from_hub("KGX").unwrap().unreachable_stations, vec!["KLN"]);
+        assert!(from_hub("XXX").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("STPX", "LONDON ST PANCRAS", "STP"),
+            Station::simple("ELYY", "ELY", "ELY")
+        ]);
+        let service = |uid: &str, stops| Service::simple(0, uid, stops);
+        let timetable = Timetable::new(None, vec![
+            service("C1", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")]),
+            // Departing Cambridge before it arrives
+            service("C2", vec![Stop::simple(3, "0900", "0900"), Stop::simple(0, "0920", "0915"), Stop::simple(1, "1000", "1000")])
+        ], Vec::new());
+        let link = |a, b, one_way| FixedLink { a, b, time: 300, kind: FixedLinkKind::Walk, one_way, availability: None };
+        let fixedlinks = vec![link(2, 2, false), link(2, 1, true)];
+
+        let report = check(&stations, &fixedlinks, &timetable, Some(0));
+        assert_eq!(report.non_monotonic_services, vec!["C2"]);
+        assert_eq!(report.self_fixed_links, vec!["STP"]);
+        assert!(report.disconnected_stations.is_empty());
+        // St Pancras can only be left for Kings Cross, and Ely is only left from
+        assert_eq!(report.unreachable_stations, vec!["STP", "ELY"]);
+        assert_eq!((report.error_count(), report.warning_count()), (1, 3));
+        assert!(check(&stations, &fixedlinks, &timetable, None).unreachable_stations.is_empty());
+    }
+}
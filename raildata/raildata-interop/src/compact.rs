@@ -0,0 +1,103 @@
+/* Copyright James Lomax 2020 */
+
+// Journey times to every station as a compact binary payload, for web maps
+// colouring the whole network at once where JSON keyed by CRS code is too
+// heavy. Times are whole minutes in station ID order, so a client lines them
+// up with a manifest of the stations fetched once, and checks it's for the
+// same stations by the manifest hash. ~2500 stations take about 5KB.
+//
+// Layout, little-endian:
+//   0   magic "RDTT"
+//   4   u16 format version
+//   6   u16 reserved, 0
+//   8   u32 station count
+//   12  u32 manifest hash
+//   16  u16 minutes for each station, UNREACHABLE where it can't be reached
+
+use raildata_core::error::{RailDataError, Result};
+use raildata_core::stations::StationList;
+
+const MAGIC: &[u8; 4] = b"RDTT";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+
+/** Minutes given for a station which can't be reached. Longer journeys are given as one less */
+pub const UNREACHABLE: u16 = u16::MAX;
+
+/** A hash (32-bit FNV-1a) of the CRS codes of $stations in ID order, identifying the order times are encoded in */
+pub fn manifest_hash(stations: &StationList) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for station in stations.iter() {
+        // Separated, so codes can't run into each other
+        for byte in station.crs_code.bytes().chain(std::iter::once(0)) {
+            hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+        }
+    }
+    hash
+}
+
+/** Encode $times in seconds by station ID, e.g. from `TravelGraph::compute_all_times`, rounded to the nearest minute */
+pub fn encode_times(stations: &StationList, times: &[Option<u32>]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + times.len()*2);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&(times.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&manifest_hash(stations).to_le_bytes());
+    for time in times {
+        let minutes = time.map_or(UNREACHABLE, |secs| (secs.saturating_add(30) / 60).min(UNREACHABLE as u32 - 1) as u16);
+        bytes.extend_from_slice(&minutes.to_le_bytes());
+    }
+    bytes
+}
+
+/** The manifest hash and minutes by station ID (None where unreachable) of a payload from `encode_times` */
+pub fn decode_times(bytes: &[u8]) -> Result<(u32, Vec<Option<u16>>)> {
+    let invalid = |field: &str, value: String| RailDataError::InvalidField { field: field.to_string(), value };
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(invalid("magic", format!("{:?}", &bytes[..bytes.len().min(4)])));
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    if u16_at(4) != VERSION {
+        return Err(invalid("version", u16_at(4).to_string()));
+    }
+    let count = u32_at(8) as usize;
+    if bytes.len() != HEADER_LEN + count*2 {
+        return Err(invalid("length", format!("{} bytes for {} stations", bytes.len(), count)));
+    }
+    let minutes = (0..count).map(|i| Some(u16_at(HEADER_LEN + i*2)).filter(|minutes| *minutes != UNREACHABLE)).collect();
+    Ok((u32_at(12), minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+
+    #[test]
+    fn test_compact_times() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("ELYY", "ELY", "ELY")
+        ]);
+        let bytes = encode_times(&stations, &[Some(0), Some(50*60 + 29), None]);
+        assert_eq!(bytes.len(), 16 + 3*2);
+        assert_eq!(decode_times(&bytes).unwrap(), (manifest_hash(&stations), vec![Some(0), Some(50), None]));
+        // Too long to tell apart from unreachable
+        assert_eq!(decode_times(&encode_times(&stations, &[Some(u32::MAX - 60)])).unwrap().1, vec![Some(UNREACHABLE - 1)]);
+
+        // Other stations, or the same in another order, have another hash
+        let reordered = StationList::new(vec![
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("ELYY", "ELY", "ELY")
+        ]);
+        assert_ne!(manifest_hash(&reordered), manifest_hash(&stations));
+
+        assert!(decode_times(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_times(b"RDT").is_err());
+        assert!(decode_times(&[b"XXXX".as_slice(), &bytes[4..]].concat()).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+/** Copyright James Lomax 2020 */
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use raildata_routing::RailServices;
+
+fn create(dir: &Path, name: &str) -> io::Result<csv::Writer<File>> {
+    Ok(csv::Writer::from_writer(File::create(dir.join(name))?))
+}
+
+/**
+ * Write the dataset as plain CSV tables into $dir: stations.csv, fixed_links.csv,
+ * services.csv and stops.csv. Stations are referred to by CRS and times are HHMM.
+ */
+pub fn write_csv(rail: &RailServices, dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    write_stations(rail, &mut create(dir, "stations.csv")?)?;
+    write_fixed_links(rail, &mut create(dir, "fixed_links.csv")?)?;
+    write_services(rail, &mut create(dir, "services.csv")?)?;
+    write_stops(rail, &mut create(dir, "stops.csv")?)
+}
+
+fn write_stations<W: io::Write>(rail: &RailServices, w: &mut csv::Writer<W>) -> io::Result<()> {
+    w.write_record(["crs", "name", "tiplocs", "min_change_time", "gref_east", "gref_north"])?;
+    for s in rail.stations.iter() {
+        w.write_record(&[
+            s.crs_code.clone(),
+            s.names[0].clone(),
+            s.tiplocs.join(" "),
+            s.min_change_time.to_string(),
+            s.gref_east.to_string(),
+            s.gref_north.to_string()
+        ])?;
+    }
+    w.flush()
+}
+
+fn write_fixed_links<W: io::Write>(rail: &RailServices, w: &mut csv::Writer<W>) -> io::Result<()> {
+    w.write_record(["from", "to", "kind", "time"])?;
+    for link in &rail.fixedlinks {
+        w.write_record(&[
+            rail.stations.get(link.a).unwrap().crs_code.clone(),
+            rail.stations.get(link.b).unwrap().crs_code.clone(),
+            format!("{:?}", link.kind),
+            link.time.to_string()
+        ])?;
+    }
+    w.flush()
+}
+
+fn write_services<W: io::Write>(rail: &RailServices, w: &mut csv::Writer<W>) -> io::Result<()> {
+    w.write_record(["id", "train_uid", "stops"])?;
+    for service in rail.timetable.services() {
+        w.write_record(&[service.id.to_string(), service.train_uid.clone(), service.stops.len().to_string()])?;
+    }
+    w.flush()
+}
+
+fn write_stops<W: io::Write>(rail: &RailServices, w: &mut csv::Writer<W>) -> io::Result<()> {
+    w.write_record(["service", "sequence", "crs", "arrival", "departure"])?;
+    for service in rail.timetable.services() {
+        for (seq, stop) in service.stops.iter().enumerate() {
+            w.write_record(&[
+                service.id.to_string(),
+                seq.to_string(),
+                rail.stations.get(stop.station).unwrap().crs_code.clone(),
+                stop.arrival.to_24h(),
+                stop.departure.to_24h()
+            ])?;
+        }
+    }
+    w.flush()
+}
+
+/**
+ * Write the dataset into an SQLite database at $path, replacing any tables
+ * previously exported there. Tables mirror the CSV export, with station IDs.
+ */
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(rail: &RailServices, path: &Path) -> io::Result<()> {
+    sqlite::write(rail, path).map_err(io::Error::other)
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use rusqlite::{params, Connection};
+
+    const SCHEMA: &str = "
+        DROP TABLE IF EXISTS stations;
+        DROP TABLE IF EXISTS station_names;
+        DROP TABLE IF EXISTS station_tiplocs;
+        DROP TABLE IF EXISTS fixed_links;
+        DROP TABLE IF EXISTS services;
+        DROP TABLE IF EXISTS stops;
+        CREATE TABLE stations (id INTEGER PRIMARY KEY, crs TEXT NOT NULL, min_change_time INTEGER, gref_east INTEGER, gref_north INTEGER);
+        CREATE TABLE station_names (station INTEGER NOT NULL, name TEXT NOT NULL);
+        CREATE TABLE station_tiplocs (station INTEGER NOT NULL, tiploc TEXT NOT NULL);
+        CREATE TABLE fixed_links (a INTEGER NOT NULL, b INTEGER NOT NULL, kind TEXT NOT NULL, time INTEGER NOT NULL);
+        CREATE TABLE services (id INTEGER PRIMARY KEY, train_uid TEXT NOT NULL);
+        CREATE TABLE stops (service INTEGER NOT NULL, sequence INTEGER NOT NULL, station INTEGER NOT NULL, arrival TEXT, departure TEXT);
+        CREATE INDEX stops_station ON stops (station);
+    ";
+
+    pub fn write(rail: &RailServices, path: &Path) -> rusqlite::Result<()> {
+        let mut conn = Connection::open(path)?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+
+        {
+            let mut station = tx.prepare("INSERT INTO stations VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            let mut name = tx.prepare("INSERT INTO station_names VALUES (?1, ?2)")?;
+            let mut tiploc = tx.prepare("INSERT INTO station_tiplocs VALUES (?1, ?2)")?;
+            for s in rail.stations.iter() {
+                station.execute(params![s.id as i64, s.crs_code, s.min_change_time, s.gref_east, s.gref_north])?;
+                for n in &s.names {
+                    name.execute(params![s.id as i64, n])?;
+                }
+                for t in &s.tiplocs {
+                    tiploc.execute(params![s.id as i64, t])?;
+                }
+            }
+
+            let mut link = tx.prepare("INSERT INTO fixed_links VALUES (?1, ?2, ?3, ?4)")?;
+            for l in &rail.fixedlinks {
+                link.execute(params![l.a as i64, l.b as i64, format!("{:?}", l.kind), l.time])?;
+            }
+
+            let mut service = tx.prepare("INSERT INTO services VALUES (?1, ?2)")?;
+            let mut stop = tx.prepare("INSERT INTO stops VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for s in rail.timetable.services() {
+                service.execute(params![s.id, s.train_uid])?;
+                for (seq, st) in s.stops.iter().enumerate() {
+                    stop.execute(params![s.id, seq as i64, st.station as i64, st.arrival.to_24h(), st.departure.to_24h()])?;
+                }
+            }
+        }
+
+        tx.commit()
+    }
+}
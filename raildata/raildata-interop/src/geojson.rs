@@ -0,0 +1,95 @@
+/* Copyright James Lomax 2020 */
+
+use serde_json::{json, Value};
+
+use raildata_core::stations::{Station, StationList};
+
+/**
+ * A GeoJSON point Feature for $station with its CRS and name as properties,
+ * or None if it has no grid reference.
+ */
+pub fn station_feature(station: &Station) -> Option<Value> {
+    let ll = station.lat_lon()?;
+    Some(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [ll.lon, ll.lat]
+        },
+        "properties": {
+            "crs": station.crs_code,
+            "name": station.names[0]
+        }
+    }))
+}
+
+pub fn feature_collection(features: Vec<Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": features
+    })
+}
+
+/**
+ * A FeatureCollection of all located stations. Given $times indexed by station,
+ * e.g. from `TravelGraph::compute_all_times`, only the stations reached are
+ * included and each gets "seconds" and "minutes" properties.
+ */
+pub fn stations_geojson(stations: &StationList, times: Option<&[Option<u32>]>) -> Value {
+    let mut features = Vec::new();
+    for station in stations.iter() {
+        let time = match times {
+            Some(times) => match times.get(station.id).copied().flatten() {
+                Some(t) => Some(t),
+                None => continue
+            },
+            None => None
+        };
+
+        if let Some(mut feature) = station_feature(station) {
+            if let Some(t) = time {
+                feature["properties"]["seconds"] = json!(t);
+                feature["properties"]["minutes"] = json!(t / 60);
+            }
+            features.push(feature);
+        }
+    }
+    feature_collection(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn located(tiploc: &str, crs: &str, east: i32, north: i32) -> Station {
+        let mut s = Station::simple(tiploc, tiploc, crs);
+        s.gref_east = east;
+        s.gref_north = north;
+        s
+    }
+
+    #[test]
+    fn test_stations_geojson() {
+        let stations = StationList::new(vec![
+            located("KNGX", "KGX", 5303, 1830),
+            located("ABRDEEN", "ABD", 3942, 8058),
+            located("NOWHERE", "XNW", 0, 0)
+        ]);
+
+        let all = stations_geojson(&stations, None);
+        let features = all["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["properties"]["crs"], "KGX");
+        let lon = features[0]["geometry"]["coordinates"][0].as_f64().unwrap();
+        let lat = features[0]["geometry"]["coordinates"][1].as_f64().unwrap();
+        assert!((lat - 51.5309).abs() < 0.0001 && (lon - -0.1229).abs() < 0.0001);
+        assert!(features[0]["properties"].get("minutes").is_none());
+
+        let times = vec![Some(0), None, Some(600)];
+        let reached = stations_geojson(&stations, Some(&times));
+        let features = reached["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["crs"], "KGX");
+        assert_eq!(features[0]["properties"]["minutes"], 0);
+    }
+}
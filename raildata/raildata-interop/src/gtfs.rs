@@ -0,0 +1,717 @@
+/** Copyright James Lomax 2020 */
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate};
+
+use raildata_core::fixed_links::{FixedLink, FixedLinkKind};
+use raildata_core::geo::{osgb36_to_wgs84, wgs84_to_osgb36, LatLon};
+use raildata_core::intern::intern;
+use raildata_core::stations::{Station, StationId, StationList};
+use raildata_core::timetable::{superseding_calendars, Accommodation, BankHolidayRunning, Calendar, Platform, Reservations, Service, ServiceTime, Stop, StopActivity, Stp, Timetable};
+use raildata_core::validate::{ParseIssue, ParseIssueKind};
+use raildata_core::RailDataError;
+use raildata_routing::{load_from, LoadWarning, RailServices, TimetableData, TimetableSource};
+
+// Every route is run by this agency
+const AGENCY_ID: &str = "NR";
+// For trips without a calendar, running every day the timetable covers
+const CALENDAR_ID: &str = "ALL";
+
+// A calendar's dates and days run, and the dates it's superseded on by STP schedules
+type CalendarKey = (NaiveDate, NaiveDate, [bool; 7], Vec<NaiveDate>);
+
+fn create(dir: &Path, name: &str) -> io::Result<csv::Writer<File>> {
+    Ok(csv::Writer::from_writer(File::create(dir.join(name))?))
+}
+
+/** Format seconds since midnight of the service day as GTFS HH:MM:SS, which may exceed 24:00:00 */
+fn gtfs_time(secs: u32) -> String {
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+/** Arrival and departure times for each stop as seconds since midnight of the day the service starts */
+fn stop_times(service: &Service) -> Vec<(u32, u32)> {
+    service.stops.iter().map(|stop| (stop.arrival.secs(), stop.departure.secs())).collect()
+}
+
+/** The dates $calendar runs on which one of $superseding takes instead */
+fn superseded_dates(calendar: &Calendar, superseding: &[&Calendar]) -> Vec<NaiveDate> {
+    calendar.runs_from.iter_days()
+        .take_while(|date| *date <= calendar.runs_to)
+        .filter(|date| calendar.runs_on(*date) && superseding.iter().any(|other| other.runs_on(*date)))
+        .collect()
+}
+
+fn gtfs_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/**
+ * Write the dataset as a GTFS feed into $dir. Stops are identified by CRS,
+ * trips by service ID and routes by their origin and destination. Trips with
+ * the same calendar share a GTFS service, and dates when an STP overlay or
+ * cancellation replaces a schedule are removed in calendar_dates.txt.
+ */
+pub fn write_gtfs(rail: &RailServices, dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let crs = |id: StationId| rail.stations.get(id).unwrap().crs_code.clone();
+
+    let mut agency = create(dir, "agency.txt")?;
+    agency.write_record(["agency_id", "agency_name", "agency_url", "agency_timezone"])?;
+    agency.write_record([AGENCY_ID, "National Rail", "https://www.nationalrail.co.uk", "Europe/London"])?;
+    agency.flush()?;
+
+    let mut stops = create(dir, "stops.txt")?;
+    stops.write_record(["stop_id", "stop_name", "stop_lat", "stop_lon", "location_type"])?;
+    for s in rail.stations.iter() {
+        let ll = osgb36_to_wgs84(s.gref_east as f64 * 100.0, s.gref_north as f64 * 100.0);
+        stops.write_record(&[s.crs_code.clone(), s.names[0].clone(), format!("{:.6}", ll.lat), format!("{:.6}", ll.lon), "0".to_string()])?;
+    }
+    stops.flush()?;
+
+    let (start, end) = match &rail.timetable.header {
+        Some(h) => (gtfs_date(h.start_date), gtfs_date(h.end_date)),
+        None => ("20000101".to_string(), "20991231".to_string())
+    };
+    let mut calendar = create(dir, "calendar.txt")?;
+    let mut calendar_dates = create(dir, "calendar_dates.txt")?;
+    calendar.write_record(["service_id", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday", "start_date", "end_date"])?;
+    calendar.write_record([CALENDAR_ID, "1", "1", "1", "1", "1", "1", "1", &start, &end])?;
+    calendar_dates.write_record(["service_id", "date", "exception_type"])?;
+
+    // The GTFS service of each trip, written as they're first used
+    let services: Vec<&Service> = rail.timetable.services().iter().collect();
+    let mut calendar_ids: HashMap<CalendarKey, String> = HashMap::new();
+    let mut trip_calendars = Vec::with_capacity(services.len());
+    for (service, superseding) in services.iter().zip(superseding_calendars(&services)) {
+        let days = match &service.calendar {
+            Some(days) if service.stops.len() >= 2 => days,
+            _ => {
+                trip_calendars.push(CALENDAR_ID.to_string());
+                continue;
+            }
+        };
+        let key = (days.runs_from, days.runs_to, days.days_run, superseded_dates(days, &superseding));
+        if let Some(id) = calendar_ids.get(&key) {
+            trip_calendars.push(id.clone());
+            continue;
+        }
+
+        let id = format!("C{}", calendar_ids.len());
+        let mut row = vec![id.clone()];
+        row.extend(days.days_run.iter().map(|runs| if *runs { "1" } else { "0" }.to_string()));
+        row.extend([gtfs_date(days.runs_from), gtfs_date(days.runs_to)]);
+        calendar.write_record(&row)?;
+        for date in &key.3 {
+            calendar_dates.write_record(&[id.clone(), gtfs_date(*date), "2".to_string()])?;
+        }
+        trip_calendars.push(id.clone());
+        calendar_ids.insert(key, id);
+    }
+    calendar.flush()?;
+    calendar_dates.flush()?;
+
+    // One route per origin/destination pair
+    let mut routes = BTreeMap::new();
+    for service in rail.timetable.services().iter().filter(|s| s.stops.len() >= 2) {
+        let key = (service.stops[0].station, service.stops.last().unwrap().station);
+        let next_id = routes.len();
+        routes.entry(key).or_insert(next_id);
+    }
+
+    let mut routes_w = create(dir, "routes.txt")?;
+    routes_w.write_record(["route_id", "agency_id", "route_short_name", "route_long_name", "route_type"])?;
+    for ((origin, dest), id) in &routes {
+        let name = format!("{} - {}", rail.stations.get(*origin).unwrap().names[0], rail.stations.get(*dest).unwrap().names[0]);
+        routes_w.write_record(&[id.to_string(), AGENCY_ID.to_string(), format!("{}-{}", crs(*origin), crs(*dest)), name, "2".to_string()])?;
+    }
+    routes_w.flush()?;
+
+    let mut trips = create(dir, "trips.txt")?;
+    let mut times = create(dir, "stop_times.txt")?;
+    trips.write_record(["route_id", "service_id", "trip_id", "trip_short_name"])?;
+    times.write_record(["trip_id", "arrival_time", "departure_time", "stop_id", "stop_sequence"])?;
+    for (service, calendar_id) in rail.timetable.services().iter().zip(&trip_calendars).filter(|(s, _)| s.stops.len() >= 2) {
+        let route = routes[&(service.stops[0].station, service.stops.last().unwrap().station)];
+        trips.write_record(&[route.to_string(), calendar_id.clone(), service.id.to_string(), service.train_uid.clone()])?;
+
+        for (seq, (stop, (arr, dep))) in service.stops.iter().zip(stop_times(service)).enumerate() {
+            times.write_record(&[service.id.to_string(), gtfs_time(arr), gtfs_time(dep), crs(stop.station), seq.to_string()])?;
+        }
+    }
+    trips.flush()?;
+    times.flush()?;
+
+    let mut transfers = create(dir, "transfers.txt")?;
+    transfers.write_record(["from_stop_id", "to_stop_id", "transfer_type", "min_transfer_time"])?;
+    for s in rail.stations.iter() {
+        transfers.write_record(&[s.crs_code.clone(), s.crs_code.clone(), "2".to_string(), (s.min_change_time * 60).to_string()])?;
+    }
+    for link in &rail.fixedlinks {
+        transfers.write_record(&[crs(link.a), crs(link.b), "2".to_string(), link.time.to_string()])?;
+        if !link.one_way {
+            transfers.write_record(&[crs(link.b), crs(link.a), "2".to_string(), link.time.to_string()])?;
+        }
+    }
+    transfers.flush()
+}
+
+/** The files of a feed read by `GtfsFeed`. Only stops.txt, trips.txt and stop_times.txt are required */
+const FEED_FILES: [&str; 7] = ["stops.txt", "routes.txt", "trips.txt", "stop_times.txt", "calendar.txt", "calendar_dates.txt", "transfers.txt"];
+// Change time at stations transfers.txt doesn't give one for, in minutes
+const DEFAULT_CHANGE_MINS: u32 = 5;
+
+/**
+ * A GTFS feed extracted into a directory, read as an alternative to CIF files.
+ * Stops become stations identified by stop ID (in place of both CRS and TIPLOC),
+ * with platforms and other stops inside a station merged into it. Each trip is
+ * a service with the trip ID as its train UID, running on the dates of its GTFS
+ * service, and transfers between stations are one way fixed links. Trips only
+ * given by frequencies.txt aren't supported.
+ */
+pub struct GtfsFeed {
+    dir: PathBuf
+}
+
+impl GtfsFeed {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+}
+
+impl TimetableSource for GtfsFeed {
+    fn name(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn files(&self) -> Vec<String> {
+        FEED_FILES.iter().map(|name| self.dir.join(name)).filter(|path| path.exists()).map(|path| path.display().to_string()).collect()
+    }
+
+    fn read(&self, warnings: &mut Vec<LoadWarning>) -> Result<TimetableData, RailDataError> {
+        read_feed(&self.dir, warnings)
+    }
+}
+
+/** Load services from the GTFS feed in $dir, or its cache while that's up to date */
+pub fn load_gtfs(dir: &Path) -> Result<RailServices, RailDataError> {
+    load_gtfs_with_warnings(dir, &mut Vec::new())
+}
+
+/** As `load_gtfs`, collecting the rows skipped in $warnings */
+pub fn load_gtfs_with_warnings(dir: &Path, warnings: &mut Vec<LoadWarning>) -> Result<RailServices, RailDataError> {
+    load_from(&GtfsFeed::new(dir), warnings)
+}
+
+// A row of a GTFS file, with its fields looked up by column name
+struct Row<'a> {
+    file: &'a str,
+    line: usize,
+    record: &'a csv::StringRecord,
+    columns: &'a HashMap<String, usize>
+}
+
+impl Row<'_> {
+    // The field in $column, empty if the file doesn't have the column
+    fn get(&self, column: &str) -> &str {
+        self.columns.get(column).and_then(|&i| self.record.get(i)).unwrap_or_default().trim()
+    }
+
+    fn require(&self, column: &str) -> Result<&str, RailDataError> {
+        match self.get(column) {
+            "" => Err(RailDataError::InvalidField { field: column.to_string(), value: String::new() }),
+            value => Ok(value)
+        }
+    }
+
+    // None if the field is blank
+    fn parse<T: std::str::FromStr>(&self, column: &str) -> Result<Option<T>, RailDataError> {
+        match self.get(column) {
+            "" => Ok(None),
+            value => value.parse().map(Some).map_err(|_| RailDataError::InvalidField { field: column.to_string(), value: value.to_string() })
+        }
+    }
+
+    fn date(&self, column: &str) -> Result<NaiveDate, RailDataError> {
+        let value = self.require(column)?;
+        NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| RailDataError::InvalidField { field: column.to_string(), value: value.to_string() })
+    }
+
+    // "H:MM:SS" since midnight of the day the trip starts, which may be past 24:00:00. None if blank
+    fn time(&self, column: &str) -> Result<Option<ServiceTime>, RailDataError> {
+        let value = self.get(column);
+        if value.is_empty() {
+            return Ok(None);
+        }
+        let parts: Option<Vec<u32>> = value.split(':')
+            .map(|part| part.bytes().all(|b| b.is_ascii_digit()).then(|| part.parse().ok()).flatten())
+            .collect();
+        match parts.as_deref() {
+            Some(&[hours, mins, secs]) if mins < 60 && secs < 60 => Ok(Some(ServiceTime::default().add(hours*60*60 + mins*60 + secs))),
+            _ => Err(RailDataError::InvalidTime { field: column.to_string(), value: value.to_string() })
+        }
+    }
+
+    fn warning(&self, kind: ParseIssueKind, message: String) -> LoadWarning {
+        LoadWarning::Parse { file: self.file.to_string(), issue: ParseIssue { line: self.line, kind, message } }
+    }
+}
+
+// Call $f with each row of $name in $dir. An $optional file that isn't there has no rows
+fn each_row(dir: &Path, name: &str, optional: bool, mut f: impl FnMut(&Row) -> Result<(), RailDataError>) -> Result<(), RailDataError> {
+    let path = dir.join(name);
+    let file_name = path.display().to_string();
+    if optional && !path.exists() {
+        return Ok(());
+    }
+    let file = File::open(&path).map_err(|e| RailDataError::from(e).in_file(&file_name))?;
+    let csv_err = |e: csv::Error| RailDataError::from(io::Error::other(e)).in_file(&file_name);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(BufReader::new(file));
+    // Some feeds start each file with a byte order mark
+    let columns: HashMap<String, usize> = reader.headers().map_err(csv_err)?.iter().enumerate()
+        .map(|(i, column)| (column.trim_start_matches('\u{feff}').trim().to_string(), i))
+        .collect();
+
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record).map_err(csv_err)? {
+        let line = record.position().map_or(0, |position| position.line() as usize);
+        let row = Row { file: &file_name, line, record: &record, columns: &columns };
+        f(&row).map_err(|e| e.at_record(line, &record.iter().collect::<Vec<_>>().join(",")).in_file(&file_name))?;
+    }
+    Ok(())
+}
+
+// The station each stop ID is, or is in, and the platform if it's one
+type StopIndex = HashMap<String, (StationId, Option<Platform>)>;
+
+// Stations from stops.txt, along with the stops in each. Entrances, nodes and boarding areas are left out
+fn read_stops(dir: &Path, warnings: &mut Vec<LoadWarning>) -> Result<(Vec<Station>, StopIndex), RailDataError> {
+    let mut stations = Vec::new();
+    let mut stops = StopIndex::new();
+    // Stops in a parent station, which may come after them in the file
+    let mut children = Vec::new();
+    each_row(dir, "stops.txt", false, |row| {
+        let id = row.require("stop_id")?.to_string();
+        let parent = row.get("parent_station");
+        match row.get("location_type") {
+            "" | "0" if !parent.is_empty() => {
+                children.push((id, parent.to_string(), Platform::new(row.get("platform_code")), row.warning(ParseIssueKind::UnknownStation(parent.to_string()), String::new())));
+                return Ok(());
+            }
+            "" | "0" | "1" => {}
+            _ => return Ok(())
+        }
+
+        // Kept as a grid reference like MSN locations, so they're less accurate further from Great Britain
+        let (east, north) = match (row.parse("stop_lat")?, row.parse("stop_lon")?) {
+            (Some(lat), Some(lon)) => wgs84_to_osgb36(LatLon { lat, lon }),
+            _ => (0.0, 0.0)
+        };
+        stops.insert(id.clone(), (stations.len(), Platform::new(row.get("platform_code"))));
+        stations.push(Station {
+            id: stations.len(),
+            tiplocs: vec![id.clone()],
+            crs_code: id,
+            names: vec![row.get("stop_name").to_string()],
+            min_change_time: DEFAULT_CHANGE_MINS,
+            gref_east: (east / 100.0).round() as i32,
+            gref_north: (north / 100.0).round() as i32
+        });
+        Ok(())
+    })?;
+
+    for (id, parent, platform, warning) in children {
+        match stops.get(&parent) {
+            Some(&(station, _)) => {
+                stops.insert(id, (station, platform));
+            }
+            None => warnings.push(match warning {
+                LoadWarning::Parse { file, issue } => LoadWarning::Parse {
+                    file,
+                    issue: ParseIssue { message: format!("Stop {} is in unknown station {}", id, parent), ..issue }
+                },
+                other => other
+            })
+        }
+    }
+    Ok((stations, stops))
+}
+
+// Change times within stations from transfers.txt, and one way fixed links for transfers between them
+fn read_transfers(dir: &Path, stations: &mut [Station], stops: &StopIndex, warnings: &mut Vec<LoadWarning>) -> Result<Vec<FixedLink>, RailDataError> {
+    let mut change_times: HashMap<StationId, u32> = HashMap::new();
+    let mut links: BTreeMap<(StationId, StationId), u32> = BTreeMap::new();
+    each_row(dir, "transfers.txt", true, |row| {
+        // Transfers between particular trips or routes, or which aren't possible, don't apply to every train
+        let specific = ["from_trip_id", "to_trip_id", "from_route_id", "to_route_id"].iter().any(|column| !row.get(column).is_empty());
+        let secs: Option<u32> = row.parse("min_transfer_time")?;
+        let secs = match secs {
+            Some(secs) if !specific && row.get("transfer_type") != "3" => secs,
+            _ => return Ok(())
+        };
+        let (from, to) = (row.require("from_stop_id")?, row.require("to_stop_id")?);
+        let (a, b) = match (stops.get(from), stops.get(to)) {
+            (Some(&(a, _)), Some(&(b, _))) => (a, b),
+            _ => {
+                let unknown = if stops.contains_key(from) { to } else { from };
+                warnings.push(row.warning(ParseIssueKind::UnknownStation(unknown.to_string()), format!("Transfer between unknown stop {}", unknown)));
+                return Ok(());
+            }
+        };
+        if a == b {
+            let mins = change_times.entry(a).or_default();
+            *mins = (*mins).max(secs.div_ceil(60));
+        } else {
+            let time = links.entry((a, b)).or_insert(secs);
+            *time = (*time).min(secs);
+        }
+        Ok(())
+    })?;
+
+    for (station, mins) in change_times {
+        stations[station].min_change_time = mins;
+    }
+    Ok(links.into_iter().map(|((a, b), time)| FixedLink { one_way: true, ..FixedLink::simple(a, b, time, FixedLinkKind::Transfer) }).collect())
+}
+
+// The dates each GTFS service runs, from calendar.txt with the exceptions in calendar_dates.txt
+fn read_service_dates(dir: &Path) -> Result<HashMap<String, BTreeSet<NaiveDate>>, RailDataError> {
+    const DAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+    let mut dates: HashMap<String, BTreeSet<NaiveDate>> = HashMap::new();
+    each_row(dir, "calendar.txt", true, |row| {
+        let days_run: Vec<bool> = DAYS.iter().map(|day| row.get(day) == "1").collect();
+        let (start, end) = (row.date("start_date")?, row.date("end_date")?);
+        let running = dates.entry(row.require("service_id")?.to_string()).or_default();
+        running.extend(start.iter_days().take_while(|date| *date <= end).filter(|date| days_run[date.weekday().num_days_from_monday() as usize]));
+        Ok(())
+    })?;
+    each_row(dir, "calendar_dates.txt", true, |row| {
+        let (date, running) = (row.date("date")?, dates.entry(row.require("service_id")?.to_string()).or_default());
+        match row.require("exception_type")? {
+            "1" => running.insert(date),
+            "2" => running.remove(&date),
+            value => return Err(RailDataError::InvalidField { field: "exception_type".to_string(), value: value.to_string() })
+        };
+        Ok(())
+    })?;
+    Ok(dates)
+}
+
+/**
+ * A permanent calendar covering $dates, with the days of the week any of them
+ * fall on, and the ranges of dates it includes which aren't in $dates. Each
+ * range ends before the next date in $dates.
+ */
+fn covering_calendar(dates: &BTreeSet<NaiveDate>) -> Option<(Calendar, Vec<(NaiveDate, NaiveDate)>)> {
+    let (&runs_from, &runs_to) = (dates.first()?, dates.last()?);
+    let mut days_run = [false; 7];
+    for date in dates {
+        days_run[date.weekday().num_days_from_monday() as usize] = true;
+    }
+    let calendar = Calendar { runs_from, runs_to, days_run, stp: Stp::Permanent };
+
+    let mut gaps: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    let mut in_gap = false;
+    for date in runs_from.iter_days().take_while(|date| *date <= runs_to) {
+        if dates.contains(&date) {
+            in_gap = false;
+        } else if calendar.runs_on(date) {
+            match gaps.last_mut() {
+                Some(gap) if in_gap => gap.1 = date,
+                _ => gaps.push((date, date))
+            }
+            in_gap = true;
+        }
+    }
+    Some((calendar, gaps))
+}
+
+// The train category CIF would give services of a GTFS route type, for their mode of transport
+fn route_category(route_type: &str) -> &'static str {
+    match route_type.parse::<u32>() {
+        Ok(3 | 200..=299 | 700..=799) => "BS",
+        Ok(4 | 1000..=1299) => "SS",
+        _ => ""
+    }
+}
+
+// A trip and the stops read for it as (stop_sequence, stop)
+struct Trip {
+    id: String,
+    route: String,
+    service: String,
+    line: usize,
+    stops: Vec<(u32, Stop)>
+}
+
+// Each trip in trips.txt with its stop times
+fn read_trips(dir: &Path, stops: &StopIndex, warnings: &mut Vec<LoadWarning>) -> Result<Vec<Trip>, RailDataError> {
+    let mut trips = Vec::new();
+    let mut by_id = HashMap::new();
+    each_row(dir, "trips.txt", false, |row| {
+        let id = row.require("trip_id")?.to_string();
+        by_id.insert(id.clone(), trips.len());
+        trips.push(Trip { id, route: row.get("route_id").to_string(), service: row.require("service_id")?.to_string(), line: row.line, stops: Vec::new() });
+        Ok(())
+    })?;
+
+    each_row(dir, "stop_times.txt", false, |row| {
+        let trip_id = row.require("trip_id")?;
+        let trip = match by_id.get(trip_id) {
+            Some(&trip) => &mut trips[trip],
+            None => {
+                warnings.push(row.warning(ParseIssueKind::Malformed, format!("Stop time for unknown trip {}", trip_id)));
+                return Ok(());
+            }
+        };
+        let stop_id = row.require("stop_id")?;
+        let (station, platform) = match stops.get(stop_id) {
+            Some(&stop) => stop,
+            None => {
+                warnings.push(row.warning(ParseIssueKind::UnknownStation(stop_id.to_string()), format!("Trip {} calls at unknown stop {}", trip.id, stop_id)));
+                return Ok(());
+            }
+        };
+        let sequence = row.parse("stop_sequence")?.ok_or_else(|| RailDataError::InvalidField { field: "stop_sequence".to_string(), value: String::new() })?;
+        // Stops between timepoints may be left untimed, and aren't somewhere to board or alight by a known time
+        let (arrival, departure) = match (row.time("arrival_time")?, row.time("departure_time")?) {
+            (Some(arrival), Some(departure)) => (arrival, departure),
+            (Some(time), None) | (None, Some(time)) => (time, time),
+            (None, None) => return Ok(())
+        };
+        let activity = match (row.get("pickup_type") == "1", row.get("drop_off_type") == "1") {
+            (false, false) => StopActivity::Normal,
+            (true, false) => StopActivity::SetDownOnly,
+            (false, true) => StopActivity::PickUpOnly,
+            (true, true) => return Ok(())
+        };
+        trip.stops.push((sequence, Stop { station, arrival, departure, platform, activity }));
+        Ok(())
+    })?;
+    Ok(trips)
+}
+
+// Read the feed in $dir, giving each trip the calendar of its dates and cancelling the dates it doesn't run within that
+fn read_feed(dir: &Path, warnings: &mut Vec<LoadWarning>) -> Result<TimetableData, RailDataError> {
+    let (mut stations, stops) = read_stops(dir, warnings)?;
+    let fixedlinks = read_transfers(dir, &mut stations, &stops, warnings)?;
+    let stations = StationList::new(stations);
+    let dates = read_service_dates(dir)?;
+
+    let mut routes = HashMap::new();
+    each_row(dir, "routes.txt", true, |row| {
+        routes.insert(row.require("route_id")?.to_string(), (intern(row.get("agency_id")), route_category(row.get("route_type"))));
+        Ok(())
+    })?;
+
+    let trips_file = dir.join("trips.txt").display().to_string();
+    let mut services = Vec::new();
+    let mut cancellations = Vec::new();
+    for mut trip in read_trips(dir, &stops, warnings)? {
+        let (calendar, gaps) = match dates.get(&trip.service).and_then(covering_calendar) {
+            Some(calendar) => calendar,
+            None => {
+                let message = format!("Trip {} has no dates in service {}", trip.id, trip.service);
+                warnings.push(LoadWarning::Parse { file: trips_file.clone(), issue: ParseIssue { line: trip.line, kind: ParseIssueKind::Malformed, message } });
+                continue;
+            }
+        };
+        let (operator, category) = routes.get(&trip.route).copied().unwrap_or(("", ""));
+        trip.stops.sort_by_key(|(sequence, _)| *sequence);
+        let stops = trip.stops.drain(..).map(|(_, stop)| stop).collect();
+        let service = |stops, calendar| Service {
+            id: 0,
+            train_uid: trip.id.clone(),
+            stops,
+            reservations: Reservations::None,
+            operator,
+            category,
+            power_type: "",
+            seating: Accommodation::FirstAndStandard,
+            sleepers: Accommodation::None,
+            bank_holidays: BankHolidayRunning::Runs,
+            calendar: Some(calendar)
+        };
+        cancellations.extend(gaps.into_iter().map(|(runs_from, runs_to)| {
+            service(Vec::new(), Calendar { runs_from, runs_to, days_run: [true; 7], stp: Stp::Cancellation })
+        }));
+        services.push(service(stops, calendar));
+    }
+
+    services.append(&mut cancellations);
+    for (id, service) in services.iter_mut().enumerate() {
+        service.id = id as u32;
+    }
+    Ok(TimetableData { stations, fixedlinks, timetable: Timetable::new(None, services, Vec::new()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::{Station, StationList};
+    use raildata_core::timetable::{Stop, Stp, Timetable};
+    use raildata_routing::TravelGraph;
+
+    #[test]
+    fn test_stop_times_past_midnight() {
+        let service = Service::simple(0, "C12345", vec![
+            Stop::simple(0, "2330", "2330"),
+            Stop::simple(1, "2355", "2405"),
+            Stop::simple(2, "2530", "2530")
+        ]);
+
+        let times: Vec<(String, String)> = stop_times(&service).iter()
+            .map(|(arr, dep)| (gtfs_time(*arr), gtfs_time(*dep)))
+            .collect();
+        assert_eq!(times, vec![
+            ("23:30:00".to_string(), "23:30:00".to_string()),
+            ("23:55:00".to_string(), "24:05:00".to_string()),
+            ("25:30:00".to_string(), "25:30:00".to_string())
+        ]);
+    }
+
+    #[test]
+    fn test_calendars() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let date = |day| NaiveDate::from_ymd_opt(2020, 9, day).unwrap();
+        let service = |id, uid: &str, depart, calendar| Service {
+            calendar,
+            ..Service::simple(id, uid, vec![Stop::simple(0, depart, depart), Stop::simple(1, "1050", "1050")])
+        };
+        let weekdays = |from, to, stp| Some(Calendar { runs_from: date(from), runs_to: date(to), days_run: [true, true, true, true, true, false, false], stp });
+        // Two weekday trains in September, one overlaid on the 15th by a later departure
+        let timetable = Timetable::new(None, vec![
+            service(0, "C00001", "1000", weekdays(1, 30, Stp::Permanent)),
+            service(1, "C00002", "0900", weekdays(1, 30, Stp::Permanent)),
+            service(2, "C00001", "1005", weekdays(15, 15, Stp::Overlay)),
+            service(3, "C00003", "1100", None)
+        ], Vec::new());
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let rail = RailServices { stations, fixedlinks: Vec::new(), timetable, graph };
+
+        let dir = std::env::temp_dir().join(format!("raildata-gtfs-{}", std::process::id()));
+        write_gtfs(&rail, &dir).unwrap();
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        let (calendar, calendar_dates, trips) = (read("calendar.txt"), read("calendar_dates.txt"), read("trips.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(calendar.lines().collect::<Vec<_>>(), vec![
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date",
+            "ALL,1,1,1,1,1,1,1,20000101,20991231",
+            "C0,1,1,1,1,1,0,0,20200901,20200930",
+            "C1,1,1,1,1,1,0,0,20200901,20200930",
+            "C2,1,1,1,1,1,0,0,20200915,20200915"
+        ]);
+        assert_eq!(calendar_dates.lines().collect::<Vec<_>>(), vec!["service_id,date,exception_type", "C0,20200915,2"]);
+        let trip_calendars: Vec<&str> = trips.lines().skip(1).map(|line| line.split(',').nth(1).unwrap()).collect();
+        assert_eq!(trip_calendars, vec!["C0", "C1", "C2", "ALL"]);
+    }
+
+    #[test]
+    fn test_read_feed() {
+        let dir = std::env::temp_dir().join(format!("raildata-gtfs-read-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name, lines: &[&str]| std::fs::write(dir.join(name), lines.join("\n")).unwrap();
+        write("stops.txt", &[
+            "\u{feff}stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station,platform_code",
+            "CBG,Cambridge,52.194,0.137,1,,",
+            "CBG1,Cambridge Platform 1,52.194,0.137,0,CBG,1",
+            "CBGE,Cambridge Entrance,52.194,0.137,2,CBG,",
+            "ELY,Ely,52.391,0.266,0,,",
+            "KGX,London Kings Cross,51.532,-0.123,,,",
+            "XXX1,Nowhere Platform 1,,,0,XXX,1"
+        ]);
+        write("routes.txt", &["route_id,agency_id,route_type", "R1,GN,2", "R2,GN,3"]);
+        write("trips.txt", &["route_id,service_id,trip_id", "R1,WEEK,T1", "R2,SAT,T2", "R1,NEVER,T3"]);
+        write("stop_times.txt", &[
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,pickup_type,drop_off_type",
+            "T1,24:55:00,25:00:00,KGX,3,0,1",
+            "T1,23:30:00,23:30:00,CBG1,1,,",
+            "T1,,,ELY,2,,",
+            "T1,23:45:00,23:45:00,MIA,4,,",
+            "T2,10:00:00,10:00:00,ELY,1,,",
+            "T2,10:30:00,,CBG,2,,"
+        ]);
+        // Weekdays in the first half of September, except the 7th and 8th, and one Saturday
+        write("calendar.txt", &[
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date",
+            "WEEK,1,1,1,1,1,0,0,20200901,20200915"
+        ]);
+        write("calendar_dates.txt", &["service_id,date,exception_type", "WEEK,20200907,2", "WEEK,20200908,2", "SAT,20200905,1"]);
+        write("transfers.txt", &[
+            "from_stop_id,to_stop_id,transfer_type,min_transfer_time",
+            "CBG1,CBG,2,400",
+            "CBG,ELY,2,1200",
+            "ELY,KGX,3,600"
+        ]);
+        let mut warnings = Vec::new();
+        let data = GtfsFeed::new(&dir).read(&mut warnings);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let data = data.unwrap();
+
+        let names: Vec<&str> = data.stations.iter().map(|s| s.crs_code.as_str()).collect();
+        assert_eq!(names, vec!["CBG", "ELY", "KGX"]);
+        let cambridge = data.stations.get_by_crs("CBG").unwrap();
+        assert_eq!(cambridge.min_change_time, 7);
+        assert!((5400..5500).contains(&cambridge.gref_east) && (2560..2590).contains(&cambridge.gref_north));
+        assert_eq!(data.fixedlinks, vec![FixedLink { one_way: true, ..FixedLink::simple(0, 1, 1200, FixedLinkKind::Transfer) }]);
+
+        let services = data.timetable.services();
+        assert_eq!(services.iter().map(|s| (s.id, s.train_uid.as_str(), s.stops.len())).collect::<Vec<_>>(), vec![(0, "T1", 2), (1, "T2", 2), (2, "T1", 0)]);
+        assert_eq!((services[0].stops[0].station, services[0].stops[0].platform), (0, Platform::new("1")));
+        assert_eq!((services[0].stops[1].arrival.day(), services[0].stops[1].departure.to_24h()), (1, "0100".to_string()));
+        assert_eq!(services[0].stops[1].activity, StopActivity::PickUpOnly);
+        assert_eq!((services[0].operator, services[0].category, services[1].category), ("GN", "", "BS"));
+        assert_eq!(services[1].stops[1].departure.to_24h(), "1030");
+
+        let date = |day| NaiveDate::from_ymd_opt(2020, 9, day).unwrap();
+        assert_eq!(services[2].calendar, Some(Calendar { runs_from: date(7), runs_to: date(8), days_run: [true; 7], stp: Stp::Cancellation }));
+        let running = |day| data.timetable.services_on(date(day)).iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!((running(4), running(5), running(7), running(9)), (vec![0], vec![1], vec![], vec![0]));
+
+        // The platform of an unknown station, the call at an unknown stop and the trip that never runs
+        let issues: Vec<ParseIssueKind> = warnings.into_iter().map(|w| match w {
+            LoadWarning::Parse { issue, .. } => issue.kind,
+            _ => panic!("Unexpected warning")
+        }).collect();
+        assert_eq!(issues, vec![
+            ParseIssueKind::UnknownStation("XXX".to_string()),
+            ParseIssueKind::UnknownStation("MIA".to_string()),
+            ParseIssueKind::Malformed
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let date = |day| NaiveDate::from_ymd_opt(2020, 9, day).unwrap();
+        let timetable = Timetable::new(None, vec![Service {
+            calendar: Some(Calendar { runs_from: date(1), runs_to: date(30), days_run: [true; 7], stp: Stp::Permanent }),
+            ..Service::simple(0, "C00001", vec![Stop::simple(0, "2350", "2350"), Stop::simple(1, "2440", "2440")])
+        }], Vec::new());
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let rail = RailServices { stations, fixedlinks: vec![FixedLink::simple(0, 1, 30*60, FixedLinkKind::Walk)], timetable, graph };
+
+        let dir = std::env::temp_dir().join(format!("raildata-gtfs-round-trip-{}", std::process::id()));
+        write_gtfs(&rail, &dir).unwrap();
+        let data = GtfsFeed::new(&dir).read(&mut Vec::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let data = data.unwrap();
+
+        assert_eq!(data.stations.get_by_crs("KGX").unwrap().names, vec!["London Kings Cross".to_string()]);
+        assert_eq!(data.fixedlinks.len(), 2);
+        let service = &data.timetable.services()[0];
+        assert_eq!((service.stops[1].arrival.day(), service.stops[1].arrival.to_24h()), (1, "0040".to_string()));
+        assert_eq!(service.calendar, rail.timetable.services()[0].calendar);
+    }
+}
@@ -0,0 +1,228 @@
+/* Copyright James Lomax 2020 */
+
+// Travel time heatmaps. The time to reach each pixel is estimated from the
+// stations around it: the journey time to a station plus the time to get from
+// there at an access speed, e.g. by bus or bike. Images are in Web Mercator so
+// tiles line up with slippy maps such as OpenStreetMap.
+
+use std::f64::consts::PI;
+#[cfg(feature = "png")]
+use std::io;
+
+use raildata_core::geo::LatLon;
+use raildata_core::stations::StationList;
+
+use crate::render::{Rgb, BAND_COLOURS};
+
+// Metres around the equator, the width of a Web Mercator map at the equator
+const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
+/** Width and height of slippy map tiles in pixels */
+pub const TILE_SIZE: u32 = 256;
+
+/** How travel times spread from stations and are coloured */
+#[derive(Debug, Clone)]
+pub struct HeatmapStyle {
+    /** Speed getting from a station to the final destination in metres per second */
+    pub access_speed: f64,
+    /** Longest time (seconds) spent getting from a station */
+    pub max_access: u32,
+    /** Travel time (seconds) coloured at the red end of the scale, beyond which nothing is drawn */
+    pub max_time: u32,
+    /** Opacity of the coloured pixels, so tiles can be laid over a basemap */
+    pub alpha: u8
+}
+
+impl Default for HeatmapStyle {
+    /** 20km/h for up to half an hour from a station, coloured up to 4 hours */
+    fn default() -> Self {
+        Self {
+            access_speed: 20.0 / 3.6,
+            max_access: 30*60,
+            max_time: 4*3600,
+            alpha: 180
+        }
+    }
+}
+
+/** An RGBA image with rows from the top, not premultiplied */
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+impl Raster {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = 4 * (y * self.width + x) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    #[cfg(feature = "png")]
+    pub fn encode_png(&self) -> io::Result<Vec<u8>> {
+        use tiny_skia::{IntSize, Pixmap};
+
+        // tiny-skia stores premultiplied alpha
+        let premultiply = |c: u8, a: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+        let data = self.pixels.chunks(4)
+            .flat_map(|p| [premultiply(p[0], p[3]), premultiply(p[1], p[3]), premultiply(p[2], p[3]), p[3]])
+            .collect();
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid image size");
+        let size = IntSize::from_wh(self.width, self.height).ok_or_else(invalid)?;
+        let pixmap = Pixmap::from_vec(data, size).ok_or_else(invalid)?;
+        pixmap.encode_png().map_err(io::Error::other)
+    }
+}
+
+/** Web Mercator position with x east from 180W and y south from 85N, both 0 to 1 */
+fn mercator(ll: LatLon) -> (f64, f64) {
+    let lat = ll.lat.to_radians();
+    ((ll.lon + 180.0) / 360.0, (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0)
+}
+
+// Metres per unit of Web Mercator distance at $y
+fn metres_per_unit(y: f64) -> f64 {
+    EARTH_CIRCUMFERENCE * (PI * (1.0 - 2.0 * y)).sinh().atan().cos()
+}
+
+fn lerp(a: Rgb, b: Rgb, f: f64) -> Rgb {
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+    (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+/** Travel times from an origin spread out from the stations reached, to draw as images or tiles */
+pub struct Heatmap {
+    // Stations reached as Web Mercator x, y and journey time, sorted by y
+    points: Vec<(f64, f64, u32)>,
+    style: HeatmapStyle
+}
+
+impl Heatmap {
+    /** From journey times (seconds) indexed by station, as from TravelGraph::compute_all_times */
+    pub fn new(stations: &StationList, times: &[Option<u32>], style: HeatmapStyle) -> Self {
+        let mut points: Vec<(f64, f64, u32)> = stations.iter()
+            .filter_map(|s| {
+                let time = times.get(s.id).copied().flatten().filter(|t| *t <= style.max_time)?;
+                let (x, y) = mercator(s.lat_lon()?);
+                Some((x, y, time))
+            })
+            .collect();
+        points.sort_by(|a, b| a.1.total_cmp(&b.1));
+        Self { points, style }
+    }
+
+    // Web Mercator distance covered in the longest access time at $y
+    fn reach(&self, y: f64) -> f64 {
+        self.style.max_access as f64 * self.style.access_speed / metres_per_unit(y)
+    }
+
+    fn estimate(&self, x: f64, y: f64) -> Option<u32> {
+        let (scale, reach) = (metres_per_unit(y), self.reach(y));
+        let start = self.points.partition_point(|p| p.1 < y - reach);
+        self.points[start..].iter()
+            .take_while(|p| p.1 <= y + reach)
+            .filter_map(|&(px, py, time)| {
+                let access = ((px - x).powi(2) + (py - y).powi(2)).sqrt() * scale / self.style.access_speed;
+                (access <= self.style.max_access as f64).then(|| time + access as u32)
+            })
+            .min()
+            .filter(|t| *t <= self.style.max_time)
+    }
+
+    /** Estimated travel time in seconds to $ll, None if it's too far from any station reached */
+    pub fn time_at(&self, ll: LatLon) -> Option<u32> {
+        let (x, y) = mercator(ll);
+        self.estimate(x, y)
+    }
+
+    fn colour(&self, time: u32) -> [u8; 4] {
+        let f = time as f64 / self.style.max_time.max(1) as f64 * (BAND_COLOURS.len() - 1) as f64;
+        let i = (f as usize).min(BAND_COLOURS.len() - 2);
+        let c = lerp(BAND_COLOURS[i], BAND_COLOURS[i + 1], f - i as f64);
+        [c.0, c.1, c.2, self.style.alpha]
+    }
+
+    // Draw the Web Mercator area from ($west, $north) to ($east, $south), transparent where there's no estimate
+    fn raster(&self, (west, north): (f64, f64), (east, south): (f64, f64), width: u32, height: u32) -> Raster {
+        let mut pixels = Vec::with_capacity(4 * (width * height) as usize);
+        for row in 0..height {
+            let y = north + (row as f64 + 0.5) / height as f64 * (south - north);
+            for col in 0..width {
+                let x = west + (col as f64 + 0.5) / width as f64 * (east - west);
+                pixels.extend(self.estimate(x, y).map_or([0; 4], |t| self.colour(t)));
+            }
+        }
+        Raster { width, height, pixels }
+    }
+
+    /** Slippy map tile $x, $y at zoom level $z (at most 30), TILE_SIZE pixels square */
+    pub fn tile(&self, z: u32, x: u32, y: u32) -> Raster {
+        let n = (1u64 << z) as f64;
+        let (west, north) = (x as f64 / n, y as f64 / n);
+        self.raster((west, north), (west + 1.0 / n, north + 1.0 / n), TILE_SIZE, TILE_SIZE)
+    }
+
+    /** The whole area reached, $width pixels across with the height to keep its shape */
+    pub fn image(&self, width: u32) -> Raster {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for &(x, y, _) in &self.points {
+            let reach = self.reach(y);
+            min_x = min_x.min(x - reach);
+            max_x = max_x.max(x + reach);
+            min_y = min_y.min(y - reach);
+            max_y = max_y.max(y + reach);
+        }
+        if self.points.is_empty() {
+            return Raster { width, height: width, pixels: vec![0; 4 * (width * width) as usize] };
+        }
+
+        let height = ((max_y - min_y) / (max_x - min_x) * width as f64).round().max(1.0) as u32;
+        self.raster((min_x, min_y), (max_x, max_y), width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+
+    fn located(tiploc: &str, crs: &str, east: i32, north: i32) -> Station {
+        let mut s = Station::simple(tiploc, tiploc, crs);
+        s.gref_east = east;
+        s.gref_north = north;
+        s
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let stations = StationList::new(vec![
+            located("KNGX", "KGX", 5303, 1830),
+            located("CAMBDGE", "CBG", 5462, 2573),
+            located("ABRDEEN", "ABD", 3942, 8058)
+        ]);
+        let times = vec![Some(0), Some(50*60), None];
+        let heatmap = Heatmap::new(&stations, &times, HeatmapStyle::default());
+
+        let kgx = stations.get(0).unwrap().lat_lon().unwrap();
+        assert_eq!(heatmap.time_at(kgx), Some(0));
+        // Around 5km north is 15 minutes at 20km/h
+        let near = heatmap.time_at(LatLon { lat: kgx.lat + 0.045, lon: kgx.lon }).unwrap();
+        assert!((870..=930).contains(&near), "{}", near);
+        // Beyond half an hour from both stations, and nothing around Aberdeen as it wasn't reached
+        assert_eq!(heatmap.time_at(LatLon { lat: 51.9, lon: -1.0 }), None);
+        assert_eq!(heatmap.time_at(stations.get(2).unwrap().lat_lon().unwrap()), None);
+
+        // The zoom 10 tile with Kings Cross is green around the station and clear in the corner
+        let (z, x, y) = (10, 511, 340);
+        let tile = heatmap.tile(z, x, y);
+        let (kx, ky) = mercator(kgx);
+        let px = ((kx * 1024.0 - x as f64) * TILE_SIZE as f64) as u32;
+        let py = ((ky * 1024.0 - y as f64) * TILE_SIZE as f64) as u32;
+        assert_eq!(tile.pixel(px, py), [26, 152, 80, 180]);
+
+        let image = heatmap.image(100);
+        assert_eq!(image.width, 100);
+        assert!(image.height > 100, "Cambridge is further north than east of London");
+        assert_eq!(image.pixel(0, 0), [0; 4]);
+    }
+}
@@ -0,0 +1,11 @@
+/** Copyright James Lomax 2020 */
+
+pub mod export;
+pub mod render;
+pub mod compact;
+#[cfg(feature = "gtfs")]
+pub mod gtfs;
+#[cfg(feature = "geojson")]
+pub mod geojson;
+#[cfg(feature = "heatmap")]
+pub mod heatmap;
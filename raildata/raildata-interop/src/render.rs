@@ -0,0 +1,248 @@
+/** Copyright James Lomax 2020 */
+
+use std::io::{self, Write};
+
+use raildata_core::stations::{StationId, StationList};
+use raildata_routing::travel_graph::{Journey, Link};
+
+/** What to draw over the basemap of all stations */
+pub enum Overlay<'a> {
+    /** Routes of journeys, drawn as lines through the stations they call at */
+    Journeys(&'a [Journey]),
+    /**
+     * Journey times (seconds) to each station as from TravelGraph::compute_all_times,
+     * coloured by the first band (upper bound in minutes) they fall in
+     */
+    Isochrone { times: &'a [Option<u32>], bands: &'a [u32] }
+}
+
+pub(crate) type Rgb = (u8, u8, u8);
+
+const BACKGROUND: Rgb = (255, 255, 255);
+const GRID: Rgb = (225, 225, 225);
+const STATION: Rgb = (190, 190, 190);
+const ROUTE: Rgb = (200, 30, 30);
+const ROUTE_STOP: Rgb = (40, 40, 40);
+
+// Green through to red for increasing isochrone bands
+pub(crate) const BAND_COLOURS: [Rgb; 6] = [(26, 152, 80), (145, 207, 96), (217, 239, 139), (254, 224, 139), (252, 141, 89), (215, 48, 39)];
+
+// National Grid squares are 100km, grid references are in units of 100m
+const GRID_SPACING: i32 = 1000;
+const MARGIN: f64 = 10.0;
+
+enum Shape {
+    Line { from: (f64, f64), to: (f64, f64), colour: Rgb, width: f64 },
+    Dot { at: (f64, f64), colour: Rgb, radius: f64 },
+    Label { at: (f64, f64), text: String }
+}
+
+/** Maps grid references onto the image, keeping the aspect ratio and flipping north upwards */
+struct Projection {
+    min_east: f64,
+    max_north: f64,
+    scale: f64
+}
+
+impl Projection {
+    fn fit(stations: &StationList, width: u32, height: u32) -> Self {
+        // Stations without a location have a zero grid reference
+        let located = stations.iter().filter(|s| s.gref_east != 0 || s.gref_north != 0);
+        let (mut min_e, mut max_e, mut min_n, mut max_n) = (i32::MAX, i32::MIN, i32::MAX, i32::MIN);
+        for s in located {
+            min_e = min_e.min(s.gref_east);
+            max_e = max_e.max(s.gref_east);
+            min_n = min_n.min(s.gref_north);
+            max_n = max_n.max(s.gref_north);
+        }
+        if min_e > max_e {
+            // Nothing to fit, use the whole of Great Britain
+            (min_e, max_e, min_n, max_n) = (0, 7000, 0, 12500);
+        }
+
+        let span_e = ((max_e - min_e) as f64).max(1.0);
+        let span_n = ((max_n - min_n) as f64).max(1.0);
+        let scale = ((width as f64 - 2.0 * MARGIN) / span_e).min((height as f64 - 2.0 * MARGIN) / span_n);
+        Self {
+            min_east: min_e as f64 - MARGIN / scale,
+            max_north: max_n as f64 + MARGIN / scale,
+            scale
+        }
+    }
+
+    fn point(&self, east: i32, north: i32) -> (f64, f64) {
+        ((east as f64 - self.min_east) * self.scale, (self.max_north - north as f64) * self.scale)
+    }
+
+    fn station(&self, stations: &StationList, id: StationId) -> (f64, f64) {
+        let s = stations.get(id).unwrap();
+        self.point(s.gref_east, s.gref_north)
+    }
+}
+
+/** Lay out the basemap (grid and all stations) followed by the overlay */
+fn scene(stations: &StationList, overlay: &Overlay, width: u32, height: u32) -> Vec<Shape> {
+    let proj = Projection::fit(stations, width, height);
+    let mut shapes = Vec::new();
+
+    let east_lines = (0..).map(|i| i * GRID_SPACING).take_while(|e| proj.point(*e, 0).0 < width as f64);
+    for east in east_lines {
+        let x = proj.point(east, 0).0;
+        if x >= 0.0 {
+            shapes.push(Shape::Line { from: (x, 0.0), to: (x, height as f64), colour: GRID, width: 1.0 });
+        }
+    }
+    let north_lines = (0..).map(|i| i * GRID_SPACING).take_while(|n| proj.point(0, *n).1 > 0.0);
+    for north in north_lines {
+        let y = proj.point(0, north).1;
+        if y <= height as f64 {
+            shapes.push(Shape::Line { from: (0.0, y), to: (width as f64, y), colour: GRID, width: 1.0 });
+        }
+    }
+
+    for s in stations.iter().filter(|s| s.gref_east != 0 || s.gref_north != 0) {
+        shapes.push(Shape::Dot { at: proj.point(s.gref_east, s.gref_north), colour: STATION, radius: 1.0 });
+    }
+
+    match overlay {
+        Overlay::Journeys(journeys) => {
+            for journey in journeys.iter() {
+                let mut calls = vec![journey.origin];
+                calls.extend(journey.links.iter().filter_map(|link| match link {
+                    Link::Rail(rl) => Some(rl.dst),
+                    Link::Fixed(fl) => Some(fl.dst),
+                    Link::Dummy => None
+                }));
+
+                for pair in calls.windows(2) {
+                    let (from, to) = (proj.station(stations, pair[0]), proj.station(stations, pair[1]));
+                    shapes.push(Shape::Line { from, to, colour: ROUTE, width: 3.0 });
+                }
+                for id in &calls {
+                    let at = proj.station(stations, *id);
+                    shapes.push(Shape::Dot { at, colour: ROUTE_STOP, radius: 3.0 });
+                    shapes.push(Shape::Label { at: (at.0 + 5.0, at.1 - 5.0), text: stations.get(*id).unwrap().crs_code.clone() });
+                }
+            }
+        }
+        Overlay::Isochrone { times, bands } => {
+            let mut bands = bands.to_vec();
+            bands.sort_unstable();
+            // Draw the furthest stations first so nearer ones stay visible
+            let mut reached: Vec<(usize, StationId)> = times.iter().enumerate()
+                .filter_map(|(id, t)| t.and_then(|t| bands.iter().position(|b| t / 60 <= *b)).map(|band| (band, id)))
+                .collect();
+            reached.sort_by(|a, b| b.cmp(a));
+
+            for (band, id) in reached {
+                let colour = BAND_COLOURS[(band * BAND_COLOURS.len() / bands.len()).min(BAND_COLOURS.len() - 1)];
+                shapes.push(Shape::Dot { at: proj.station(stations, id), colour, radius: 3.0 });
+            }
+        }
+    }
+
+    shapes
+}
+
+fn svg_colour(c: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.0, c.1, c.2)
+}
+
+/** Draw $overlay over a basemap of all stations as an SVG image */
+pub fn render_svg<W: Write>(w: &mut W, stations: &StationList, overlay: &Overlay, width: u32, height: u32) -> io::Result<()> {
+    writeln!(w, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">", width, height, width, height)?;
+    writeln!(w, "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>", svg_colour(BACKGROUND))?;
+    for shape in scene(stations, overlay, width, height) {
+        match shape {
+            Shape::Line { from, to, colour, width } => writeln!(w,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"/>",
+                from.0, from.1, to.0, to.1, svg_colour(colour), width)?,
+            Shape::Dot { at, colour, radius } => writeln!(w,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\"/>", at.0, at.1, radius, svg_colour(colour))?,
+            Shape::Label { at, text } => writeln!(w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" font-family=\"sans-serif\" font-size=\"10\">{}</text>", at.0, at.1, text)?
+        }
+    }
+    writeln!(w, "</svg>")
+}
+
+/**
+ * Draw $overlay over a basemap of all stations as a PNG image at $path.
+ * tiny-skia has no text rendering so station labels are left out.
+ */
+#[cfg(feature = "png")]
+pub fn render_png(path: &std::path::Path, stations: &StationList, overlay: &Overlay, width: u32, height: u32) -> io::Result<()> {
+    use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    let mut pixmap = Pixmap::new(width, height).ok_or_else(|| invalid("Invalid image size"))?;
+    pixmap.fill(Color::from_rgba8(BACKGROUND.0, BACKGROUND.1, BACKGROUND.2, 255));
+
+    let paint = |c: Rgb| {
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(c.0, c.1, c.2, 255);
+        paint.anti_alias = true;
+        paint
+    };
+
+    for shape in scene(stations, overlay, width, height) {
+        match shape {
+            Shape::Line { from, to, colour, width } => {
+                let mut pb = PathBuilder::new();
+                pb.move_to(from.0 as f32, from.1 as f32);
+                pb.line_to(to.0 as f32, to.1 as f32);
+                if let Some(path) = pb.finish() {
+                    let stroke = Stroke { width: width as f32, ..Stroke::default() };
+                    pixmap.stroke_path(&path, &paint(colour), &stroke, Transform::identity(), None);
+                }
+            }
+            Shape::Dot { at, colour, radius } => {
+                if let Some(path) = PathBuilder::from_circle(at.0 as f32, at.1 as f32, radius as f32) {
+                    pixmap.fill_path(&path, &paint(colour), FillRule::Winding, Transform::identity(), None);
+                }
+            }
+            Shape::Label { .. } => {}
+        }
+    }
+
+    pixmap.save_png(path).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+    use raildata_core::timetable::RailTime;
+    use raildata_routing::travel_graph::RailLink;
+
+    fn located(tiploc: &str, crs: &str, east: i32, north: i32) -> Station {
+        let mut s = Station::simple(tiploc, tiploc, crs);
+        s.gref_east = east;
+        s.gref_north = north;
+        s
+    }
+
+    #[test]
+    fn test_render_journey_svg() {
+        let stations = StationList::new(vec![
+            located("CAMBDGE", "CBG", 5462, 2573),
+            located("KINGSX", "KGX", 5303, 1830)
+        ]);
+        let journeys = vec![Journey {
+            origin: 0,
+            depart: RailTime::new(9, 0),
+            time: 50*60,
+            links: vec![Link::Rail(RailLink { dst: 1, service: 0, depart: RailTime::new(9, 0).into(), time: 50*60, depart_platform: None, arrive_platform: None, calls: Box::default() })]
+        }];
+
+        let mut out = Vec::new();
+        render_svg(&mut out, &stations, &Overlay::Journeys(&journeys), 200, 400).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+
+        // Cambridge is north east of Kings Cross so should be up and to the right
+        assert!(svg.contains("<line x1=\"91.3\" y1=\"10.0\" x2=\"10.0\" y2=\"390.0\""));
+        assert!(svg.contains(">CBG</text>"));
+        assert!(svg.contains(">KGX</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
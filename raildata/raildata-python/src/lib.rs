@@ -0,0 +1,226 @@
+/* Copyright James Lomax 2020 */
+
+// Python bindings for batch analyses from pandas and notebooks, built as the
+// `raildata` module with maturin (see pyproject.toml). Results are plain dicts
+// and lists; results for every station are lists indexed by station ID, lined
+// up with the columns from `stations()`, so they go straight into a DataFrame
+// or NumPy array.
+
+use std::sync::Mutex;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use raildata::config::RoutingConfig;
+use raildata::overlay::parse_date;
+use raildata::{load_dataset, Journey, JourneyConstraints, Link, LookupOptions, QueryScratch, RailServices, RailTime, Station, StationId, StationList};
+
+fn crs(stations: &StationList, id: StationId) -> &str {
+    &stations.get(id).unwrap().crs_code
+}
+
+fn hh_mm(t: &RailTime) -> String {
+    let s = t.to_24h();
+    format!("{}:{}", &s[0..2], &s[2..4])
+}
+
+// $s as HHMM or HH:MM
+fn parse_time(s: &str) -> PyResult<RailTime> {
+    RailTime::from_24h(&s.replace(':', "")).ok_or_else(|| PyValueError::new_err(format!("Could not parse time {}", s)))
+}
+
+fn station_dict<'py>(py: Python<'py>, station: &Station) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("id", station.id)?;
+    dict.set_item("crs", &station.crs_code)?;
+    dict.set_item("name", &station.names[0])?;
+    dict.set_item("tiplocs", &station.tiplocs)?;
+    let location = station.lat_lon();
+    dict.set_item("lat", location.map(|ll| ll.lat))?;
+    dict.set_item("lon", location.map(|ll| ll.lon))?;
+    Ok(dict)
+}
+
+// $journey with the fields of the server's v2 journeys, None if it couldn't be made
+fn journey_dict<'py>(py: Python<'py>, stations: &StationList, journey: &Journey) -> PyResult<Option<Bound<'py, PyDict>>> {
+    if journey.time == u32::MAX {
+        return Ok(None);
+    }
+    let mut legs = Vec::new();
+    for leg in journey.legs() {
+        let (mode, service) = match leg.link {
+            Link::Rail(rl) => ("rail".to_string(), Some(rl.service)),
+            Link::Fixed(fl) => (format!("{:?}", fl.kind).to_lowercase(), None),
+            Link::Dummy => continue
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("mode", mode)?;
+        dict.set_item("from", crs(stations, leg.from))?;
+        dict.set_item("to", crs(stations, leg.to))?;
+        dict.set_item("depart", hh_mm(&leg.depart))?;
+        dict.set_item("depart_day", leg.depart_day)?;
+        dict.set_item("arrive", hh_mm(&leg.arrive))?;
+        dict.set_item("arrive_day", leg.arrive_day)?;
+        dict.set_item("wait", leg.wait)?;
+        dict.set_item("service", service)?;
+        legs.push(dict);
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("duration", journey.time)?;
+    dict.set_item("changes", journey.changes())?;
+    dict.set_item("legs", legs)?;
+    Ok(Some(dict))
+}
+
+/** Loaded services, searched from Python. Searches release the GIL, so other Python threads carry on meanwhile */
+#[pyclass(name = "RailServices", frozen)]
+pub struct PyRailServices {
+    rail: RailServices,
+    scratch: Mutex<QueryScratch>
+}
+
+impl PyRailServices {
+    pub fn new(rail: RailServices) -> Self {
+        let scratch = Mutex::new(QueryScratch::new(&rail.graph));
+        Self { rail, scratch }
+    }
+
+    fn station_id(&self, code: &str) -> PyResult<StationId> {
+        self.rail.stations.get_by_crs(&code.to_uppercase()).map(|station| station.id)
+            .ok_or_else(|| PyValueError::new_err(format!("Could not find CRS {}", code)))
+    }
+
+    // Search parameters in seconds, defaulting to the usual routing settings
+    fn routing(contingency_mins: Option<u32>, flexi_depart_mins: Option<u32>) -> (u32, u32) {
+        let defaults = RoutingConfig::default();
+        (contingency_mins.unwrap_or(defaults.contingency_mins)*60, flexi_depart_mins.unwrap_or(defaults.flexi_depart_mins)*60)
+    }
+}
+
+#[pymethods]
+impl PyRailServices {
+    /** The station with CRS code $crs as a dict, or None */
+    fn station<'py>(&self, py: Python<'py>, crs: &str) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.rail.stations.get_by_crs(&crs.to_uppercase()).map(|station| station_dict(py, station)).transpose()
+    }
+
+    /** Stations for $name as the server looks them up: the exact CRS match first (if any), then name matches best first */
+    fn lookup<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let stations = &self.rail.stations;
+        let (found, _) = stations.lookup(name, &LookupOptions::default());
+        found.into_iter().map(|id| station_dict(py, stations.get(id).unwrap())).collect()
+    }
+
+    /** Every station as columns (id, crs, name, lat, lon) in ID order, e.g. for `pandas.DataFrame`. Unknown locations are NaN */
+    fn stations<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stations = &self.rail.stations;
+        let locations: Vec<_> = stations.iter().map(Station::lat_lon).collect();
+        let dict = PyDict::new(py);
+        dict.set_item("id", stations.iter().map(|station| station.id).collect::<Vec<_>>())?;
+        dict.set_item("crs", stations.iter().map(|station| station.crs_code.as_str()).collect::<Vec<_>>())?;
+        dict.set_item("name", stations.iter().map(|station| station.names[0].as_str()).collect::<Vec<_>>())?;
+        dict.set_item("lat", locations.iter().map(|ll| ll.map_or(f64::NAN, |ll| ll.lat)).collect::<Vec<_>>())?;
+        dict.set_item("lon", locations.iter().map(|ll| ll.map_or(f64::NAN, |ll| ll.lon)).collect::<Vec<_>>())?;
+        Ok(dict)
+    }
+
+    /** The fastest journey from $origin to each of $dests (CRS codes) leaving at $start (HHMM or HH:MM) as a dict, or None. Only by trains running on $date (YYYY-MM-DD) if given */
+    #[pyo3(signature = (origin, dests, start, date=None, contingency_mins=None, flexi_depart_mins=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn compute_journeys<'py>(&self, py: Python<'py>, origin: &str, dests: Vec<String>, start: &str, date: Option<&str>,
+            contingency_mins: Option<u32>, flexi_depart_mins: Option<u32>) -> PyResult<Vec<Option<Bound<'py, PyDict>>>> {
+        let origin = self.station_id(origin)?;
+        let dest_ids = dests.iter().map(|dest| self.station_id(dest)).collect::<PyResult<Vec<_>>>()?;
+        let start = parse_time(start)?;
+        let date = date.map(parse_date).transpose().map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let (contingency, flexi_depart) = Self::routing(contingency_mins, flexi_depart_mins);
+
+        let journeys = py.detach(|| {
+            let mut scratch = self.scratch.lock().unwrap();
+            self.rail.graph.compute_journeys(&mut scratch, date, start, origin, dest_ids, contingency, flexi_depart, &JourneyConstraints::default())
+        });
+        journeys.iter().map(|journey| journey_dict(py, &self.rail.stations, journey)).collect()
+    }
+
+    /** Seconds from $origin to every station leaving at $start, indexed by station ID with NaN where unreachable. Otherwise as compute_journeys */
+    #[pyo3(signature = (origin, start, date=None, contingency_mins=None, flexi_depart_mins=None))]
+    fn isochrone(&self, py: Python<'_>, origin: &str, start: &str, date: Option<&str>, contingency_mins: Option<u32>,
+            flexi_depart_mins: Option<u32>) -> PyResult<Vec<f64>> {
+        let origin = self.station_id(origin)?;
+        let start = parse_time(start)?;
+        let date = date.map(parse_date).transpose().map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let (contingency, flexi_depart) = Self::routing(contingency_mins, flexi_depart_mins);
+
+        let times = py.detach(|| {
+            let mut scratch = self.scratch.lock().unwrap();
+            self.rail.graph.compute_all_times(&mut scratch, date, start, origin, contingency, flexi_depart)
+        });
+        Ok(times.into_iter().map(|time| time.map_or(f64::NAN, f64::from)).collect())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("RailServices({} stations, {} services)", self.rail.stations.count(), self.rail.timetable.services().len())
+    }
+}
+
+/** Load services from a CIF file prefix, a .zip archive or a GTFS feed directory, as the server and command line tool do */
+#[pyfunction]
+fn load_services(py: Python<'_>, path: &str) -> PyResult<PyRailServices> {
+    let rail = py.detach(|| load_dataset(path)).map_err(|e| PyIOError::new_err(format!("Loading {}: {}", path, e)))?;
+    Ok(PyRailServices::new(rail))
+}
+
+#[pymodule]
+#[pyo3(name = "raildata")]
+fn raildata_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(load_services, m)?)?;
+    m.add_class::<PyRailServices>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata::stations::Station;
+    use raildata::timetable::{Service, Stop, Timetable};
+    use raildata::TravelGraph;
+
+    fn services() -> PyRailServices {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("ELYY", "ELY", "ELY")
+        ]);
+        let timetable = Timetable::new(
+            None,
+            vec![Service::simple(0, "C12345", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")])],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        PyRailServices::new(RailServices { stations, fixedlinks: Vec::new(), timetable, graph })
+    }
+
+    #[test]
+    fn test_python_api() {
+        let rail = services();
+        Python::attach(|py| {
+            let station = rail.station(py, "kgx").unwrap().unwrap();
+            assert_eq!(station.get_item("name").unwrap().unwrap().extract::<String>().unwrap(), "LONDON KINGS CROSS");
+            assert!(rail.station(py, "XXX").unwrap().is_none());
+            assert_eq!(rail.lookup(py, "cambrige").unwrap()[0].get_item("crs").unwrap().unwrap().extract::<String>().unwrap(), "CBG");
+            let columns = rail.stations(py).unwrap();
+            assert_eq!(columns.get_item("crs").unwrap().unwrap().extract::<Vec<String>>().unwrap(), vec!["CBG", "KGX", "ELY"]);
+
+            let journeys = rail.compute_journeys(py, "CBG", vec!["KGX".to_string(), "ELY".to_string()], "08:50", None, Some(0), Some(0)).unwrap();
+            let journey = journeys[0].as_ref().unwrap();
+            assert_eq!(journey.get_item("duration").unwrap().unwrap().extract::<u32>().unwrap(), 60*60);
+            assert!(journeys[1].is_none());
+            assert!(rail.compute_journeys(py, "CBG", vec![], "0900", Some("someday"), None, None).is_err());
+
+            let times = rail.isochrone(py, "CBG", "0850", None, Some(0), Some(0)).unwrap();
+            assert_eq!(times[..2], [0.0, 3600.0]);
+            assert!(times[2].is_nan());
+        });
+    }
+}
@@ -0,0 +1,201 @@
+/* Copyright James Lomax 2020 */
+
+// Loaded services saved in binary form next to the timetable files, so later
+// loads can skip parsing. A cache is only used while it's newer than all the
+// files it came from, and rewritten when they change.
+
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use raildata_core::fixed_links::FixedLink;
+use raildata_core::{RailDataError, StationList, Timetable};
+
+use crate::binary;
+use crate::{LoadWarning, RailServices, TravelGraph};
+
+const MAGIC: &[u8; 8] = b"RAILDATA";
+// Bump whenever a serialized type changes, so old caches are re-parsed rather than misread
+const FORMAT_VERSION: u32 = 13;
+
+#[derive(Serialize)]
+struct CacheRef<'a> {
+    stations: &'a StationList,
+    fixedlinks: &'a [FixedLink],
+    timetable: &'a Timetable,
+    graph: &'a TravelGraph,
+    warnings: &'a [LoadWarning]
+}
+
+#[derive(Deserialize)]
+struct Cache {
+    stations: StationList,
+    fixedlinks: Vec<FixedLink>,
+    timetable: Timetable,
+    graph: TravelGraph,
+    warnings: Vec<LoadWarning>
+}
+
+fn bad_cache(e: binary::Error) -> RailDataError {
+    RailDataError::Cache(e.to_string())
+}
+
+/** Where the cache for the timetable files sharing $prefix goes */
+pub fn cache_path(prefix: &str) -> String {
+    format!("{}.cache", prefix)
+}
+
+fn encode(w: &mut dyn Write, rail: &RailServices, warnings: &[LoadWarning]) -> Result<(), RailDataError> {
+    w.write_all(MAGIC)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    let cache = CacheRef {
+        stations: &rail.stations,
+        fixedlinks: &rail.fixedlinks,
+        timetable: &rail.timetable,
+        graph: &rail.graph,
+        warnings
+    };
+    binary::to_writer(w, &cache).map_err(bad_cache)
+}
+
+fn write(path: &Path, rail: &RailServices, warnings: &[LoadWarning]) -> Result<(), RailDataError> {
+    // Written under another name first so a reader never sees half a cache
+    let partial = path.with_extension("cache.partial");
+    let mut w = BufWriter::new(File::create(&partial)?);
+    encode(&mut w, rail, warnings)?;
+    w.flush()?;
+    drop(w);
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn decode(data: &[u8]) -> Result<(RailServices, Vec<LoadWarning>), RailDataError> {
+    let header = MAGIC.len() + 4;
+    if data.len() < header || &data[..MAGIC.len()] != MAGIC {
+        return Err(RailDataError::Cache("Not a cache file".to_string()));
+    }
+    let version = u32::from_le_bytes(data[MAGIC.len()..header].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(RailDataError::Cache(format!("Format version {}, expected {}", version, FORMAT_VERSION)));
+    }
+
+    let cache: Cache = binary::from_slice(&data[header..]).map_err(bad_cache)?;
+    let rail = RailServices {
+        stations: cache.stations,
+        fixedlinks: cache.fixedlinks,
+        timetable: cache.timetable,
+        graph: cache.graph
+    };
+    Ok((rail, cache.warnings))
+}
+
+fn read(path: &Path) -> Result<(RailServices, Vec<LoadWarning>), RailDataError> {
+    decode(&fs::read(path)?)
+}
+
+impl RailServices {
+    /** Save the services to $path, to load again with `load_cache` */
+    pub fn save_cache(&self, path: &Path) -> Result<(), RailDataError> {
+        write(path, self, &[])
+    }
+
+    /** Load services saved by `save_cache`, failing if the file is corrupt or from another version */
+    pub fn load_cache(path: &Path) -> Result<Self, RailDataError> {
+        read(path).map(|(rail, _)| rail)
+    }
+
+    /** The services in the cache format, e.g. to serve to a browser which loads them with `from_cache_bytes` */
+    pub fn to_cache_bytes(&self) -> Result<Vec<u8>, RailDataError> {
+        let mut data = Vec::new();
+        encode(&mut data, self, &[])?;
+        Ok(data)
+    }
+
+    /** Load services from the contents of a cache file, as `load_cache` but without file access */
+    pub fn from_cache_bytes(data: &[u8]) -> Result<Self, RailDataError> {
+        decode(data).map(|(rail, _)| rail)
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/** The cached services for $prefix if the cache is newer than each of $sources, adding its load warnings to $warnings */
+pub(crate) fn load_fresh(prefix: &str, sources: &[&str], warnings: &mut Vec<LoadWarning>) -> Option<RailServices> {
+    let path = cache_path(prefix);
+    let cached = modified(&path)?;
+    if sources.iter().any(|source| modified(source).is_none_or(|m| m >= cached)) {
+        return None;
+    }
+
+    match read(Path::new(&path)) {
+        Ok((rail, cached_warnings)) => {
+            tracing::info!(cache = %path, "Loaded rail services from cache");
+            warnings.extend(cached_warnings);
+            Some(rail)
+        }
+        Err(e) => {
+            tracing::warn!(cache = %path, %e, "Ignoring unreadable cache");
+            None
+        }
+    }
+}
+
+/** Cache $rail for $prefix. Failing to is only logged, as the services are loaded anyway */
+pub(crate) fn store(prefix: &str, rail: &RailServices, warnings: &[LoadWarning]) {
+    let path = cache_path(prefix);
+    if let Err(e) = write(Path::new(&path), rail, warnings) {
+        tracing::warn!(cache = %path, %e, "Could not write cache");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+    use raildata_core::timetable::{RailTime, Service, Stop};
+    use crate::{JourneyConstraints, QueryScratch};
+
+    #[test]
+    fn test_save_load() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX")
+        ]);
+        let timetable = Timetable::new(
+            None,
+            vec![Service::simple(0, "C12345", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")])],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let rail = RailServices { stations, fixedlinks: Vec::new(), timetable, graph };
+
+        let path = std::env::temp_dir().join(format!("raildata-cache-{}.cache", std::process::id()));
+        rail.save_cache(&path).unwrap();
+        let loaded = RailServices::load_cache(&path);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, b"RAILDATA\x00\x00\x00\x00").unwrap();
+        let old = RailServices::load_cache(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.stations.get_by_crs("KGX").unwrap().id, 1);
+        assert_eq!(loaded.timetable.services()[0].train_uid, "C12345");
+        assert!(loaded.graph == rail.graph);
+        let mut scratch = QueryScratch::new(&loaded.graph);
+        let journeys = loaded.graph.compute_journeys(&mut scratch, None, RailTime::new(9, 0), 0, vec![1], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 50*60);
+
+        assert!(matches!(old, Err(RailDataError::Cache(_))));
+
+        // The same format in memory
+        assert_eq!(rail.to_cache_bytes().unwrap(), bytes);
+        assert!(RailServices::from_cache_bytes(&bytes).unwrap().graph == rail.graph);
+        assert!(RailServices::from_cache_bytes(&bytes[..20]).is_err());
+    }
+}
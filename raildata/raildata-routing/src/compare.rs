@@ -0,0 +1,202 @@
+/* Copyright James Lomax 2020 */
+
+// Options between two stations across a departure window, for choosing
+// between e.g. the fastest journey, one with fewer changes and the cheapest.
+
+use chrono::Duration;
+
+use raildata_core::fares::{FareEstimate, FareTable};
+use raildata_core::stations::StationId;
+use raildata_core::timetable::{RailTime, Service};
+
+use crate::overlay::TimetableView;
+use crate::travel_graph::{Call, Journey, JourneyConstraints, Link, QueryScratch, RailLink};
+
+const DAY: u32 = 24*60*60;
+// Journeys with more changes than this are only candidates when they're the fastest
+const MAX_CHANGES: u32 = 3;
+
+// Journey to $destination by $service calling there after $origin, if it does
+fn direct(service: &Service, origin: StationId, destination: StationId) -> Option<Journey> {
+    let board = service.stops.iter().position(|stop| stop.station == origin && stop.activity.can_board())?;
+    let alight = board + 1 + service.stops[board+1..].iter().position(|stop| stop.station == destination && stop.activity.can_alight())?;
+    let depart = service.stops[board].departure;
+    let time = service.stops[alight].arrival.since(&depart);
+    let calls = service.stops[board+1..alight].iter()
+        .map(|stop| Call { station: stop.station, arrive: stop.arrival, depart: stop.departure, platform: stop.platform })
+        .collect();
+    let link = RailLink {
+        dst: destination,
+        service: service.id,
+        depart,
+        time,
+        depart_platform: service.stops[board].platform,
+        arrive_platform: service.stops[alight].platform,
+        calls
+    };
+
+    Some(Journey { origin, depart: depart.time(), time, links: vec![Link::Rail(link)] })
+}
+
+/**
+ * Estimated walk-up prices of $journey from $fares, valid on $view's date if it has
+ * one, by the operators of its trains (see `FareTable::estimate_legs`). Walks are free.
+ * None if a station has no CRS code or there's no fare
+ */
+pub fn journey_fare(view: &TimetableView, fares: &FareTable, journey: &Journey) -> Option<FareEstimate> {
+    let crs = |id: StationId| view.rail.stations.get(id).map(|station| station.crs_code.as_str());
+    let legs = journey.legs().iter().filter_map(|leg| match leg.link {
+        Link::Rail(rl) => Some((leg.from, rl)),
+        _ => None
+    }).map(|(from, rl)| Some((crs(from)?, crs(rl.dst)?, view.service(rl.service)?.operator)))
+        .collect::<Option<Vec<_>>>()?;
+    fares.estimate_legs(&legs, view.date)
+}
+
+/**
+ * Journeys from $origin to $destination leaving within $window seconds of
+ * $depart, keeping only those which no other is at least as fast with at most
+ * as many changes and, with $fares, a single at most as dear (`journey_fare`).
+ * Journeys without a fare count as dearer than any with one, so without $fares
+ * only duration and changes are traded off. Candidates are, from each departure
+ * in the window, the fastest journey and the fastest with each number of changes
+ * up to MAX_CHANGES, and every direct service. Ordered by departure. Searches the
+ * timetable in effect in $view, on its date if it has one.
+ */
+#[tracing::instrument(level = "debug", skip(view, scratch, fares))]
+#[allow(clippy::too_many_arguments)]
+pub fn compare_journeys(view: &TimetableView, scratch: &mut QueryScratch, depart: RailTime, window: u32,
+        origin: StationId, destination: StationId, contingency: u32, fares: Option<&FareTable>) -> Vec<Journey> {
+    // Offsets from $depart of each train leaving the origin in the window, and $depart itself for walks
+    let graph = view.graph();
+    let mut offsets: Vec<u32> = graph.links(origin).iter().filter_map(|link| match link {
+        Link::Rail(rl) => Some(depart.timetil(&rl.depart.time())),
+        _ => None
+    }).filter(|offset| *offset <= window).collect();
+    offsets.push(0);
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    let mut journeys = Vec::new();
+    for offset in offsets {
+        // Leave exactly at the offset (within a second) rather than up to an hour later
+        let mut found = graph.compute_journeys(scratch, view.date, depart.add(offset), origin, vec![destination], contingency, 1, &JourneyConstraints::default());
+        journeys.extend(found.pop().filter(|j| j.time != u32::MAX));
+        let mut fronts = graph.compute_pareto(scratch, view.date, depart.add(offset), origin, vec![destination], contingency, 1, MAX_CHANGES);
+        journeys.extend(fronts.pop().unwrap_or_default());
+    }
+    // Direct services running on the day they'd leave within the window
+    let runs = |journey: &Journey| match (view.date, &journey.links[0]) {
+        (Some(date), Link::Rail(rl)) => graph.runs(rl, date + Duration::days(((depart.secs() + depart.timetil(&rl.depart.time())) / DAY) as i64)),
+        _ => true
+    };
+    journeys.extend(view.services_from(origin).into_iter().filter_map(|service| direct(service, origin, destination)).filter(runs));
+
+    // A search may wait at the origin for a train beyond the window
+    let mut candidates: Vec<(u32, Journey)> = journeys.into_iter()
+        .map(|j| (depart.timetil(&j.depart), j))
+        .filter(|(offset, _)| *offset <= window)
+        .collect();
+
+    // Of equal options keep the earliest, then the one found first
+    candidates.sort_by_key(|(offset, _)| *offset);
+    let single = |j: &Journey| fares.and_then(|fares| journey_fare(view, fares, j)?.single).unwrap_or(u32::MAX);
+    let keys: Vec<(u32, u32, u32)> = candidates.iter().map(|(_, j)| (j.time, j.changes(), single(j))).collect();
+    let dominated = |i: usize| (0..keys.len()).any(|k| {
+        k != i && keys[k].0 <= keys[i].0 && keys[k].1 <= keys[i].1 && keys[k].2 <= keys[i].2 && (keys[k] != keys[i] || k < i)
+    });
+
+    candidates.into_iter().enumerate()
+        .filter(|(i, _)| !dominated(*i))
+        .map(|(_, (_, journey))| journey)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use raildata_core::fixed_links::FixedLink;
+    use raildata_core::stations::{Station, StationList};
+    use raildata_core::timetable::{Service, Stop, Timetable};
+    use crate::{RailServices, TravelGraph};
+
+    fn service(id: u32, stops: Vec<Stop>) -> Service {
+        Service::simple(id, &format!("S{}", id), stops)
+    }
+
+    #[test]
+    fn test_compare_journeys() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("STEVNGE", "Stevenage", "SVG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        // A slow direct train, and a quicker journey changing at SVG. A later direct train is slower still
+        let timetable = Timetable::new(
+            None,
+            vec![
+                service(0, vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1040", "1042"), Stop::simple(2, "1110", "1110")]),
+                service(1, vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1020", "1020")]),
+                service(2, vec![Stop::simple(1, "1025", "1025"), Stop::simple(2, "1045", "1045")]),
+                service(3, vec![Stop::simple(0, "1030", "1030"), Stop::simple(2, "1150", "1150")])
+            ],
+            Vec::new()
+        );
+        let fixedlinks: Vec<FixedLink> = Vec::new();
+        let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+        let rail = RailServices { stations, fixedlinks, timetable, graph };
+
+        let mut scratch = QueryScratch::new(&rail.graph);
+        let view = TimetableView::base(&rail);
+        let journeys = compare_journeys(&view, &mut scratch, RailTime::new(9, 55), 60*60, 0, 2, 0, None);
+        let options: Vec<(u32, u32)> = journeys.iter().map(|j| (j.time / 60, j.changes())).collect();
+        assert_eq!(options, vec![(45, 1), (70, 0)]);
+        assert_eq!(journeys[1].depart, RailTime::new(10, 0));
+
+        // Nothing leaves in a window before the first train
+        assert!(compare_journeys(&view, &mut scratch, RailTime::new(8, 0), 60*60, 0, 2, 0, None).is_empty());
+    }
+
+    #[test]
+    fn test_compare_journeys_fares() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("STEVNGE", "Stevenage", "SVG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        // As above, with the later, slower direct train run by an operator with cheaper fares of its own
+        let timetable = Timetable::new(
+            None,
+            vec![
+                service(0, vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1040", "1042"), Stop::simple(2, "1110", "1110")]),
+                service(1, vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1020", "1020")]),
+                service(2, vec![Stop::simple(1, "1025", "1025"), Stop::simple(2, "1045", "1045")]),
+                Service { operator: "XC", ..service(3, vec![Stop::simple(0, "1030", "1030"), Stop::simple(2, "1150", "1150")]) }
+            ],
+            Vec::new()
+        );
+        let fixedlinks: Vec<FixedLink> = Vec::new();
+        let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+        let rail = RailServices { stations, fixedlinks, timetable, graph };
+
+        let locations = HashMap::from([("0433".to_string(), "CBG".to_string()), ("6121".to_string(), "KGX".to_string())]);
+        let ffl = [
+            "RF0433612100000000AR3112299901012020ECR   0000001",
+            "RF0433612100700000AS3112299901012020XCC   0000002",
+            "RT0000001SOS00003000  ",
+            "RT0000002SOS00002000  "
+        ].join("\n");
+        let fares = FareTable::read_flows(&mut ffl.as_bytes(), &locations).unwrap()
+            .with_operators(HashMap::from([("XCC".to_string(), "XC".to_string())]));
+
+        let mut scratch = QueryScratch::new(&rail.graph);
+        let view = TimetableView::base(&rail);
+        let journeys = compare_journeys(&view, &mut scratch, RailTime::new(9, 55), 60*60, 0, 2, 0, Some(&fares));
+        let options: Vec<(u32, u32, Option<u32>)> = journeys.iter()
+            .map(|j| (j.time / 60, j.changes(), journey_fare(&view, &fares, j).and_then(|fare| fare.single)))
+            .collect();
+        assert_eq!(options, vec![(45, 1, Some(3000)), (70, 0, Some(3000)), (80, 0, Some(2000))]);
+    }
+}
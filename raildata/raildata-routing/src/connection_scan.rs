@@ -0,0 +1,517 @@
+/* Copyright James Lomax 2020 */
+
+// Connection Scan: the graph's rail links as one array of connections sorted by
+// departure time, scanned in order a day at a time. Each station keeps its
+// earliest arrival and each connection whether it was ridden, so staying aboard
+// needs no change time. Far less work than Dijkstra's per-station edge lists
+// for the one-to-all case, as nothing is queued or revisited.
+//
+// With a flexible departure, journeys leaving the origin later can be quicker
+// despite arriving later, so stations keep each arrival that isn't beaten by
+// one both arriving sooner and leaving the origin no earlier.
+//
+// Scanned backwards from an arrival time instead, each station keeps the
+// latest it can be left to arrive in time, giving the latest departure.
+
+use std::sync::OnceLock;
+
+use chrono::NaiveDate;
+
+use raildata_core::stations::StationId;
+use raildata_core::timetable::{RailTime, ServiceId};
+
+use crate::travel_graph::{FixedLink, Link, TravelGraph};
+
+const DAY: u32 = 24*60*60;
+const NONE: u32 = u32::MAX;
+
+#[derive(Clone, PartialEq, Debug)]
+struct Connection {
+    src: u32,
+    dst: u32,
+    // Seconds since midnight
+    depart: u32,
+    time: u32,
+    service: ServiceId,
+    // Days after the service started that it departs, 1 after its first midnight
+    day: u32,
+    // The connection of the same service arriving where this departs, NONE at the start of the service
+    prev: u32,
+    // Index of its link in the graph
+    link: u32
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct Connections {
+    connections: Vec<Connection>,
+    // Fixed links from each station
+    footpaths: Vec<Vec<FixedLink>>,
+    // Stations linked to from each station by any train or fixed link
+    neighbours: Vec<Vec<u32>>,
+    // Fixed links to each station, as the station they're from and their index in the graph
+    incoming: Vec<Vec<(u32, u32)>>
+}
+
+/** Connections for a graph, built the first time the graph is searched with Connection Scan */
+#[derive(Default)]
+pub(crate) struct LazyConnections(OnceLock<Connections>);
+
+impl LazyConnections {
+    pub(crate) fn get(&self, graph: &TravelGraph) -> &Connections {
+        self.0.get_or_init(|| Connections::new(graph))
+    }
+}
+
+// Derived from the graph, so a copy starts empty and any two compare equal
+impl Clone for LazyConnections {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for LazyConnections {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Debug for LazyConnections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LazyConnections")
+    }
+}
+
+impl Connections {
+    fn new(graph: &TravelGraph) -> Self {
+        let mut connections = Vec::new();
+        let mut footpaths = Vec::with_capacity(graph.station_count());
+        let mut neighbours = Vec::with_capacity(graph.station_count());
+        let mut incoming = vec![Vec::new(); graph.station_count()];
+        for src in 0..graph.station_count() {
+            let mut fixed = Vec::new();
+            let mut linked: Vec<u32> = graph.links(src).iter().filter_map(|link| match link {
+                Link::Rail(rlink) => Some(rlink.dst as u32),
+                Link::Fixed(flink) => Some(flink.dst as u32),
+                Link::Dummy => None
+            }).collect();
+            linked.sort_unstable();
+            linked.dedup();
+            neighbours.push(linked);
+            for index in graph.link_range(src) {
+                match graph.link(index as u32) {
+                    Link::Rail(rlink) => connections.push(Connection {
+                        src: src as u32,
+                        dst: rlink.dst as u32,
+                        depart: rlink.depart.time().secs(),
+                        time: rlink.time,
+                        service: rlink.service,
+                        day: rlink.depart.day(),
+                        prev: NONE,
+                        link: index as u32
+                    }),
+                    Link::Fixed(flink) => {
+                        fixed.push(flink.clone());
+                        incoming[flink.dst].push((src as u32, index as u32));
+                    }
+                    Link::Dummy => {}
+                }
+            }
+            footpaths.push(fixed);
+        }
+        // Stable, so a service's links leaving at the same time stay in calling order
+        connections.sort_by_key(|c| (c.depart, c.time));
+
+        let services = connections.iter().map(|c| c.service as usize + 1).max().unwrap_or(0);
+        let mut by_service: Vec<Vec<u32>> = vec![Vec::new(); services];
+        for (i, c) in connections.iter().enumerate() {
+            by_service[c.service as usize].push(i as u32);
+        }
+        for indices in by_service.iter_mut() {
+            // In order from the start of the service, each departing where an earlier one arrived
+            indices.sort_by_key(|&i| {
+                let c = &connections[i as usize];
+                (c.day * DAY + c.depart, c.time)
+            });
+            let mut arrived: Vec<(u32, u32)> = Vec::new();
+            for &i in indices.iter() {
+                let (src, dst) = (connections[i as usize].src, connections[i as usize].dst);
+                connections[i as usize].prev = arrived.iter().rev().find(|(station, _)| *station == src).map_or(NONE, |(_, i)| *i);
+                arrived.push((dst, i));
+            }
+        }
+
+        tracing::debug!(connections = connections.len(), "Built connections");
+        Self { connections, footpaths, neighbours, incoming }
+    }
+}
+
+// How a latest departure search goes on from a station
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+enum Hop {
+    // It's the destination
+    #[default]
+    Arrive,
+    // By boarding connection $0 on day $1 of the search
+    Board(u32, u32),
+    // Along the fixed link with index $0 in the graph to station $1
+    Walk(u32, u32)
+}
+
+/** Working memory for Connection Scan searches, kept in a QueryScratch */
+#[derive(Default)]
+pub(crate) struct ScanScratch {
+    // For each station, the arrivals there which aren't beaten by arriving sooner having left the
+    // origin later, as (arrival, left origin) in seconds from the start of the first day, by arrival
+    arrivals: Vec<Vec<(u32, u32)>>,
+    // When a train can first be caught at each station, allowing for the change
+    ready: Vec<u32>,
+    // The last arrival at each station and when the origin was left for it, to save searching the arrivals
+    last: Vec<(u32, u32)>,
+    // Time to allow for changing at each station
+    change: Vec<u32>,
+    // Shortest journey time to each station
+    best: Vec<u32>,
+    // For each connection, the day its service started when last ridden, and the latest the
+    // origin could have been left to be aboard. Each search numbers its days on from the last,
+    // so what was ridden never needs clearing
+    ridden: Vec<(u32, u32)>,
+    first_day: u32,
+    // Stations linked to the origin but not yet arrived at
+    unreached: usize,
+    // Arrivals to walk on from, or stations to visit when finding those linked to the origin
+    walks: Vec<(StationId, u32, u32)>,
+    linked: Vec<bool>,
+    // For latest departure searches, the latest each station can be reached to still arrive
+    // in time, 0 if it can't be, and how to go on from there
+    latest: Vec<u32>,
+    hops: Vec<Hop>,
+    // For each connection on each day searched, the next connection of its service when
+    // staying aboard arrives in time, stamped with the search so it never needs clearing
+    onward: Vec<(u32, u32)>,
+    searches: u32
+}
+
+impl ScanScratch {
+    /**
+     * The journey time from $origin to every station, as TravelGraph::compute_all_times but
+     * using the connections. Trains leaving the origin within $flexi_depart of $depart can be
+     * caught without waiting, each journey being timed from when its first train leaves.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn all_times(&mut self, graph: &TravelGraph, connections: &Connections, date: Option<NaiveDate>, depart: RailTime,
+            origin: StationId, contingency: u32, flexi_depart: u32) -> Vec<Option<u32>> {
+        let stations = graph.station_count();
+        self.best.clear();
+        self.best.resize(stations, NONE);
+        self.arrivals.resize_with(stations, Vec::new);
+        self.arrivals.iter_mut().for_each(Vec::clear);
+        self.ready.clear();
+        self.ready.resize(stations, NONE);
+        self.last.clear();
+        self.last.resize(stations, (NONE, 0));
+        self.change.clear();
+        self.change.extend((0..stations).map(|station| graph.transfer_time(station) + contingency));
+        self.unreached = self.link(connections, origin);
+        if self.ridden.len() != connections.connections.len() {
+            self.ridden.clear();
+            self.ridden.resize(connections.connections.len(), (0, 0));
+        }
+        // Day 0 is never ridden, and the day before the search starts is in it for trains running overnight
+        self.first_day += 2;
+
+        let start = depart.secs();
+        let window = start + flexi_depart;
+        self.arrive(connections, date, origin, start, start);
+
+        // Every day is alike without a date, so once a whole day passes without a new arrival there are none to come
+        let mut horizon = start + DAY;
+        // Once every station is reached, nothing leaving after the longest journey so far from the end of the window can be quicker
+        let mut until = NONE;
+        let mut latest_left = start;
+        let mut day = start / DAY;
+        let mut first = connections.connections.partition_point(|c| c.depart < start % DAY);
+        loop {
+            for (i, c) in connections.connections.iter().enumerate().skip(first) {
+                let departs = day * DAY + c.depart;
+                // Checked now and then, as it means looking at every station
+                if self.unreached == 0 && i % 16384 == 0 {
+                    until = window + self.best.iter().filter(|&&time| time != NONE).max().unwrap_or(&0);
+                }
+                if departs >= horizon || departs >= until {
+                    self.first_day += day;
+                    return self.best.iter().map(|&time| if time == NONE { None } else { Some(time) }).collect();
+                }
+
+                let started = (self.first_day + day).wrapping_sub(c.day);
+                // Trains leaving the origin within the window are caught without waiting
+                let src = c.src as usize;
+                let mut left = if src == origin && departs < window {
+                    departs
+                } else {
+                    self.boards(src, departs)
+                };
+                // Staying aboard only matters if the origin could have been left later for it
+                if left != latest_left && c.prev != NONE {
+                    let (ridden_started, aboard) = self.ridden[c.prev as usize];
+                    if ridden_started == started {
+                        left = if left == NONE { aboard } else { left.max(aboard) };
+                    }
+                }
+                if left == NONE {
+                    continue;
+                }
+                if let Some(date) = date {
+                    if !runs(graph, c, date, day) {
+                        continue;
+                    }
+                }
+                latest_left = latest_left.max(left);
+
+                self.ridden[i] = (started, left);
+                let arrives = departs + c.time;
+                if !self.dominated(c.dst as usize, arrives, left) && self.arrive(connections, date, c.dst as usize, arrives, left) {
+                    horizon = horizon.max(departs + DAY);
+                }
+            }
+            day += 1;
+            first = 0;
+        }
+    }
+
+    /**
+     * The latest departure from $origin reaching $destination by $arrive_by, at most a day
+     * before, scanning the connections backwards from $arrive_by. As TravelGraph::latest_departure,
+     * giving the departure, the journey time and the indices in the graph of the links taken.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn latest_departure(&mut self, graph: &TravelGraph, connections: &Connections, date: Option<NaiveDate>,
+            origin: StationId, destination: StationId, arrive_by: RailTime, contingency: u32) -> Option<(RailTime, u32, Vec<u32>)> {
+        // Seconds from the start of two days before $arrive_by, so nothing a day before it goes below zero
+        let arrive = 2*DAY + arrive_by.secs();
+        let earliest = arrive - DAY + 1;
+        // The date of day 1 or 2 of the search
+        let on = |day: u32| date.map(|date| date - chrono::Duration::days(2 - day as i64));
+
+        if origin == destination {
+            return Some((arrive_by, 0, Vec::new()));
+        }
+        let stations = graph.station_count();
+        self.latest.clear();
+        self.latest.resize(stations, 0);
+        self.hops.clear();
+        self.hops.resize(stations, Hop::Arrive);
+        if self.onward.len() != 2 * connections.connections.len() {
+            self.onward.clear();
+            self.onward.resize(2 * connections.connections.len(), (0, 0));
+        }
+        self.searches += 1;
+        let stamp = self.searches;
+
+        // When the origin can be left and how, 0 until it can
+        let mut best = (0, Hop::Arrive);
+        self.reach(graph, connections, &on, origin, earliest, destination, arrive, Hop::Arrive, &mut best);
+
+        // From the last connection leaving by $arrive_by, to the first leaving a day before
+        let last = connections.connections.partition_point(|c| c.depart <= arrive_by.secs());
+        'days: for day in [2, 1] {
+            let end = if day == 2 { last } else { connections.connections.len() };
+            for i in (0..end).rev() {
+                let c = &connections.connections[i];
+                let departs = day * DAY + c.depart;
+                if departs < earliest || departs < best.0 {
+                    break 'days;
+                }
+                if let Some(date) = on(day) {
+                    if !graph.service_runs(c.service, c.day, date) {
+                        continue;
+                    }
+                }
+                // Either alighting or staying aboard arrives in time
+                let aboard = self.onward[2*i + day as usize - 1].0 == stamp;
+                if !aboard && departs + c.time > self.latest[c.dst as usize] {
+                    continue;
+                }
+                if c.prev != NONE {
+                    let prev = c.prev as usize;
+                    let prev_day = if connections.connections[prev].depart <= c.depart { day } else { day - 1 };
+                    if prev_day > 0 {
+                        self.onward[2*prev + prev_day as usize - 1] = (stamp, i as u32);
+                    }
+                }
+
+                // Trains are boarded at the origin as they leave, and elsewhere after the change
+                let src = c.src as usize;
+                let hop = Hop::Board(i as u32, day);
+                if src == origin && departs > best.0 {
+                    best = (departs, hop);
+                }
+                let ready = departs.saturating_sub(graph.transfer_time(src) + contingency);
+                self.reach(graph, connections, &on, origin, earliest, src, ready, hop, &mut best);
+            }
+        }
+
+        let (depart, mut hop) = best;
+        if depart == 0 {
+            return None;
+        }
+        // Follow the hops from the origin, staying aboard wherever that arrives in time
+        let mut links = Vec::new();
+        let mut now = depart;
+        loop {
+            match hop {
+                Hop::Arrive => break,
+                Hop::Board(mut i, mut day) => loop {
+                    let c = &connections.connections[i as usize];
+                    links.push(c.link);
+                    now = day * DAY + c.depart + c.time;
+                    let (stamped, next) = self.onward[2 * i as usize + day as usize - 1];
+                    if stamped != stamp {
+                        hop = self.hops[c.dst as usize];
+                        break;
+                    }
+                    if connections.connections[next as usize].depart < c.depart {
+                        day += 1;
+                    }
+                    i = next;
+                },
+                Hop::Walk(index, dst) => {
+                    links.push(index);
+                    if let Link::Fixed(flink) = graph.link(index) {
+                        now += flink.wait(RailTime::new(0, 0).add(now % DAY)) + flink.time;
+                    }
+                    hop = self.hops[dst as usize];
+                }
+            }
+        }
+        Some((RailTime::new(0, 0).add(depart % DAY), now - depart, links))
+    }
+
+    // Let $station be reached by $time to arrive in time, going on by $hop, if that's later than
+    // before, then likewise the stations with fixed links to it. Leaving the origin later than
+    // $best, at $earliest or after, becomes the best
+    #[allow(clippy::too_many_arguments)]
+    fn reach(&mut self, graph: &TravelGraph, connections: &Connections, on: &impl Fn(u32) -> Option<NaiveDate>, origin: StationId,
+            earliest: u32, station: StationId, time: u32, hop: Hop, best: &mut (u32, Hop)) {
+        if time <= self.latest[station] {
+            return;
+        }
+        self.latest[station] = time;
+        self.hops[station] = hop;
+        self.walks.push((station, time, 0));
+        while let Some((station, time, _)) = self.walks.pop() {
+            for &(src, index) in &connections.incoming[station] {
+                let flink = match graph.link(index) {
+                    Link::Fixed(flink) => flink,
+                    _ => continue
+                };
+                // The latest the link can be set off on to get there in time
+                let sets_off = time.saturating_sub(flink.time);
+                let sets_off = sets_off.saturating_sub(flink.back(RailTime::new(0, 0).add(sets_off % DAY)));
+                if sets_off < earliest || on(sets_off / DAY).is_some_and(|date| !flink.runs_on(date)) {
+                    continue;
+                }
+                let (src, hop) = (src as usize, Hop::Walk(index, station as u32));
+                if src == origin && sets_off > best.0 {
+                    *best = (sets_off, hop);
+                }
+                if sets_off > self.latest[src] {
+                    self.latest[src] = sets_off;
+                    self.hops[src] = hop;
+                    self.walks.push((src, sets_off, 0));
+                }
+            }
+        }
+    }
+
+    // Count the stations which can be reached from $origin at any time
+    fn link(&mut self, connections: &Connections, origin: StationId) -> usize {
+        self.linked.clear();
+        self.linked.resize(connections.neighbours.len(), false);
+        self.linked[origin] = true;
+        self.walks.push((origin, 0, 0));
+        let mut count = 1;
+        while let Some((station, _, _)) = self.walks.pop() {
+            for &next in &connections.neighbours[station] {
+                if !self.linked[next as usize] {
+                    self.linked[next as usize] = true;
+                    self.walks.push((next as usize, 0, 0));
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    // The latest the origin could have been left to catch a train leaving $station at $departs, changing there
+    #[inline]
+    fn boards(&self, station: StationId, departs: u32) -> u32 {
+        if departs < self.ready[station] {
+            return NONE;
+        }
+        let (last, last_left) = self.last[station];
+        let change = self.change[station];
+        if departs >= last + change {
+            last_left
+        } else {
+            self.arrivals[station].iter().rev().find(|(arrival, _)| arrival + change <= departs).map_or(NONE, |(_, left)| *left)
+        }
+    }
+
+    // Arrive at $station at $arrives having left the origin at $left, then walk on along its fixed links.
+    // False if it's no better than an earlier arrival
+    fn arrive(&mut self, connections: &Connections, date: Option<NaiveDate>, station: StationId, arrives: u32, left: u32) -> bool {
+        if !self.label(station, arrives, left) {
+            return false;
+        }
+        self.walks.push((station, arrives, left));
+        while let Some((station, now, left)) = self.walks.pop() {
+            for flink in &connections.footpaths[station] {
+                let departs = now + flink.wait(RailTime::new(0, 0).add(now % DAY));
+                if let Some(date) = date {
+                    if !flink.runs_on(date + chrono::Duration::days((departs / DAY) as i64)) {
+                        continue;
+                    }
+                }
+                let arrives = departs + flink.time;
+                if self.label(flink.dst, arrives, left) {
+                    self.walks.push((flink.dst, arrives, left));
+                }
+            }
+        }
+        true
+    }
+
+    // Whether arriving at $station at $arrives is no better than the last arrival there, most being later yet having left no later
+    #[inline]
+    fn dominated(&self, station: StationId, arrives: u32, left: u32) -> bool {
+        let (last, last_left) = self.last[station];
+        last != NONE && arrives >= last && left <= last_left
+    }
+
+    fn label(&mut self, station: StationId, arrives: u32, left: u32) -> bool {
+        let arrivals = &mut self.arrivals[station];
+        if arrivals.iter().any(|&(arrival, earlier)| arrival <= arrives && earlier >= left) {
+            return false;
+        }
+        if arrivals.is_empty() {
+            self.unreached = self.unreached.saturating_sub(1);
+        }
+        arrivals.retain(|&(arrival, earlier)| arrival < arrives || earlier > left);
+        let at = arrivals.partition_point(|&(arrival, _)| arrival < arrives);
+        arrivals.insert(at, (arrives, left));
+        let (first, last) = (arrivals[0], arrivals[arrivals.len() - 1]);
+        self.ready[station] = first.0 + self.change[station];
+        self.last[station] = last;
+
+        let time = arrives - left;
+        if time < self.best[station] {
+            self.best[station] = time;
+        }
+        true
+    }
+}
+
+// Whether $c's service runs when it departs on $day days after $date
+fn runs(graph: &TravelGraph, c: &Connection, date: NaiveDate, day: u32) -> bool {
+    graph.service_runs(c.service, c.day, date + chrono::Duration::days(day as i64))
+}
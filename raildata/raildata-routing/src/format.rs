@@ -0,0 +1,117 @@
+/** Copyright James Lomax 2020 */
+
+use std::fmt::Write;
+
+use raildata_core::stations::{StationId, StationList};
+use crate::travel_graph::{Journey, Link};
+use raildata_core::timetable::RailTime;
+
+fn crs(stations: &StationList, id: StationId) -> &str {
+    &stations.get(id).unwrap().crs_code
+}
+
+fn hh_mm(t: &RailTime) -> String {
+    let s = t.to_24h();
+    format!("{}:{}", &s[0..2], &s[2..4])
+}
+
+// Time with the day when it's after the day of departure, e.g. "07:15+1"
+fn hh_mm_day(t: &RailTime, day: u32) -> String {
+    if day == 0 { hh_mm(t) } else { format!("{}+{}", hh_mm(t), day) }
+}
+
+/**
+ * Format a journey as an aligned itinerary, one leg per line, e.g.
+ *
+ * CBG -> STP: depart 09:00, arrive 10:00 (60 mins, 1 change)
+ *   Depart  From Arrive  To   Wait Via
+ *   09:00   CBG  09:50   KGX     0 Service 12
+ *   09:55   KGX  10:00   STP     5 Walk
+ *
+ * Waits are minutes spent at the leg's departure station, and times on
+ * later days than the departure are suffixed e.g. "06:10+1". A journey with
+ * time u32::MAX (unreachable) is formatted as a single line.
+ */
+pub fn journey_text(stations: &StationList, journey: &Journey) -> String {
+    let legs = journey.legs();
+
+    let mut out = String::new();
+    if journey.time == u32::MAX {
+        writeln!(out, "{}: no journey found", crs(stations, journey.origin)).unwrap();
+        return out;
+    }
+
+    let (depart, dest, arrive) = match (legs.first(), legs.last()) {
+        (Some(first), Some(last)) => (hh_mm_day(&first.depart, first.depart_day), last.to, hh_mm_day(&last.arrive, last.arrive_day)),
+        _ => (hh_mm(&journey.depart), journey.origin, hh_mm(&journey.depart))
+    };
+    let changes = journey.changes();
+    writeln!(out, "{} -> {}: depart {}, arrive {} ({} mins, {} change{})",
+        crs(stations, journey.origin), crs(stations, dest),
+        depart, arrive,
+        journey.time / 60, changes, if changes == 1 { "" } else { "s" }).unwrap();
+
+    writeln!(out, "  {:<7} {:<4} {:<7} {:<4} {:>4} Via", "Depart", "From", "Arrive", "To", "Wait").unwrap();
+    for leg in &legs {
+        let via = match leg.link {
+            Link::Rail(rl) => match &rl.depart_platform {
+                Some(platform) => format!("Service {} from platform {}", rl.service, platform),
+                None => format!("Service {}", rl.service)
+            },
+            Link::Fixed(fl) => format!("{:?}", fl.kind),
+            Link::Dummy => String::new()
+        };
+        writeln!(out, "  {:<7} {:<4} {:<7} {:<4} {:>4} {}",
+            hh_mm_day(&leg.depart, leg.depart_day), crs(stations, leg.from),
+            hh_mm_day(&leg.arrive, leg.arrive_day), crs(stations, leg.to),
+            leg.wait / 60, via).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+    use raildata_core::fixed_links::{FixedLink, FixedLinkKind};
+    use raildata_core::timetable::{Platform, Service, Stop, Timetable};
+    use crate::travel_graph::{JourneyConstraints, QueryScratch, TravelGraph};
+
+    #[test]
+    fn test_journey_text() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX"),
+            Station::simple("STPX", "London St Pancras", "STP"),
+            Station::simple("LUTON", "Luton", "LUT")
+        ]);
+        let fixedlinks = vec![FixedLink::simple(1, 2, 5*60, FixedLinkKind::Walk)];
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "C00001", vec![Stop { platform: Platform::new("1"), ..Stop::simple(0, "0900", "0900") }, Stop::simple(1, "0950", "0950")]),
+                Service::simple(1, "C00002", vec![Stop::simple(2, "1010", "1010"), Stop::simple(3, "1040", "1040")])
+            ],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::from_24h("0900").unwrap(), 0, vec![3], 0, 0, &JourneyConstraints::default()).remove(0);
+
+        assert_eq!(journey_text(&stations, &journey), "\
+CBG -> LUT: depart 09:00, arrive 10:40 (100 mins, 1 change)
+  Depart  From Arrive  To   Wait Via
+  09:00   CBG  09:50   KGX     0 Service 0 from platform 1
+  09:50   KGX  09:55   STP     0 Walk
+  10:10   STP  10:40   LUT    15 Service 1
+");
+
+        // Missing the last train waits overnight for the first one
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::from_24h("2300").unwrap(), 1, vec![3], 0, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!(journey_text(&stations, &journey), "\
+KGX -> LUT: depart 10:05+1, arrive 10:40+1 (700 mins, 0 changes)
+  Depart  From Arrive  To   Wait Via
+  10:05+1 KGX  10:10+1 STP     0 Walk
+  10:10+1 STP  10:40+1 LUT     0 Service 1
+");
+    }
+}
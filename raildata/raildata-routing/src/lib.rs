@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+/** Copyright James Lomax 2020 */
+
+pub mod travel_graph;
+mod connection_scan;
+pub mod format;
+pub mod viz;
+pub mod compare;
+pub mod overlay;
+#[cfg(feature = "realtime")]
+pub mod realtime;
+#[cfg(feature = "cache")]
+mod binary;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod source;
+
+use std::fs::File;
+use std::io::BufReader;
+#[cfg(feature = "zip")]
+use std::path::Path;
+use raildata_core::fixed_links;
+use raildata_core::{RailDataError, StationList, Timetable};
+use raildata_core::timetable::UpdateCounts;
+use raildata_core::validate::ParseIssue;
+pub use travel_graph::{Engine, Journey, JourneyConstraints, JourneyLeg, TravelGraph, GraphIssue, Link, QueryScratch, SearchStats};
+pub use source::{load_from, load_services_from_bytes, CifFiles, TimetableData, TimetableSource};
+#[cfg(feature = "zip")]
+pub use source::CifArchive;
+
+pub struct RailServices {
+    pub stations: StationList,
+    pub fixedlinks: Vec<fixed_links::FixedLink>,
+    pub timetable: Timetable,
+    pub graph: TravelGraph
+}
+
+// Loaded services are shared between server workers, each searching with its own QueryScratch
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<RailServices>();
+};
+
+/** Something tolerated while loading, e.g. a dropped stop or a station merged by CRS */
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LoadWarning {
+    // From one of the timetable files, named by $file
+    Parse { file: String, issue: ParseIssue },
+    // A service or fixed link left out of the travel graph
+    Graph(GraphIssue)
+}
+
+pub fn load_services(file_prefix: &str) -> Result<RailServices, RailDataError> {
+    load_services_with_warnings(file_prefix, &mut Vec::new())
+}
+
+/** Load services as `load_services`, collecting what was skipped or merged in $warnings */
+pub fn load_services_with_warnings(file_prefix: &str, warnings: &mut Vec<LoadWarning>) -> Result<RailServices, RailDataError> {
+    load_from(&CifFiles::new(file_prefix), warnings)
+}
+
+/** Load services from the MSN, ALF or FLF, and MCA files in a timetable archive (e.g. ttis748.zip) without extracting it */
+#[cfg(feature = "zip")]
+pub fn load_services_from_zip(path: &Path) -> Result<RailServices, RailDataError> {
+    load_services_from_zip_with_warnings(path, &mut Vec::new())
+}
+
+/** Load services as `load_services_from_zip`, collecting what was skipped or merged in $warnings */
+#[cfg(feature = "zip")]
+pub fn load_services_from_zip_with_warnings(path: &Path, warnings: &mut Vec<LoadWarning>) -> Result<RailServices, RailDataError> {
+    load_from(&CifArchive::new(path), warnings)
+}
+
+pub(crate) fn parse_warnings(warnings: &mut Vec<LoadWarning>, file: &str, issues: Vec<ParseIssue>) {
+    warnings.extend(issues.into_iter().map(|issue| LoadWarning::Parse { file: file.to_string(), issue }));
+}
+
+impl RailServices {
+    /**
+     * Apply the CIF update extract $path (see `Timetable::apply_update_file`) and rebuild the
+     * travel graph, rather than loading a full extract. Overlays over the old timetable need remaking
+     */
+    pub fn apply_update_file(&mut self, path: &str, warnings: &mut Vec<LoadWarning>) -> Result<UpdateCounts, RailDataError> {
+        let file = File::open(path).map_err(|e| RailDataError::from(e).in_file(path))?;
+        let mut issues = Vec::new();
+        let counts = self.timetable.apply_update_file_with_warnings(&self.stations, &mut BufReader::new(file), &mut issues)
+            .map_err(|e| e.in_file(path))?;
+        parse_warnings(warnings, path, issues);
+        self.rebuild_graph(warnings)?;
+        Ok(counts)
+    }
+
+    /** Rebuild the travel graph after changing the timetable or fixed links */
+    pub fn rebuild_graph(&mut self, warnings: &mut Vec<LoadWarning>) -> Result<(), RailDataError> {
+        let (mut graph, issues) = TravelGraph::new_checked(&self.stations, &self.fixedlinks, &self.timetable, false)?;
+        warnings.extend(issues.into_iter().map(LoadWarning::Graph));
+        graph.set_bank_holidays(self.graph.bank_holidays().clone());
+        self.graph = graph;
+        Ok(())
+    }
+
+    /** Override the minimum change time, in minutes, at the station with CRS $code, e.g. where the MSN's is unrealistic */
+    pub fn set_change_time(&mut self, code: &str, mins: u32) -> Result<(), RailDataError> {
+        let id = self.stations.set_min_change_time(code, mins)?;
+        self.graph.set_transfer_time(id, mins*60);
+        Ok(())
+    }
+}
+
+pub(crate) fn build(stations: StationList, fixedlinks: Vec<fixed_links::FixedLink>, timetable: Timetable, warnings: &mut Vec<LoadWarning>)
+        -> Result<RailServices, RailDataError> {
+    let (graph, issues) = TravelGraph::new_checked(&stations, &fixedlinks, &timetable, false)?;
+    warnings.extend(issues.into_iter().map(LoadWarning::Graph));
+    tracing::info!(stations = stations.count(), fixed_links = fixedlinks.len(), services = timetable.services().len(),
+        warnings = warnings.len(), "Loaded rail services");
+
+    Ok(RailServices {
+        stations,
+        fixedlinks,
+        timetable,
+        graph
+    })
+}
+
+#[cfg(all(test, feature = "zip"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_load_from_zip() {
+        let msn = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let flf = "ADDITIONAL LINK: WALK BETWEEN KGX AND KLN IN 999 MINUTES\n";
+        let mca = "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821                    
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+BX         GNYGN161701                                                          
+LOKLYNN   1045 10451         TB                                                 
+LTKNGX    1235 12356     TF                                                     
+";
+
+        let path = std::env::temp_dir().join(format!("raildata-ttis-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, contents) in &[("ttis748/RJTTF748.MSN", msn), ("ttis748/RJTTF748.FLF", flf), ("ttis748/RJTTF748.MCA", mca)] {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut warnings = Vec::new();
+        let loaded = load_services_from_zip_with_warnings(&path, &mut warnings);
+        std::fs::remove_file(&path).unwrap();
+        #[cfg(feature = "cache")]
+        let _ = std::fs::remove_file(cache::cache_path(&path.display().to_string()));
+
+        let rail = loaded.unwrap();
+        assert_eq!(rail.stations.count(), 2);
+        assert_eq!(rail.fixedlinks.len(), 1);
+        assert_eq!(rail.timetable.services()[0].train_uid, "L22108");
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+/* Copyright James Lomax 2020 */
+
+// Amended timetables for a date range (e.g. strike or engineering work
+// timetables) searched instead of the base timetable on those dates. The
+// base data is left as it is, so queries for other dates are unaffected.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use chrono::NaiveDate;
+
+use raildata_core::error::{RailDataError, Result};
+use raildata_core::stations::StationId;
+use raildata_core::timetable::{Association, RailTime, Service, ServiceId, Timetable};
+
+use crate::travel_graph::TravelGraph;
+use crate::RailServices;
+
+const DAY: u32 = 24*60*60;
+
+/** Amended services layered over the base timetable between two dates */
+pub struct TimetableOverlay {
+    // Inclusive
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    // Train UIDs of base services which don't run, including those replaced by amended services
+    pub withdrawn: HashSet<String>,
+    // Amended services, numbered on from the base timetable's
+    pub services: Vec<Service>,
+    pub graph: TravelGraph
+}
+
+impl TimetableOverlay {
+    /**
+     * Layer $amended over $rail's timetable between $start and $end. An amended service
+     * replaces any base service with the same train UID, and base services with a train
+     * UID in $cancelled don't run.
+     */
+    pub fn new(rail: &RailServices, start: NaiveDate, end: NaiveDate, mut amended: Timetable, cancelled: impl IntoIterator<Item = String>) -> Self {
+        let mut withdrawn: HashSet<String> = cancelled.into_iter().collect();
+        let mut services = std::mem::take(amended.services_mut());
+        let first_id = rail.timetable.services().len() as ServiceId;
+        for (i, service) in services.iter_mut().enumerate() {
+            service.id = first_id + i as ServiceId;
+            withdrawn.insert(service.train_uid.clone());
+        }
+
+        let running = rail.timetable.services().iter()
+            .filter(|service| !withdrawn.contains(&service.train_uid))
+            .chain(services.iter());
+        let associations: Vec<Association> = rail.timetable.associations.iter().chain(&amended.associations).cloned().collect();
+        let mut graph = TravelGraph::from_services(&rail.stations, &rail.fixedlinks, running, &associations);
+        graph.set_bank_holidays(rail.graph.bank_holidays().clone());
+        tracing::info!(%start, %end, amended = services.len(), withdrawn = withdrawn.len(), "Built timetable overlay");
+
+        Self { start, end, withdrawn, services, graph }
+    }
+
+    /**
+     * Read amended services from the MCA file $mca and cancelled train UIDs from the
+     * optional CSV $cancelled. The dates are taken from the MCA header unless given.
+     */
+    pub fn load(rail: &RailServices, mca: &str, cancelled: Option<&str>, dates: Option<(NaiveDate, NaiveDate)>) -> Result<Self> {
+        let file = File::open(mca).map_err(|e| RailDataError::from(e).in_file(mca))?;
+        let amended = Timetable::read_mca_file(&rail.stations, &mut BufReader::new(file)).map_err(|e| e.in_file(mca))?;
+        let (start, end) = match (dates, &amended.header) {
+            (Some(dates), _) => dates,
+            (None, Some(header)) => (header.start_date, header.end_date),
+            (None, None) => return Err(RailDataError::MissingHeader.in_file(mca))
+        };
+
+        let cancelled = match cancelled {
+            Some(path) => {
+                let file = File::open(path).map_err(|e| RailDataError::from(e).in_file(path))?;
+                read_cancellations(&mut BufReader::new(file)).map_err(|e| e.in_file(path))?
+            }
+            None => Vec::new()
+        };
+        Ok(Self::new(rail, start, end, amended, cancelled))
+    }
+
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+}
+
+/** A date given as YYYY-MM-DD */
+pub fn parse_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| RailDataError::InvalidField { field: "date".to_string(), value: s.to_string() })
+}
+
+/** Train UIDs from the first column of a CSV, skipping blank lines and # comments */
+pub fn read_cancellations(reader: &mut dyn BufRead) -> Result<Vec<String>> {
+    let mut uids = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let uid = line.split(',').next().unwrap_or_default().trim();
+        if !uid.is_empty() && !uid.starts_with('#') {
+            uids.push(uid.to_string());
+        }
+    }
+    Ok(uids)
+}
+
+/** The timetable in effect on a date: the base timetable, or an overlay covering the date */
+#[derive(Clone, Copy)]
+pub struct TimetableView<'a> {
+    pub rail: &'a RailServices,
+    pub overlay: Option<&'a TimetableOverlay>,
+    // Searches only use services running on the date, if there is one
+    pub date: Option<NaiveDate>
+}
+
+impl<'a> TimetableView<'a> {
+    /** The base timetable, with every service running */
+    pub fn base(rail: &'a RailServices) -> Self {
+        Self { rail, overlay: None, date: None }
+    }
+
+    /** The view for $date, using the first of $overlays covering it. No date means the base timetable */
+    pub fn on(rail: &'a RailServices, overlays: &'a [TimetableOverlay], date: Option<NaiveDate>) -> Self {
+        let overlay = date.and_then(|date| overlays.iter().find(|overlay| overlay.covers(date)));
+        Self { rail, overlay, date }
+    }
+
+    pub fn graph(&self) -> &'a TravelGraph {
+        self.overlay.map_or(&self.rail.graph, |overlay| &overlay.graph)
+    }
+
+    /** A running service by ID */
+    pub fn service(&self, id: ServiceId) -> Option<&'a Service> {
+        let base = self.rail.timetable.services();
+        match self.overlay {
+            None => base.get(id as usize),
+            Some(overlay) if (id as usize) < base.len() => base.get(id as usize).filter(|s| !overlay.withdrawn.contains(&s.train_uid)),
+            Some(overlay) => overlay.services.get(id as usize - base.len())
+        }
+    }
+
+    /** Every running schedule of the train $uid, from the overlay if it amends the train */
+    pub fn services_by_uid(&self, uid: &str) -> Vec<&'a Service> {
+        match self.overlay {
+            Some(overlay) if overlay.withdrawn.contains(uid) => overlay.services.iter().filter(|service| service.train_uid == uid).collect(),
+            _ => self.rail.timetable.get_by_uid(uid)
+        }
+    }
+
+    /** All running services */
+    pub fn services(&self) -> impl Iterator<Item = &'a Service> + 'a {
+        let overlay = self.overlay;
+        let amended = overlay.map(|overlay| overlay.services.iter()).into_iter().flatten();
+        self.rail.timetable.services().iter()
+            .filter(move |service| overlay.is_none_or(|overlay| !overlay.withdrawn.contains(&service.train_uid)))
+            .chain(amended)
+    }
+
+    /**
+     * Running services passengers can board at $station, whatever the time, by ID.
+     * Base services come from the timetable's calls index (`Timetable::departures`)
+     */
+    pub fn services_from(&self, station: StationId) -> Vec<&'a Service> {
+        let overlay = self.overlay;
+        let amended = overlay.map(|overlay| overlay.services.iter()).into_iter().flatten()
+            .filter(|service| service.stops.split_last().is_some_and(|(_, stops)| stops.iter().any(|stop| stop.station == station && stop.activity.can_board())));
+        let mut services: Vec<&'a Service> = self.rail.timetable.departures(station, RailTime::new(0, 0), DAY).into_iter()
+            .map(|departure| departure.service)
+            .filter(|service| overlay.is_none_or(|overlay| !overlay.withdrawn.contains(&service.train_uid)))
+            .chain(amended)
+            .collect();
+        // Services calling more than once are listed once
+        services.sort_by_key(|service| service.id);
+        services.dedup_by_key(|service| service.id);
+        services
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use raildata_core::stations::{Station, StationList};
+    use raildata_core::timetable::{RailTime, Stop};
+    use crate::travel_graph::{JourneyConstraints, QueryScratch};
+
+    fn service(uid: &str, stops: Vec<Stop>) -> Service {
+        Service::simple(0, uid, stops)
+    }
+
+    fn date(d: u32) -> NaiveDate {
+        parse_date(&format!("2020-09-{:02}", d)).unwrap()
+    }
+
+    #[test]
+    fn test_overlay() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let mut timetable = Timetable::new(
+            None,
+            vec![
+                service("C00001", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")]),
+                service("C00002", vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1050", "1050")])
+            ],
+            Vec::new()
+        );
+        timetable.services_mut()[1].id = 1;
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let rail = RailServices { stations, fixedlinks: Vec::new(), timetable, graph };
+
+        // On strike days the 09:00 is cancelled and the 10:00 runs slower
+        let amended = Timetable::new(
+            None,
+            vec![service("C00002", vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1110", "1110")])],
+            Vec::new()
+        );
+        let cancelled = read_cancellations(&mut io::Cursor::new("# Cancelled\nC00001,strike\n\n")).unwrap();
+        let overlays = vec![TimetableOverlay::new(&rail, date(14), date(15), amended, cancelled)];
+
+        let search = |view: TimetableView| {
+            let graph = view.graph();
+            graph.compute_journeys(&mut QueryScratch::new(graph), view.date, RailTime::new(8, 0), 0, vec![1], 0, 0, &JourneyConstraints::default()).remove(0)
+        };
+        assert_eq!(search(TimetableView::on(&rail, &overlays, Some(date(13)))).time, 110*60);
+        assert_eq!(search(TimetableView::on(&rail, &overlays, None)).time, 110*60);
+
+        let strike = TimetableView::on(&rail, &overlays, Some(date(15)));
+        let journey = search(strike);
+        assert_eq!(journey.time, 190*60);
+        assert_eq!(journey.links[0], crate::Link::Rail(crate::travel_graph::RailLink {
+            dst: 1, service: 2, depart: RailTime::new(10, 0).into(), time: 70*60, depart_platform: None, arrive_platform: None,
+            calls: Box::default()
+        }));
+        assert_eq!(strike.service(2).unwrap().train_uid, "C00002");
+        assert!(strike.service(0).is_none());
+        assert_eq!(strike.services().count(), 1);
+        assert_eq!(strike.services_from(0).iter().map(|service| service.id).collect::<Vec<_>>(), vec![2]);
+        assert!(strike.services_from(1).is_empty());
+        assert_eq!(strike.services_by_uid("C00002")[0].id, 2);
+        assert!(strike.services_by_uid("C00001").is_empty());
+        assert_eq!(TimetableView::base(&rail).services_by_uid("C00001")[0].id, 0);
+
+        // The base timetable is untouched
+        assert_eq!(rail.timetable.services()[1].stops[1].arrival.time(), RailTime::new(10, 50));
+        assert_eq!(TimetableView::base(&rail).services().count(), 2);
+        assert_eq!(TimetableView::base(&rail).services_from(0).len(), 2);
+    }
+}
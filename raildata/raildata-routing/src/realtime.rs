@@ -0,0 +1,175 @@
+/* Copyright James Lomax 2020 */
+
+// Current running from Darwin layered over the day's timetable. Delayed trains
+// are replaced by copies with their forecast times, with the delay carried on
+// past the last forecast, and cancelled trains are withdrawn. The result is a
+// TimetableOverlay for the day, so searches use it the same way as amended
+// timetables and the base data is left alone.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+
+use chrono::NaiveDate;
+
+use raildata_core::darwin::{read_push_port, LocationUpdate, RunningUpdate};
+use raildata_core::error::{RailDataError, Result};
+use raildata_core::timetable::{BankHolidayRunning, Calendar, RailTime, Service, ServiceTime, Stop, Stp, Timetable};
+
+use crate::overlay::TimetableOverlay;
+use crate::RailServices;
+
+const DAY: i64 = 24*60*60;
+
+// Seconds from $scheduled to $actual, negative if early. Assumes they're within 12 hours
+fn offset(scheduled: RailTime, actual: RailTime) -> i64 {
+    let secs = scheduled.timetil(&actual) as i64;
+    if secs > DAY / 2 { secs - DAY } else { secs }
+}
+
+// $t moved by $secs, no earlier than the start of the service's first day
+fn shift(t: ServiceTime, secs: i64) -> ServiceTime {
+    ServiceTime::default().add((t.secs() as i64 + secs).max(0) as u32)
+}
+
+/** $service as it's running given the latest update at each location, None if it no longer runs */
+fn running(rail: &RailServices, service: &Service, date: NaiveDate, locations: &HashMap<&str, &LocationUpdate>) -> Option<Service> {
+    let mut delay = 0;
+    let mut stops = Vec::with_capacity(service.stops.len());
+    for stop in &service.stops {
+        let station = rail.stations.get(stop.station)?;
+        let update = station.tiplocs.iter().find_map(|tiploc| locations.get(tiploc.as_str()));
+        if update.is_some_and(|update| update.cancelled) {
+            continue;
+        }
+
+        let arrival = match update.and_then(|update| update.arrival) {
+            Some(arrival) => shift(stop.arrival, offset(stop.arrival.time(), arrival)),
+            None => shift(stop.arrival, delay)
+        };
+        // Late trains leave as soon as they've arrived, making up any time allowed at the stop
+        let departure = match update.and_then(|update| update.departure) {
+            Some(departure) => shift(stop.departure, offset(stop.departure.time(), departure)),
+            None if arrival > stop.departure => arrival,
+            None => stop.departure
+        };
+        delay = departure.secs() as i64 - stop.departure.secs() as i64;
+        stops.push(Stop { station: stop.station, arrival, departure, platform: stop.platform, activity: stop.activity });
+    }
+
+    if stops.len() < 2 {
+        return None;
+    }
+    Some(Service {
+        id: 0,
+        train_uid: service.train_uid.clone(),
+        stops,
+        reservations: service.reservations,
+        operator: service.operator,
+        category: service.category,
+        power_type: service.power_type,
+        seating: service.seating,
+        sleepers: service.sleepers,
+        bank_holidays: BankHolidayRunning::Runs,
+        calendar: Some(Calendar { runs_from: date, runs_to: date, days_run: [true; 7], stp: Stp::Overlay })
+    })
+}
+
+/**
+ * Layer the $updates for trains starting on $date over $rail's timetable. Trains
+ * without updates run as timetabled.
+ */
+pub fn realtime_overlay(rail: &RailServices, date: NaiveDate, updates: &[RunningUpdate]) -> TimetableOverlay {
+    // The latest update at each location of each train
+    let mut by_uid: HashMap<&str, HashMap<&str, &LocationUpdate>> = HashMap::new();
+    for update in updates.iter().filter(|update| update.start_date == date) {
+        let locations = by_uid.entry(&update.train_uid).or_default();
+        for location in &update.locations {
+            locations.insert(&location.tiploc, location);
+        }
+    }
+
+    let mut services = Vec::new();
+    let mut cancelled = HashSet::new();
+    for service in rail.timetable.services_on(date) {
+        let locations = match by_uid.get(service.train_uid.as_str()) {
+            Some(locations) => locations,
+            None => continue
+        };
+        match running(rail, service, date, locations) {
+            Some(amended) => services.push(amended),
+            None => {
+                cancelled.insert(service.train_uid.clone());
+            }
+        }
+    }
+
+    tracing::info!(%date, updates = updates.len(), amended = services.len(), cancelled = cancelled.len(), "Applied real-time updates");
+    let amended = Timetable::new(None, services, Vec::new());
+    TimetableOverlay::new(rail, date, date, amended, cancelled)
+}
+
+/** Read a Push Port document, e.g. a Darwin snapshot, from $path and layer it over $rail for $date */
+pub fn load_realtime(rail: &RailServices, path: &str, date: NaiveDate) -> Result<TimetableOverlay> {
+    let file = File::open(path).map_err(|e| RailDataError::from(e).in_file(path))?;
+    let updates = read_push_port(BufReader::new(file)).map_err(|e| e.in_file(path))?;
+    Ok(realtime_overlay(rail, date, &updates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::{Station, StationList};
+    use crate::overlay::TimetableView;
+    use crate::travel_graph::QueryScratch;
+    use crate::TravelGraph;
+
+    fn location(tiploc: &str, arrival: Option<RailTime>, departure: Option<RailTime>, cancelled: bool) -> LocationUpdate {
+        LocationUpdate { tiploc: tiploc.to_string(), arrival, departure, cancelled }
+    }
+
+    #[test]
+    fn test_realtime_overlay() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("ROYSTON", "Royston", "RYS"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "C00001", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0915", "0920"), Stop::simple(2, "0950", "0950")]),
+                Service::simple(1, "C00002", vec![Stop::simple(0, "0930", "0930"), Stop::simple(2, "1020", "1020")])
+            ],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let rail = RailServices { stations, fixedlinks: Vec::new(), timetable, graph };
+
+        // The 09:00 left 10 minutes late and makes up 3 at Royston, the 09:30 is cancelled, and yesterday's update is ignored
+        let date = NaiveDate::from_ymd_opt(2020, 6, 5).unwrap();
+        let update = |uid: &str, start_date, locations| RunningUpdate { rid: String::new(), train_uid: uid.to_string(), start_date, locations };
+        let updates = vec![
+            update("C00001", date, vec![location("CAMBDGE", None, Some(RailTime::new(9, 10)), false)]),
+            update("C00002", date, vec![location("CAMBDGE", None, None, true), location("KNGX", None, None, true)]),
+            update("C00001", date.pred_opt().unwrap(), vec![location("KNGX", Some(RailTime::new(12, 0)), None, false)])
+        ];
+        let overlay = realtime_overlay(&rail, date, &updates);
+
+        let times: Vec<(String, String)> = overlay.services[0].stops.iter().map(|s| (s.arrival.to_24h(), s.departure.to_24h())).collect();
+        assert_eq!(times, vec![
+            ("0900".to_string(), "0910".to_string()),
+            ("0925".to_string(), "0925".to_string()),
+            ("0955".to_string(), "0955".to_string())
+        ]);
+        assert!(overlay.withdrawn.contains("C00002"));
+
+        let view = TimetableView::on(&rail, std::slice::from_ref(&overlay), Some(date));
+        let graph = view.graph();
+        let times = graph.compute_all_times(&mut QueryScratch::new(graph), view.date, RailTime::new(9, 0), 0, 0, 0);
+        assert_eq!(times[2], Some(55*60));
+        // Nothing after the cancelled 09:30 within the day the overlay covers
+        let times = graph.compute_all_times(&mut QueryScratch::new(graph), view.date, RailTime::new(9, 20), 0, 0, 0);
+        assert_eq!(times[2], None);
+    }
+}
@@ -0,0 +1,222 @@
+/* Copyright James Lomax 2020 */
+
+// Where loaded services come from. Each source reads the stations, fixed links
+// and timetable its own way, e.g. CIF files or a GTFS feed (raildata-interop),
+// and loading builds the travel graph and caches the result the same way for
+// all of them.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use raildata_core::fixed_links::{self, FixedLink};
+use raildata_core::{RailDataError, StationList, Timetable};
+
+use crate::{build, parse_warnings, LoadWarning, RailServices};
+
+/** Everything a source reads, from which the travel graph is built */
+pub struct TimetableData {
+    pub stations: StationList,
+    pub fixedlinks: Vec<FixedLink>,
+    pub timetable: Timetable
+}
+
+/** A source of timetable data, loaded with `load_from` */
+pub trait TimetableSource {
+    /** The source's path, naming it in logs and its cache */
+    fn name(&self) -> String;
+
+    /** The files read, so a cache is only used while it's newer than all of them */
+    fn files(&self) -> Vec<String>;
+
+    /** Read the data, adding anything tolerated to $warnings */
+    fn read(&self, warnings: &mut Vec<LoadWarning>) -> Result<TimetableData, RailDataError>;
+}
+
+/** Load services from $source, or its cache while that's up to date, collecting what was skipped or merged in $warnings */
+#[tracing::instrument(skip_all, fields(source = %source.name()))]
+pub fn load_from(source: &dyn TimetableSource, warnings: &mut Vec<LoadWarning>) -> Result<RailServices, RailDataError> {
+    #[cfg(feature = "cache")]
+    let name = source.name();
+    #[cfg(feature = "cache")]
+    {
+        let files = source.files();
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+        if let Some(rail) = crate::cache::load_fresh(&name, &files, warnings) {
+            return Ok(rail);
+        }
+    }
+
+    let data = source.read(warnings)?;
+    let rail = build(data.stations, data.fixedlinks, data.timetable, warnings)?;
+    #[cfg(feature = "cache")]
+    crate::cache::store(&name, &rail, warnings);
+    Ok(rail)
+}
+
+/** A CIF extract as files sharing a prefix, e.g. ttis748/RJTTF748.MSN, .FLF (or .ALF) and .MCA */
+pub struct CifFiles {
+    prefix: String
+}
+
+impl CifFiles {
+    pub fn new(prefix: &str) -> Self {
+        Self { prefix: prefix.to_string() }
+    }
+
+    // The ALF has everything in the FLF and when the links can be used, so is preferred if it's there
+    fn links(&self) -> (String, bool) {
+        let alf = format!("{}.ALF", self.prefix);
+        if Path::new(&alf).exists() { (alf, true) } else { (format!("{}.FLF", self.prefix), false) }
+    }
+}
+
+impl TimetableSource for CifFiles {
+    fn name(&self) -> String {
+        self.prefix.clone()
+    }
+
+    fn files(&self) -> Vec<String> {
+        vec![format!("{}.MSN", self.prefix), self.links().0, format!("{}.MCA", self.prefix)]
+    }
+
+    fn read(&self, warnings: &mut Vec<LoadWarning>) -> Result<TimetableData, RailDataError> {
+        let msnname = format!("{}.MSN", self.prefix);
+        let (flfname, alf) = self.links();
+        let mcaname = format!("{}.MCA", self.prefix);
+        let open = |name: &str| File::open(name).map_err(|e| RailDataError::from(e).in_file(name));
+
+        let stations = read_stations(&msnname, &mut BufReader::new(open(&msnname)?), warnings)?;
+        let fixedlinks = read_fixed_links(&flfname, alf, &stations, &mut BufReader::new(open(&flfname)?), warnings)?;
+        let timetable = read_timetable(&mcaname, &stations, &mut BufReader::with_capacity(1024*1024, open(&mcaname)?), warnings)?;
+        Ok(TimetableData { stations, fixedlinks, timetable })
+    }
+}
+
+/** A CIF extract in its zip archive (e.g. ttis748.zip), read without extracting it */
+#[cfg(feature = "zip")]
+pub struct CifArchive {
+    path: std::path::PathBuf
+}
+
+#[cfg(feature = "zip")]
+impl CifArchive {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+}
+
+#[cfg(feature = "zip")]
+impl TimetableSource for CifArchive {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn files(&self) -> Vec<String> {
+        vec![self.name()]
+    }
+
+    fn read(&self, warnings: &mut Vec<LoadWarning>) -> Result<TimetableData, RailDataError> {
+        let archive_name = self.name();
+        let zip_err = |e: zip::result::ZipError| RailDataError::from(std::io::Error::other(e)).in_file(&archive_name);
+        let file = File::open(&self.path).map_err(|e| RailDataError::from(e).in_file(&archive_name))?;
+        let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(zip_err)?;
+
+        // The members are named after the extract, e.g. RJTTF748.MSN, possibly in a directory
+        let find = |archive: &zip::ZipArchive<_>, ext: &str| archive.file_names()
+            .find(|name| name.rsplit_once('.').is_some_and(|(_, e)| e.eq_ignore_ascii_case(ext)))
+            .map(|name| name.to_string());
+        let member = |archive: &zip::ZipArchive<_>, ext: &str| {
+            find(archive, ext).ok_or_else(|| RailDataError::from(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("No .{} file in the archive", ext))).in_file(&archive_name))
+        };
+        let alf = find(&archive, "ALF");
+        let (msn, mca) = (member(&archive, "MSN")?, member(&archive, "MCA")?);
+        let flf = match &alf {
+            Some(alf) => alf.clone(),
+            None => member(&archive, "FLF")?
+        };
+        let in_archive = |name: &str| format!("{}/{}", archive_name, name);
+
+        let stations = read_stations(&in_archive(&msn), &mut BufReader::new(archive.by_name(&msn).map_err(zip_err)?), warnings)?;
+        let fixedlinks = read_fixed_links(&in_archive(&flf), alf.is_some(), &stations, &mut BufReader::new(archive.by_name(&flf).map_err(zip_err)?), warnings)?;
+        let mut mcareader = BufReader::with_capacity(1024*1024, archive.by_name(&mca).map_err(zip_err)?);
+        let timetable = read_timetable(&in_archive(&mca), &stations, &mut mcareader, warnings)?;
+        Ok(TimetableData { stations, fixedlinks, timetable })
+    }
+}
+
+/**
+ * Load services from the contents of an MSN, FLF (or ALF if $alf) and MCA file, e.g. fetched
+ * by a browser, without file access. Nothing is cached
+ */
+pub fn load_services_from_bytes(msn: &[u8], links: &[u8], alf: bool, mca: &[u8], warnings: &mut Vec<LoadWarning>)
+        -> Result<RailServices, RailDataError> {
+    let stations = read_stations("MSN", &mut &msn[..], warnings)?;
+    let fixedlinks = read_fixed_links(if alf { "ALF" } else { "FLF" }, alf, &stations, &mut &links[..], warnings)?;
+    let timetable = read_timetable("MCA", &stations, &mut &mca[..], warnings)?;
+    build(stations, fixedlinks, timetable, warnings)
+}
+
+// Master Station Names (MSN) file, called $name in errors and warnings
+fn read_stations(name: &str, reader: &mut dyn BufRead, warnings: &mut Vec<LoadWarning>) -> Result<StationList, RailDataError> {
+    let mut issues = Vec::new();
+    let stations = StationList::read_msn_file_with_warnings(reader, &mut issues).map_err(|e| e.in_file(name))?;
+    parse_warnings(warnings, name, issues);
+    Ok(stations)
+}
+
+// Fixed Leg File (FLF), or the Additional Links File (ALF) if $alf
+fn read_fixed_links(name: &str, alf: bool, stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<LoadWarning>)
+        -> Result<Vec<FixedLink>, RailDataError> {
+    let mut issues = Vec::new();
+    let fixedlinks = if alf {
+        fixed_links::parse_additional_links_with_warnings(stations, reader, &mut issues)
+    } else {
+        fixed_links::parse_fixed_links_with_warnings(stations, reader, &mut issues)
+    };
+    let fixedlinks = fixedlinks.map_err(|e| e.in_file(name))?;
+    parse_warnings(warnings, name, issues);
+    Ok(fixedlinks)
+}
+
+// Services (MCA) file
+fn read_timetable(name: &str, stations: &StationList, reader: &mut dyn BufRead, warnings: &mut Vec<LoadWarning>) -> Result<Timetable, RailDataError> {
+    let mut issues = Vec::new();
+    let timetable = Timetable::read_mca_file_with_warnings(stations, reader, &mut issues).map_err(|e| e.in_file(name))?;
+    parse_warnings(warnings, name, issues);
+    Ok(timetable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_from_bytes() {
+        let msn = "/!! Start of file
+A                             FILE-SPEC=05 1.00 25/08/20 18.05.31   748           
+A    KINGS LYNN                    1KLYNN  KLN   KLN15623 63201 5                 
+A    LONDON KINGS CROSS            3KNGX   KGX   KGX15303 6183015                 
+";
+        let flf = "ADDITIONAL LINK: WALK BETWEEN KGX AND KLN IN 999 MINUTES\n";
+        let mca = "HDTPS.UDFROC1.PD2008252508201805DFROC1A       FA250820250821                    
+BSNL221082005232012120000010 PXX1T25    121725000 EMU365 100D     B            P
+BX         GNYGN161701                                                          
+LOKLYNN   1045 10451         TB                                                 
+LTKNGX    1235 12356     TF                                                     
+";
+
+        let mut warnings = Vec::new();
+        let rail = load_services_from_bytes(msn.as_bytes(), flf.as_bytes(), false, mca.as_bytes(), &mut warnings).unwrap();
+        assert_eq!(rail.stations.count(), 2);
+        assert_eq!(rail.fixedlinks.len(), 1);
+        assert_eq!(rail.timetable.services()[0].train_uid, "L22108");
+        assert!(warnings.is_empty());
+
+        // Errors name the kind of file, as there's no path
+        let flf = "ADDITIONAL LINK: WALK BETWEEN KGX AND XXX IN 5 MINUTES\n";
+        let e = load_services_from_bytes(msn.as_bytes(), flf.as_bytes(), false, mca.as_bytes(), &mut Vec::new());
+        assert!(matches!(e, Err(RailDataError::InFile { file, .. }) if file == "FLF"));
+    }
+}
@@ -0,0 +1,2321 @@
+/** Copyright James Lomax 2020 */
+
+use raildata_core::error::RailDataError;
+use raildata_core::stations::{StationId, StationList};
+use std::collections::HashMap;
+
+use raildata_core::timetable::{superseding_calendars, Association, BankHolidayRunning, Calendar, Platform, Service, ServiceId, ServiceTime, Stop, Stp, Timetable, RailTime};
+use raildata_core::fixed_links;
+use raildata_core::fares::{FareEstimate, FareTable};
+use raildata_core::holidays::BankHolidays;
+use raildata_core::fixed_links::{FixedLinkKind, LinkAvailability};
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::connection_scan::{LazyConnections, ScanScratch};
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RailLink {
+    pub dst: StationId,
+    pub service: ServiceId,
+    // On the service's schedule, so links after midnight are on a later day of the service
+    pub depart: ServiceTime,
+    pub time: u32,
+    // Platforms boarded from and alighted at, where published
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub depart_platform: Option<Platform>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub arrive_platform: Option<Platform>,
+    // Where the train calls between boarding and $dst, on links joined into a journey's leg. Empty on links in the graph
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub calls: Box<[Call]>
+}
+
+/** A stop a train makes on the way through a journey leg */
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Call {
+    pub station: StationId,
+    // On the service's schedule, as for RailLink::depart
+    pub arrive: ServiceTime,
+    pub depart: ServiceTime,
+    pub platform: Option<Platform>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedLink {
+    pub dst: StationId,
+    pub time: u32,
+    pub kind: FixedLinkKind,
+    // Boxed as few links have one, keeping Link small
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub availability: Option<Box<LinkAvailability>>
+}
+
+impl FixedLink {
+    /** Seconds to wait from $t until the link can be set off on */
+    pub fn wait(&self, t: RailTime) -> u32 {
+        self.availability.as_ref().map_or(0, |a| a.wait_from(t))
+    }
+
+    /** Seconds back from $t to the latest time at or before it the link can be set off on */
+    pub fn back(&self, t: RailTime) -> u32 {
+        self.availability.as_ref().map_or(0, |a| a.back_from(t))
+    }
+
+    /** Whether the link can be used on $date */
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        self.availability.as_ref().is_none_or(|a| a.calendar.runs_on(date))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Link {
+    Rail(RailLink),
+    Fixed(FixedLink),
+    Dummy
+}
+
+impl Link {
+    fn simple_rail(dst: StationId, service: ServiceId, depart: &str, time: u32) -> Self {
+        Link::Rail(RailLink {
+            dst,
+            service,
+            depart: ServiceTime::from_24h(depart).unwrap(),
+            time,
+            depart_platform: None,
+            arrive_platform: None,
+            calls: Box::default()
+        })
+    }
+    
+    fn simple_fixed(dst: StationId, time: u32, kind: FixedLinkKind) -> Self {
+        Link::Fixed(FixedLink {
+            dst,
+            time,
+            kind,
+            availability: None
+        })
+    }
+
+    fn service(&self) -> Option<ServiceId> {
+        match self {
+            Link::Rail(rl) => Some(rl.service),
+            _ => None
+        }
+    }
+
+    /**
+     * Any time we are changing (i.e. not just sitting) from self to other service
+     */
+    fn ischange(&self, other: &Self) -> bool {
+        self.service() != other.service() || self.service() == None
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Journey {
+    pub origin: StationId,
+    pub depart: RailTime,
+    pub time: u32,
+    pub links: Vec<Link>
+}
+
+/**
+ * A leg of a journey with its times. Days count from the day of the search's
+ * start time, so a leg after waiting overnight for the first train has day 1.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct JourneyLeg<'a> {
+    pub from: StationId,
+    pub to: StationId,
+    pub depart: RailTime,
+    pub arrive: RailTime,
+    pub depart_day: u32,
+    pub arrive_day: u32,
+    // Seconds spent at $from before the leg departs
+    pub wait: u32,
+    pub link: &'a Link
+}
+
+const DAY: u32 = 24*60*60;
+
+// Search labels' link for the start of a journey, standing in for Link::Dummy
+const NO_LINK: u32 = u32::MAX;
+static DUMMY: Link = Link::Dummy;
+
+impl Journey {
+    /** The legs of the journey in order, with the waits between them */
+    pub fn legs(&self) -> Vec<JourneyLeg<'_>> {
+        let mut legs = Vec::new();
+        // Seconds after departure at which each leg departs and arrives
+        let mut spans = Vec::new();
+        let mut station = self.origin;
+        let mut time = self.depart;
+        let mut elapsed = 0;
+
+        for link in &self.links {
+            let (dst, depart, duration) = match link {
+                Link::Rail(rl) => (rl.dst, rl.depart.time(), rl.time),
+                Link::Fixed(fl) => (fl.dst, time.add(fl.wait(time)), fl.time),
+                Link::Dummy => continue
+            };
+
+            let wait = time.timetil(&depart);
+            elapsed += wait;
+            spans.push((elapsed, elapsed + duration));
+            legs.push(JourneyLeg {
+                from: station,
+                to: dst,
+                depart,
+                arrive: depart.add(duration),
+                depart_day: 0,
+                arrive_day: 0,
+                wait,
+                link
+            });
+            elapsed += duration;
+            station = dst;
+            time = depart.add(duration);
+        }
+
+        // The journey time counts from the search's start, which may be the day before departure
+        let before = self.time.saturating_sub(elapsed);
+        let start = self.depart.sub(before % DAY).secs();
+        for (leg, (depart, arrive)) in legs.iter_mut().zip(spans) {
+            leg.depart_day = (start + before + depart) / DAY;
+            leg.arrive_day = (start + before + arrive) / DAY;
+        }
+        legs
+    }
+
+    /** When the journey leaves its origin, for a search from $date. None if it can't be made */
+    pub fn departure(&self, date: NaiveDate) -> Option<NaiveDateTime> {
+        let legs = self.legs();
+        let (depart, day) = legs.first().map_or((self.depart, 0), |leg| (leg.depart, leg.depart_day));
+        self.on(date, depart, day)
+    }
+
+    /** When the journey reaches its destination, for a search from $date, on a later day if it runs overnight */
+    pub fn arrival(&self, date: NaiveDate) -> Option<NaiveDateTime> {
+        let legs = self.legs();
+        let (arrive, day) = legs.last().map_or((self.depart, 0), |leg| (leg.arrive, leg.arrive_day));
+        self.on(date, arrive, day)
+    }
+
+    // $time $day days after $date, if the journey can be made
+    fn on(&self, date: NaiveDate, time: RailTime, day: u32) -> Option<NaiveDateTime> {
+        if self.time == u32::MAX {
+            return None;
+        }
+        date.checked_add_days(chrono::Days::new(day as u64)).map(|date| time.on(date))
+    }
+
+    /** The station the journey ends at, its origin if it has no links */
+    pub fn destination(&self) -> StationId {
+        self.links.iter().rev().find_map(|link| match link {
+            Link::Rail(rl) => Some(rl.dst),
+            Link::Fixed(fl) => Some(fl.dst),
+            Link::Dummy => None
+        }).unwrap_or(self.origin)
+    }
+
+    /** Estimated walk-up prices from the journey's origin to its destination, valid on $date if given. None without fares between them */
+    pub fn fare(&self, fares: &FareTable, stations: &StationList, date: Option<NaiveDate>) -> Option<FareEstimate> {
+        let crs = |id: StationId| stations.get(id).map(|station| station.crs_code.as_str());
+        fares.estimate(crs(self.origin)?, crs(self.destination())?, date)
+    }
+
+    // The journey from a search starting $later seconds after this one's at $start, if it's the same journey.
+    // $change is the time needed to board a first train at each origin
+    fn started_later(&self, start: RailTime, later: u32, change: impl Fn(StationId) -> u32) -> Option<Journey> {
+        if self.time == u32::MAX {
+            return Some(self.clone());
+        }
+        let legs = self.legs();
+        let first = match legs.first() {
+            Some(leg) => leg,
+            // At the destination already, from the later start
+            None => return Some(Journey { depart: start.add(later), ..self.clone() })
+        };
+        let leaves = (first.depart_day*DAY + first.depart.secs()).saturating_sub(start.secs());
+        let needed = if matches!(first.link, Link::Rail(_)) { change(first.from) } else { 0 };
+        (leaves >= later + needed).then(|| Journey { time: self.time - later, ..self.clone() })
+    }
+
+    /** Changes between rail services, 0 for a direct or walking-only journey */
+    pub fn changes(&self) -> u32 {
+        let services = self.links.iter().filter(|link| matches!(link, Link::Rail(_))).count() as u32;
+        services.max(1) - 1
+    }
+}
+
+// A station's links while the graph is being built
+#[derive(Clone, PartialEq, Debug)]
+struct TGNode {
+    links: Vec<Link>,
+    transfer_time: u32
+}
+
+// When a service runs
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ServiceDays {
+    calendar: Option<Calendar>,
+    // Of STP cancellations and overlays of the same train, which it doesn't run on
+    superseded: Vec<Calendar>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    bank_holidays: BankHolidayRunning
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TravelGraph {
+    // Every station's links in one array, those from station i at links[offsets[i]..offsets[i + 1]].
+    // Searches refer to links by index rather than copying them
+    links: Vec<Link>,
+    offsets: Vec<u32>,
+    // Indexed by station
+    transfer_times: Vec<u32>,
+    // Indexed by service ID, None for services which run every day
+    calendars: Vec<Option<ServiceDays>>,
+    // For services which don't run on bank holidays
+    #[cfg_attr(feature = "serde", serde(default))]
+    holidays: BankHolidays,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    connections: LazyConnections
+}
+
+/**
+ * How to search a graph. Connection Scan's journey times are never longer, as
+ * Dijkstra keeps one arrival at each station so can miss staying aboard a train
+ * or, with flexi_depart, a quicker journey leaving later.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Engine {
+    // Dijkstra's algorithm over each station's links
+    #[default]
+    Dijkstra,
+    // Scanning every link in departure order, much faster when searching to every station
+    ConnectionScan
+}
+
+/** A problem found while building the graph. The offending service or fixed link is left out */
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GraphIssue {
+    // A service (by train UID) with fewer than two stops
+    ShortService(String),
+    // A service or fixed link referring to a station ID outside the station list
+    UnknownStation(StationId)
+}
+
+impl From<GraphIssue> for RailDataError {
+    fn from(issue: GraphIssue) -> Self {
+        match issue {
+            GraphIssue::ShortService(uid) => RailDataError::ShortService(uid),
+            GraphIssue::UnknownStation(id) => RailDataError::UnknownStation(format!("#{}", id))
+        }
+    }
+}
+
+/** Restrictions on the journeys a search finds. The default allows any journey */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JourneyConstraints {
+    // Most changes between trains, None for any number
+    pub max_changes: Option<u32>,
+    // Stations the journey mustn't call at, whether changing there or staying aboard
+    pub avoid: Vec<StationId>,
+    // A station the journey must call at on the way
+    pub via: Option<StationId>,
+    // Services which mustn't be used, e.g. those of an operator on strike
+    pub exclude_services: Vec<ServiceId>,
+    // Percentage of each station's change time to allow, e.g. 150 for cautious travellers. None for 100
+    pub change_time_percent: Option<u32>
+}
+
+/** Counts of the work done by searches, to diagnose slow queries */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    pub searches: u64,
+    // Labels taken off the queue and followed, each the best way yet found to a station
+    pub settled: u64,
+    // Most labels queued at once in any one search
+    pub queue_peak: usize
+}
+
+impl SearchStats {
+    fn record(&mut self, settled: u64, queue_peak: usize) {
+        self.searches += 1;
+        self.settled += settled;
+        self.queue_peak = self.queue_peak.max(queue_peak);
+    }
+
+    fn add(&mut self, other: &SearchStats) {
+        self.searches += other.searches;
+        self.settled += other.settled;
+        self.queue_peak = self.queue_peak.max(other.queue_peak);
+    }
+}
+
+/**
+ * Working memory for journey searches. The graph is only read during a search
+ * so it can be shared between threads, each keeping its own scratch to reuse
+ * across queries rather than allocating per query.
+ */
+pub struct QueryScratch {
+    pathfinder: dijkstras::TimeDijkstras,
+    alternatives: dijkstras::KBestDijkstras,
+    scan: ScanScratch
+}
+
+impl QueryScratch {
+    pub fn new(graph: &TravelGraph) -> Self {
+        Self {
+            pathfinder: dijkstras::TimeDijkstras::new(graph.station_count(), 0),
+            alternatives: dijkstras::KBestDijkstras::new(),
+            scan: ScanScratch::default()
+        }
+    }
+
+    /** Count the work done by later Dijkstra searches with this scratch, until taken by `take_stats` */
+    pub fn collect_stats(&mut self) {
+        self.pathfinder.stats.get_or_insert_with(SearchStats::default);
+        self.alternatives.stats.get_or_insert_with(SearchStats::default);
+    }
+
+    /** The work done since stats were last taken, starting the count again. None unless collecting */
+    pub fn take_stats(&mut self) -> Option<SearchStats> {
+        let mut stats = self.pathfinder.stats.replace(SearchStats::default())?;
+        if let Some(alternatives) = self.alternatives.stats.replace(SearchStats::default()) {
+            stats.add(&alternatives);
+        }
+        Some(stats)
+    }
+}
+
+impl TravelGraph {
+    /** Build the graph, leaving out services and fixed links which can't be used (see new_checked) */
+    pub fn new(stations: &StationList, fixedlinks: &Vec<fixed_links::FixedLink>, timetable: &Timetable) -> Self {
+        Self::from_services(stations, fixedlinks, timetable.services(), &timetable.associations)
+    }
+
+    /** Build the graph from a selection of services, e.g. a timetable with amendments layered over it */
+    pub fn from_services<'a>(stations: &StationList, fixedlinks: &Vec<fixed_links::FixedLink>, services: impl IntoIterator<Item = &'a Service>,
+            associations: &[Association]) -> Self {
+        let mut issues = Vec::new();
+        let graph = Self::build(stations, fixedlinks, services, associations, &mut issues);
+        for issue in issues {
+            tracing::debug!("Ignoring: {:?}", issue);
+        }
+        graph
+    }
+
+    /**
+     * Build the graph, also returning the services and fixed links left out.
+     * In strict mode the first of these is an error instead.
+     */
+    pub fn new_checked(stations: &StationList, fixedlinks: &Vec<fixed_links::FixedLink>, timetable: &Timetable, strict: bool)
+            -> Result<(Self, Vec<GraphIssue>), RailDataError> {
+        let mut issues = Vec::new();
+        let graph = Self::build(stations, fixedlinks, timetable.services(), &timetable.associations, &mut issues);
+        match issues.first() {
+            Some(issue) if strict => Err(issue.clone().into()),
+            _ => Ok((graph, issues))
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn build<'a>(stations: &StationList, fixedlinks: &Vec<fixed_links::FixedLink>, services: impl IntoIterator<Item = &'a Service>,
+            associations: &[Association], issues: &mut Vec<GraphIssue>) -> Self {
+        // Each station's links are gathered separately, then packed into one array at the end
+        let mut graph = GraphBuilder {
+            stations: stations.iter().map(|station| TGNode { links: Vec::new(), transfer_time: station.min_change_time*60 }).collect(),
+            calendars: Vec::new()
+        };
+        let count = graph.stations.len();
+
+        // Add all the fixed links
+        for flink in fixedlinks {
+            if flink.a >= count || flink.b >= count {
+                issues.push(GraphIssue::UnknownStation(flink.a.max(flink.b)));
+                continue;
+            }
+            let link = |dst| Link::Fixed(FixedLink {
+                dst,
+                time: flink.time,
+                kind: flink.kind,
+                availability: flink.availability.clone().map(Box::new)
+            });
+            graph.stations[flink.a].links.push(link(flink.b));
+            if !flink.one_way {
+                graph.stations[flink.b].links.push(link(flink.a));
+            }
+        }
+
+        // Iterate over the services and add connections. Cancellations only stop other schedules running
+        let services: Vec<&Service> = services.into_iter().collect();
+        let superseding = superseding_calendars(&services);
+        let mut added: HashMap<&str, Vec<&Service>> = HashMap::new();
+        for (service, superseded) in services.into_iter().zip(superseding) {
+            if service.is_cancellation() {
+                continue;
+            }
+            if service.stops.len() < 2 {
+                issues.push(GraphIssue::ShortService(service.train_uid.clone()));
+                continue;
+            }
+            if let Some(stop) = service.stops.iter().find(|stop| stop.station >= count) {
+                issues.push(GraphIssue::UnknownStation(stop.station));
+                continue;
+            }
+
+            if service.calendar.is_some() || !superseded.is_empty() || service.bank_holidays != BankHolidayRunning::Runs {
+                let id = service.id as usize;
+                if graph.calendars.len() <= id {
+                    graph.calendars.resize(id + 1, None);
+                }
+                graph.calendars[id] = Some(ServiceDays {
+                    calendar: service.calendar.clone(),
+                    superseded: superseded.into_iter().cloned().collect(),
+                    bank_holidays: service.bank_holidays
+                });
+            }
+
+            graph.add_rail_links(service.id, &service.stops);
+            added.entry(&service.train_uid).or_default().push(service);
+        }
+
+        // Passengers on a train which joins or divides from another can stay aboard
+        for association in associations.iter().filter(|a| a.passenger && a.calendar.stp != Stp::Cancellation) {
+            let (before, after) = match association.through() {
+                Some(uids) => uids,
+                None => continue
+            };
+            let during = |service: &&&Service| service.calendar.as_ref().is_none_or(|calendar| calendar.overlaps(&association.calendar));
+            for through in added.get(before).into_iter().flatten().filter(during) {
+                for onward in added.get(after).into_iter().flatten().filter(during) {
+                    graph.add_through_links(through, onward, association.location);
+                }
+            }
+        }
+
+        let mut built = TravelGraph::from_nodes(graph.stations);
+        built.calendars = graph.calendars;
+        built
+    }
+
+    // The graph with $nodes' links packed together
+    fn from_nodes(nodes: Vec<TGNode>) -> Self {
+        let mut graph = TravelGraph {
+            links: Vec::with_capacity(nodes.iter().map(|node| node.links.len()).sum()),
+            offsets: Vec::with_capacity(nodes.len() + 1),
+            transfer_times: Vec::with_capacity(nodes.len()),
+            ..Default::default()
+        };
+        graph.offsets.push(0);
+        for node in nodes {
+            graph.links.extend(node.links);
+            graph.offsets.push(graph.links.len() as u32);
+            graph.transfer_times.push(node.transfer_time);
+        }
+        graph
+    }
+}
+
+// A TravelGraph being built, with a vector of links per station until they're all added
+struct GraphBuilder {
+    stations: Vec<TGNode>,
+    calendars: Vec<Option<ServiceDays>>
+}
+
+impl GraphBuilder {
+    /**
+     * Links from each stop passengers can board at to the next they can alight at, passing
+     * over pick up only stops. Set down only stops can't be boarded, so there are links
+     * past them as well as to them, up to the next stop with no restrictions.
+     */
+    fn add_rail_links(&mut self, service: ServiceId, stops: &[Stop]) {
+        for (i, s1) in stops.iter().enumerate().filter(|(_, stop)| stop.activity.can_board()) {
+            for s2 in stops[i+1..].iter().filter(|stop| stop.activity.can_alight()) {
+                self.stations[s1.station].links.push(
+                    Link::Rail(RailLink {
+                        dst: s2.station,
+                        service,
+                        depart: s1.departure,
+                        time: s2.arrival.since(&s1.departure),
+                        depart_platform: s1.platform,
+                        arrive_platform: s2.platform,
+                        calls: Box::default()
+                    })
+                );
+                if s2.activity.can_board() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /**
+     * Links along $onward's stops after $location belonging to $through, so staying
+     * aboard where the trains join or divide isn't a change. They follow $through's calendar.
+     */
+    fn add_through_links(&mut self, through: &Service, onward: &Service, location: StationId) {
+        if !through.stops.iter().any(|stop| stop.station == location) {
+            return;
+        }
+        if let Some(at) = onward.stops.iter().position(|stop| stop.station == location) {
+            self.add_rail_links(through.id, &onward.stops[at..]);
+        }
+    }
+}
+
+impl TravelGraph {
+    /**
+     * Compute the journey times to each destination
+     * 
+     * @param scratch   Search state, reset before use
+     * @param date      Day of the earliest departure, to only use services running then. None uses every service
+     * @param depart    Earliest departure time
+     * @param origin    Start station
+     * @param destinations  List of destinations to extract journeys for
+     * @param contingency   Time (seconds) to allow for each change of train services
+     * @param flexi_depart  Time (seconds) from the earliest departure to the latest first train we would take. 0 means depart ASAP.
+     * @param constraints   Journeys which can't be used, pruned during the search. Destinations with none left are unreachable
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch, destinations), fields(destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_journeys(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime, origin: StationId, destinations: Vec<StationId>,
+            contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints) -> Vec<Journey> {
+        let pathfinder = &mut scratch.pathfinder;
+        // Counting trains boarded in layers only when they're limited
+        let layers = constraints.max_changes.map_or(1, |changes| changes as usize + 2);
+        pathfinder.reset(self.station_count(), contingency, layers, constraints);
+        pathfinder.perform(self, &[origin], date, depart, flexi_depart);
+
+        destinations.iter().map(|dest| {
+            pathfinder.best_journey(self, *dest)
+        }).collect()
+    }
+
+    /**
+     * Compute the best journey from any of $origins to any station of each
+     * destination group, e.g. from any London terminal. A group with no reachable
+     * stations gives a journey taking u32::MAX, as for compute_journeys.
+     *
+     * Other parameters are as for compute_journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch, destinations), fields(destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_group_journeys(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime, origins: &[StationId],
+            destinations: &[Vec<StationId>], contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints) -> Vec<Journey> {
+        let pathfinder = &mut scratch.pathfinder;
+        let layers = constraints.max_changes.map_or(1, |changes| changes as usize + 2);
+        pathfinder.reset(self.station_count(), contingency, layers, constraints);
+        pathfinder.perform(self, origins, date, depart, flexi_depart);
+
+        destinations.iter().map(|group| {
+            group.iter()
+                .map(|dest| pathfinder.best_journey(self, *dest))
+                .min_by_key(|journey| journey.time)
+                .unwrap_or_else(|| Journey { origin: origins.first().copied().unwrap_or(0), depart, time: u32::MAX, links: Vec::new() })
+        }).collect()
+    }
+
+    /**
+     * The best journey to each destination group leaving at each of $departs, as
+     * rows by departure, e.g. every 15 minutes from 07:00 to 10:00 to show how
+     * journeys vary through the day. Each is as from compute_group_journeys.
+     *
+     * Without flexi_depart, a journey which can still be caught from the next
+     * departure is also the best from then, so the next departure is only searched
+     * if some destination's journey can't. This relies on $departs being in order
+     * within a day.
+     *
+     * Other parameters are as for compute_journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch, departs, destinations), fields(departs = departs.len(), destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_profile(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, departs: &[RailTime], origins: &[StationId],
+            destinations: &[Vec<StationId>], contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints) -> Vec<Vec<Journey>> {
+        let change_time_percent = constraints.change_time_percent.unwrap_or(100);
+        let change = |station: StationId| self.transfer_time(station).saturating_mul(change_time_percent) / 100 + contingency;
+        let mut rows: Vec<Vec<Journey>> = Vec::with_capacity(departs.len());
+        for (index, depart) in departs.iter().enumerate() {
+            let reused = match (index.checked_sub(1).map(|last| (departs[last], &rows[last])), flexi_depart) {
+                (Some((last, journeys)), 0) if last.secs() <= depart.secs() => {
+                    let later = depart.secs() - last.secs();
+                    journeys.iter().map(|journey| journey.started_later(last, later, change)).collect::<Option<Vec<_>>>()
+                }
+                _ => None
+            };
+            let journeys = reused.unwrap_or_else(|| {
+                self.compute_group_journeys(scratch, date, *depart, origins, destinations, contingency, flexi_depart, constraints)
+            });
+            rows.push(journeys);
+        }
+        rows
+    }
+
+    /**
+     * Compute up to $k journeys to each destination, fastest first. Each takes a
+     * different sequence of services, e.g. a different first train or another
+     * route, so there's an alternative when a connection is tight.
+     *
+     * Other parameters are as for compute_journeys. Unreachable destinations have no journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch, destinations), fields(destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_alternatives(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime, origin: StationId,
+            destinations: Vec<StationId>, contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints, k: usize) -> Vec<Vec<Journey>> {
+        let pathfinder = &mut scratch.alternatives;
+        pathfinder.perform(self, origin, date, depart, contingency, flexi_depart, constraints, k);
+
+        destinations.iter().map(|dest| pathfinder.journeys(self, *dest)).collect()
+    }
+
+    /**
+     * Compute the trade-off between time and changes to each destination: the
+     * fastest journey with no changes, then with one change if that's faster,
+     * and so on up to $max_changes. Fewest changes first, so the fastest is last.
+     *
+     * Other parameters are as for compute_journeys. Unreachable destinations have no journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch, destinations), fields(destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_pareto(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime, origin: StationId,
+            destinations: Vec<StationId>, contingency: u32, flexi_depart: u32, max_changes: u32) -> Vec<Vec<Journey>> {
+        let pathfinder = &mut scratch.pathfinder;
+        // Layers for no trains (walking only) up to max_changes + 1 trains
+        pathfinder.reset(self.station_count(), contingency, max_changes as usize + 2, &JourneyConstraints::default());
+        pathfinder.perform(self, &[origin], date, depart, flexi_depart);
+
+        destinations.iter().map(|dest| pathfinder.pareto_journeys(self, *dest)).collect()
+    }
+
+    /**
+     * The latest departure from $origin which reaches $destination by $arrive_by, at most
+     * a day before, with the journey leaving then. None if no journey makes it.
+     *
+     * Searches backwards from $destination at $arrive_by over the connections (see
+     * connection_scan), finding the latest each station can be left to arrive in time.
+     * The journey's days count from its departure, which is the day before $arrive_by
+     * if later in the day.
+     *
+     * @param date  Day of $arrive_by, to only use services running then. None uses every service
+     * Other parameters are as for compute_journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch))]
+    pub fn latest_departure(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, origin: StationId, destination: StationId,
+            arrive_by: RailTime, contingency: u32) -> Option<(RailTime, Journey)> {
+        let connections = self.connections.get(self);
+        let (depart, time, links) = scratch.scan.latest_departure(self, connections, date, origin, destination, arrive_by, contingency)?;
+        let backwards = links.iter().rev().map(|&index| self.link(index)).collect();
+        let journey = dijkstras::journey_from(origin, depart.add(time), time, backwards);
+        Some((depart, Journey { depart, ..journey }))
+    }
+
+    /**
+     * Compute the journey time from origin to every station, indexed by station ID.
+     * Unreachable stations are None.
+     * 
+     * Parameters are as for compute_journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, scratch))]
+    pub fn compute_all_times(&self, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime, origin: StationId,
+            contingency: u32, flexi_depart: u32) -> Vec<Option<u32>> {
+        let pathfinder = &mut scratch.pathfinder;
+        pathfinder.reset(self.station_count(), contingency, 1, &JourneyConstraints::default());
+        pathfinder.perform(self, &[origin], date, depart, flexi_depart);
+
+        (0..self.station_count()).map(|station| pathfinder.best_time(station)).collect()
+    }
+
+    /** As compute_all_times, searching with $engine */
+    #[tracing::instrument(level = "debug", skip(self, scratch))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_all_times_with(&self, engine: Engine, scratch: &mut QueryScratch, date: Option<NaiveDate>, depart: RailTime,
+            origin: StationId, contingency: u32, flexi_depart: u32) -> Vec<Option<u32>> {
+        match engine {
+            Engine::Dijkstra => self.compute_all_times(scratch, date, depart, origin, contingency, flexi_depart),
+            Engine::ConnectionScan => {
+                let connections = self.connections.get(self);
+                scratch.scan.all_times(self, connections, date, depart, origin, contingency, flexi_depart)
+            }
+        }
+    }
+
+    /**
+     * Compute the best journey from each of $origins to each of $destinations, as
+     * rows of journeys by origin. With the parallel feature the searches from each
+     * origin run on rayon's thread pool, each thread with its own QueryScratch.
+     *
+     * Other parameters are as for compute_journeys.
+     */
+    #[tracing::instrument(level = "debug", skip(self, origins, destinations), fields(origins = origins.len(), destinations = destinations.len()))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_journey_matrix(&self, date: Option<NaiveDate>, depart: RailTime, origins: &[StationId], destinations: &[StationId],
+            contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints) -> Vec<Vec<Journey>> {
+        let search = |scratch: &mut QueryScratch, origin: &StationId| {
+            self.compute_journeys(scratch, date, depart, *origin, destinations.to_vec(), contingency, flexi_depart, constraints)
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            origins.par_iter().map_init(|| QueryScratch::new(self), search).collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut scratch = QueryScratch::new(self);
+            origins.iter().map(|origin| search(&mut scratch, origin)).collect()
+        }
+    }
+
+    /** Whether the service of $link runs when the link departs on $date */
+    pub fn runs(&self, link: &RailLink, date: NaiveDate) -> bool {
+        self.service_runs(link.service, link.depart.day(), date)
+    }
+
+    // Whether $service runs when one of its links departs on $date, $day days after the service started
+    pub(crate) fn service_runs(&self, service: ServiceId, day: u32, date: NaiveDate) -> bool {
+        match self.calendars.get(service as usize) {
+            Some(Some(days)) => {
+                let started = date.checked_sub_days(chrono::Days::new(day as u64));
+                started.is_some_and(|started| {
+                    days.calendar.as_ref().is_none_or(|calendar| calendar.runs_on(started))
+                        && !days.superseded.iter().any(|calendar| calendar.runs_on(started))
+                        && !days.bank_holidays.excludes(&self.holidays, started)
+                })
+            }
+            _ => true
+        }
+    }
+
+    /** The bank holidays searches on a date go by, the built-in ones unless set */
+    pub fn bank_holidays(&self) -> &BankHolidays {
+        &self.holidays
+    }
+
+    /** Search by $holidays, e.g. with one-off holidays added, for services which don't run on bank holidays */
+    pub fn set_bank_holidays(&mut self, holidays: BankHolidays) {
+        self.holidays = holidays;
+    }
+
+    /** Allow $secs to change trains at $station, in place of its minimum change time */
+    pub fn set_transfer_time(&mut self, station: StationId, secs: u32) {
+        self.transfer_times[station] = secs;
+    }
+
+    pub(crate) fn transfer_time(&self, station: StationId) -> u32 {
+        self.transfer_times[station]
+    }
+
+    pub fn station_count(&self) -> usize {
+        self.transfer_times.len()
+    }
+
+    /** All outgoing links from a station, fixed links first */
+    pub fn links(&self, station: StationId) -> &[Link] {
+        &self.links[self.link_range(station)]
+    }
+
+    // Indices of $station's links
+    pub(crate) fn link_range(&self, station: StationId) -> std::ops::Range<usize> {
+        self.offsets[station] as usize..self.offsets[station + 1] as usize
+    }
+
+    // The link at $index, or Dummy for NO_LINK
+    pub(crate) fn link(&self, index: u32) -> &Link {
+        self.links.get(index as usize).unwrap_or(&DUMMY)
+    }
+
+    /** Approximate bytes of memory used by the graph, not counting the Connection Scan arrays built on first use */
+    pub fn heap_size(&self) -> usize {
+        let availability: usize = self.links.iter()
+            .filter_map(|link| match link {
+                Link::Fixed(FixedLink { availability: Some(_), .. }) => Some(std::mem::size_of::<LinkAvailability>()),
+                _ => None
+            })
+            .sum();
+        let calendars: usize = self.calendars.iter().flatten().map(|days| days.superseded.capacity() * std::mem::size_of::<Calendar>()).sum();
+        self.links.capacity() * std::mem::size_of::<Link>()
+            + availability
+            + (self.offsets.capacity() + self.transfer_times.capacity()) * std::mem::size_of::<u32>()
+            + self.calendars.capacity() * std::mem::size_of::<Option<ServiceDays>>()
+            + calendars
+    }
+
+    pub fn stat_edges(&self) -> (usize, usize, usize) {
+        let mut total = 0;
+        let mut min = if self.station_count() == 0 { 0 } else { usize::MAX };
+        let mut max = 0;
+        for station in 0..self.station_count() {
+            let l = self.link_range(station).len();
+            total += l;
+            min = std::cmp::min(min, l);
+            max = std::cmp::max(max, l);
+        }
+        return (total, min, max);
+    }
+}
+
+
+mod dijkstras {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Eq, PartialEq, Clone)]
+    struct ToVisit {
+        // Index into TimeDijkstras::nodes
+        node: usize,
+        time: u32
+    }
+
+    // Ordering by time required to pick next station to visit
+    impl std::cmp::Ord for ToVisit {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            if self.time == other.time {
+                self.node.cmp(&other.node)
+            } else {
+                self.time.cmp(&other.time)
+            }
+        }
+    }
+
+    impl std::cmp::PartialOrd for ToVisit {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    #[derive(Clone)]
+    struct BestJourney {
+        time: u32,
+        depart: RailTime,
+        // Days since the search started at $depart
+        day: u32,
+        last_node: usize,
+        // Index in the graph of the link taken here, NO_LINK at the start
+        last_link: u32
+    }
+
+    fn unvisited() -> BestJourney {
+        BestJourney {
+            time: u32::MAX,
+            depart: RailTime::new(0, 0),
+            day: 0,
+            last_node: 0,
+            last_link: NO_LINK
+        }
+    }
+
+    // A search's JourneyConstraints other than the number of changes, for looking up as it runs
+    #[derive(Default)]
+    struct Limits {
+        // Indexed by station
+        avoid: Vec<bool>,
+        // Indexed by service, only as long as the highest excluded
+        excluded: Vec<bool>,
+        via: Option<StationId>,
+        change_time_percent: u32
+    }
+
+    impl Limits {
+        fn set(&mut self, station_count: usize, constraints: &JourneyConstraints) {
+            self.avoid.clear();
+            self.avoid.resize(station_count, false);
+            for station in constraints.avoid.iter().filter(|station| **station < station_count) {
+                self.avoid[*station] = true;
+            }
+            self.excluded.clear();
+            for service in &constraints.exclude_services {
+                let service = *service as usize;
+                if self.excluded.len() <= service {
+                    self.excluded.resize(service + 1, false);
+                }
+                self.excluded[service] = true;
+            }
+            self.via = constraints.via;
+            self.change_time_percent = constraints.change_time_percent.unwrap_or(100);
+        }
+
+        // Time to allow to change trains at $station
+        fn change_time(&self, graph: &TravelGraph, station: StationId) -> u32 {
+            graph.transfer_times[station].saturating_mul(self.change_time_percent) / 100
+        }
+
+        fn excludes(&self, service: ServiceId) -> bool {
+            self.excluded.get(service as usize).copied().unwrap_or(false)
+        }
+
+        // Whether a journey which had reached the via station ($via) has once it arrives at $station
+        fn reached_via(&self, via: bool, station: StationId) -> bool {
+            via || self.via.is_none() || self.via == Some(station)
+        }
+    }
+
+    pub struct TimeDijkstras {
+        visitq: BTreeSet<ToVisit>,
+        contingency: u32,
+        // One node per station, or with $layers > 1 one per station and number of trains boarded.
+        // Searches via a station have a copy of these for before and after reaching it
+        nodes: Vec<BestJourney>,
+        layers: usize,
+        phases: usize,
+        limits: Limits,
+        // Any of these can be the start, e.g. a group of stations
+        origins: Vec<StationId>,
+        flexi_depart: u32,
+        date: Option<NaiveDate>,
+        pub(super) stats: Option<SearchStats>
+    }
+
+    /** Travel Dijkstras....
+     * 
+     * Store BestJourney for each station
+     * 
+     * Store set of ToVisit's (visitq), sorted by time in descending order,
+     * which is used to pick the next station to visit.
+     * 
+     * Start by adding ($originstation, 0) for each origin, then continually pick off set to visit..
+     * 
+     * Visiting:
+     *  - If the ToVisit.time is > the current best in the station, it's an old ToVisit, discard!
+     * Iterate through all the links:
+     *  - If a link leads to an improved route to another station, apply the improvement,
+     *  and add current station and reached station to visitq.
+     *  - If there are no improving links, don't re-add ourselves (we're done at this station)
+     * 
+     * The algorithm is complete when visitq is empty.
+     *
+     * With more than one layer, each station has a node per number of trains boarded
+     * (from 0 to $layers - 1) and boarding another train moves to the next layer. The
+     * best journey in each layer gives the trade-off between time and changes.
+     */
+    impl TimeDijkstras {
+        pub fn new(station_count: usize, contingency: u32) -> Self {
+            let mut s = Self {
+                visitq: BTreeSet::new(),
+                contingency: contingency,
+                nodes: Vec::new(),
+                layers: 1,
+                phases: 1,
+                limits: Limits::default(),
+                origins: Vec::new(),
+                flexi_depart: 0,
+                date: None,
+                stats: None
+            };
+            s.nodes.resize(station_count, unvisited());
+            s.limits.set(station_count, &JourneyConstraints::default());
+            return s;
+        }
+
+        /** Clear the results of any previous search, ready for another on a graph of $station_count stations */
+        pub fn reset(&mut self, station_count: usize, contingency: u32, layers: usize, constraints: &JourneyConstraints) {
+            self.contingency = contingency;
+            self.layers = layers;
+            self.phases = if constraints.via.is_some() { 2 } else { 1 };
+            self.limits.set(station_count, constraints);
+            self.nodes.clear();
+            self.nodes.resize(station_count * layers * self.phases, unvisited());
+        }
+
+        fn node(&self, station: StationId, layer: usize, via: bool) -> usize {
+            (station * self.layers + layer) * self.phases + (via as usize).min(self.phases - 1)
+        }
+
+        // The station, layer and whether the via station has been reached for $node
+        fn split(&self, node: usize) -> (StationId, usize, bool) {
+            let phase = node % self.phases;
+            ((node / self.phases) / self.layers, (node / self.phases) % self.layers, phase + 1 == self.phases)
+        }
+
+        /** Search from whichever of $start_stations gives the best journey to each station */
+        pub fn perform(&mut self, graph: &TravelGraph, start_stations: &[StationId], date: Option<NaiveDate>, start_time: RailTime, flexi_depart: u32) {
+            self.visitq.clear();
+            for start_station in start_stations {
+                let start = self.node(*start_station, 0, self.limits.reached_via(false, *start_station));
+                self.nodes[start] = BestJourney {
+                    time: 0,
+                    depart: start_time,
+                    day: 0,
+                    last_node: start,
+                    last_link: NO_LINK
+                };
+                self.visitq.insert(ToVisit {
+                    node: start,
+                    time: 0
+                });
+            }
+
+            self.origins.clear();
+            self.origins.extend_from_slice(start_stations);
+            self.flexi_depart = flexi_depart;
+            self.date = date;
+
+            // While visitq is non empty
+            let (mut settled, mut queue_peak) = (0, 0);
+            while let Some(tovisit) = self.visitq.pop_first() {
+                queue_peak = queue_peak.max(self.visitq.len() + 1);
+                // If tovisit.time > best.time then no point visiting
+                if tovisit.time <= self.nodes[tovisit.node].time {
+                    // If tovisit.time < best.time then somethings gone wrong
+                    assert_eq!(tovisit.time, self.nodes[tovisit.node].time);
+
+                    settled += 1;
+                    self.visit_next(&graph, tovisit);
+                }
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.record(settled, queue_peak);
+            }
+        }
+
+        fn visit_next(&mut self, graph: &TravelGraph, tovisit: ToVisit) {
+            let (station, layer, via) = self.split(tovisit.node);
+            let curtime = self.nodes[tovisit.node].depart;
+            let lastlink = graph.link(self.nodes[tovisit.node].last_link);
+            // Seconds from the start of the search's first day
+            let now = self.nodes[tovisit.node].day * DAY + curtime.secs();
+
+            for index in graph.link_range(station) {
+                let link = &graph.links[index];
+                match link {
+                    Link::Rail(rlink) => {
+                        let change = lastlink.ischange(link);
+                        let chngtime = if change {
+                            self.limits.change_time(graph, station) + self.contingency
+                        } else {
+                            0
+                        };
+                        // Boarding another train, when counting them
+                        let dstlayer = if change && self.layers > 1 { layer + 1 } else { layer };
+                        if dstlayer >= self.layers {
+                            continue;
+                        }
+
+                        let (waittime, departs) = if self.origins.contains(&station) && curtime.timetil(&rlink.depart.time()) < self.flexi_depart {
+                            // Origin station, person can arrive on time for train
+                            (0, now + curtime.timetil(&rlink.depart.time()))
+                        } else {
+                            // Normal situation, person must wait for train
+                            let wait = chngtime + curtime.add(chngtime).timetil(&rlink.depart.time());
+                            (wait, now + wait)
+                        };
+                        if let Some(date) = self.date {
+                            if !graph.runs(rlink, date + chrono::Duration::days((departs / DAY) as i64)) {
+                                continue;
+                            }
+                        }
+                        if self.limits.avoid[rlink.dst] || self.limits.excludes(rlink.service) {
+                            continue;
+                        }
+                        let dsttime = tovisit.time + waittime + rlink.time;
+                        let dst = self.node(rlink.dst, dstlayer, self.limits.reached_via(via, rlink.dst));
+
+                        if dsttime < self.nodes[dst].time {
+                            // Update best
+                            let day = (departs + rlink.time) / DAY;
+                            self.update_best(dst, dsttime, rlink.depart.time().add(rlink.time), day, tovisit.node, index as u32);
+
+                            // Done visiting
+                            self.visitq.insert(tovisit);
+                            return;
+                        }
+                    },
+                    Link::Fixed(flink) => {
+                        if self.limits.avoid[flink.dst] {
+                            continue;
+                        }
+                        // Links only open at some times are like trains, the origin can be left later for them
+                        let wait = flink.wait(curtime);
+                        let waittime = if self.origins.contains(&station) && wait < self.flexi_depart { 0 } else { wait };
+                        let departs = now + wait;
+                        if let Some(date) = self.date {
+                            if !flink.runs_on(date + chrono::Duration::days((departs / DAY) as i64)) {
+                                continue;
+                            }
+                        }
+                        let dsttime = tovisit.time + waittime + flink.time;
+                        let dst = self.node(flink.dst, layer, self.limits.reached_via(via, flink.dst));
+
+                        if dsttime < self.nodes[dst].time {
+                            // Update best
+                            let day = (departs + flink.time) / DAY;
+                            self.update_best(dst, dsttime, curtime.add(wait + flink.time), day, tovisit.node, index as u32);
+
+                            // Done visiting
+                            self.visitq.insert(tovisit);
+                            return;
+                        }
+                    },
+                    _ => { }
+                }
+            }
+        }
+
+        fn update_best(&mut self, node: usize, time: u32, depart: RailTime, day: u32, last: usize, link: u32) {
+            let best = &mut self.nodes[node];
+            best.time = time;
+            best.depart = depart;
+            best.day = day;
+            best.last_node = last;
+            best.last_link = link;
+
+            self.visitq.insert(ToVisit {
+                time,
+                node
+            });
+        }
+
+        // The fastest node for $station over all layers, after the via station
+        fn best_node(&self, station: StationId) -> usize {
+            (0..self.layers).map(|layer| self.node(station, layer, true)).min_by_key(|node| self.nodes[*node].time).unwrap()
+        }
+
+        pub fn best_time(&self, destination: StationId) -> Option<u32> {
+            match self.nodes[self.best_node(destination)].time {
+                u32::MAX => None,
+                time => Some(time)
+            }
+        }
+
+        pub fn best_journey(&self, graph: &TravelGraph, destination: StationId) -> Journey {
+            self.journey(graph, self.best_node(destination))
+        }
+
+        /** For each number of trains boarded, the fastest journey to $destination if it's faster than any with fewer */
+        pub fn pareto_journeys(&self, graph: &TravelGraph, destination: StationId) -> Vec<Journey> {
+            let mut journeys = Vec::new();
+            let mut fastest = u32::MAX;
+            for node in (0..self.layers).map(|layer| self.node(destination, layer, true)) {
+                if self.nodes[node].time < fastest {
+                    fastest = self.nodes[node].time;
+                    journeys.push(self.journey(graph, node));
+                }
+            }
+            journeys
+        }
+
+        fn journey(&self, graph: &TravelGraph, node: usize) -> Journey {
+            // Create a journey by backtracking
+            let mut backwards = Vec::new();
+            let mut best = &self.nodes[node];
+            while best.last_link != NO_LINK {
+                backwards.push(graph.link(best.last_link));
+                best = &self.nodes[best.last_node];
+            }
+
+            // Start node stores last_node=start_node
+            journey_from(self.split(best.last_node).0, self.nodes[node].depart, self.nodes[node].time, backwards)
+        }
+    }
+
+    /**
+     * The journey from $origin taking $time, arriving at $arrive by the links
+     * $backwards (last first), joining consecutive links of the same service
+     */
+    pub(super) fn journey_from(origin: StationId, arrive: RailTime, time: u32, backwards: Vec<&Link>) -> Journey {
+        let mut links: Vec<Link> = Vec::new();
+        let mut depart = arrive;
+        for link in backwards {
+            if let (Some(Link::Rail(rlast)), Link::Rail(rnext)) = (links.last_mut(), link) {
+                if rlast.service == rnext.service {
+                    // Same service, update rlast with rnext assuming departure from new station,
+                    // including the time the train waits between them, and calling where they meet
+                    let call = Call {
+                        station: rnext.dst,
+                        arrive: rnext.depart.add(rnext.time),
+                        depart: rlast.depart,
+                        platform: rnext.arrive_platform.or(rlast.depart_platform)
+                    };
+                    rlast.calls = std::iter::once(call).chain(rlast.calls.iter().cloned()).collect();
+                    rlast.time += rnext.depart.time().timetil(&rlast.depart.time());
+                    rlast.depart = rnext.depart;
+                    rlast.depart_platform = rnext.depart_platform;
+                } else {
+                    // New service, add link
+                    links.push(link.clone());
+                }
+            } else {
+                // New service, add link
+                links.push(link.clone());
+            }
+
+            match link {
+                Link::Rail(rl) => {
+                    depart = rl.depart.time();
+                }
+                Link::Fixed(fl) => {
+                    depart = depart.sub(fl.time)
+                }
+                _ => {}
+            }
+        }
+
+        links.reverse();
+
+        Journey {
+            origin,
+            depart,
+            time,
+            links
+        }
+    }
+
+    #[derive(Clone)]
+    struct Label {
+        station: StationId,
+        time: u32,
+        // Time and day at $station, as for BestJourney
+        depart: RailTime,
+        day: u32,
+        // Identifies the services taken to get here, to tell journeys by different trains apart
+        route: u64,
+        boardings: u32,
+        // Whether it's been to the via station, if there is one
+        via: bool,
+        prev: Option<usize>,
+        // Index in the graph of the link taken here, NO_LINK at the start
+        link: u32
+    }
+
+    fn extend_route(route: u64, service: ServiceId) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (route, service).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /**
+     * Like TimeDijkstras but keeping up to $k journeys to each station, each by a
+     * different sequence of services. Labels are settled fastest first; one is
+     * dropped if its station already has $k, or one by the same services.
+     */
+    pub struct KBestDijkstras {
+        visitq: BTreeSet<(u32, usize)>,
+        labels: Vec<Label>,
+        // Indices of settled labels at each station, fastest first
+        settled: Vec<Vec<usize>>,
+        k: usize,
+        max_boardings: u32,
+        limits: Limits,
+        contingency: u32,
+        origin: StationId,
+        flexi_depart: u32,
+        date: Option<NaiveDate>,
+        pub(super) stats: Option<SearchStats>
+    }
+
+    impl KBestDijkstras {
+        pub fn new() -> Self {
+            Self {
+                visitq: BTreeSet::new(),
+                labels: Vec::new(),
+                settled: Vec::new(),
+                k: 1,
+                max_boardings: u32::MAX,
+                limits: Limits::default(),
+                contingency: 0,
+                origin: 0,
+                flexi_depart: 0,
+                date: None,
+                stats: None
+            }
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn perform(&mut self, graph: &TravelGraph, start_station: StationId, date: Option<NaiveDate>, start_time: RailTime,
+                contingency: u32, flexi_depart: u32, constraints: &JourneyConstraints, k: usize) {
+            self.visitq.clear();
+            self.labels.clear();
+            for settled in &mut self.settled {
+                settled.clear();
+            }
+            self.settled.resize(graph.station_count(), Vec::new());
+            self.k = k;
+            self.max_boardings = constraints.max_changes.map_or(u32::MAX, |changes| changes.saturating_add(1));
+            self.limits.set(graph.station_count(), constraints);
+            self.contingency = contingency;
+            self.origin = start_station;
+            self.flexi_depart = flexi_depart;
+            self.date = date;
+
+            self.push(Label {
+                station: start_station,
+                time: 0,
+                depart: start_time,
+                day: 0,
+                route: 0,
+                boardings: 0,
+                via: self.limits.reached_via(false, start_station),
+                prev: None,
+                link: NO_LINK
+            });
+
+            // Labels before and after the via station are settled separately
+            let (mut settled, mut queue_peak) = (0, 0);
+            while let Some((_, i)) = self.visitq.pop_first() {
+                queue_peak = queue_peak.max(self.visitq.len() + 1);
+                let label = &self.labels[i];
+                let (count, duplicate) = self.settled[label.station].iter()
+                    .map(|j| &self.labels[*j])
+                    .filter(|other| other.via == label.via)
+                    .fold((0, false), |(count, duplicate), other| (count + 1, duplicate || other.route == label.route));
+                if count >= self.k || duplicate {
+                    continue;
+                }
+                self.settled[label.station].push(i);
+                settled += 1;
+                self.expand(graph, i);
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.record(settled, queue_peak);
+            }
+        }
+
+        fn push(&mut self, label: Label) {
+            if self.limits.avoid[label.station] || label.boardings > self.max_boardings {
+                return;
+            }
+            if self.settled[label.station].iter().filter(|j| self.labels[**j].via == label.via).count() < self.k {
+                self.visitq.insert((label.time, self.labels.len()));
+                self.labels.push(label);
+            }
+        }
+
+        // Follow every link from the station of label $i
+        fn expand(&mut self, graph: &TravelGraph, i: usize) {
+            let label = self.labels[i].clone();
+            let curtime = label.depart;
+            let now = label.day * DAY + curtime.secs();
+            let lastlink = graph.link(label.link);
+
+            for index in graph.link_range(label.station) {
+                let link = &graph.links[index];
+                match link {
+                    Link::Rail(rlink) => {
+                        if self.limits.excludes(rlink.service) {
+                            continue;
+                        }
+                        let chngtime = if lastlink.ischange(link) {
+                            self.limits.change_time(graph, label.station) + self.contingency
+                        } else {
+                            0
+                        };
+
+                        let (waittime, departs) = if label.station == self.origin && curtime.timetil(&rlink.depart.time()) < self.flexi_depart {
+                            (0, now + curtime.timetil(&rlink.depart.time()))
+                        } else {
+                            let wait = chngtime + curtime.add(chngtime).timetil(&rlink.depart.time());
+                            (wait, now + wait)
+                        };
+                        if let Some(date) = self.date {
+                            if !graph.runs(rlink, date + chrono::Duration::days((departs / DAY) as i64)) {
+                                continue;
+                            }
+                        }
+
+                        let boarding = lastlink.ischange(link);
+                        let route = if lastlink.service() == Some(rlink.service) { label.route } else { extend_route(label.route, rlink.service) };
+                        self.push(Label {
+                            station: rlink.dst,
+                            time: label.time + waittime + rlink.time,
+                            depart: rlink.depart.time().add(rlink.time),
+                            day: (departs + rlink.time) / DAY,
+                            route,
+                            boardings: label.boardings + boarding as u32,
+                            via: self.limits.reached_via(label.via, rlink.dst),
+                            prev: Some(i),
+                            link: index as u32
+                        });
+                    }
+                    Link::Fixed(flink) => {
+                        let wait = flink.wait(curtime);
+                        let waittime = if label.station == self.origin && wait < self.flexi_depart { 0 } else { wait };
+                        if let Some(date) = self.date {
+                            if !flink.runs_on(date + chrono::Duration::days(((now + wait) / DAY) as i64)) {
+                                continue;
+                            }
+                        }
+                        self.push(Label {
+                            station: flink.dst,
+                            time: label.time + waittime + flink.time,
+                            depart: curtime.add(wait + flink.time),
+                            day: (now + wait + flink.time) / DAY,
+                            route: label.route,
+                            boardings: label.boardings,
+                            via: self.limits.reached_via(label.via, flink.dst),
+                            prev: Some(i),
+                            link: index as u32
+                        });
+                    }
+                    _ => { }
+                }
+            }
+        }
+
+        /** The journeys found to $destination, fastest first */
+        pub fn journeys(&self, graph: &TravelGraph, destination: StationId) -> Vec<Journey> {
+            self.settled[destination].iter().filter(|i| self.labels[**i].via).map(|&i| {
+                let mut backwards = Vec::new();
+                let mut label = &self.labels[i];
+                while let Some(prev) = label.prev {
+                    backwards.push(graph.link(label.link));
+                    label = &self.labels[prev];
+                }
+                journey_from(label.station, self.labels[i].depart, self.labels[i].time, backwards)
+            }).collect()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+    use raildata_core::timetable::{AssociationKind, StopActivity};
+    use raildata_core::holidays::Region;
+
+    #[test]
+    fn test_simple_graph() {
+        // Construct a simple two-way service
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX")
+        ]);
+        
+        let fixedlinks = vec![
+            fixed_links::FixedLink::simple(0, 1, 5*60, FixedLinkKind::Bus)
+        ];
+
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "OUTBOUND", vec![
+                    Stop::simple(0, "0000", "0000"),
+                    Stop::simple(1, "0100", "0100")
+                ]),
+                Service::simple(1, "INBOUND", vec![
+                    Stop::simple(1, "0110", "0110"),
+                    Stop::simple(0, "0215", "0215")
+                ])
+            ],
+            Vec::new()
+        );
+
+        let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+
+        assert_eq!(graph, TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_fixed(1, 5*60, FixedLinkKind::Bus),
+                        Link::simple_rail(1, 0, "0000", 60*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_fixed(0, 5*60, FixedLinkKind::Bus),
+                        Link::simple_rail(0, 1, "0110", 65*60)
+                    ],
+                    transfer_time: 0
+                }
+        ]));
+    }
+
+    #[test]
+    fn test_graph_issues() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX")
+        ]);
+
+        let fixedlinks = vec![
+            fixed_links::FixedLink::simple(0, 7, 5*60, FixedLinkKind::Walk)
+        ];
+
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "EMPTY", vec![]),
+                Service::simple(1, "OUTBOUND", vec![
+                    Stop::simple(0, "0000", "0000"),
+                    Stop::simple(1, "0100", "0100")
+                ])
+            ],
+            Vec::new()
+        );
+
+        let (graph, issues) = TravelGraph::new_checked(&stations, &fixedlinks, &timetable, false).unwrap();
+        assert_eq!(issues, vec![GraphIssue::UnknownStation(7), GraphIssue::ShortService("EMPTY".to_string())]);
+        assert_eq!(graph.links(0), &[Link::simple_rail(1, 1, "0000", 60*60)][..]);
+        assert_eq!(graph, TravelGraph::new(&stations, &fixedlinks, &timetable));
+
+        let err = TravelGraph::new_checked(&stations, &fixedlinks, &timetable, true).unwrap_err();
+        assert!(matches!(err, RailDataError::UnknownStation(_)));
+    }
+
+    #[test]
+    fn test_time_dijkstras() {
+        // This simple graph example consists of 3 stations in a row, 0,1,2
+        // Links:
+        //  0 -> 2 : 0000 -> 0100 s=0
+        //  0 -> 1 : 0130 -> 0205 s=1
+        //  1 -> 2 : 0030 -> 0105 s=2
+        //  1 -> 2 : 0130 -> 0205 s=4
+        //  2 -> 1 : 0110 -> 0130 s=3
+        //  1 -> 0 : 0130 -> 0145 s=3
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 0, "0000", 60*60),
+                        Link::simple_rail(1, 1, "0130", 35*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 2, "0030", 35*60),
+                        Link::simple_rail(2, 4, "0130", 35*60),
+                        Link::simple_rail(0, 3, "0130", 15*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(1, 3, "0110", 20*60)
+                    ],
+                    transfer_time: 0
+                }
+        ]);
+
+        let mut paths = dijkstras::TimeDijkstras::new(3, 0);
+        paths.perform(&graph, &[0], None, RailTime::new(0, 0), 0);
+
+        let j1 = paths.best_journey(&graph, 1);
+
+        assert_eq!(j1.time, 90*60);
+        let j2 = paths.best_journey(&graph, 2);
+        assert_eq!(j2.time, 60*60);
+
+        // Try it from 2
+        let journeys = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(1, 0), 2, vec![0, 1], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[1].time, 30*60);
+        assert_eq!(journeys[0].time, 45*60);
+
+        // Staying aboard service 3 through 1 makes one leg calling there
+        let time = |t| ServiceTime::from_24h(t).unwrap();
+        assert_eq!(journeys[0].links, vec![Link::Rail(RailLink {
+            dst: 0,
+            service: 3,
+            depart: time("0110"),
+            time: 35*60,
+            depart_platform: None,
+            arrive_platform: None,
+            calls: vec![Call { station: 1, arrive: time("0130"), depart: time("0130"), platform: None }].into()
+        })]);
+    }
+
+    #[test]
+    fn test_dijkstras_transfer() {
+        // Transfer times test, three stations 0,1,2, with services:
+        //  0 -> 1 : 0000 -> 0030 (~0)
+        //  0 -> 2 : 0030 -> 0110 (~1)
+        //  1 -> 2 : 0035 -> 0100 (~2)
+        //  1 -> 2 : 0105 -> 0130 (~3)
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(1, 0, "0000", 30*60),
+                        Link::simple_rail(2, 1, "0030", 40*60)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 2, "0035", 25*60),
+                        Link::simple_rail(2, 3, "0105", 25*60)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![],
+                    transfer_time: 2*60
+                }
+        ]);
+
+        // One scratch for every query, so each must be unaffected by the last
+        let mut scratch = QueryScratch::new(&graph);
+        let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(23, 50), 0, vec![1, 2], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 40*60);
+        assert_eq!(journeys[1].time, 70*60);
+        assert_eq!(journeys[1].links.len(), 2);
+
+        let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(23, 50), 0, vec![1, 2], 4*60, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 40*60);
+        assert_eq!(journeys[1].time, 80*60);
+        assert_eq!(journeys[1].links.len(), 1);
+
+        // Allowing three times the change time, or a longer one at 1, misses the 0035 too
+        let cautious = JourneyConstraints { change_time_percent: Some(300), ..Default::default() };
+        let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(23, 50), 0, vec![2], 0, 0, &cautious);
+        assert_eq!(journeys[0].time, 80*60);
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(23, 50), 0, vec![2], 0, 0, &cautious, 1);
+        assert_eq!(alternatives[0][0].time, 80*60);
+        let mut graph = graph;
+        graph.set_transfer_time(1, 6*60);
+        let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(23, 50), 0, vec![2], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 80*60);
+            
+        // Test that for unreachable nodes, we get u32::MAX
+        // AND test that with a origin_time we allow flexi_depart we only count the time from departure
+        let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), 1, vec![0, 2], 4*60, 60*60, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, std::u32::MAX);
+        assert_eq!(journeys[1].time, 25*60);
+        assert_eq!(journeys[1].depart, RailTime::new(0, 35));
+
+        let times = graph.compute_all_times(&mut scratch, None, RailTime::new(0, 0), 1, 4*60, 60*60);
+        assert_eq!(times, vec![None, Some(0), Some(25*60)]);
+    }
+
+    // Trading time against changes:
+    //  0 -> 2 : 0000 -> 0200 (~0), slow but direct
+    //  0 -> 1 : 0000 -> 0030 (~1)
+    //  1 -> 2 : 0035 -> 0100 (~2)
+    //  1 -> 3 : 0035 -> 0040 (~3)
+    //  3 -> 2 : 0045 -> 0050 (~4)
+    fn changes_graph() -> TravelGraph {
+        TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 0, "0000", 120*60),
+                        Link::simple_rail(1, 1, "0000", 30*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 2, "0035", 25*60),
+                        Link::simple_rail(3, 3, "0035", 5*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![],
+                    transfer_time: 0
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 4, "0045", 5*60)
+                    ],
+                    transfer_time: 0
+                }
+        ])
+    }
+
+    #[test]
+    fn test_pareto() {
+        let graph = changes_graph();
+        let mut scratch = QueryScratch::new(&graph);
+        let summary = |journeys: &Vec<Journey>| -> Vec<(u32, u32)> {
+            journeys.iter().map(|j| (j.time / 60, j.changes())).collect()
+        };
+        let fronts = graph.compute_pareto(&mut scratch, None, RailTime::new(0, 0), 0, vec![2, 0], 0, 0, 3);
+        assert_eq!(summary(&fronts[0]), vec![(120, 0), (60, 1), (50, 2)]);
+        assert_eq!(summary(&fronts[1]), vec![(0, 0)]);
+
+        let fronts = graph.compute_pareto(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, 1);
+        assert_eq!(summary(&fronts[0]), vec![(120, 0), (60, 1)]);
+
+        // The same scratch still gives the fastest journey for a single-layer search
+        let journey = graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!((journey.time / 60, journey.changes()), (50, 2));
+        let fronts = graph.compute_pareto(&mut scratch, None, RailTime::new(0, 0), 2, vec![0], 0, 0, 3);
+        assert!(fronts[0].is_empty());
+    }
+
+    #[test]
+    fn test_constraints() {
+        let graph = changes_graph();
+        let mut scratch = QueryScratch::new(&graph);
+        let mut search = |constraints: JourneyConstraints| {
+            let journey = graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &constraints).remove(0);
+            (journey.time / 60, journey.changes())
+        };
+        assert_eq!(search(JourneyConstraints::default()), (50, 2));
+        assert_eq!(search(JourneyConstraints { max_changes: Some(1), ..Default::default() }), (60, 1));
+        assert_eq!(search(JourneyConstraints { max_changes: Some(0), ..Default::default() }), (120, 0));
+        assert_eq!(search(JourneyConstraints { avoid: vec![3], ..Default::default() }), (60, 1));
+        assert_eq!(search(JourneyConstraints { avoid: vec![1], ..Default::default() }), (120, 0));
+        assert_eq!(search(JourneyConstraints { via: Some(1), max_changes: Some(1), ..Default::default() }), (60, 1));
+        // Via 3 takes three trains
+        assert_eq!(search(JourneyConstraints { via: Some(3), max_changes: Some(1), ..Default::default() }).0, u32::MAX / 60);
+        assert_eq!(search(JourneyConstraints { exclude_services: vec![4], ..Default::default() }), (60, 1));
+        assert_eq!(search(JourneyConstraints { exclude_services: vec![1], ..Default::default() }), (120, 0));
+
+        let summary = |journeys: &Vec<Journey>| -> Vec<(u32, u32)> {
+            journeys.iter().map(|j| (j.time / 60, j.changes())).collect()
+        };
+        let constraints = JourneyConstraints { avoid: vec![3], ..Default::default() };
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &constraints, 3);
+        assert_eq!(summary(&alternatives[0]), vec![(60, 1), (120, 0)]);
+        let constraints = JourneyConstraints { via: Some(1), ..Default::default() };
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &constraints, 3);
+        assert_eq!(summary(&alternatives[0]), vec![(50, 2), (60, 1)]);
+        let constraints = JourneyConstraints { max_changes: Some(0), ..Default::default() };
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &constraints, 3);
+        assert_eq!(summary(&alternatives[0]), vec![(120, 0)]);
+        let constraints = JourneyConstraints { exclude_services: vec![4], ..Default::default() };
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &constraints, 3);
+        assert_eq!(summary(&alternatives[0]), vec![(60, 1), (120, 0)]);
+    }
+
+    #[test]
+    fn test_search_stats() {
+        let graph = changes_graph();
+        let mut scratch = QueryScratch::new(&graph);
+        graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &JourneyConstraints::default());
+        assert_eq!(scratch.take_stats(), None);
+
+        // Every station is reached, some more than once as better ways are found
+        scratch.collect_stats();
+        graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &JourneyConstraints::default());
+        graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 0, vec![2], 0, 0, &JourneyConstraints::default(), 2);
+        let stats = scratch.take_stats().unwrap();
+        assert_eq!(stats.searches, 2);
+        assert!(stats.settled >= 2*4);
+        assert!(stats.queue_peak >= 2);
+        assert_eq!(scratch.take_stats(), Some(SearchStats::default()));
+    }
+
+    #[test]
+    fn test_group_journeys() {
+        let graph = changes_graph();
+        let mut scratch = QueryScratch::new(&graph);
+        let journeys = graph.compute_group_journeys(&mut scratch, None, RailTime::new(0, 0), &[0], &[vec![2, 3], vec![1, 2], vec![]], 0, 0, &JourneyConstraints::default());
+        let summary: Vec<(StationId, u32)> = journeys.iter().map(|j| (j.origin, j.time / 60)).collect();
+        assert_eq!(summary, vec![(0, 40), (0, 30), (0, u32::MAX / 60)]);
+
+        // Too late for the trains from 1, but not the one from 3
+        let journeys = graph.compute_group_journeys(&mut scratch, None, RailTime::new(0, 36), &[1, 3], &[vec![2]], 0, 0, &JourneyConstraints::default());
+        assert_eq!((journeys[0].origin, journeys[0].time / 60), (3, 14));
+    }
+
+    #[test]
+    fn test_profile() {
+        let graph = changes_graph();
+        let mut scratch = QueryScratch::new(&graph);
+        scratch.collect_stats();
+        let departs = [RailTime::new(0, 0), RailTime::new(23, 0), RailTime::new(23, 30)];
+        let profile = graph.compute_profile(&mut scratch, None, &departs, &[0], &[vec![2], vec![0]], 0, 0, &JourneyConstraints::default());
+        let summary: Vec<Vec<u32>> = profile.iter().map(|row| row.iter().map(|j| j.time / 60).collect()).collect();
+        assert_eq!(summary, vec![vec![50, 0], vec![110, 0], vec![80, 0]]);
+        // The journey from 23:00 doesn't leave until midnight, so also serves 23:30
+        assert_eq!(scratch.take_stats().unwrap().searches, 2);
+        assert_eq!(profile[2][1].depart, RailTime::new(23, 30));
+
+        // The same journeys as searching from each time
+        for (depart, row) in departs.iter().zip(&profile) {
+            let journeys = graph.compute_group_journeys(&mut scratch, None, *depart, &[0], &[vec![2], vec![0]], 0, 0, &JourneyConstraints::default());
+            assert_eq!(row.iter().map(Journey::legs).collect::<Vec<_>>(), journeys.iter().map(Journey::legs).collect::<Vec<_>>());
+        }
+
+        // Every time is searched with flexi_depart, which makes later trains as good as leaving at once
+        graph.compute_profile(&mut scratch, None, &departs, &[0], &[vec![2]], 0, 60*60, &JourneyConstraints::default());
+        assert_eq!(scratch.take_stats().unwrap().searches, 3 + 3);
+
+        // With 40 minutes to board at the origin, 23:30 is too late for the midnight train
+        let mut graph = graph;
+        graph.set_transfer_time(0, 40*60);
+        let profile = graph.compute_profile(&mut scratch, None, &departs[1..], &[0], &[vec![2]], 0, 0, &JourneyConstraints::default());
+        assert_eq!(profile.iter().map(|row| row[0].time / 60).collect::<Vec<_>>(), vec![110, 24*60 + 80]);
+    }
+
+    #[test]
+    fn test_journey_matrix() {
+        let graph = changes_graph();
+        let origins = [0, 1, 3];
+        let destinations = [1, 2];
+        let matrix = graph.compute_journey_matrix(None, RailTime::new(0, 0), &origins, &destinations, 0, 0, &JourneyConstraints::default());
+        let summary = |journeys: &Vec<Journey>| -> Vec<(StationId, u32, usize)> {
+            journeys.iter().map(|j| (j.origin, j.time, j.links.len())).collect()
+        };
+        let mut scratch = QueryScratch::new(&graph);
+        assert_eq!(matrix.len(), origins.len());
+        for (origin, row) in origins.iter().zip(&matrix) {
+            let journeys = graph.compute_journeys(&mut scratch, None, RailTime::new(0, 0), *origin, destinations.to_vec(), 0, 0, &JourneyConstraints::default());
+            assert_eq!(summary(row), summary(&journeys));
+        }
+        assert!(graph.compute_journey_matrix(None, RailTime::new(0, 0), &[], &destinations, 0, 0, &JourneyConstraints::default()).is_empty());
+    }
+
+    #[test]
+    fn test_latest_departure() {
+        //  0 -> 1 : 0900, 1000 and 1100, taking 30 minutes (~0, ~1, ~2)
+        //  0 -> 2 : 5 minute walk
+        //  2 -> 1 : 1040 -> 1050 (~3)
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_fixed(2, 5*60, FixedLinkKind::Walk),
+                        Link::simple_rail(1, 0, "0900", 30*60),
+                        Link::simple_rail(1, 1, "1000", 30*60),
+                        Link::simple_rail(1, 2, "1100", 30*60)
+                    ],
+                    transfer_time: 0
+                },
+                TGNode { links: vec![], transfer_time: 0 },
+                TGNode { links: vec![Link::simple_rail(1, 3, "1040", 10*60)], transfer_time: 0 }
+        ]);
+
+        let mut scratch = QueryScratch::new(&graph);
+        let mut latest = |arrive_by: RailTime, contingency: u32| {
+            graph.latest_departure(&mut scratch, None, 0, 1, arrive_by, contingency)
+                .map(|(depart, journey)| (depart, journey.time / 60, journey.links.len()))
+        };
+        assert_eq!(latest(RailTime::new(10, 45), 0), Some((RailTime::new(10, 0), 30, 1)));
+        // Walking to catch ~3, which needs leaving earlier to allow for the change
+        assert_eq!(latest(RailTime::new(10, 55), 0), Some((RailTime::new(10, 35), 15, 2)));
+        assert_eq!(latest(RailTime::new(10, 55), 5*60), Some((RailTime::new(10, 30), 20, 2)));
+        // Before the first train it's the last the day before
+        assert_eq!(latest(RailTime::new(8, 20), 0), Some((RailTime::new(11, 0), 30, 1)));
+        assert_eq!(graph.latest_departure(&mut scratch, None, 1, 0, RailTime::new(12, 0), 0).map(|(depart, _)| depart), None);
+        assert_eq!(graph.latest_departure(&mut scratch, None, 1, 1, RailTime::new(12, 0), 0).map(|(depart, j)| (depart, j.time, j.destination())),
+            Some((RailTime::new(12, 0), 0, 1)));
+        let (_, journey) = graph.latest_departure(&mut scratch, None, 0, 1, RailTime::new(10, 55), 0).unwrap();
+        assert_eq!(journey.destination(), 1);
+    }
+
+    #[test]
+    fn test_latest_departure_fixed_links() {
+        //  0 -> 2 -> 3 : 5 then 10 minute walks, with 5 minutes to change at 3
+        //  3 -> 1 : 1000 -> 1030 (~0)
+        //  0 -> 1 : an hour's bus, setting off between 0600 and 0900
+        let bus = Link::Fixed(FixedLink {
+            dst: 1,
+            time: 60*60,
+            kind: FixedLinkKind::Bus,
+            availability: Some(Box::new(LinkAvailability {
+                start: RailTime::new(6, 0),
+                end: RailTime::new(9, 0),
+                calendar: Calendar { runs_from: NaiveDate::MIN, runs_to: NaiveDate::MAX, days_run: [true; 7], stp: Stp::Permanent }
+            }))
+        });
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode { links: vec![Link::simple_fixed(2, 5*60, FixedLinkKind::Walk), bus], transfer_time: 0 },
+                TGNode { links: vec![], transfer_time: 0 },
+                TGNode { links: vec![Link::simple_fixed(3, 10*60, FixedLinkKind::Walk)], transfer_time: 0 },
+                TGNode { links: vec![Link::simple_rail(1, 0, "1000", 30*60)], transfer_time: 5*60 }
+        ]);
+
+        let mut scratch = QueryScratch::new(&graph);
+        let mut latest = |arrive_by: RailTime| {
+            graph.latest_departure(&mut scratch, None, 0, 1, arrive_by, 0).map(|(depart, journey)| (depart, journey.time / 60, journey.links.len()))
+        };
+        // Both walks, leaving just in time to change at 3
+        assert_eq!(latest(RailTime::new(10, 45)), Some((RailTime::new(9, 40), 50, 3)));
+        // Too early for the train, so the last bus
+        assert_eq!(latest(RailTime::new(10, 20)), Some((RailTime::new(9, 0), 60, 1)));
+        assert_eq!(latest(RailTime::new(9, 30)), Some((RailTime::new(8, 30), 60, 1)));
+
+        // A search from the departure gets there in time
+        let journey = graph.compute_journeys(&mut scratch, None, RailTime::new(9, 40), 0, vec![1], 0, 1, &JourneyConstraints::default()).remove(0);
+        assert_eq!(journey.time, 50*60);
+    }
+
+    #[test]
+    fn test_alternatives() {
+        // As test_dijkstras_transfer, with one more service:
+        //  1 -> 2 : 0105 -> 0130 (~3)
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(1, 0, "0000", 30*60),
+                        Link::simple_rail(2, 1, "0030", 40*60)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 2, "0035", 25*60),
+                        Link::simple_rail(2, 3, "0105", 25*60)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![],
+                    transfer_time: 2*60
+                }
+        ]);
+
+        let mut scratch = QueryScratch::new(&graph);
+        let summary = |journeys: &Vec<Journey>| -> Vec<(u32, Vec<ServiceId>)> {
+            journeys.iter().map(|j| (j.time / 60, j.links.iter().filter_map(|link| link.service()).collect())).collect()
+        };
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(23, 50), 0, vec![1, 2], 0, 0, &JourneyConstraints::default(), 3);
+        assert_eq!(summary(&alternatives[0]), vec![(40, vec![0])]);
+        assert_eq!(summary(&alternatives[1]), vec![(70, vec![0, 2]), (80, vec![1]), (100, vec![0, 3])]);
+
+        // With time to change ~2 is missed, leaving ~3 as the alternative
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(23, 50), 0, vec![2], 4*60, 0, &JourneyConstraints::default(), 2);
+        assert_eq!(summary(&alternatives[0]), vec![(80, vec![1]), (100, vec![0, 3])]);
+        let best = graph.compute_journeys(&mut scratch, None, RailTime::new(23, 50), 0, vec![2], 4*60, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!(best.links, alternatives[0][0].links);
+
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(0, 0), 1, vec![0], 0, 0, &JourneyConstraints::default(), 3);
+        assert!(alternatives[0].is_empty());
+    }
+
+    #[test]
+    fn test_overnight_legs() {
+        //  0 -> 1 : 2330 -> 2350 (~0), the last train
+        //  1 -> 2 : 0600 -> 0630 (~1), the first train the next morning
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode { links: vec![Link::simple_rail(1, 0, "2330", 20*60)], transfer_time: 0 },
+                TGNode { links: vec![Link::simple_rail(2, 1, "0600", 30*60)], transfer_time: 0 },
+                TGNode { links: vec![], transfer_time: 0 }
+        ]);
+
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(23, 0), 0, vec![2], 0, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!(journey.time, 7*60*60 + 30*60);
+        let days: Vec<(u32, u32, u32)> = journey.legs().iter().map(|leg| (leg.depart_day, leg.arrive_day, leg.wait)).collect();
+        assert_eq!(days, vec![(0, 0, 0), (1, 1, 6*60*60 + 10*60)]);
+
+        // Just missing the last train, it's caught the next night and the journey ends the day after
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(23, 45), 0, vec![2], 0, 0, &JourneyConstraints::default()).remove(0);
+        let days: Vec<(u32, u32)> = journey.legs().iter().map(|leg| (leg.depart_day, leg.arrive_day)).collect();
+        assert_eq!(days, vec![(1, 1), (2, 2)]);
+
+        let times = graph.compute_all_times_with(Engine::ConnectionScan, &mut QueryScratch::new(&graph), None, RailTime::new(23, 45), 0, 0, 0);
+        assert_eq!(times, vec![Some(0), Some(24*60*60 + 5*60), Some(30*60*60 + 45*60)]);
+    }
+
+    #[test]
+    fn test_calendars() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX"),
+            Station::simple("EDINBUR", "Edinburgh", "EDB")
+        ]);
+        let days = |days_run: [bool; 7]| Some(Calendar {
+            runs_from: NaiveDate::from_ymd_opt(2020, 9, 1).unwrap(),
+            runs_to: NaiveDate::from_ymd_opt(2020, 9, 30).unwrap(),
+            days_run,
+            stp: Stp::Permanent
+        });
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service {
+                    calendar: days([true, true, true, true, true, false, false]),
+                    ..Service::simple(0, "WEEKDAY", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0930", "0930")])
+                },
+                // Friday nights, reaching KGX after midnight
+                Service {
+                    calendar: days([false, false, false, false, true, false, false]),
+                    ..Service::simple(1, "SLEEPER", vec![Stop::simple(0, "2330", "2330"), Stop::simple(1, "2430", "2435"), Stop::simple(2, "2530", "2530")])
+                }
+            ],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let mut scratch = QueryScratch::new(&graph);
+        let mut search = |date: Option<&str>, depart: RailTime, origin: StationId, dest: StationId| {
+            let date = date.map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap());
+            graph.compute_journeys(&mut scratch, date, depart, origin, vec![dest], 0, 0, &JourneyConstraints::default()).remove(0).time / 60
+        };
+
+        assert_eq!(search(Some("2020-09-14"), RailTime::new(8, 0), 0, 1), 90);
+        assert_eq!(search(Some("2020-09-12"), RailTime::new(8, 0), 0, 1), u32::MAX / 60);
+        assert_eq!(search(None, RailTime::new(8, 0), 0, 1), 90);
+        // Outside the validity dates
+        assert_eq!(search(Some("2020-10-05"), RailTime::new(8, 0), 0, 1), u32::MAX / 60);
+
+        // The sleeper's calls after midnight run on Saturday mornings
+        assert_eq!(search(Some("2020-09-11"), RailTime::new(23, 0), 0, 2), 150);
+        assert_eq!(search(Some("2020-09-12"), RailTime::new(0, 0), 1, 2), 90);
+        assert_eq!(search(Some("2020-09-13"), RailTime::new(0, 0), 1, 2), u32::MAX / 60);
+        let friday = NaiveDate::from_ymd_opt(2020, 9, 11).unwrap();
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), Some(friday), RailTime::new(23, 0), 0, vec![2], 0, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!(journey.departure(friday), Some(RailTime::new(23, 30).on(friday)));
+        assert_eq!(journey.arrival(friday), Some(RailTime::new(1, 30).on(friday.succ_opt().unwrap())));
+
+        // Arriving early on Saturday, the latest departure is the sleeper on Friday night
+        let saturday = friday.succ_opt();
+        let latest = |date: Option<NaiveDate>, dest: StationId, arrive_by: RailTime| {
+            graph.latest_departure(&mut QueryScratch::new(&graph), date, 0, dest, arrive_by, 0).map(|(depart, journey)| (depart, journey.time / 60))
+        };
+        assert_eq!(latest(saturday, 2, RailTime::new(2, 0)), Some((RailTime::new(23, 30), 120)));
+        assert_eq!(latest(saturday, 1, RailTime::new(1, 0)), Some((RailTime::new(23, 30), 60)));
+        assert_eq!(latest(saturday.and_then(|d| d.succ_opt()), 2, RailTime::new(2, 0)), None);
+        // Or the weekday train on Friday morning, arriving later
+        assert_eq!(latest(saturday, 1, RailTime::new(0, 20)), Some((RailTime::new(9, 0), 30)));
+        assert_eq!(latest(friday.succ_opt().and_then(|d| d.succ_opt()).and_then(|d| d.succ_opt()), 1, RailTime::new(0, 20)), None);
+        let (_, journey) = graph.latest_departure(&mut scratch, saturday, 0, 2, RailTime::new(2, 0), 0).unwrap();
+        assert_eq!(journey.departure(friday), Some(RailTime::new(23, 30).on(friday)));
+        assert_eq!(journey.links.len(), 1);
+
+        // Connection Scan follows the same calendars
+        for (date, depart, origin) in [("2020-09-14", RailTime::new(8, 0), 0), ("2020-09-11", RailTime::new(23, 0), 0), ("2020-09-12", RailTime::new(0, 0), 1),
+                ("2020-09-13", RailTime::new(0, 0), 1)] {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+            assert_eq!(graph.compute_all_times_with(Engine::ConnectionScan, &mut scratch, date, depart, origin, 0, 0),
+                graph.compute_all_times(&mut scratch, date, depart, origin, 0, 0));
+        }
+    }
+
+    #[test]
+    fn test_stop_activity() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("STEVNGE", "Stevenage", "SVG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let stop = |station, time, activity| Stop { activity, ..Stop::simple(station, time, time) };
+        let service = |id, stops| Service::simple(id, &format!("C0000{}", id), stops);
+        let timetable = Timetable::new(
+            None,
+            vec![
+                // Only picking up at Stevenage towards London, and only setting down on the way back
+                service(0, vec![stop(0, "0900", StopActivity::Normal), stop(1, "0930", StopActivity::PickUpOnly), stop(2, "1000", StopActivity::Normal)]),
+                service(1, vec![stop(2, "1100", StopActivity::Normal), stop(1, "1130", StopActivity::SetDownOnly), stop(0, "1200", StopActivity::Normal)])
+            ],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let mut scratch = QueryScratch::new(&graph);
+        let mut search = |depart: &str, origin: StationId, dest: StationId| {
+            let depart = RailTime::from_24h(depart).unwrap();
+            graph.compute_journeys(&mut scratch, None, depart, origin, vec![dest], 0, 0, &JourneyConstraints::default()).remove(0).time / 60
+        };
+
+        assert_eq!(search("0900", 0, 2), 60);
+        assert_eq!(search("0900", 1, 2), 60);
+        assert_eq!(search("1100", 2, 1), 30);
+        assert_eq!(search("1100", 2, 0), 60);
+        // Stevenage can't be reached from Cambridge on the way to London, only on the way back,
+        // and Cambridge can't be reached from Stevenage by the train back, only the next morning's via London
+        assert_eq!(search("0900", 0, 1), 150);
+        assert_eq!(search("1100", 1, 0), 25*60);
+
+        let alternatives = graph.compute_alternatives(&mut scratch, None, RailTime::new(9, 0), 0, vec![1], 0, 0, &JourneyConstraints::default(), 2);
+        assert_eq!(alternatives[0][0].time, 150*60);
+    }
+
+    #[test]
+    fn test_stp_variants() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let date = |d: u32| NaiveDate::from_ymd_opt(2020, 9, d).unwrap();
+        let service = |id: u32, depart: &str, arrive: &str, from: u32, to: u32, stp: Stp| Service {
+            calendar: Some(Calendar { runs_from: date(from), runs_to: date(to), days_run: [true; 7], stp }),
+            ..Service::simple(id, "C00001", if depart.is_empty() { vec![] } else { vec![Stop::simple(0, depart, depart), Stop::simple(1, arrive, arrive)] })
+        };
+        // A later train on the 14th and none on the 15th
+        let timetable = Timetable::new(
+            None,
+            vec![
+                service(0, "0900", "0930", 1, 30, Stp::Permanent),
+                service(1, "1000", "1030", 14, 14, Stp::Overlay),
+                service(2, "", "", 15, 15, Stp::Cancellation)
+            ],
+            Vec::new()
+        );
+        let (graph, issues) = TravelGraph::new_checked(&stations, &Vec::new(), &timetable, true).unwrap();
+        assert!(issues.is_empty());
+
+        let mut scratch = QueryScratch::new(&graph);
+        let times: Vec<u32> = [13, 14, 15].iter().map(|d| {
+            graph.compute_journeys(&mut scratch, Some(date(*d)), RailTime::new(8, 0), 0, vec![1], 0, 0, &JourneyConstraints::default()).remove(0).time
+        }).collect();
+        assert_eq!(times, vec![90*60, 150*60, u32::MAX]);
+    }
+
+    #[test]
+    fn test_bank_holidays() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KNGX", "London Kings Cross", "KGX")
+        ]);
+        let date = |m: u32, d: u32| NaiveDate::from_ymd_opt(2020, m, d).unwrap();
+        // Every day but bank holiday Mondays
+        let timetable = Timetable::new(
+            None,
+            vec![Service {
+                bank_holidays: BankHolidayRunning::NotBankHolidayMondays,
+                ..Service::simple(0, "C00001", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0930", "0930")])
+            }],
+            Vec::new()
+        );
+        let mut graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let search = |graph: &TravelGraph, date: Option<NaiveDate>| {
+            graph.compute_journeys(&mut QueryScratch::new(graph), date, RailTime::new(8, 0), 0, vec![1], 0, 0, &JourneyConstraints::default()).remove(0).time
+        };
+        assert_eq!(search(&graph, Some(date(9, 7))), 90*60);
+        assert_eq!(search(&graph, Some(date(8, 31))), u32::MAX);
+        assert_eq!(search(&graph, None), 90*60);
+
+        // A one-off holiday
+        let mut holidays = graph.bank_holidays().clone();
+        holidays.add(Region::EnglandWales, date(9, 7));
+        graph.set_bank_holidays(holidays);
+        assert_eq!(search(&graph, Some(date(9, 7))), u32::MAX);
+    }
+
+    #[test]
+    fn test_associations() {
+        let stations = StationList::new(vec![
+            Station::simple("KNGX", "London Kings Cross", "KGX"),
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("ELYY", "Ely", "ELY"),
+            Station::simple("KLYNN", "Kings Lynn", "KLN")
+        ]);
+        let divide = |kind: AssociationKind| Association {
+            main_uid: "C00001".to_string(),
+            assoc_uid: "C00002".to_string(),
+            kind,
+            location: 1,
+            day_offset: 0,
+            passenger: true,
+            calendar: Calendar {
+                runs_from: NaiveDate::from_ymd_opt(2020, 5, 23).unwrap(),
+                runs_to: NaiveDate::from_ymd_opt(2020, 12, 12).unwrap(),
+                days_run: [true; 7],
+                stp: Stp::Permanent
+            }
+        };
+        // The Kings Lynn portion leaves Cambridge too soon after the main train arrives to change, so it's a day's wait
+        let mut timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "C00001", vec![Stop::simple(0, "1000", "1000"), Stop::simple(1, "1050", "1055"), Stop::simple(2, "1115", "1115")]),
+                Service::simple(1, "C00002", vec![Stop::simple(1, "1052", "1052"), Stop::simple(3, "1140", "1140")])
+            ],
+            vec![divide(AssociationKind::Next)]
+        );
+
+        let journey = |timetable: &Timetable| {
+            let graph = TravelGraph::new(&stations, &Vec::new(), timetable);
+            let mut scratch = QueryScratch::new(&graph);
+            graph.compute_journeys(&mut scratch, None, RailTime::new(9, 50), 0, vec![3], 10*60, 0, &JourneyConstraints::default()).remove(0)
+        };
+        assert!(journey(&timetable).time > DAY);
+
+        // Staying aboard through the divide
+        timetable.associations = vec![divide(AssociationKind::Divide)];
+        let through = journey(&timetable);
+        assert_eq!(through.time, 110*60);
+        assert_eq!(through.changes(), 0);
+        let time = |t| ServiceTime::from_24h(t).unwrap();
+        assert_eq!(through.links, vec![Link::Rail(RailLink {
+            dst: 3,
+            service: 0,
+            depart: time("1000"),
+            time: 100*60,
+            depart_platform: None,
+            arrive_platform: None,
+            calls: vec![Call { station: 1, arrive: time("1050"), depart: time("1052"), platform: None }].into()
+        })]);
+    }
+
+    #[test]
+    fn test_fixed_link_graph() {
+        // Transfer times test, three stations 0,1,2 with services:
+        // 0 -> 2 : 0000 -> 0100 (~0)
+        // 1 -> 2 : 0020 -> 0040 (~1)
+        // 2 -> 1 : 0100 -> 0120 (~2)
+        // And a walk between 0 and 1 of 10 mins
+        let graph = TravelGraph::from_nodes(vec![
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 0, "0000", 60*60),
+                        Link::simple_fixed(1, 10*60, FixedLinkKind::Walk)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![
+                        Link::simple_rail(2, 1, "0020", 20*60),
+                        Link::simple_fixed(0, 10*60, FixedLinkKind::Walk)
+                    ],
+                    transfer_time: 2*60
+                },
+                TGNode {
+                    links: vec![Link::simple_rail(1, 2, "0100", 20*60)],
+                    transfer_time: 2*60
+                }
+        ]);
+
+        // From station 0
+        let journeys = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(0, 0), 0, vec![1, 2], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 10*60);
+        assert_eq!(journeys[0].links, vec![Link::simple_fixed(1, 10*60, FixedLinkKind::Walk)]);
+        assert_eq!(journeys[1].time, 40*60);
+        assert_eq!(journeys[1].links, vec![
+            Link::simple_fixed(1, 10*60, FixedLinkKind::Walk),
+            Link::simple_rail(2, 1, "0020", 20*60)
+        ]);
+
+        // From station 2
+        let journeys = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(0, 0), 2, vec![0, 1], 0, 0, &JourneyConstraints::default());
+        assert_eq!(journeys[0].time, 90*60);
+        assert_eq!(journeys[0].links, vec![
+            Link::simple_rail(1, 2, "0100", 20*60),
+            Link::simple_fixed(0, 10*60, FixedLinkKind::Walk)
+        ]);
+        assert_eq!(journeys[1].time, 80*60);
+        assert_eq!(journeys[1].links, vec![Link::simple_rail(1, 2, "0100", 20*60)]);
+    }
+
+    #[test]
+    fn test_restricted_fixed_link() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX")
+        ]);
+        // A bus one way, setting off between 0600 and 2200 on weekdays
+        let bus = fixed_links::FixedLink {
+            one_way: true,
+            availability: Some(LinkAvailability {
+                start: RailTime::new(6, 0),
+                end: RailTime::new(22, 0),
+                calendar: Calendar {
+                    runs_from: NaiveDate::MIN,
+                    runs_to: NaiveDate::MAX,
+                    days_run: [true, true, true, true, true, false, false],
+                    stp: Stp::Permanent
+                }
+            }),
+            ..fixed_links::FixedLink::simple(0, 1, 30*60, FixedLinkKind::Bus)
+        };
+        let graph = TravelGraph::new(&stations, &vec![bus], &Timetable::new(None, Vec::new(), Vec::new()));
+        let mut scratch = QueryScratch::new(&graph);
+        let mut times = |date: Option<NaiveDate>, depart: RailTime, origin: StationId| graph.compute_all_times(&mut scratch, date, depart, origin, 0, 0);
+
+        assert_eq!(times(None, RailTime::new(12, 0), 0), vec![Some(0), Some(30*60)]);
+        assert_eq!(times(None, RailTime::new(5, 0), 0), vec![Some(0), Some(90*60)]);
+        assert_eq!(times(None, RailTime::new(12, 0), 1), vec![None, Some(0)]);
+
+        // Friday then Saturday, and late on Friday it doesn't run until Monday
+        let friday = NaiveDate::from_ymd_opt(2020, 6, 5);
+        assert_eq!(times(friday, RailTime::new(12, 0), 0)[1], Some(30*60));
+        assert_eq!(times(friday.and_then(|d| d.succ_opt()), RailTime::new(12, 0), 0)[1], None);
+        assert_eq!(times(friday, RailTime::new(23, 0), 0)[1], None);
+
+        // Setting off when the link opens, as for the first train
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::new(5, 0), 0, vec![1], 0, 0, &JourneyConstraints::default()).remove(0);
+        assert_eq!((journey.depart, journey.time), (RailTime::new(6, 0), 90*60));
+        let legs = journey.legs();
+        assert_eq!((legs[0].depart, legs[0].arrive), (RailTime::new(6, 0), RailTime::new(6, 30)));
+    }
+}
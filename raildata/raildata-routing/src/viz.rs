@@ -0,0 +1,179 @@
+/** Copyright James Lomax 2020 */
+
+use std::io::{self, Write};
+
+use raildata_core::stations::{StationId, StationList};
+use crate::travel_graph::{Journey, Link, TravelGraph};
+use raildata_core::timetable::RailTime;
+
+fn crs(stations: &StationList, id: StationId) -> &str {
+    &stations.get(id).unwrap().crs_code
+}
+
+/** Short edge label, e.g. R(12, 0930, 45) for rail or F(5, Walk) for a fixed link. Times in minutes */
+fn link_label(link: &Link) -> Option<(StationId, String)> {
+    match link {
+        Link::Rail(rl) => Some((rl.dst, format!("R({}, {}, {})", rl.service, rl.depart.to_24h(), rl.time/60))),
+        Link::Fixed(fl) => Some((fl.dst, format!("F({}, {:?})", fl.time/60, fl.kind))),
+        Link::Dummy => None
+    }
+}
+
+fn station_label(stations: &StationList, id: StationId, times: Option<&[Option<u32>]>) -> String {
+    match times.map(|t| t[id]) {
+        Some(Some(time)) => format!("{} ({})", crs(stations, id), time/60),
+        Some(None) => format!("{} (-)", crs(stations, id)),
+        None => crs(stations, id).to_string()
+    }
+}
+
+/**
+ * Stations visited by a journey with the time it arrives at each one.
+ * The first entry is the origin at the departure time.
+ */
+fn journey_steps(journey: &Journey) -> Vec<(StationId, RailTime, Option<&Link>)> {
+    let mut steps = vec![(journey.origin, journey.depart, None)];
+    let mut time = journey.depart;
+
+    for link in &journey.links {
+        let (dst, arrive) = match link {
+            Link::Rail(rl) => (rl.dst, rl.depart.time().add(rl.time)),
+            Link::Fixed(fl) => (fl.dst, time.add(fl.wait(time) + fl.time)),
+            Link::Dummy => continue
+        };
+        steps.push((dst, arrive, Some(link)));
+        time = arrive;
+    }
+    steps
+}
+
+/**
+ * Write the whole travel graph as a PlantUML component diagram. If $times is
+ * given (as from TravelGraph::compute_all_times) each station is labelled with
+ * its best journey time in minutes.
+ *
+ * Only useful for small graphs - the full timetable has millions of links.
+ */
+pub fn write_graph_plantuml<W: Write>(w: &mut W, stations: &StationList, graph: &TravelGraph, times: Option<&[Option<u32>]>) -> io::Result<()> {
+    writeln!(w, "@startuml")?;
+    for id in 0..graph.station_count() {
+        writeln!(w, "[{}] as d{}", station_label(stations, id, times), id)?;
+    }
+
+    for id in 0..graph.station_count() {
+        for (dst, label) in graph.links(id).iter().filter_map(link_label) {
+            writeln!(w, "d{} --> d{} : {}", id, dst, label)?;
+        }
+    }
+    writeln!(w, "@enduml")
+}
+
+/** Write the whole travel graph as a Graphviz DOT digraph, labelled as for write_graph_plantuml */
+pub fn write_graph_dot<W: Write>(w: &mut W, stations: &StationList, graph: &TravelGraph, times: Option<&[Option<u32>]>) -> io::Result<()> {
+    writeln!(w, "digraph travel_graph {{")?;
+    for id in 0..graph.station_count() {
+        writeln!(w, "  d{} [label=\"{}\"];", id, station_label(stations, id, times))?;
+    }
+
+    for id in 0..graph.station_count() {
+        for (dst, label) in graph.links(id).iter().filter_map(link_label) {
+            writeln!(w, "  d{} -> d{} [label=\"{}\"];", id, dst, label)?;
+        }
+    }
+    writeln!(w, "}}")
+}
+
+/** Write a single journey as a PlantUML diagram, one node per station with its arrival time */
+pub fn write_journey_plantuml<W: Write>(w: &mut W, stations: &StationList, journey: &Journey) -> io::Result<()> {
+    writeln!(w, "@startuml")?;
+    let steps = journey_steps(journey);
+    for (i, (station, time, _)) in steps.iter().enumerate() {
+        writeln!(w, "[{} {}] as s{}", crs(stations, *station), time.to_24h(), i)?;
+    }
+    for (i, (_, _, link)) in steps.iter().enumerate().skip(1) {
+        if let Some((_, label)) = link.and_then(link_label) {
+            writeln!(w, "s{} --> s{} : {}", i - 1, i, label)?;
+        }
+    }
+    writeln!(w, "@enduml")
+}
+
+/** Write a single journey as a Graphviz DOT digraph, as for write_journey_plantuml */
+pub fn write_journey_dot<W: Write>(w: &mut W, stations: &StationList, journey: &Journey) -> io::Result<()> {
+    writeln!(w, "digraph journey {{")?;
+    writeln!(w, "  rankdir=LR;")?;
+    let steps = journey_steps(journey);
+    for (i, (station, time, _)) in steps.iter().enumerate() {
+        writeln!(w, "  s{} [label=\"{} {}\"];", i, crs(stations, *station), time.to_24h())?;
+    }
+    for (i, (_, _, link)) in steps.iter().enumerate().skip(1) {
+        if let Some((_, label)) = link.and_then(link_label) {
+            writeln!(w, "  s{} -> s{} [label=\"{}\"];", i - 1, i, label)?;
+        }
+    }
+    writeln!(w, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata_core::stations::Station;
+    use raildata_core::fixed_links::{FixedLink, FixedLinkKind};
+    use raildata_core::timetable::{Service, Stop, Timetable};
+    use crate::travel_graph::{JourneyConstraints, QueryScratch};
+
+    fn fixture() -> (StationList, TravelGraph) {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "Cambridge", "CBG"),
+            Station::simple("KINGSX", "London Kings Cross", "KGX"),
+            Station::simple("STPX", "London St Pancras", "STP")
+        ]);
+        let fixedlinks = vec![FixedLink::simple(1, 2, 5*60, FixedLinkKind::Walk)];
+        let timetable = Timetable::new(
+            None,
+            vec![
+                Service::simple(0, "C00001", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")])
+            ],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+        (stations, graph)
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let (stations, graph) = fixture();
+        let times = vec![Some(0), Some(50*60), None];
+
+        let mut out = Vec::new();
+        write_graph_dot(&mut out, &stations, &graph, Some(&times)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+digraph travel_graph {
+  d0 [label=\"CBG (0)\"];
+  d1 [label=\"KGX (50)\"];
+  d2 [label=\"STP (-)\"];
+  d0 -> d1 [label=\"R(0, 0900, 50)\"];
+  d1 -> d2 [label=\"F(5, Walk)\"];
+  d2 -> d1 [label=\"F(5, Walk)\"];
+}
+");
+    }
+
+    #[test]
+    fn test_journey_plantuml() {
+        let (stations, graph) = fixture();
+        let journey = graph.compute_journeys(&mut QueryScratch::new(&graph), None, RailTime::from_24h("0830").unwrap(), 0, vec![2], 0, 60*60, &JourneyConstraints::default()).remove(0);
+
+        let mut out = Vec::new();
+        write_journey_plantuml(&mut out, &stations, &journey).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+@startuml
+[CBG 0900] as s0
+[KGX 0950] as s1
+[STP 0955] as s2
+s0 --> s1 : R(0, 0900, 50)
+s1 --> s2 : F(5, Walk)
+@enduml
+");
+    }
+}
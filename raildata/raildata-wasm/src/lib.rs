@@ -0,0 +1,169 @@
+/* Copyright James Lomax 2020 */
+
+// Journey search in the browser. A wasm-bindgen wrapper over raildata which
+// loads services from bytes the page has fetched, usually a cache file written
+// by the server or command line tool, and answers station lookups and journey
+// searches as plain objects. Build with `wasm-pack build --target web`.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use raildata::overlay::parse_date;
+use raildata::{
+    load_services_from_bytes, RailServices, Station, StationId, StationList,
+    RailTime, Journey, JourneyConstraints, Link, LookupOptions, QueryScratch
+};
+
+fn crs(stations: &StationList, id: StationId) -> &str {
+    &stations.get(id).unwrap().crs_code
+}
+
+fn hh_mm(t: &RailTime) -> String {
+    let s = t.to_24h();
+    format!("{}:{}", &s[0..2], &s[2..4])
+}
+
+fn station_json(station: &Station) -> Value {
+    let location = station.lat_lon().map(|ll| json!({ "lat": ll.lat, "lon": ll.lon }));
+    json!({ "id": station.id, "crs": station.crs_code, "name": station.names[0], "location": location })
+}
+
+/** Stations for a lookup as the server gives them: the exact CRS match first (if any), then name matches best first */
+pub fn lookup_json(rail: &RailServices, name: &str) -> Value {
+    let (found, _) = rail.stations.lookup(name, &LookupOptions::default());
+    Value::Array(found.into_iter().map(|id| station_json(rail.stations.get(id).unwrap())).collect())
+}
+
+// $journey with the fields of the server's v2 journeys, null if it couldn't be made
+fn journey_json(stations: &StationList, journey: &Journey) -> Value {
+    if journey.time == u32::MAX {
+        return Value::Null;
+    }
+    let legs: Vec<Value> = journey.legs().iter().filter(|leg| !matches!(leg.link, Link::Dummy)).map(|leg| {
+        let (mode, service) = match leg.link {
+            Link::Rail(rl) => ("rail".to_string(), Some(rl.service)),
+            Link::Fixed(fl) => (format!("{:?}", fl.kind).to_lowercase(), None),
+            Link::Dummy => unreachable!()
+        };
+        json!({
+            "mode": mode,
+            "from": crs(stations, leg.from),
+            "to": crs(stations, leg.to),
+            "depart": hh_mm(&leg.depart),
+            "depart_day": leg.depart_day,
+            "arrive": hh_mm(&leg.arrive),
+            "arrive_day": leg.arrive_day,
+            "wait": leg.wait,
+            "service": service
+        })
+    }).collect();
+    json!({ "duration": journey.time, "changes": journey.changes(), "legs": legs })
+}
+
+/**
+ * The journey from $origin to each of $dests (CRS codes) leaving at $start (HHMM or HH:MM),
+ * only by trains running on $date (YYYY-MM-DD) if given. Contingency and flexi_depart are in minutes
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn journeys_json(rail: &RailServices, scratch: &mut QueryScratch, origin: &str, dests: &[String], start: &str,
+        date: Option<&str>, contingency_mins: u32, flexi_depart_mins: u32) -> Result<Value, String> {
+    let station_id = |code: &str| rail.stations.get_by_crs(&code.to_uppercase()).map(|s| s.id)
+        .ok_or_else(|| format!("Could not find CRS {}", code));
+    let origin = station_id(origin)?;
+    let dest_ids = dests.iter().map(|dest| station_id(dest)).collect::<Result<Vec<_>, _>>()?;
+    let start = RailTime::from_24h(&start.replace(':', "")).ok_or_else(|| format!("Could not parse time {}", start))?;
+    let date = date.map(parse_date).transpose().map_err(|e| e.to_string())?;
+
+    let journeys = rail.graph.compute_journeys(scratch, date, start, origin, dest_ids, contingency_mins*60, flexi_depart_mins*60,
+        &JourneyConstraints::default());
+    Ok(Value::Array(journeys.iter().map(|journey| journey_json(&rail.stations, journey)).collect()))
+}
+
+// Objects rather than Maps in JS
+fn to_js(value: &Value) -> Result<JsValue, JsError> {
+    value.serialize(&serde_wasm_bindgen::Serializer::json_compatible()).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/** Loaded services and the search state reused between searches */
+#[wasm_bindgen]
+pub struct RailData {
+    rail: RailServices,
+    scratch: QueryScratch
+}
+
+#[wasm_bindgen]
+impl RailData {
+    /** Load the services in $cache, the contents of a cache file (see `RailServices::to_cache_bytes`) */
+    #[wasm_bindgen(constructor)]
+    pub fn new(cache: &[u8]) -> Result<RailData, JsError> {
+        let rail = RailServices::from_cache_bytes(cache).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self::from_services(rail))
+    }
+
+    /** Load services from the contents of the MSN, FLF and MCA files of a CIF extract */
+    #[wasm_bindgen(js_name = fromCif)]
+    pub fn from_cif(msn: &[u8], flf: &[u8], mca: &[u8]) -> Result<RailData, JsError> {
+        let rail = load_services_from_bytes(msn, flf, false, mca, &mut Vec::new()).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self::from_services(rail))
+    }
+
+    /** Stations with names like $name, as `{id, crs, name, location}`, best match first */
+    pub fn lookup(&self, name: &str) -> Result<JsValue, JsError> {
+        to_js(&lookup_json(&self.rail, name))
+    }
+
+    /** The journey to each of $dests as for `journeys_json`, null for each that can't be reached */
+    #[wasm_bindgen(js_name = computeJourneys)]
+    pub fn compute_journeys(&mut self, origin: &str, dests: Vec<String>, start: &str, date: Option<String>, contingency_mins: u32,
+            flexi_depart_mins: u32) -> Result<JsValue, JsError> {
+        let journeys = journeys_json(&self.rail, &mut self.scratch, origin, &dests, start, date.as_deref(), contingency_mins, flexi_depart_mins)
+            .map_err(|msg| JsError::new(&msg))?;
+        to_js(&journeys)
+    }
+}
+
+impl RailData {
+    pub fn from_services(rail: RailServices) -> Self {
+        let scratch = QueryScratch::new(&rail.graph);
+        Self { rail, scratch }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raildata::stations::Station;
+    use raildata::timetable::{Service, Stop, Timetable};
+    use raildata::TravelGraph;
+
+    #[test]
+    fn test_search_from_cache_bytes() {
+        let stations = StationList::new(vec![
+            Station::simple("CAMBDGE", "CAMBRIDGE", "CBG"),
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX")
+        ]);
+        let timetable = Timetable::new(
+            None,
+            vec![Service::simple(0, "C12345", vec![Stop::simple(0, "0900", "0900"), Stop::simple(1, "0950", "0950")])],
+            Vec::new()
+        );
+        let graph = TravelGraph::new(&stations, &Vec::new(), &timetable);
+        let bytes = RailServices { stations, fixedlinks: Vec::new(), timetable, graph }.to_cache_bytes().unwrap();
+        let RailData { rail, mut scratch } = RailData::from_services(RailServices::from_cache_bytes(&bytes).unwrap());
+
+        assert_eq!(lookup_json(&rail, "kgx")[0]["name"], "LONDON KINGS CROSS");
+        assert_eq!(lookup_json(&rail, "cambrige")[0]["crs"], "CBG");
+
+        let journeys = journeys_json(&rail, &mut scratch, "CBG", &["KGX".to_string()], "08:50", None, 0, 0).unwrap();
+        assert_eq!(journeys[0]["duration"], 60*60);
+        assert_eq!(journeys[0]["legs"][0]["depart"], "09:00");
+        assert_eq!(journeys[0]["legs"][0]["service"], 0);
+        // Without a date services run every day, so after the last one it's the next day's
+        let journeys = journeys_json(&rail, &mut scratch, "CBG", &["KGX".to_string()], "1000", None, 0, 0).unwrap();
+        assert_eq!(journeys[0]["legs"][0]["depart_day"], 1);
+
+        assert!(journeys_json(&rail, &mut scratch, "XXX", &[], "0900", None, 0, 0).is_err());
+        assert!(journeys_json(&rail, &mut scratch, "CBG", &[], "0900", Some("tomorrow"), 0, 0).is_err());
+    }
+}
@@ -0,0 +1,441 @@
+/* Copyright James Lomax 2020 */
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(feature = "realtime")]
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use raildata_core::error::Result;
+use raildata_core::fares::FareTable;
+use raildata_core::holidays::{BankHolidays, Region};
+use raildata_routing::overlay::{parse_date, TimetableOverlay};
+use crate::{RailDataError, RailServices, StationList};
+
+/** Config file read by `Config::load` when no path is given, if it exists */
+pub const DEFAULT_CONFIG_FILE: &str = "raildata.toml";
+
+/**
+ * Settings shared by the server and CLI, e.g.
+ *
+ *   [data]
+ *   prefix = "ttis/RJTTF748"
+ *
+ *   [[data.overlays]]
+ *   mca = "strike/RJTTF748.MCA"
+ *
+ *   [[data.groups]]
+ *   code = "LON"
+ *   name = "London Terminals"
+ *   stations = ["KGX", "STP", "EUS"]
+ *
+ *   [data.bank_holidays]
+ *   add = ["2022-06-03"]
+ *
+ *   [data.interchange]
+ *   BHM = 15
+ *
+ *   [routing]
+ *   contingency_mins = 10
+ *
+ * Missing sections and keys take their defaults.
+ */
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data: DataConfig,
+    pub routing: RoutingConfig,
+    pub server: ServerConfig,
+    pub update: UpdateConfig,
+    pub realtime: RealtimeConfig
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    // File prefix of the timetable extract, i.e. without the .MSN/.FLF/.MCA extension, the .zip archive or a GTFS feed directory
+    pub prefix: String,
+    // Amended timetables searched instead of the base one on their dates
+    pub overlays: Vec<OverlayConfig>,
+    // Stations searched as one, which the MSN doesn't list
+    pub groups: Vec<GroupConfig>,
+    // File prefix of the RDG fares feed (the .FFL and .LOC files, and .TOC if there is one), to estimate prices of journeys
+    pub fares: Option<String>,
+    // Changes to the built-in bank holidays
+    pub bank_holidays: BankHolidaysConfig,
+    // Minimum change times in minutes by CRS code, in place of the MSN's
+    pub interchange: BTreeMap<String, u32>
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "../../Starter/out/RJTTF748".to_string(),
+            overlays: Vec::new(),
+            groups: Vec::new(),
+            fares: None,
+            bank_holidays: BankHolidaysConfig::default(),
+            interchange: BTreeMap::new()
+        }
+    }
+}
+
+impl DataConfig {
+    /** Load each of the configured overlays over $rail */
+    pub fn load_overlays(&self, rail: &RailServices) -> Result<Vec<TimetableOverlay>> {
+        self.overlays.iter().map(|overlay| {
+            let dates = match (&overlay.start, &overlay.end) {
+                (Some(start), Some(end)) => Some((parse_date(start)?, parse_date(end)?)),
+                (None, None) => None,
+                _ => return Err(RailDataError::Config(format!("Overlay {} needs both start and end, or neither", overlay.mca)))
+            };
+            TimetableOverlay::load(rail, &overlay.mca, overlay.cancelled.as_deref(), dates)
+        }).collect()
+    }
+
+    /** Load the configured fares, None if there aren't any */
+    pub fn load_fares(&self) -> Result<Option<FareTable>> {
+        self.fares.as_deref().map(FareTable::load).transpose()
+    }
+
+    /** The built-in bank holidays with the configured changes */
+    pub fn bank_holidays(&self) -> Result<BankHolidays> {
+        let config = &self.bank_holidays;
+        let mut holidays = BankHolidays::default();
+        for (region, dates) in [(Region::EnglandWales, &config.remove), (Region::Scotland, &config.scotland_remove)] {
+            for date in dates {
+                holidays.remove(region, parse_date(date)?);
+            }
+        }
+        for (region, dates) in [(Region::EnglandWales, &config.add), (Region::Scotland, &config.scotland_add)] {
+            for date in dates {
+                holidays.add(region, parse_date(date)?);
+            }
+        }
+        Ok(holidays)
+    }
+
+    /** Override the minimum change times of $rail's stations with those configured */
+    pub fn set_change_times(&self, rail: &mut RailServices) -> Result<()> {
+        for (code, mins) in &self.interchange {
+            rail.set_change_time(code, *mins).map_err(|e| RailDataError::Config(format!("Interchange {}: {}", code, e)))?;
+        }
+        Ok(())
+    }
+
+    /** Add each of the configured groups to $stations */
+    pub fn add_groups(&self, stations: &mut StationList) -> Result<()> {
+        for group in &self.groups {
+            stations.add_group(&group.code, &group.name, &group.stations)
+                .map_err(|e| RailDataError::Config(format!("Group {}: {}", group.code, e)))?;
+        }
+        Ok(())
+    }
+}
+
+/** A group of stations by CRS code, searchable by its own code, e.g. "any Manchester station" */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    pub code: String,
+    pub name: String,
+    pub stations: Vec<String>
+}
+
+/**
+ * One-off bank holidays (YYYY-MM-DD) to add to those built in, e.g. for a jubilee,
+ * and built-in ones to remove, e.g. when one's moved. Those for England and Wales
+ * are `add` and `remove`, and those for Scotland `scotland_add` and `scotland_remove`.
+ */
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BankHolidaysConfig {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+    pub scotland_add: Vec<String>,
+    pub scotland_remove: Vec<String>
+}
+
+/**
+ * An MCA file of amended services, e.g. a strike timetable, with an optional CSV
+ * of cancelled train UIDs. The dates (YYYY-MM-DD, inclusive) default to those in
+ * the MCA header.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayConfig {
+    pub mca: String,
+    #[serde(default)]
+    pub cancelled: Option<String>,
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>
+}
+
+/** Journey search defaults, in minutes (the search itself takes seconds) */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    pub contingency_mins: u32,
+    pub flexi_depart_mins: u32
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self { contingency_mins: 15, flexi_depart_mins: 60 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    // Origins allowed to make cross-origin requests, e.g. "https://example.com". Any origin if empty
+    pub cors_origins: Vec<String>,
+    // Bearer token for the admin endpoints, which are disabled without one
+    pub admin_token: Option<String>,
+    // Journey and isochrone results kept for repeated requests, 0 to compute every request
+    pub result_cache_size: usize
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { address: "localhost".to_string(), port: 8000, cors_origins: Vec::new(), admin_token: None, result_cache_size: 256 }
+    }
+}
+
+/** Scheduled fetching of new data by the server, disabled unless an hour is set */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    // Local hour of the day (0-23) to update at
+    pub hour: Option<u32>,
+    // RDG open data username and password, as for `raildata prepare`
+    pub credentials: String,
+    // Each update is kept in a subdirectory, the newest being loaded on startup
+    pub snapshot_dir: String,
+    pub keep_snapshots: usize
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            hour: None,
+            credentials: "credentials.txt".to_string(),
+            snapshot_dir: "snapshots".to_string(),
+            keep_snapshots: 3
+        }
+    }
+}
+
+/**
+ * Darwin real-time running searched on request, disabled unless there's a source:
+ * a Push Port document (e.g. a snapshot kept current by a feed consumer), or an
+ * http(s) URL to fetch one from with the download feature.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RealtimeConfig {
+    pub source: Option<String>,
+    // Minutes between reading the source again
+    pub refresh_mins: u32
+}
+
+impl Default for RealtimeConfig {
+    fn default() -> Self {
+        Self { source: None, refresh_mins: 5 }
+    }
+}
+
+#[cfg(feature = "realtime")]
+impl RealtimeConfig {
+    /** Layer the running from the source over $rail for $date, None if there's no source */
+    pub fn load(&self, rail: &RailServices, date: NaiveDate) -> Result<Option<TimetableOverlay>> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Ok(None)
+        };
+        if source.starts_with("http://") || source.starts_with("https://") {
+            #[cfg(feature = "download")]
+            {
+                let body = crate::download::fetch(source).map_err(|e| RailDataError::from(e).in_file(source))?;
+                let updates = crate::darwin::read_push_port(&body[..]).map_err(|e| e.in_file(source))?;
+                return Ok(Some(crate::realtime::realtime_overlay(rail, date, &updates)));
+            }
+            #[cfg(not(feature = "download"))]
+            return Err(RailDataError::Config(format!("Fetching real-time source {} needs the download feature", source)));
+        }
+        crate::realtime::load_realtime(rail, source, date).map(Some)
+    }
+}
+
+impl Config {
+    /**
+     * Read the config from $path, else $RAILDATA_CONFIG, else raildata.toml if it exists,
+     * falling back to the defaults. RAILDATA_* environment variables then override the file.
+     */
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = path.map(PathBuf::from)
+            .or_else(|| env::var_os("RAILDATA_CONFIG").map(PathBuf::from))
+            .or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()));
+
+        let mut config = match path {
+            Some(path) => Self::read(&path)?,
+            None => Self::default()
+        };
+        config.apply_overrides(|name| env::var(name).ok())?;
+        Ok(config)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(|e| RailDataError::Config(e.to_string()))
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let name = path.display().to_string();
+        let s = fs::read_to_string(path).map_err(|e| RailDataError::from(e).in_file(&name))?;
+        Self::from_toml(&s).map_err(|e| e.in_file(&name))
+    }
+
+    /** Apply the RAILDATA_* overrides, looking each variable up with $var */
+    fn apply_overrides(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(v) = var("RAILDATA_DATA") {
+            self.data.prefix = v;
+        }
+        if let Some(v) = var("RAILDATA_CONTINGENCY") {
+            self.routing.contingency_mins = parse_var("RAILDATA_CONTINGENCY", &v)?;
+        }
+        if let Some(v) = var("RAILDATA_FLEXI_DEPART") {
+            self.routing.flexi_depart_mins = parse_var("RAILDATA_FLEXI_DEPART", &v)?;
+        }
+        if let Some(v) = var("RAILDATA_ADDRESS") {
+            self.server.address = v;
+        }
+        if let Some(v) = var("RAILDATA_PORT") {
+            self.server.port = parse_var("RAILDATA_PORT", &v)?;
+        }
+        if let Some(v) = var("RAILDATA_CORS_ORIGINS") {
+            self.server.cors_origins = v.split(',').map(str::trim).filter(|origin| !origin.is_empty()).map(str::to_string).collect();
+        }
+        if let Some(v) = var("RAILDATA_RESULT_CACHE") {
+            self.server.result_cache_size = parse_var("RAILDATA_RESULT_CACHE", &v)?;
+        }
+        if let Some(v) = var("RAILDATA_ADMIN_TOKEN") {
+            self.server.admin_token = Some(v).filter(|token| !token.is_empty());
+        }
+        if let Some(v) = var("RAILDATA_REALTIME") {
+            self.realtime.source = Some(v);
+        }
+        if let Some(v) = var("RAILDATA_UPDATE_HOUR") {
+            self.update.hour = Some(parse_var("RAILDATA_UPDATE_HOUR", &v)?);
+        }
+        if self.update.hour.is_some_and(|hour| hour > 23) {
+            return Err(RailDataError::Config(format!("Update hour {} isn't between 0 and 23", self.update.hour.unwrap())));
+        }
+        Ok(())
+    }
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| RailDataError::Config(format!("Could not parse {} '{}'", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Station;
+
+    #[test]
+    fn test_partial_toml() {
+        let config = Config::from_toml("[routing]\ncontingency_mins = 5\n").unwrap();
+        assert_eq!(config.routing.contingency_mins, 5);
+        assert_eq!(config.routing.flexi_depart_mins, 60);
+        assert_eq!(config.data, DataConfig::default());
+        assert_eq!(config.server.port, 8000);
+        assert_eq!(config.realtime, RealtimeConfig::default());
+
+        assert!(Config::from_toml("[routing]\ncontingency = 5\n").is_err());
+    }
+
+    #[test]
+    fn test_overlays() {
+        let config = Config::from_toml("[[data.overlays]]\nmca = \"strike.MCA\"\nstart = \"2020-09-14\"\nend = \"2020-09-15\"\n").unwrap();
+        assert_eq!(config.data.prefix, DataConfig::default().prefix);
+        assert_eq!(config.data.overlays, vec![OverlayConfig {
+            mca: "strike.MCA".to_string(),
+            cancelled: None,
+            start: Some("2020-09-14".to_string()),
+            end: Some("2020-09-15".to_string())
+        }]);
+
+        assert!(Config::from_toml("[[data.overlays]]\ncancelled = \"cancelled.csv\"\n").is_err());
+    }
+
+    #[test]
+    fn test_groups() {
+        let config = Config::from_toml("[[data.groups]]\ncode = \"LON\"\nname = \"London\"\nstations = [\"KGX\", \"STP\"]\n").unwrap();
+        let mut stations = StationList::new(vec![
+            Station::simple("KNGX", "LONDON KINGS CROSS", "KGX"),
+            Station::simple("STPX", "LONDON ST PANCRAS", "STP")
+        ]);
+        config.data.add_groups(&mut stations).unwrap();
+        assert_eq!(stations.resolve("LON"), Some(vec![0, 1]));
+        assert_eq!(stations.resolve("STP"), Some(vec![1]));
+        assert_eq!(stations.get_group("LON").unwrap().name, "London");
+
+        let config = Config::from_toml("[[data.groups]]\ncode = \"MAN\"\nname = \"Manchester\"\nstations = [\"MCV\"]\n").unwrap();
+        assert!(matches!(config.data.add_groups(&mut stations), Err(RailDataError::Config(_))));
+    }
+
+    #[test]
+    fn test_bank_holidays() {
+        let config = Config::from_toml("[data.bank_holidays]\nadd = [\"2022-06-03\"]\nremove = [\"2022-05-30\"]\n").unwrap();
+        let holidays = config.data.bank_holidays().unwrap();
+        let date = |s| parse_date(s).unwrap();
+        assert!(holidays.is_holiday(Region::EnglandWales, date("2022-06-03")));
+        assert!(!holidays.is_holiday(Region::EnglandWales, date("2022-05-30")));
+        assert!(holidays.is_holiday(Region::Scotland, date("2022-05-30")));
+
+        let config = Config::from_toml("[data.bank_holidays]\nscotland_add = [\"someday\"]\n").unwrap();
+        assert!(config.data.bank_holidays().is_err());
+    }
+
+    #[test]
+    fn test_interchange() {
+        let mut rail = crate::testing::generate(&crate::testing::NetworkSpec { stations: 4, lines: 1, stops_per_line: 4, ..Default::default() });
+        let code = rail.stations.get(0).unwrap().crs_code.clone();
+        let config = Config::from_toml(&format!("[data.interchange]\n{} = 15\n", code)).unwrap();
+        config.data.set_change_times(&mut rail).unwrap();
+        assert_eq!(rail.stations.get(0).unwrap().min_change_time, 15);
+
+        let config = Config::from_toml("[data.interchange]\nXXX = 15\n").unwrap();
+        assert!(matches!(config.data.set_change_times(&mut rail), Err(RailDataError::Config(_))));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut config = Config::from_toml("[data]\nprefix = \"a/RJTTF001\"\n").unwrap();
+        config.apply_overrides(|name| match name {
+            "RAILDATA_DATA" => Some("b/RJTTF002".to_string()),
+            "RAILDATA_PORT" => Some("9000".to_string()),
+            "RAILDATA_CORS_ORIGINS" => Some("https://a.example, https://b.example".to_string()),
+            "RAILDATA_ADMIN_TOKEN" => Some("secret".to_string()),
+            _ => None
+        }).unwrap();
+        assert_eq!(config.data.prefix, "b/RJTTF002");
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.cors_origins, vec!["https://a.example".to_string(), "https://b.example".to_string()]);
+        assert_eq!(config.server.admin_token.as_deref(), Some("secret"));
+
+        let err = config.apply_overrides(|name| if name == "RAILDATA_PORT" { Some("x".to_string()) } else { None });
+        assert!(err.is_err());
+        let err = config.apply_overrides(|name| if name == "RAILDATA_UPDATE_HOUR" { Some("24".to_string()) } else { None });
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,95 @@
+/* Copyright James Lomax 2020 */
+
+// Fetching the timetable from the RDG open data feed, shared by the CLI's
+// prepare command and the server's scheduled updates.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+const AUTH_ENDPOINT: &str = "https://opendata.nationalrail.co.uk/authenticate";
+const TIMETABLE_ENDPOINT: &str = "https://opendata.nationalrail.co.uk/api/staticfeeds/3.0/timetable";
+
+// Members of the timetable archive which the loader needs
+const EXTENSIONS: [&str; 3] = ["MSN", "FLF", "MCA"];
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn http_err(e: ureq::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/**
+ * Download the latest timetable archive to $dest, logging in with the RDG open
+ * data username and password on the first two lines of the file $credentials
+ */
+pub fn download(credentials: &Path, dest: &Path) -> io::Result<()> {
+    let contents = fs::read_to_string(credentials)?;
+    let mut lines = contents.lines().map(|l| l.trim());
+    let (username, password) = match (lines.next(), lines.next()) {
+        (Some(u), Some(p)) => (u, p),
+        _ => return Err(invalid_input(format!("Expected a username and password in {}", credentials.display())))
+    };
+
+    let auth: serde_json::Value = ureq::post(AUTH_ENDPOINT)
+        .send_form(&[("username", username), ("password", password)])
+        .map_err(http_err)?
+        .into_json()?;
+    let token = match auth["token"].as_str() {
+        Some(token) => token.to_string(),
+        None => return Err(invalid_input("Authentication response did not contain a token".to_string()))
+    };
+
+    let rsp = ureq::get(TIMETABLE_ENDPOINT)
+        .set("X-Auth-Token", &token)
+        .call()
+        .map_err(http_err)?;
+    io::copy(&mut rsp.into_reader(), &mut BufWriter::new(File::create(dest)?))?;
+    Ok(())
+}
+
+/** Fetch the body of $url, e.g. a Darwin snapshot */
+pub fn fetch(url: &str) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    ureq::get(url).call().map_err(http_err)?.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/**
+ * Extract the MSN, FLF and MCA files from the archive into $out and return
+ * the file prefix they share, e.g. data/RJTTF748
+ */
+pub fn extract(archive: &Path, out: &Path) -> io::Result<PathBuf> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+    let mut prefix: Option<String> = None;
+    let mut found = Vec::new();
+
+    for i in 0..zip.len() {
+        let mut member = zip.by_index(i).map_err(io::Error::other)?;
+        let name = match member.enclosed_name().and_then(|p| p.file_name()).and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue
+        };
+        let (stem, ext) = match name.rsplit_once('.') {
+            Some((stem, ext)) if EXTENSIONS.contains(&ext.to_uppercase().as_str()) => (stem.to_string(), ext.to_uppercase()),
+            _ => continue
+        };
+
+        match &prefix {
+            Some(p) if *p != stem => return Err(invalid_input(format!("Archive contains timetables for both {} and {}", p, stem))),
+            _ => prefix = Some(stem.clone())
+        }
+
+        io::copy(&mut member, &mut BufWriter::new(File::create(out.join(format!("{}.{}", stem, ext)))?))?;
+        found.push(ext);
+    }
+
+    for ext in EXTENSIONS.iter() {
+        if !found.iter().any(|f| f == ext) {
+            return Err(invalid_input(format!("Archive {} has no .{} file", archive.display(), ext)));
+        }
+    }
+    Ok(out.join(prefix.unwrap()))
+}
@@ -1,58 +1,74 @@
-#![allow(dead_code)]
-#![feature(map_first_last)]
 /** Copyright James Lomax 2020 */
 
-#[macro_use]
-extern crate lazy_static;
+// Convenience crate re-exporting the parsing/model (raildata-core), routing
+// (raildata-routing) and import/export (raildata-interop) crates under one
+// name. Embedders wanting less can depend on those crates directly.
+//
+// Stability: the prelude and the items re-exported at the crate root follow
+// semver, with breaking changes only in a new minor version while at 0.x. The
+// modules below are public for advanced use (e.g. lenient parsing or the
+// exporters) and may change between minor versions. Record parsing and search
+// internals are private to their crates.
 
-#[macro_use]
-mod record_parsing;
-mod utils;
-pub mod stations;
-pub mod timetable;
-pub mod fixed_links;
-pub mod travel_graph;
+pub use raildata_core::{error, stations, timetable, fixed_links, validate, diff, fares, holidays};
+#[cfg(feature = "geo")]
+pub use raildata_core::geo;
+pub use raildata_routing::{travel_graph, format, viz, compare, overlay};
+#[cfg(feature = "cache")]
+pub use raildata_routing::cache;
+#[cfg(feature = "realtime")]
+pub use raildata_core::darwin;
+#[cfg(feature = "realtime")]
+pub use raildata_routing::realtime;
+pub use raildata_interop::{export, render, compact};
+#[cfg(feature = "gtfs")]
+pub use raildata_interop::gtfs;
+#[cfg(feature = "geojson")]
+pub use raildata_interop::geojson;
+#[cfg(feature = "heatmap")]
+pub use raildata_interop::heatmap;
 
-use std::fs::File;
-use std::io::BufReader;
-pub use stations::{Station, StationList, StationId};
-pub use fixed_links::FixedLinkKind;
-pub use timetable::{Timetable, RailTime, Service, ServiceId};
-pub use travel_graph::{Journey, TravelGraph, Link};
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod snapshot;
+pub mod results;
 
-pub struct RailServices {
-    pub stations: StationList,
-    pub fixedlinks: Vec<fixed_links::FixedLink>,
-    pub timetable: Timetable,
-    pub graph: TravelGraph
-}
-
-pub fn load_services(file_prefix: &str) -> std::io::Result<RailServices> {
-    // Load Master Station Names (MSN) file
-    let msnname = format!("{}.MSN", file_prefix);
-    let msnfile = File::open(&msnname)?;
-    let mut msnreader = BufReader::new(msnfile);
-    let stations = StationList::read_msn_file(&mut msnreader)?;
-
-    // Load Fixed Leg File (FLF)
-    let flfname = format!("{}.FLF", file_prefix);
-    let flffile = File::open(&flfname)?;
-    let mut flfreader = BufReader::new(flffile);
-    let fixedlinks = fixed_links::parse_fixed_links(&stations, &mut flfreader)?;
+pub use raildata_core::{RailDataError, Station, StationList, StationGroup, StationId, LookupOptions, LookupOrder, FixedLinkKind, Timetable, ServiceIter, Departure, RailTime, ServiceTime, Reservations, Accommodation, Platform, ServiceMode, StopActivity, Calendar, Stp, Service, ServiceId, Association, AssociationKind};
+pub use raildata_routing::{Engine, Journey, JourneyConstraints, JourneyLeg, TravelGraph, GraphIssue, Link, QueryScratch, SearchStats, RailServices, LoadWarning, load_services, load_services_with_warnings, load_services_from_bytes};
+pub use raildata_routing::{load_from, TimetableSource, TimetableData, CifFiles};
+#[cfg(feature = "zip")]
+pub use raildata_routing::{load_services_from_zip, load_services_from_zip_with_warnings, CifArchive};
 
-    // Load services file (MCA) file
-    let mcaname = format!("{}.MCA", file_prefix);
-    let mcafile = File::open(&mcaname)?;
-    let mut mcareader = BufReader::with_capacity(1024*1024, mcafile);
-    let timetable = Timetable::read_mca_file(&stations, &mut mcareader)?;
+/**
+ * Load services from $path, whichever form the data is in: the timetable files
+ * sharing a prefix, a .zip archive (with the zip feature) or a GTFS feed
+ * directory (with the gtfs feature). Collects what was skipped or merged in $warnings.
+ */
+pub fn load_dataset_with_warnings(path: &str, warnings: &mut Vec<LoadWarning>) -> Result<RailServices, RailDataError> {
+    #[cfg(feature = "zip")]
+    if path.to_lowercase().ends_with(".zip") {
+        return load_services_from_zip_with_warnings(std::path::Path::new(path), warnings);
+    }
+    #[cfg(feature = "gtfs")]
+    if std::path::Path::new(path).join("stops.txt").exists() {
+        return gtfs::load_gtfs_with_warnings(std::path::Path::new(path), warnings);
+    }
+    load_services_with_warnings(path, warnings)
+}
 
-    // Compute graph
-    let graph = TravelGraph::new(&stations, &fixedlinks, &timetable);
+/** Load services from $path as `load_dataset_with_warnings` */
+pub fn load_dataset(path: &str) -> Result<RailServices, RailDataError> {
+    load_dataset_with_warnings(path, &mut Vec::new())
+}
 
-    return Ok(RailServices {
-        stations: stations,
-        fixedlinks: fixedlinks,
-        timetable: timetable,
-        graph: graph
-    });
+/** The commonly used types, for `use raildata::prelude::*` */
+pub mod prelude {
+    pub use crate::{load_services, load_dataset, RailServices, RailDataError};
+    pub use crate::{Station, StationList, StationId, FixedLinkKind};
+    pub use crate::{Timetable, Service, ServiceId, RailTime, ServiceTime};
+    pub use crate::{TravelGraph, QueryScratch, Journey, JourneyConstraints, Link};
 }
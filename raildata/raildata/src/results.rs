@@ -0,0 +1,147 @@
+/* Copyright James Lomax 2020 */
+
+// A bounded cache of computed results, e.g. a server's journey searches keyed
+// on the request, so repeating a query doesn't repeat the search. Results are
+// for one generation of the loaded data: asking about a newer generation
+// empties the cache, so nothing computed from replaced data is returned.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+struct Entries<K, V> {
+    generation: u64,
+    // Incremented on each use, so the least recently used entry has the lowest tick
+    tick: u64,
+    values: HashMap<K, (V, u64)>,
+    by_use: BTreeMap<u64, K>
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    // Empty the cache if it's for an older generation than $generation
+    fn advance(&mut self, generation: u64) {
+        if generation > self.generation {
+            tracing::debug!(from = self.generation, to = generation, dropped = self.values.len(), "Result cache flushed for new data");
+            self.generation = generation;
+            self.values.clear();
+            self.by_use.clear();
+        }
+    }
+
+    fn touch(&mut self, key: &K) -> Option<&V> {
+        let tick = self.tick + 1;
+        let (value, used) = self.values.get_mut(key)?;
+        self.by_use.remove(used);
+        self.by_use.insert(tick, key.clone());
+        *used = tick;
+        self.tick = tick;
+        Some(value)
+    }
+}
+
+/** Thread-safe cache of the $capacity most recently used results, disabled if that's 0 */
+pub struct ResultCache<K, V> {
+    capacity: usize,
+    entries: Mutex<Entries<K, V>>
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> ResultCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries { generation: 0, tick: 0, values: HashMap::new(), by_use: BTreeMap::new() })
+        }
+    }
+
+    /** The result for $key computed from data $generation, if it's cached */
+    pub fn get(&self, generation: u64, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.advance(generation);
+        let value = entries.touch(key).cloned();
+        if value.is_some() {
+            tracing::debug!(generation, "Result cache hit");
+        } else {
+            tracing::debug!(generation, "Result cache miss");
+        }
+        value
+    }
+
+    /**
+     * Cache $value for $key, computed from data $generation, dropping the least
+     * recently used result if full. Ignored if data from a later generation has
+     * been asked about since, as it's out of date.
+     */
+    pub fn insert(&self, generation: u64, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.advance(generation);
+        if self.capacity == 0 || generation < entries.generation {
+            return;
+        }
+        if entries.touch(&key).is_some() {
+            entries.values.get_mut(&key).unwrap().0 = value;
+            return;
+        }
+        if entries.values.len() >= self.capacity {
+            if let Some((_, oldest)) = entries.by_use.pop_first() {
+                entries.values.remove(&oldest);
+            }
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.by_use.insert(tick, key.clone());
+        entries.values.insert(key, (value, tick));
+    }
+
+    /** The cached result for $key, or else computed by $f (without holding the lock) and cached if it succeeds */
+    pub fn get_or_try_insert_with<E>(&self, generation: u64, key: K, f: impl FnOnce() -> Result<V, E>) -> Result<V, E> {
+        if let Some(value) = self.get(generation, &key) {
+            return Ok(value);
+        }
+        let value = f()?;
+        self.insert(generation, key, value.clone());
+        Ok(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_cache() {
+        let cache = ResultCache::new(2);
+        cache.insert(0, "CBG", 1);
+        cache.insert(0, "KGX", 2);
+        // Using CBG makes KGX the least recently used, so it's dropped for ELY
+        assert_eq!(cache.get(0, &"CBG"), Some(1));
+        cache.insert(0, "ELY", 3);
+        assert_eq!((cache.get(0, &"CBG"), cache.get(0, &"KGX"), cache.get(0, &"ELY")), (Some(1), None, Some(3)));
+        assert_eq!(cache.len(), 2);
+
+        // Failures aren't cached
+        let mut calls = 0;
+        let mut search = |result: Result<u32, ()>| cache.get_or_try_insert_with(0, "STP", || { calls += 1; result });
+        assert_eq!(search(Err(())), Err(()));
+        assert_eq!(search(Ok(4)), Ok(4));
+        assert_eq!(search(Ok(5)), Ok(4));
+        assert_eq!(calls, 2);
+
+        // Reloaded data empties the cache, and results from the old data aren't kept
+        assert_eq!(cache.get(1, &"STP"), None);
+        assert!(cache.is_empty());
+        cache.insert(0, "CBG", 1);
+        assert!(cache.is_empty());
+
+        let disabled = ResultCache::new(0);
+        disabled.insert(0, "CBG", 1);
+        assert_eq!(disabled.get(0, &"CBG"), None);
+    }
+}
@@ -0,0 +1,128 @@
+/* Copyright James Lomax 2020 */
+
+// A directory of timetable snapshots, one subdirectory each, so a server
+// updating its data can keep the last few for rollback. Snapshots are built
+// under a .partial name and renamed when complete, so a failed or interrupted
+// update is never picked up.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PARTIAL: &str = ".partial";
+
+/** Snapshots in $dir, named so they sort oldest first (e.g. by timestamp), keeping the newest $keep */
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    pub dir: PathBuf,
+    pub keep: usize
+}
+
+impl Snapshots {
+    pub fn new(dir: impl Into<PathBuf>, keep: usize) -> Self {
+        Self { dir: dir.into(), keep }
+    }
+
+    /** Names of the complete snapshots, oldest first */
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() && !name.ends_with(PARTIAL) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /** An empty directory to build snapshot $name in, replacing any left by a failed attempt */
+    pub fn start(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.dir.join(format!("{}{}", name, PARTIAL));
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    /** Mark snapshot $name complete, returning its directory */
+    pub fn commit(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.dir.join(name);
+        fs::rename(self.dir.join(format!("{}{}", name, PARTIAL)), &path)?;
+        Ok(path)
+    }
+
+    /** Remove the partial snapshot $name after a failed attempt */
+    pub fn discard(&self, name: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.dir.join(format!("{}{}", name, PARTIAL)))
+    }
+
+    /** Remove all but the newest $keep snapshots, returning the names removed */
+    pub fn prune(&self) -> io::Result<Vec<String>> {
+        let mut names = self.list()?;
+        let excess = names.len().saturating_sub(self.keep.max(1));
+        names.truncate(excess);
+        for name in &names {
+            fs::remove_dir_all(self.dir.join(name))?;
+        }
+        Ok(names)
+    }
+
+    /** The file prefix of the timetable in snapshot $name, e.g. snapshots/20200901-0300/RJTTF748 */
+    pub fn prefix(&self, name: &str) -> io::Result<Option<String>> {
+        timetable_prefix(&self.dir.join(name))
+    }
+
+    /** The file prefix of the newest snapshot, if there are any */
+    pub fn latest(&self) -> io::Result<Option<String>> {
+        match self.list()?.last() {
+            Some(name) => self.prefix(name),
+            None => Ok(None)
+        }
+    }
+}
+
+// The prefix shared by the .MSN file in $dir and the others
+fn timetable_prefix(dir: &Path) -> io::Result<Option<String>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "MSN") {
+            return Ok(Some(path.with_extension("").to_string_lossy().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("raildata-snapshots-{}", std::process::id()));
+        let snapshots = Snapshots::new(&dir, 2);
+        assert_eq!(snapshots.latest().unwrap(), None);
+
+        for name in &["20200901-0300", "20200902-0300", "20200903-0300"] {
+            let path = snapshots.start(name).unwrap();
+            fs::write(path.join("RJTTF748.MSN"), "").unwrap();
+            snapshots.commit(name).unwrap();
+        }
+        // A failed update isn't listed
+        snapshots.start("20200904-0300").unwrap();
+
+        let removed = snapshots.prune();
+        let list = snapshots.list();
+        let latest = snapshots.latest();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(removed.unwrap(), vec!["20200901-0300"]);
+        assert_eq!(list.unwrap(), vec!["20200902-0300", "20200903-0300"]);
+        assert_eq!(latest.unwrap(), Some(dir.join("20200903-0300/RJTTF748").to_string_lossy().to_string()));
+    }
+}
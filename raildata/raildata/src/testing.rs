@@ -0,0 +1,307 @@
+/* Copyright James Lomax 2020 */
+
+// Randomised but plausible networks, so tests, fuzzing and benchmarks can run
+// without the licensed RDG extract. The same spec and seed always give the
+// same network.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use raildata_core::fixed_links::FixedLink;
+use raildata_core::timetable::{ServiceTime, Stop, StopActivity};
+use crate::{FixedLinkKind, RailServices, RailTime, Service, Station, StationId, StationList, Timetable, TravelGraph};
+
+// Spacing of the station grid in National Grid units of 100m
+const GRID_SPACING: i32 = 60;
+
+/** Shape of a generated network */
+#[derive(Debug, Clone)]
+pub struct NetworkSpec {
+    // Stations are laid out on a jittered square grid
+    pub stations: usize,
+    // Each line runs services in both directions between its stops
+    pub lines: usize,
+    pub stops_per_line: usize,
+    pub headway_mins: u32,
+    // Services leave their first stop between first_departure and last_departure
+    pub first_departure: RailTime,
+    pub last_departure: RailTime,
+    // Walking links between nearby stations
+    pub transfer_links: usize,
+    pub seed: u64
+}
+
+impl Default for NetworkSpec {
+    fn default() -> Self {
+        Self {
+            stations: 100,
+            lines: 12,
+            stops_per_line: 8,
+            headway_mins: 30,
+            first_departure: RailTime::new(6, 0),
+            last_departure: RailTime::new(21, 0),
+            transfer_links: 20,
+            seed: 0
+        }
+    }
+}
+
+/** A generated network, before building the travel graph */
+pub struct Network {
+    pub stations: StationList,
+    pub fixedlinks: Vec<FixedLink>,
+    pub timetable: Timetable
+}
+
+// Fixed width code for $n from $alphabet, e.g. CRS codes from A-Z
+fn code(mut n: usize, len: usize, alphabet: &[u8]) -> String {
+    let mut s = vec![alphabet[0]; len];
+    for c in s.iter_mut().rev() {
+        *c = alphabet[n % alphabet.len()];
+        n /= alphabet.len();
+    }
+    String::from_utf8(s).unwrap()
+}
+
+const LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+fn distance(a: &Station, b: &Station) -> f64 {
+    let (dx, dy) = ((a.gref_east - b.gref_east) as f64, (a.gref_north - b.gref_north) as f64);
+    (dx*dx + dy*dy).sqrt()
+}
+
+// Minutes by train at about 100km/h, at least 2
+fn rail_mins(a: &Station, b: &Station) -> u32 {
+    ((distance(a, b) * 0.06).round() as u32).max(2)
+}
+
+// Minutes on foot at about 5km/h
+fn walk_mins(a: &Station, b: &Station) -> u32 {
+    ((distance(a, b) * 1.2).round() as u32).max(1)
+}
+
+// The $k stations nearest $from, excluding it, nearest first
+fn nearest(stations: &[Station], from: StationId, k: usize) -> Vec<StationId> {
+    let mut ids: Vec<StationId> = (0..stations.len()).filter(|id| *id != from).collect();
+    ids.sort_by(|a, b| distance(&stations[from], &stations[*a]).partial_cmp(&distance(&stations[from], &stations[*b])).unwrap());
+    ids.truncate(k);
+    ids
+}
+
+impl Network {
+    /**
+     * Generate a network from $spec. Lines wander between neighbouring
+     * stations, so most but not necessarily all stations are served.
+     */
+    pub fn generate(spec: &NetworkSpec) -> Self {
+        assert!(spec.stations >= 2 && spec.stations <= LETTERS.len().pow(3), "stations must be between 2 and 17576");
+        assert!(spec.headway_mins > 0, "headway_mins must be positive");
+        let mut rng = StdRng::seed_from_u64(spec.seed);
+
+        let side = (spec.stations as f64).sqrt().ceil() as usize;
+        let spacing = GRID_SPACING.min(9000 / side as i32);
+        let stations: Vec<Station> = (0..spec.stations).map(|i| {
+            let crs = code(i, 3, LETTERS);
+            let mut station = Station::simple(&format!("S{}", code(i, 6, ALPHANUMERIC)), &format!("SYNTHETIC {}", crs), &crs);
+            station.min_change_time = rng.gen_range(3..=10);
+            station.gref_east = 500 + (i % side) as i32 * spacing + rng.gen_range(-spacing/3..=spacing/3);
+            station.gref_north = 500 + (i / side) as i32 * spacing + rng.gen_range(-spacing/3..=spacing/3);
+            station
+        }).collect();
+
+        let mut fixedlinks = Vec::new();
+        for _ in 0..spec.transfer_links {
+            let a = rng.gen_range(0..stations.len());
+            let b = nearest(&stations, a, 1)[0];
+            if fixedlinks.iter().any(|l: &FixedLink| (l.a, l.b) == (a, b) || (l.a, l.b) == (b, a)) {
+                continue;
+            }
+            fixedlinks.push(FixedLink::simple(a, b, walk_mins(&stations[a], &stations[b])*60, FixedLinkKind::Walk));
+        }
+
+        let mut services = Vec::new();
+        for _ in 0..spec.lines {
+            // Wander to a random one of the nearest few unvisited stations
+            let mut route = vec![rng.gen_range(0..stations.len())];
+            while route.len() < spec.stops_per_line.min(stations.len()) {
+                let here = *route.last().unwrap();
+                let next: Vec<StationId> = nearest(&stations, here, stations.len()).into_iter()
+                    .filter(|id| !route.contains(id))
+                    .take(4)
+                    .collect();
+                route.push(next[rng.gen_range(0..next.len())]);
+            }
+            if route.len() < 2 {
+                continue;
+            }
+
+            let offset = rng.gen_range(0..spec.headway_mins)*60;
+            for reverse in &[false, true] {
+                if *reverse {
+                    route.reverse();
+                }
+                let mut depart = spec.first_departure.secs() + offset;
+                while depart <= spec.last_departure.secs() {
+                    services.push(Self::service(&stations, &route, RailTime::new(0, 0).add(depart), services.len()));
+                    depart += spec.headway_mins*60;
+                }
+            }
+        }
+
+        Self {
+            stations: StationList::new(stations),
+            fixedlinks,
+            timetable: Timetable::new(None, services, Vec::new())
+        }
+    }
+
+    // A service calling at each of $route, with a minute at each intermediate stop
+    fn service(stations: &[Station], route: &[StationId], depart: RailTime, index: usize) -> Service {
+        let depart = ServiceTime::from(depart);
+        let mut stops = vec![Stop { station: route[0], arrival: depart, departure: depart, platform: None, activity: StopActivity::Normal }];
+        let mut time = depart;
+        for pair in route.windows(2) {
+            let arrival = time.add(rail_mins(&stations[pair[0]], &stations[pair[1]])*60);
+            time = if pair[1] == *route.last().unwrap() { arrival } else { arrival.add(60) };
+            stops.push(Stop { station: pair[1], arrival, departure: time, platform: None, activity: StopActivity::Normal });
+        }
+
+        Service::simple(index as u32, &format!("Z{}", code(index, 5, ALPHANUMERIC)), stops)
+    }
+
+    /** Build the travel graph, giving the same services as loading the files written by `write_cif` */
+    pub fn into_services(self) -> RailServices {
+        let graph = TravelGraph::new(&self.stations, &self.fixedlinks, &self.timetable);
+        RailServices {
+            stations: self.stations,
+            fixedlinks: self.fixedlinks,
+            timetable: self.timetable,
+            graph
+        }
+    }
+
+    /** Write the network as $prefix.MSN, .FLF and .MCA files, to load with `load_services` */
+    pub fn write_cif(&self, prefix: &str) -> io::Result<()> {
+        let mut msn = BufWriter::new(File::create(format!("{}.MSN", prefix))?);
+        writeln!(msn, "/!! Synthetic network")?;
+        writeln!(msn, "{:<80}", "A                             FILE-SPEC=05 1.00")?;
+        for s in self.stations.iter() {
+            let line = format!("A    {:<26}    0{:<7}{:<3}   {:<3}1{:04} 6{:04}{:>2}",
+                s.names[0], s.tiplocs[0], s.crs_code, s.crs_code, s.gref_east, s.gref_north, s.min_change_time);
+            writeln!(msn, "{:<80}", line)?;
+        }
+        msn.flush()?;
+
+        let mut flf = BufWriter::new(File::create(format!("{}.FLF", prefix))?);
+        for l in &self.fixedlinks {
+            let (a, b) = (self.stations.get(l.a).unwrap(), self.stations.get(l.b).unwrap());
+            writeln!(flf, "ADDITIONAL LINK: WALK BETWEEN {} AND {} IN {:>3} MINUTES", a.crs_code, b.crs_code, l.time / 60)?;
+        }
+        flf.flush()?;
+
+        let mut mca = BufWriter::new(File::create(format!("{}.MCA", prefix))?);
+        for service in self.timetable.services() {
+            writeln!(mca, "{:<79}P", format!("BSN{:<6}2001012012311111111 P", service.train_uid))?;
+            let last = service.stops.len() - 1;
+            for (i, stop) in service.stops.iter().enumerate() {
+                let tiploc = &self.stations.get(stop.station).unwrap().tiplocs[0];
+                let (arr, dep) = (stop.arrival.to_24h(), stop.departure.to_24h());
+                let line = match i {
+                    0 => format!("LO{:<7} {} {}", tiploc, dep, dep),
+                    i if i == last => format!("LT{:<7} {} {}", tiploc, arr, arr),
+                    _ => format!("LI{:<7} {} {}      {}{}", tiploc, arr, dep, arr, dep)
+                };
+                writeln!(mca, "{:<80}", line)?;
+            }
+        }
+        mca.flush()
+    }
+}
+
+/** Generate a network from $spec and build its travel graph */
+pub fn generate(spec: &NetworkSpec) -> RailServices {
+    Network::generate(spec).into_services()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_services, Engine, JourneyConstraints, QueryScratch};
+
+    fn stops(rail: &RailServices) -> Vec<String> {
+        rail.timetable.services().iter().map(|s| format!("{} {:?}", s.train_uid, s.stops)).collect()
+    }
+
+    #[test]
+    fn test_generate() {
+        let spec = NetworkSpec::default();
+        let rail = generate(&spec);
+        assert_eq!(rail.stations.count(), 100);
+        assert!(!rail.fixedlinks.is_empty());
+        // 12 lines, both ways, every 30 minutes for 15 hours
+        assert!(rail.timetable.services().len() >= 12*2*30);
+        assert!(rail.timetable.services().iter().all(|s| s.stops.len() == 8));
+
+        assert_eq!(stops(&rail), stops(&generate(&spec)));
+        assert_ne!(stops(&rail), stops(&generate(&NetworkSpec { seed: 1, ..spec })));
+
+        // The end of a line is reachable from its start
+        let service = &rail.timetable.services()[0];
+        let (origin, dest) = (service.stops[0].station, service.stops.last().unwrap().station);
+        let journeys = rail.graph.compute_journeys(&mut QueryScratch::new(&rail.graph), None, service.stops[0].departure.time(), origin, vec![dest], 0, 0, &JourneyConstraints::default());
+        assert!(journeys[0].time != u32::MAX);
+    }
+
+    #[test]
+    fn test_write_cif() {
+        let spec = NetworkSpec { stations: 30, lines: 3, ..NetworkSpec::default() };
+        let network = Network::generate(&spec);
+        let prefix = std::env::temp_dir().join(format!("raildata-synthetic-{}", std::process::id()));
+        let prefix = prefix.to_str().unwrap();
+        network.write_cif(prefix).unwrap();
+
+        let loaded = load_services(prefix);
+        for ext in &["MSN", "FLF", "MCA"] {
+            std::fs::remove_file(format!("{}.{}", prefix, ext)).unwrap();
+        }
+        let loaded = loaded.unwrap();
+        let rail = network.into_services();
+
+        assert_eq!(loaded.stations.count(), rail.stations.count());
+        assert_eq!(loaded.stations.get(7).unwrap().names, rail.stations.get(7).unwrap().names);
+        assert_eq!(loaded.fixedlinks, rail.fixedlinks);
+        assert_eq!(stops(&loaded), stops(&rail));
+    }
+
+    #[test]
+    fn test_connection_scan() {
+        let rail = generate(&NetworkSpec::default());
+        let graph = &rail.graph;
+        let mut scratch = QueryScratch::new(graph);
+        let mut times = |engine, depart, origin, contingency, flexi_depart| graph.compute_all_times_with(engine, &mut scratch, None, depart, origin, contingency, flexi_depart);
+
+        for (origin, depart) in [(0, RailTime::new(7, 0)), (42, RailTime::new(12, 40)), (17, RailTime::new(20, 30))] {
+            assert_eq!(times(Engine::ConnectionScan, depart, origin, 0, 0), times(Engine::Dijkstra, depart, origin, 0, 0));
+            // Dijkstra keeps one arrival per station, so can miss staying aboard a later train rather than changing
+            let scan = times(Engine::ConnectionScan, depart, origin, 5*60, 0);
+            let dijkstra = times(Engine::Dijkstra, depart, origin, 5*60, 0);
+            assert!(scan.iter().zip(&dijkstra).all(|(scan, dijkstra)| scan.is_some() == dijkstra.is_some() && scan <= dijkstra));
+        }
+
+        // The best of leaving on each train within the window
+        let (origin, depart, flexi_depart) = (93, RailTime::new(5, 0), 2*60*60);
+        let mut best = times(Engine::Dijkstra, depart, origin, 0, 0);
+        for stop in rail.timetable.services().iter().flat_map(|s| &s.stops).filter(|stop| stop.station == origin && depart.timetil(&stop.departure.time()) < flexi_depart) {
+            for (best, time) in best.iter_mut().zip(times(Engine::Dijkstra, stop.departure.time(), origin, 0, 1)) {
+                if time.is_some() && (best.is_none() || time < *best) {
+                    *best = time;
+                }
+            }
+        }
+        assert_eq!(times(Engine::ConnectionScan, depart, origin, 0, flexi_depart), best);
+    }
+}
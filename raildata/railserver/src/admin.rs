@@ -0,0 +1,110 @@
+/* Copyright James Lomax 2020 */
+
+// Admin endpoints, enabled by setting server.admin_token and called with it as
+// a bearer token. POST /admin/reload loads data in a background thread and
+// swaps it in once ready, so requests are answered from the current data
+// meanwhile and those in progress finish with it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use axum::extract::{FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use tracing::{error, info, info_span};
+
+use raildata::config::Config;
+use raildata::overlay::TimetableOverlay;
+use raildata::RailServices;
+
+use crate::data::{AppState, Live};
+use crate::load;
+use crate::update::check_services;
+
+/** Extractor passing requests bearing the configured admin token */
+pub struct Admin;
+
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, StatusCode> {
+        // Without a token the endpoints are disabled, answering as if they didn't exist
+        let token = state.config.server.admin_token.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let given = parts.headers.get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // Compared in constant time, so how long it takes gives nothing away about the token
+        match given {
+            Some(given) if bool::from(given.as_bytes().ct_eq(token.as_bytes())) => Ok(Admin),
+            _ => Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+/** Set while a reload is running, so there's only one at a time */
+#[derive(Clone, Default)]
+pub struct Reloading(Arc<AtomicBool>);
+
+#[derive(Deserialize)]
+pub struct ReloadRequest {
+    // File prefix, .zip archive or GTFS feed directory to load, defaulting to where the current data came from
+    #[serde(default)]
+    data: Option<String>
+}
+
+// Load $prefix with the configured groups, change times, bank holidays and overlays
+fn load_configured(config: &Config, prefix: &str) -> Result<(RailServices, Vec<TimetableOverlay>), String> {
+    let mut rail = load(prefix).map_err(|e| e.to_string())?;
+    config.data.add_groups(&mut rail.stations).map_err(|e| e.to_string())?;
+    config.data.set_change_times(&mut rail).map_err(|e| e.to_string())?;
+    rail.graph.set_bank_holidays(config.data.bank_holidays().map_err(|e| e.to_string())?);
+    let overlays = config.data.load_overlays(&rail).map_err(|e| e.to_string())?;
+    Ok((rail, overlays))
+}
+
+/** Load $prefix in the background and serve it once it's ready, keeping the current data if it fails */
+fn spawn_reload(config: Config, live: Live, reloading: Reloading, prefix: String) -> Result<(), String> {
+    thread::Builder::new()
+        .name("reload".to_string())
+        .spawn(move || {
+            let _span = info_span!("reload", %prefix).entered();
+            let loaded = load_configured(&config, &prefix)
+                .and_then(|(rail, overlays)| check_services(&live.get().rail, &rail).map(|()| (rail, overlays)));
+            match loaded {
+                Ok((rail, overlays)) => {
+                    info!(services = rail.timetable.services().len(), "Serving reloaded data");
+                    live.replace(prefix, rail, overlays);
+                }
+                Err(msg) => error!(%msg, "Reload failed, still serving the previous data")
+            }
+            reloading.0.store(false, Ordering::SeqCst);
+        })
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+async fn reload(_admin: Admin, State(state): State<AppState>, req: Option<Json<ReloadRequest>>) -> (StatusCode, String) {
+    let reloading = &state.reloading;
+    if reloading.0.swap(true, Ordering::SeqCst) {
+        return (StatusCode::CONFLICT, "A reload is already running".to_string());
+    }
+    let prefix = req.and_then(|req| req.0.data).unwrap_or_else(|| state.live.get().source.clone());
+    info!(%prefix, "Reloading rail database");
+    match spawn_reload(Config::clone(&state.config), state.live.clone(), reloading.clone(), prefix.clone()) {
+        Ok(()) => (StatusCode::ACCEPTED, format!("Loading {}", prefix)),
+        Err(msg) => {
+            reloading.0.store(false, Ordering::SeqCst);
+            (StatusCode::INTERNAL_SERVER_ERROR, msg)
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/reload", post(reload))
+}
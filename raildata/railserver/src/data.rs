@@ -0,0 +1,130 @@
+/* Copyright James Lomax 2020 */
+
+// The data the server is currently answering from. Scheduled updates swap in
+// a new copy; requests already in progress keep the one they started with.
+
+use std::convert::Infallible;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use chrono::{DateTime, Local};
+
+use raildata::config::Config;
+use raildata::fares::FareTable;
+use raildata::overlay::TimetableOverlay;
+use raildata::results::ResultCache;
+use raildata::{RailServices, RailTime, StationId};
+
+use crate::admin::Reloading;
+use crate::v2::JourneyInfo;
+use crate::ComputeJourneysRequest;
+
+pub struct Data {
+    // The file prefix, archive or feed directory the data was loaded from, and when
+    pub source: String,
+    pub loaded: DateTime<Local>,
+    pub rail: RailServices,
+    pub overlays: Vec<TimetableOverlay>,
+    // Current running over this data, replaced as the real-time source is read again
+    pub realtime: RwLock<Option<Arc<TimetableOverlay>>>,
+    // Counts loads, so per-worker search state sized for an old graph is replaced
+    pub generation: u64
+}
+
+impl Data {
+    pub fn realtime(&self) -> Option<Arc<TimetableOverlay>> {
+        self.realtime.read().unwrap().clone()
+    }
+}
+
+/** Handle on the current data, shared by the request handlers and the update thread */
+#[derive(Clone)]
+pub struct Live(Arc<RwLock<Arc<Data>>>);
+
+impl Live {
+    pub fn new(source: String, rail: RailServices, overlays: Vec<TimetableOverlay>) -> Self {
+        let data = Data { source, loaded: Local::now(), rail, overlays, realtime: RwLock::new(None), generation: 0 };
+        Live(Arc::new(RwLock::new(Arc::new(data))))
+    }
+
+    pub fn get(&self) -> Arc<Data> {
+        self.0.read().unwrap().clone()
+    }
+
+    /**
+     * Answer new requests from $rail, loaded from $source. The old data is freed once requests
+     * using it finish. There's no real-time running over the new data until the source is next read
+     */
+    pub fn replace(&self, source: String, rail: RailServices, overlays: Vec<TimetableOverlay>) {
+        let mut current = self.0.write().unwrap();
+        let generation = current.generation + 1;
+        *current = Arc::new(Data { source, loaded: Local::now(), rail, overlays, realtime: RwLock::new(None), generation });
+    }
+}
+
+/** Everything shared by the request handlers */
+#[derive(Clone)]
+pub struct AppState {
+    pub live: Live,
+    pub config: Arc<Config>,
+    pub results: Arc<Results>,
+    pub reloading: Reloading,
+    // Loaded once at startup from data.fares, to estimate journeys' prices
+    pub fares: Arc<Option<FareTable>>
+}
+
+#[cfg(test)]
+impl AppState {
+    /** State serving a generated network of 16 stations with the default configuration */
+    pub fn synthetic() -> Self {
+        let rail = raildata::testing::generate(&raildata::testing::NetworkSpec { stations: 16, ..Default::default() });
+        AppState {
+            live: Live::new("synthetic".to_string(), rail, Vec::new()),
+            config: Arc::new(Config::default()),
+            results: Arc::new(Results::new(0)),
+            reloading: Reloading::default(),
+            fares: Arc::new(None)
+        }
+    }
+}
+
+/** Extractor giving the data current when the request arrived, which can be moved to the blocking pool for a search */
+#[derive(Clone)]
+pub struct Current(Arc<Data>);
+
+impl Deref for Current {
+    type Target = Data;
+
+    fn deref(&self) -> &Data {
+        &self.0
+    }
+}
+
+impl FromRequestParts<AppState> for Current {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        Ok(Current(state.live.get()))
+    }
+}
+
+// Origin CRS, start time, date, contingency and flexi_depart of a search to every station
+pub type TimesKey = (String, String, Option<String>, Option<u32>, Option<u32>);
+
+/**
+ * Results of recent searches, for answering repeated requests without searching
+ * again. Each is for a generation of the data, so the caches empty when it's replaced.
+ */
+pub struct Results {
+    pub journeys: ResultCache<ComputeJourneysRequest, Vec<JourneyInfo>>,
+    pub times: ResultCache<TimesKey, (StationId, RailTime, Vec<Option<u32>>)>
+}
+
+impl Results {
+    /** Keeping up to $capacity results of each kind */
+    pub fn new(capacity: usize) -> Self {
+        Self { journeys: ResultCache::new(capacity), times: ResultCache::new(capacity) }
+    }
+}
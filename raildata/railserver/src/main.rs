@@ -1,283 +1,434 @@
-#![feature(proc_macro_hygiene, decl_macro)]
 /* Copyright James Lomax 2020 */
 
-#[macro_use] extern crate rocket;
-
-use rocket::State;
-use rocket::response::status;
-use rocket_contrib::json::Json;
-use serde::{Serialize, Deserialize};
-
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{middleware, Router};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{debug, error, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use raildata::config::Config;
+use raildata::overlay::{parse_date, TimetableOverlay, TimetableView};
 use raildata::{
-    load_services, RailServices,
-    Station, StationId, StationList,
-    FixedLinkKind,
-    RailTime, Service, ServiceId,
-    Journey, Link
+    load_dataset_with_warnings, RailServices,
+    StationId, ServiceId, ServiceMode,
+    RailTime, Journey, JourneyConstraints, QueryScratch
 };
 
-fn print_journey(stations: &StationList, journey: &Journey) {
-    let startname = &stations.get(journey.origin).unwrap().crs_code;
-    print!("{}@{}", startname, journey.depart.to_24h());
+use crate::data::{AppState, Data, Live, Results};
+use crate::metrics::METRICS;
 
-    for link in &journey.links {
-        match link {
-            Link::Rail(rl) => {
-                let dstname = &stations.get(rl.dst).unwrap().crs_code;
-                print!(" -[{}@{}]-> {}", rl.service, rl.depart.to_24h(), dstname);
-            }
-            Link::Fixed(fl) => {
-                let dstname = &stations.get(fl.dst).unwrap().crs_code;
-                print!(" -[{:?}]-> {}", fl.kind, dstname);
-            }
-            _ => {
-                print!(" -?-> ?");
-            }
-        }
-    }
+mod admin;
+mod data;
+mod metrics;
+mod realtime;
+mod tiles;
+mod update;
+mod v1;
+mod v2;
 
-    println!(" (total={})", journey.time/60);
-}
+/** A 400 response with a message saying what's wrong with the request */
+pub struct BadRequest(pub String);
 
-#[derive(Serialize)]
-struct StationInfo {
-    crs: String,
-    tiplocs: Vec<String>,
-    names: Vec<String>,
-    min_change_time: u32,
-    gref_east: i32,
-    gref_north: i32
-}
-
-impl StationInfo {
-    fn new(s: &Station) -> Self {
-        Self {
-            crs: s.crs_code.clone(),
-            tiplocs: s.tiplocs.clone(),
-            names: s.names.clone(),
-            min_change_time: s.min_change_time,
-            gref_east: s.gref_east,
-            gref_north: s.gref_north
-        }
+impl IntoResponse for BadRequest {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, self.0).into_response()
     }
 }
 
-#[get("/station/<crs>")]
-fn station_info(rail: State<RailServices>, crs: String) -> Option<Json<StationInfo>> {
-    if let Some(station) = rail.stations.get_by_crs(&crs) {
-        Some(Json(StationInfo::new(station)))
-    } else {
-        None
-    }
+/**
+ * Run the CPU-heavy $f, e.g. a journey search, on the blocking thread pool so it
+ * doesn't hold up the async workers answering other requests
+ */
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f).await.expect("error while searching")
 }
 
-#[get("/lookup/<name>")]
-fn station_lookup(rail: State<RailServices>, name: String) -> Json<Vec<StationInfo>> {
-    let name = name.to_uppercase();
-    let mut searchrs = rail.stations.name_search(&name);
-    let mut infs = Vec::new();
-    if let Some(station) = rail.stations.get_by_crs(&name) {
-        infs.push(StationInfo::new(station));
-        searchrs.remove(&station.id); // Don't repeat the results...
-    }
+#[derive(Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ComputeJourneysRequest {
+    start: String,
+    origin: String,
+    dests: Vec<String>,
+    // Seconds, defaulting to the configured routing settings
+    #[serde(default)]
+    contingency: Option<u32>,
+    #[serde(default)]
+    flexi_depart: Option<u32>,
+    // YYYY-MM-DD, to only use services running that day, from any overlay timetable covering it.
+    // Otherwise every service in the base timetable
+    #[serde(default)]
+    date: Option<String>,
+    // Journeys per destination, each by different trains, the fastest first. Defaults to 1
+    #[serde(default)]
+    alternatives: Option<usize>,
+    // Constraints on the journeys, with stations by CRS
+    #[serde(default)]
+    max_changes: Option<u32>,
+    #[serde(default)]
+    avoid: Vec<String>,
+    #[serde(default)]
+    via: Option<String>,
+    // Percentage of each station's minimum change time to allow, e.g. 150 to be cautious. Defaults to 100
+    #[serde(default)]
+    change_time_percent: Option<u32>,
+    // ATOC codes of the only operators to travel with (any if empty), and of operators not to
+    #[serde(default)]
+    operators: Vec<String>,
+    #[serde(default)]
+    exclude_operators: Vec<String>,
+    // CIF train categories not to travel on, e.g. "BR" for replacement buses, and whether to leave out all buses and ships
+    #[serde(default)]
+    exclude_categories: Vec<String>,
+    #[serde(default)]
+    rail_only: bool,
+    // Whether to leave out services recorded without seats, and whether to
+    // travel by sleepers at all (defaulting to true)
+    #[serde(default)]
+    require_seated: bool,
+    #[serde(default)]
+    allow_sleeper: Option<bool>,
+    // Search the current running from Darwin rather than the timetable. Only for today
+    #[serde(default)]
+    realtime: bool
+}
 
-    for rs in searchrs {
-        infs.push(StationInfo::new(rail.stations.get(rs).unwrap()));
-    }
+// Bounds the work of a single request
+const MAX_ALTERNATIVES: usize = 10;
+const MAX_CHANGE_TIME_PERCENT: u32 = 1000;
+// Every 15 minutes through a day
+const MAX_PROFILE_DEPARTURES: usize = 96;
 
-    Json(infs)
+thread_local! {
+    // Search state for this blocking pool thread and the data generation it was made for, created on the first query it handles
+    static SCRATCH: std::cell::RefCell<Option<(u64, QueryScratch)>> = const { std::cell::RefCell::new(None) };
 }
 
-#[derive(Serialize, Clone)]
-struct ServiceStopInfo {
-    station: String,
-    arrival: String,
-    departure: String
+/** Run $f with this thread's search state, remaking it if $data has been updated since, and record the work its searches did */
+fn with_scratch<T>(data: &Data, f: impl FnOnce(&mut QueryScratch) -> T) -> T {
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        if scratch.as_ref().is_none_or(|(generation, _)| *generation != data.generation) {
+            let mut new = QueryScratch::new(&data.rail.graph);
+            new.collect_stats();
+            *scratch = Some((data.generation, new));
+        }
+        let scratch = &mut scratch.as_mut().unwrap().1;
+        let result = f(scratch);
+        if let Some(stats) = scratch.take_stats() {
+            debug!(searches = stats.searches, settled = stats.settled, queue_peak = stats.queue_peak, "Search work");
+            METRICS.record_search(&stats);
+        }
+        result
+    })
 }
 
-#[derive(Serialize, Clone)]
-struct ServiceInfo {
-    id: ServiceId,
-    train_uid: String,
-    stops: Vec<ServiceStopInfo>
+/** The timetable in effect on $date, or a message if it can't be parsed */
+fn timetable_on<'a>(data: &'a Data, date: Option<&str>) -> Result<TimetableView<'a>, String> {
+    let date = date.map(parse_date).transpose().map_err(|e| e.to_string())?;
+    Ok(TimetableView::on(&data.rail, &data.overlays, date))
 }
 
-impl ServiceInfo {
-    fn new(stations: &StationList, service: &Service) -> Self {
-        Self {
-            id: service.id,
-            train_uid: service.train_uid.clone(),
-            stops: service.stops.iter().map(|stop| {
-                ServiceStopInfo {
-                    station: stations.get(stop.station).unwrap().crs_code.clone(),
-                    arrival: stop.arrival.to_24h(),
-                    departure: stop.departure.to_24h()
-                }
-            }).collect()
-        }
+/**
+ * The timetable for a journey request: the $realtime running if $req asks for it
+ * (from data.realtime(), held by the caller), otherwise the timetable on its date
+ */
+fn request_view<'a>(data: &'a Data, realtime: &'a Option<Arc<TimetableOverlay>>, req: &ComputeJourneysRequest) -> Result<TimetableView<'a>, String> {
+    if !req.realtime {
+        return timetable_on(data, req.date.as_deref());
     }
+    let overlay = realtime.as_deref().ok_or_else(|| "No real-time running available".to_string())?;
+    let date = req.date.as_deref().map(parse_date).transpose().map_err(|e| e.to_string())?;
+    if date.is_some_and(|date| !overlay.covers(date)) {
+        return Err(format!("Real-time running is only for {}", overlay.start));
+    }
+    Ok(TimetableView { rail: &data.rail, overlay: Some(overlay), date: Some(overlay.start) })
 }
 
-#[get("/service/<id>")]
-fn service_info(rail: State<RailServices>, id: ServiceId) -> Option<Json<ServiceInfo>> {
-    if let Some(service) = rail.timetable.services.get(id as usize) {
-        Some(Json(ServiceInfo::new(&rail.stations, service)))
-    } else {
-        None
+/** Services in $view run by operators, of categories or with accommodation $req doesn't want to travel with */
+fn excluded_services(view: &TimetableView, req: &ComputeJourneysRequest) -> Vec<ServiceId> {
+    let allow_sleeper = req.allow_sleeper.unwrap_or(true);
+    if req.operators.is_empty() && req.exclude_operators.is_empty() && req.exclude_categories.is_empty() && !req.rail_only
+            && !req.require_seated && allow_sleeper {
+        return Vec::new();
     }
+    view.services()
+        .filter(|service| {
+            (!req.operators.is_empty() && !req.operators.iter().any(|operator| operator == service.operator))
+                || req.exclude_operators.iter().any(|operator| operator == service.operator)
+                || req.exclude_categories.iter().any(|category| category == service.category)
+                || (req.rail_only && service.mode() != ServiceMode::Rail)
+                || (req.require_seated && !service.is_seated())
+                || (!allow_sleeper && service.is_sleeper())
+        })
+        .map(|service| service.id)
+        .collect()
 }
 
-#[derive(Deserialize)]
-struct ComputeJourneysRequest {
-    start: String,
-    origin: String,
-    dests: Vec<String>,
+// The search asked for by a journey request, with its stations and settings resolved
+struct JourneySearch {
+    start: RailTime,
+    // The origin and each destination may be a group of stations, searched as one
+    origins: Vec<StationId>,
+    destinations: Vec<Vec<StationId>>,
+    constraints: JourneyConstraints,
     contingency: u32,
     flexi_depart: u32
 }
 
-#[derive(Serialize, Clone)]
-struct RailLinkInfo {
-    dst: String,
-    time: u32,
-    depart: String,
-    service: ServiceId
-}
+impl JourneySearch {
+    /** The search for $req in $view, or a message for a bad request */
+    fn new(rail: &RailServices, view: &TimetableView, config: &Config, req: &ComputeJourneysRequest) -> Result<Self, String> {
+        let start = match RailTime::from_24h(&req.start) {
+            Some(st) => st,
+            None => return Err(format!("Could not parse time {}", req.start))
+        };
+
+        let resolve = |code: &String| match rail.stations.resolve(code) {
+            Some(ids) if !ids.is_empty() => Ok(ids),
+            _ => Err(format!("Could not find CRS or group {}", code))
+        };
+        let origins = resolve(&req.origin)?;
+        let destinations = req.dests.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+
+        let station_id = |crs: &String| rail.stations.get_by_crs(crs).map(|s| s.id).ok_or_else(|| format!("Could not find CRS {}", crs));
+        let constraints = JourneyConstraints {
+            max_changes: req.max_changes,
+            avoid: req.avoid.iter().map(station_id).collect::<Result<_, _>>()?,
+            via: req.via.as_ref().map(station_id).transpose()?,
+            exclude_services: excluded_services(view, req),
+            change_time_percent: req.change_time_percent
+        };
+        if req.change_time_percent.is_some_and(|percent| percent > MAX_CHANGE_TIME_PERCENT) {
+            return Err(format!("Change time percent must be at most {}", MAX_CHANGE_TIME_PERCENT));
+        }
 
-#[derive(Serialize, Clone)]
-struct FixedLinkInfo {
-    dst: String,
-    time: u32
+        Ok(Self {
+            start,
+            origins,
+            destinations,
+            constraints,
+            contingency: req.contingency.unwrap_or(config.routing.contingency_mins*60),
+            flexi_depart: req.flexi_depart.unwrap_or(config.routing.flexi_depart_mins*60)
+        })
+    }
 }
 
-#[derive(Serialize, Clone)]
-#[serde(tag = "type")]
-enum LinkInfo {
-    Rail(RailLinkInfo),
-    Walk(FixedLinkInfo),
-    Tube(FixedLinkInfo),
-    Metro(FixedLinkInfo),
-    Bus(FixedLinkInfo),
-    Ferry(FixedLinkInfo),
-    Transfer(FixedLinkInfo),
-    Dummy
-}
+/** The origin and the journey to each destination of $req, or a message for a bad request */
+fn find_journeys(data: &Data, view: &TimetableView, config: &Config, req: &ComputeJourneysRequest)
+        -> Result<Vec<Vec<Journey>>, String>
+{
+    let _span = info_span!("computejourneys", origin = %req.origin, dests = req.dests.len()).entered();
+    let JourneySearch { start: start_time, origins: origin_ids, destinations: dst_groups, constraints, contingency, flexi_depart } =
+        JourneySearch::new(&data.rail, view, config, req)?;
 
-impl LinkInfo {
-    fn new(stations: &StationList, link: &Link) -> Self {
-        match link {
-            Link::Rail(rl) => {
-                LinkInfo::Rail(RailLinkInfo {
-                    dst: stations.get(rl.dst).unwrap().crs_code.clone(),
-                    time: rl.time,
-                    depart: rl.depart.to_24h(),
-                    service: rl.service
-                })
-            }
-            Link::Fixed(fl) => {
-                let l = FixedLinkInfo {
-                    dst: stations.get(fl.dst).unwrap().crs_code.clone(),
-                    time: fl.time
-                };
-
-                match fl.kind {
-                    FixedLinkKind::Walk => LinkInfo::Walk(l),
-                    FixedLinkKind::Tube => LinkInfo::Tube(l),
-                    FixedLinkKind::Metro => LinkInfo::Metro(l),
-                    FixedLinkKind::Bus => LinkInfo::Bus(l),
-                    FixedLinkKind::Ferry => LinkInfo::Ferry(l),
-                    FixedLinkKind::Transfer => LinkInfo::Transfer(l)
+    let alternatives = req.alternatives.unwrap_or(1);
+    if alternatives == 0 || alternatives > MAX_ALTERNATIVES {
+        return Err(format!("Alternatives must be from 1 to {}", MAX_ALTERNATIVES));
+    }
+    let journeys: Vec<Vec<Journey>> = with_scratch(data, |scratch| {
+        let graph = view.graph();
+        if alternatives == 1 {
+            let journeys = graph.compute_group_journeys(scratch, view.date, start_time, &origin_ids, &dst_groups, contingency, flexi_depart, &constraints);
+            journeys.into_iter().map(|journey| vec![journey]).collect()
+        } else {
+            // Alternatives from each origin to each member of each group, keeping the fastest of them all
+            let dst_ids: Vec<StationId> = dst_groups.iter().flatten().copied().collect();
+            let mut found: Vec<Vec<Journey>> = dst_ids.iter().map(|_| Vec::new()).collect();
+            for origin_id in &origin_ids {
+                let journeys = graph.compute_alternatives(scratch, view.date, start_time, *origin_id, dst_ids.clone(), contingency, flexi_depart, &constraints, alternatives);
+                for (found, journeys) in found.iter_mut().zip(journeys) {
+                    found.extend(journeys);
                 }
             }
-            Link::Dummy => LinkInfo::Dummy
+
+            let mut found = found.into_iter();
+            dst_groups.iter().map(|group| {
+                let mut journeys: Vec<Journey> = found.by_ref().take(group.len()).flatten().collect();
+                journeys.sort_by_key(|journey| journey.time);
+                journeys.truncate(alternatives);
+                journeys
+            }).collect()
         }
-    }
-}
+    });
+    METRICS.record_journeys(journeys.iter().map(Vec::len).sum());
 
-#[derive(Serialize, Clone)]
-struct JourneyInfo {
-    origin: String,
-    depart: String,
-    time: u32,
-    links: Vec<LinkInfo>
+    Ok(journeys)
 }
 
-#[post("/computejourneys", data = "<req>")]
-fn compute_journeys(rail: State<RailServices>, req: Json<ComputeJourneysRequest>) 
-        -> Result<Json<Vec<JourneyInfo>>, status::BadRequest<String>>
+/**
+ * Journeys for $req leaving every $every minutes from its start until $until (HHMM, the same day),
+ * as each departure time with the journey to each destination. Each leaves at its time unless
+ * the request gives a flexi_depart. Journeys still caught from the next time aren't searched again
+ */
+fn find_profile(data: &Data, view: &TimetableView, config: &Config, req: &ComputeJourneysRequest, until: &str, every: u32)
+        -> Result<Vec<(RailTime, Vec<Journey>)>, String>
 {
-    let mut start_time = RailTime::new(0, 0);
-    if let Some(st) = RailTime::from_24h(&req.start) {
-        start_time = st;
-    } else {
-        let msg = format!("Could not parse time {}", req.start);
-        return Err(status::BadRequest(Some(msg)));
+    let _span = info_span!("journeyprofile", origin = %req.origin, dests = req.dests.len(), until, every).entered();
+    let search = JourneySearch::new(&data.rail, view, config, req)?;
+    let until = RailTime::from_24h(until).ok_or_else(|| format!("Could not parse time {}", until))?;
+    if until.secs() < search.start.secs() {
+        return Err("The profile must end after it starts, on the same day".to_string());
     }
-
-    let mut origin_id = 0;
-    if let Some(origin) = rail.stations.get_by_crs(&req.origin) {
-        origin_id = origin.id;
-    } else {
-        let msg = format!("Could not find CRS {}", req.origin);
-        return Err(status::BadRequest(Some(msg)));
+    if every == 0 {
+        return Err("Departures must be at least a minute apart".to_string());
+    }
+    let count = (search.start.timetil(&until) / every.saturating_mul(60)) as usize + 1;
+    if count > MAX_PROFILE_DEPARTURES {
+        return Err(format!("At most {} departure times", MAX_PROFILE_DEPARTURES));
+    }
+    let departs: Vec<RailTime> = (0..count as u32).map(|n| search.start.add(n*every*60)).collect();
+    if req.alternatives.is_some_and(|alternatives| alternatives != 1) {
+        return Err("Profiles give one journey per departure time".to_string());
     }
 
-    let mut dst_ids = Vec::new();
-    for dst in &req.dests {
-        if let Some(s) = rail.stations.get_by_crs(&dst) {
-            dst_ids.push(s.id);
-        } else {
-            let msg = format!("Could not find CRS {}", dst);
-            return Err(status::BadRequest(Some(msg)));
+    let flexi_depart = req.flexi_depart.unwrap_or(0);
+    let profile = with_scratch(data, |scratch| {
+        view.graph().compute_profile(scratch, view.date, &departs, &search.origins, &search.destinations, search.contingency, flexi_depart, &search.constraints)
+    });
+    METRICS.record_journeys(profile.iter().map(Vec::len).sum());
+
+    Ok(departs.into_iter().zip(profile).collect())
+}
+
+const USAGE: &str = "Usage: railserver [--config FILE] [--data PREFIX] [--address ADDRESS] [--port PORT]";
+
+/** Command line options, each overriding the config */
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    data: Option<String>,
+    address: Option<String>,
+    port: Option<u16>
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "--data" => parsed.data = Some(value()?),
+            "--address" => parsed.address = Some(value()?),
+            "--port" => {
+                let port = value()?;
+                parsed.port = Some(port.parse().map_err(|_| format!("Could not parse port '{}'", port))?);
+            }
+            _ => return Err(format!("Unknown argument {}", arg))
         }
     }
+    Ok(parsed)
+}
 
-    let journeys = rail.graph.compute_journeys(start_time, origin_id, dst_ids, req.contingency, req.flexi_depart);
-    let journeys = journeys.iter().map(|journey| {
-        JourneyInfo {
-            origin: rail.stations.get(journey.origin).unwrap().crs_code.clone(),
-            depart: journey.depart.to_24h(),
-            time: journey.time,
-            links: journey.links.iter()
-                    .map(|link| LinkInfo::new(&rail.stations, link))
-                    .collect()
+/** Load the timetable files sharing $prefix, in a .zip archive or a GTFS feed directory */
+fn load(prefix: &str) -> Result<RailServices, raildata::RailDataError> {
+    let mut warnings = Vec::new();
+    let start = Instant::now();
+    let rail = load_dataset_with_warnings(prefix, &mut warnings)?;
+    METRICS.record_load(start.elapsed());
+    if !warnings.is_empty() {
+        warn!(count = warnings.len(), "Records skipped or merged while loading, RUST_LOG=debug to list them");
+        for warning in &warnings {
+            debug!(?warning, "Load warning");
         }
-    }).collect();
+    }
+    Ok(rail)
+}
 
-    Ok(Json(journeys))
+/** Log $msg and exit, for problems with the config or data found on startup */
+fn fail(msg: impl std::fmt::Display) -> ! {
+    error!(%msg, "Could not start the server");
+    process::exit(1)
 }
 
 fn main() {
-    println!("Loading rail database... (this can take a while)");
-    let rail = load_services("../../Starter/out/RJTTF748").unwrap();
-    println!("Loaded {} stations, {} fixed legs and {} services!", rail.stations.count(), rail.fixedlinks.len(), rail.timetable.services.len());
+    // Log level and targets from RUST_LOG, e.g. RUST_LOG=raildata=debug. Span close events give timings
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|msg| fail(format!("{}\n{}", msg, USAGE)));
+    // --config, else raildata.toml (or $RAILDATA_CONFIG) with RAILDATA_* overrides, see raildata::config.
+    // The other arguments override those
+    let mut config = Config::load(args.config.as_deref()).unwrap_or_else(|e| fail(e));
+    if let Some(data) = args.data {
+        config.data.prefix = data;
+    }
+    if let Some(address) = args.address {
+        config.server.address = address;
+    }
+    if let Some(port) = args.port {
+        config.server.port = port;
+    }
+
+    // With scheduled updates, start from the newest snapshot they've written
+    let snapshot = match config.update.hour {
+        Some(_) => update::snapshots(&config).latest().unwrap_or_else(|e| fail(e)),
+        None => None
+    };
+    let prefix = snapshot.unwrap_or_else(|| config.data.prefix.clone());
+
+    info!(%prefix, "Loading rail database... (this can take a while)");
+    let mut rail = load(&prefix).unwrap_or_else(|e| fail(format!("Loading {}: {}", prefix, e)));
     let (total, min, max) = rail.graph.stat_edges();
-    println!("Loaded travel graph with ed.g.es total={} min/max = {}/{}", total, min, max);
-    
-    // let yat_id = rail.stations.get_by_crs("YAT").unwrap().id;
-    // let dest_ids = vec!["BRI", "MAN", "PAD", "TAU", "CBG"].drain(..)
-    //     .map(|crs| rail.stations.get_by_crs(crs).unwrap().id)
-    //     .collect::<Vec<StationId>>();
-    // println!("YAT={} and others={:?}", yat_id, dest_ids);
-
-    // println!("Computing journeys...");
-    // let journeys = rail.graph.compute_journeys(RailTime::new(9,30), yat_id, dest_ids, 15*60, 60*60);
-    // for j in journeys {
-    //     println!("Journey 1 taking {} mins", j.time / 60);
-    //     print_journey(&rail.stations, &j);
-    // }
-
-    let default = rocket_cors::CorsOptions::default();
-    let cors = default.to_cors().expect("error while building CORS object");
-
-    rocket::ignite()
-        .manage(rail)
-        .mount("/", routes![
-            station_info, 
-            station_lookup, 
-            service_info,
-            compute_journeys
-        ])
-        .attach(cors)
-        .launch();
+    info!(total, min, max, "Loaded travel graph edges");
+    config.data.add_groups(&mut rail.stations).unwrap_or_else(|e| fail(e));
+    config.data.set_change_times(&mut rail).unwrap_or_else(|e| fail(e));
+    rail.graph.set_bank_holidays(config.data.bank_holidays().unwrap_or_else(|e| fail(e)));
+    let overlays = config.data.load_overlays(&rail).unwrap_or_else(|e| fail(e));
+    let live = Live::new(prefix, rail, overlays);
+    let fares = config.data.load_fares().unwrap_or_else(|e| fail(e));
+    if let Some(fares) = &fares {
+        info!(pairs = fares.len(), "Loaded fares");
+    }
+    update::spawn(config.clone(), live.clone());
+    realtime::spawn(config.clone(), live.clone());
+
+    let origins = match config.server.cors_origins.as_slice() {
+        [] => AllowOrigin::any(),
+        origins => AllowOrigin::list(origins.iter().map(|origin| {
+            HeaderValue::from_str(origin).unwrap_or_else(|_| fail(format!("Invalid CORS origin '{}'", origin)))
+        }))
+    };
+    // Lookups give their total in a header, which pages on other origins need to read
+    let cors = CorsLayer::new().allow_origin(origins).allow_methods(Any).allow_headers(Any)
+        .expose_headers([HeaderName::from_static("x-total-count")]);
+
+    let address = (config.server.address.clone(), config.server.port);
+    let state = AppState {
+        live,
+        results: Arc::new(Results::new(config.server.result_cache_size)),
+        config: Arc::new(config),
+        reloading: admin::Reloading::default(),
+        fares: Arc::new(fares)
+    };
+    let app = Router::new()
+        // Unprefixed routes are the original v1 API
+        .merge(v1::routes())
+        .nest("/v1", v1::routes())
+        .nest("/v2", v2::routes())
+        .route("/version", get(v2::version))
+        .route("/datareport", get(v2::data_report))
+        .nest("/admin", admin::routes())
+        .merge(tiles::routes())
+        .merge(metrics::routes())
+        .route_layer(middleware::from_fn(metrics::track))
+        .layer(cors)
+        .with_state(state);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap_or_else(|e| fail(e));
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&address).await
+            .unwrap_or_else(|e| fail(format!("Listening on {}:{}: {}", address.0, address.1, e)));
+        info!(address = %address.0, port = address.1, "Serving");
+        axum::serve(listener, app).await.unwrap_or_else(|e| fail(e));
+    });
 }
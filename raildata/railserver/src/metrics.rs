@@ -0,0 +1,161 @@
+/* Copyright James Lomax 2020 */
+
+// Prometheus metrics, served as text from GET /metrics. Requests are counted
+// and timed per route by a middleware layer, searches report the work done by
+// the pathfinder, and the size and load time of the current data are read at
+// each scrape.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+
+use raildata::SearchStats;
+
+use crate::data::{AppState, Current};
+
+/** Metrics of the whole process, shared by every request */
+pub static METRICS: Metrics = Metrics::new();
+
+// Upper bounds in seconds of the request duration histogram's buckets
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    // Requests no slower than each of BUCKETS
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+pub struct Metrics {
+    // By route, method and status
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    // By route
+    durations: Mutex<BTreeMap<String, Histogram>>,
+    journeys: AtomicU64,
+    searches: AtomicU64,
+    settled: AtomicU64,
+    queue_peak: AtomicU64,
+    // Of the last load of the data, in milliseconds
+    load_millis: AtomicU64
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            requests: Mutex::new(BTreeMap::new()),
+            durations: Mutex::new(BTreeMap::new()),
+            journeys: AtomicU64::new(0),
+            searches: AtomicU64::new(0),
+            settled: AtomicU64::new(0),
+            queue_peak: AtomicU64::new(0),
+            load_millis: AtomicU64::new(0)
+        }
+    }
+
+    pub fn record_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        *self.requests.lock().unwrap().entry((route.to_string(), method.to_string(), status)).or_default() += 1;
+        self.durations.lock().unwrap().entry(route.to_string()).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    /** Count $count journeys computed, rather than answered from the result cache */
+    pub fn record_journeys(&self, count: usize) {
+        self.journeys.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_search(&self, stats: &SearchStats) {
+        self.searches.fetch_add(stats.searches, Ordering::Relaxed);
+        self.settled.fetch_add(stats.settled, Ordering::Relaxed);
+        self.queue_peak.fetch_max(stats.queue_peak as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_load(&self, elapsed: Duration) {
+        self.load_millis.store(elapsed.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /** The metrics in the Prometheus text format, with those of the current $data */
+    pub fn render(&self, data: &Current) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+        };
+        metric("railserver_requests_total", "counter", "Requests answered, by route, method and status");
+        metric("railserver_request_duration_seconds", "histogram", "Time taken to answer requests, by route");
+        metric("railserver_journeys_total", "counter", "Journeys computed rather than answered from the result cache");
+        metric("railserver_searches_total", "counter", "Dijkstra searches run");
+        metric("railserver_search_labels_settled_total", "counter", "Labels settled by Dijkstra searches");
+        metric("railserver_search_queue_peak", "gauge", "Most labels queued at once in any search");
+        metric("railserver_data_load_seconds", "gauge", "Time taken by the last load of the data");
+        metric("railserver_data_loaded_timestamp_seconds", "gauge", "When the current data was loaded");
+        metric("railserver_data_generation", "gauge", "Loads of the data since startup, 0 for the first");
+        metric("railserver_stations", "gauge", "Stations in the current data");
+        metric("railserver_services", "gauge", "Services in the current data");
+
+        for ((route, method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "railserver_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}", escape(route), method, status, count);
+        }
+        for (route, histogram) in self.durations.lock().unwrap().iter() {
+            let route = escape(route);
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(out, "railserver_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}", route, bound, count);
+            }
+            let _ = writeln!(out, "railserver_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, histogram.count);
+            let _ = writeln!(out, "railserver_request_duration_seconds_sum{{route=\"{}\"}} {}", route, histogram.sum);
+            let _ = writeln!(out, "railserver_request_duration_seconds_count{{route=\"{}\"}} {}", route, histogram.count);
+        }
+        let _ = writeln!(out, "railserver_journeys_total {}", self.journeys.load(Ordering::Relaxed));
+        let _ = writeln!(out, "railserver_searches_total {}", self.searches.load(Ordering::Relaxed));
+        let _ = writeln!(out, "railserver_search_labels_settled_total {}", self.settled.load(Ordering::Relaxed));
+        let _ = writeln!(out, "railserver_search_queue_peak {}", self.queue_peak.load(Ordering::Relaxed));
+        let _ = writeln!(out, "railserver_data_load_seconds {}", self.load_millis.load(Ordering::Relaxed) as f64 / 1000.0);
+        let _ = writeln!(out, "railserver_data_loaded_timestamp_seconds {}", data.loaded.timestamp());
+        let _ = writeln!(out, "railserver_data_generation {}", data.generation);
+        let _ = writeln!(out, "railserver_stations {}", data.rail.stations.count());
+        let _ = writeln!(out, "railserver_services {}", data.rail.timetable.services().len());
+        out
+    }
+}
+
+// $value for a label, which can't hold unescaped quotes or backslashes
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/** Middleware counting and timing each request by the route it matched */
+pub async fn track(req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str).to_string();
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let response = next.run(req).await;
+    METRICS.record_request(&route, &method, response.status().as_u16(), start.elapsed());
+    response
+}
+
+async fn metrics(data: Current) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], METRICS.render(&data))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/metrics", get(metrics))
+}
@@ -0,0 +1,38 @@
+/* Copyright James Lomax 2020 */
+
+// Real-time running: read the configured Darwin source every few minutes and
+// layer it over the current data for today. Journey requests asking for
+// real-time search this instead of the timetable.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use tracing::{info, warn};
+
+use raildata::config::Config;
+
+use crate::data::Live;
+
+/** Start reading the real-time source, if one is configured */
+pub fn spawn(config: Config, live: Live) {
+    let source = match &config.realtime.source {
+        Some(source) => source.clone(),
+        None => return
+    };
+    info!(%source, refresh_mins = config.realtime.refresh_mins, "Reading real-time running");
+
+    thread::Builder::new()
+        .name("realtime".to_string())
+        .spawn(move || loop {
+            let data = live.get();
+            match config.realtime.load(&data.rail, Local::now().date_naive()) {
+                Ok(overlay) => *data.realtime.write().unwrap() = overlay.map(Arc::new),
+                Err(e) => warn!(%e, "Could not read real-time running, keeping the last read")
+            }
+            drop(data);
+            thread::sleep(Duration::from_secs(config.realtime.refresh_mins.max(1) as u64 * 60));
+        })
+        .expect("error while starting real-time thread");
+}
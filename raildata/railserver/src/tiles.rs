@@ -0,0 +1,64 @@
+/* Copyright James Lomax 2020 */
+
+// Slippy map tiles of travel times from an origin, to lay over a basemap
+// such as OpenStreetMap with e.g. /tiles/KGX/{z}/{x}/{y}.png?start=0900
+
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+
+use raildata::heatmap::{Heatmap, HeatmapStyle};
+
+use crate::data::{AppState, Current};
+use crate::v2::all_times;
+use crate::{blocking, BadRequest};
+
+// Beyond this tiles are only showing the access speed
+const MAX_ZOOM: u32 = 18;
+
+#[derive(Deserialize)]
+pub struct TileQuery {
+    start: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    contingency: Option<u32>,
+    #[serde(default)]
+    flexi_depart: Option<u32>,
+    #[serde(default)]
+    max_time: Option<u32>
+}
+
+/**
+ * Heatmap tile $x, $y at zoom $z of journey times from $origin leaving at $start (HHMM),
+ * with $date, $contingency and $flexi_depart as for the isochrone. $max_time (minutes)
+ * is the time coloured red, beyond which the tile is clear.
+ */
+pub(crate) async fn tile(data: Current, State(state): State<AppState>, Path((origin, z, x, y)): Path<(String, u32, u32, String)>,
+        Query(query): Query<TileQuery>) -> Result<impl IntoResponse, BadRequest>
+{
+    let y: u32 = y.strip_suffix(".png").and_then(|y| y.parse().ok())
+        .ok_or_else(|| BadRequest(format!("Expected a tile like 12.png, not {}", y)))?;
+    if z > MAX_ZOOM || x >= 1 << z || y >= 1 << z {
+        return Err(BadRequest(format!("No tile {}/{}/{}", z, x, y)));
+    }
+
+    let png = blocking(move || {
+        let (_, _, times) = all_times(&data, &state.config, &state.results, &origin, &query.start, query.date.as_deref(),
+            query.contingency, query.flexi_depart)?;
+        let mut style = HeatmapStyle::default();
+        if let Some(minutes) = query.max_time {
+            style.max_time = minutes * 60;
+        }
+
+        Ok(Heatmap::new(&data.rail.stations, &times, style).tile(z, x, y).encode_png().expect("error while encoding tile"))
+    }).await?;
+    Ok(([(header::CONTENT_TYPE, "image/png")], png))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/tiles/{origin}/{z}/{x}/{y}", get(tile))
+}
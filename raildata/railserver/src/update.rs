@@ -0,0 +1,135 @@
+/* Copyright James Lomax 2020 */
+
+// Daily data updates: at the configured hour fetch the latest timetable into
+// a new snapshot, check it loads and looks complete, then swap it in and
+// prune old snapshots. On any failure the server carries on with what it has.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime};
+use tracing::{error, info, info_span, warn};
+
+use raildata::config::Config;
+use raildata::download::{download, extract};
+use raildata::overlay::TimetableOverlay;
+use raildata::snapshot::Snapshots;
+use raildata::{load_services_with_warnings, RailServices};
+
+use crate::data::Live;
+
+// Refuse data with fewer than this fraction of the current services, e.g. a truncated download
+const MIN_SERVICES_RATIO: f64 = 0.5;
+
+/** Check $rail looks complete against the $current data it would replace */
+pub fn check_services(current: &RailServices, rail: &RailServices) -> Result<(), String> {
+    let (services, previous) = (rail.timetable.services().len(), current.timetable.services().len());
+    if (services as f64) < previous as f64 * MIN_SERVICES_RATIO {
+        return Err(format!("New timetable has only {} services against {} now", services, previous));
+    }
+    Ok(())
+}
+
+pub fn snapshots(config: &Config) -> Snapshots {
+    Snapshots::new(&config.update.snapshot_dir, config.update.keep_snapshots)
+}
+
+/** Start the update thread, if updates are configured */
+pub fn spawn(config: Config, live: Live) {
+    let hour = match config.update.hour {
+        Some(hour) => hour,
+        None => return
+    };
+    info!(hour, dir = %config.update.snapshot_dir, "Scheduling daily data updates");
+
+    thread::Builder::new()
+        .name("update".to_string())
+        .spawn(move || loop {
+            thread::sleep(until(Local::now().naive_local(), hour));
+            let _span = info_span!("update").entered();
+            if let Err(msg) = update(&config, &live) {
+                error!(%msg, "Update failed, still serving the previous data");
+            }
+        })
+        .expect("error while starting update thread");
+}
+
+// Time from $now until the next $hour o'clock
+fn until(now: NaiveDateTime, hour: u32) -> Duration {
+    let mut next = now.date().and_hms_opt(hour, 0, 0).unwrap();
+    if next <= now {
+        next += chrono::Duration::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
+
+/** Fetch, check and swap in new data, keeping it as a snapshot */
+pub fn update(config: &Config, live: &Live) -> Result<(), String> {
+    let snapshots = snapshots(config);
+    let name = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let dir = snapshots.start(&name).map_err(|e| e.to_string())?;
+
+    let (prefix, rail, overlays) = match fetch(config, live, &dir) {
+        Ok(loaded) => loaded,
+        Err(msg) => {
+            if let Err(e) = snapshots.discard(&name) {
+                warn!(%e, "Could not remove failed snapshot");
+            }
+            return Err(msg);
+        }
+    };
+
+    snapshots.commit(&name).map_err(|e| e.to_string())?;
+    info!(snapshot = %name, services = rail.timetable.services().len(), "Serving updated data");
+    live.replace(prefix, rail, overlays);
+
+    for removed in snapshots.prune().map_err(|e| e.to_string())? {
+        info!(snapshot = %removed, "Removed old snapshot");
+    }
+    Ok(())
+}
+
+// Download and load the timetable into $dir, checking it against the current data
+fn fetch(config: &Config, live: &Live, dir: &Path) -> Result<(String, RailServices, Vec<TimetableOverlay>), String> {
+    let archive = dir.join("timetable.zip");
+    info!(archive = %archive.display(), "Downloading timetable");
+    download(Path::new(&config.update.credentials), &archive).map_err(|e| e.to_string())?;
+    let prefix = extract(&archive, dir).map_err(|e| e.to_string())?;
+    fs::remove_file(&archive).map_err(|e| e.to_string())?;
+
+    let prefix = prefix.to_string_lossy().to_string();
+    let mut warnings = Vec::new();
+    let mut rail = load_services_with_warnings(&prefix, &mut warnings).map_err(|e| e.to_string())?;
+    if !warnings.is_empty() {
+        warn!(count = warnings.len(), "Records skipped or merged while loading");
+    }
+
+    check_services(&live.get().rail, &rail)?;
+
+    config.data.add_groups(&mut rail.stations).map_err(|e| e.to_string())?;
+    config.data.set_change_times(&mut rail).map_err(|e| e.to_string())?;
+    rail.graph.set_bank_holidays(config.data.bank_holidays().map_err(|e| e.to_string())?);
+    let overlays = config.data.load_overlays(&rail).map_err(|e| e.to_string())?;
+    Ok((prefix, rail, overlays))
+}
+
+#[cfg(test)]
+mod tests {
+    use raildata::testing::{generate, NetworkSpec};
+
+    use super::*;
+
+    #[test]
+    fn test_check_services() {
+        let spec = NetworkSpec { stations: 16, ..Default::default() };
+        let current = generate(&spec);
+        assert!(check_services(&current, &generate(&spec)).is_ok());
+
+        let mut truncated = generate(&spec);
+        let keep = truncated.timetable.services().len() / 3;
+        truncated.timetable.services_mut().truncate(keep);
+        assert!(check_services(&current, &truncated).is_err());
+    }
+}
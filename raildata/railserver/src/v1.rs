@@ -0,0 +1,222 @@
+/* Copyright James Lomax 2020 */
+
+// Original response schema, also served without a version prefix for the
+// existing web client. Each DTO converts from its v2 equivalent.
+
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+
+use raildata::{Platform, StationId, ServiceId};
+
+use crate::data::{AppState, Current};
+use crate::v2;
+use crate::{BadRequest, ComputeJourneysRequest};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Times in v1 are "HHMM"
+fn hhmm(t: &str) -> String {
+    t.replace(':', "")
+}
+
+#[derive(Serialize, Clone)]
+pub struct StationInfo {
+    pub schema_version: u32,
+    pub id: StationId,
+    pub tiplocs: Vec<String>,
+    pub crs: String,
+    pub names: Vec<String>,
+    pub min_change_time: u32,
+    pub gref_east: i32,
+    pub gref_north: i32
+}
+
+impl From<v2::StationInfo> for StationInfo {
+    fn from(s: v2::StationInfo) -> Self {
+        let mut names = vec![s.name];
+        names.extend(s.aliases);
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: s.id,
+            tiplocs: s.tiplocs,
+            crs: s.crs,
+            names,
+            min_change_time: s.min_change_time,
+            gref_east: s.grid.east,
+            gref_north: s.grid.north
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ServiceStopInfo {
+    pub station: String,
+    pub arrival: String,
+    pub departure: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>
+}
+
+#[derive(Serialize, Clone)]
+pub struct ServiceInfo {
+    pub schema_version: u32,
+    pub id: ServiceId,
+    pub train_uid: String,
+    pub operator: String,
+    pub stops: Vec<ServiceStopInfo>
+}
+
+impl From<v2::ServiceInfo> for ServiceInfo {
+    fn from(s: v2::ServiceInfo) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: s.id,
+            train_uid: s.train_uid,
+            operator: s.operator,
+            stops: s.stops.into_iter().map(|stop| {
+                ServiceStopInfo {
+                    station: stop.station,
+                    arrival: hhmm(&stop.arrival),
+                    departure: hhmm(&stop.departure),
+                    platform: stop.platform
+                }
+            }).collect()
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct RailLinkInfo {
+    pub dst: String,
+    pub time: u32,
+    pub depart: String,
+    pub service: ServiceId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depart_platform: Option<Platform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrive_platform: Option<Platform>,
+    // CRS codes of the stations called at on the way to $dst
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calling_at: Vec<String>
+}
+
+#[derive(Serialize, Clone)]
+pub struct FixedLinkInfo {
+    pub dst: String,
+    pub time: u32
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum LinkInfo {
+    Rail(RailLinkInfo),
+    Walk(FixedLinkInfo),
+    Tube(FixedLinkInfo),
+    Metro(FixedLinkInfo),
+    Bus(FixedLinkInfo),
+    Ferry(FixedLinkInfo),
+    Transfer(FixedLinkInfo)
+}
+
+impl From<v2::LegInfo> for LinkInfo {
+    fn from(leg: v2::LegInfo) -> Self {
+        let fixed = FixedLinkInfo { dst: leg.to.clone(), time: leg.duration };
+        // Scheduled buses and ships are still rail links in v1
+        match (leg.mode, leg.service) {
+            (v2::Mode::Rail, _) | (_, Some(_)) => LinkInfo::Rail(RailLinkInfo {
+                dst: leg.to,
+                time: leg.duration,
+                depart: hhmm(&leg.depart),
+                service: leg.service.unwrap_or_default(),
+                depart_platform: leg.depart_platform,
+                arrive_platform: leg.arrive_platform,
+                calling_at: leg.calling_at.into_iter().map(|call| call.station).collect()
+            }),
+            (v2::Mode::Walk, None) => LinkInfo::Walk(fixed),
+            (v2::Mode::Tube, None) => LinkInfo::Tube(fixed),
+            (v2::Mode::Metro, None) => LinkInfo::Metro(fixed),
+            (v2::Mode::Bus, None) => LinkInfo::Bus(fixed),
+            (v2::Mode::Ferry, None) => LinkInfo::Ferry(fixed),
+            (v2::Mode::Transfer, None) => LinkInfo::Transfer(fixed)
+        }
+    }
+}
+
+/** Unreachable destinations have time u32::MAX and no links */
+#[derive(Serialize, Clone)]
+pub struct JourneyInfo {
+    pub schema_version: u32,
+    pub origin: String,
+    pub depart: String,
+    pub time: u32,
+    pub links: Vec<LinkInfo>
+}
+
+impl From<v2::JourneyInfo> for JourneyInfo {
+    fn from(j: v2::JourneyInfo) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            origin: j.origin,
+            depart: j.depart.map(|t| hhmm(&t)).unwrap_or_else(|| "0000".to_string()),
+            time: j.duration.unwrap_or(u32::MAX),
+            links: j.legs.into_iter().map(LinkInfo::from).collect()
+        }
+    }
+}
+
+async fn station_info(data: Current, crs: Path<String>) -> Result<Json<StationInfo>, StatusCode> {
+    v2::station_info(data, crs).await.map(|s| Json(s.0.into()))
+}
+
+async fn station_lookup(data: Current, name: Path<String>, query: Query<v2::LookupQuery>)
+        -> Result<([(&'static str, String); 1], Json<Vec<StationInfo>>), BadRequest>
+{
+    let (headers, found) = v2::station_lookup(data, name, query).await?;
+    Ok((headers, Json(found.0.into_iter().map(StationInfo::from).collect())))
+}
+
+async fn service_info(data: Current, id: Path<ServiceId>) -> Result<Json<ServiceInfo>, StatusCode> {
+    v2::service_info(data, id, Query(v2::ServiceQuery::default())).await.map(|s| Json(s.0.into()))
+}
+
+// The web client has always posted JSON as text/plain, which the Json extractor
+// refuses, so the body is parsed whatever its content type
+async fn compute_journeys(data: Current, state: State<AppState>, body: Bytes)
+        -> Result<Json<Vec<JourneyInfo>>, BadRequest>
+{
+    let req: ComputeJourneysRequest = serde_json::from_slice(&body).map_err(|e| BadRequest(format!("Invalid request: {}", e)))?;
+    let journeys = v2::compute_journeys(data, state, Json(req)).await?;
+    Ok(Json(journeys.0.into_iter().map(JourneyInfo::from).collect()))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/station/{crs}", get(station_info))
+        .route("/lookup/{name}", get(station_lookup))
+        .route("/service/{id}", get(service_info))
+        .route("/computejourneys", post(compute_journeys))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compute_journeys_without_content_type() {
+        let app = routes().with_state(AppState::synthetic());
+
+        let post = |body: &str| Request::post("/computejourneys").body(Body::from(body.to_string())).unwrap();
+        let response = app.clone().oneshot(post(r#"{"start": "0800", "origin": "AAA", "dests": ["AAB"]}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(post("{")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
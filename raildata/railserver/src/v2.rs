@@ -0,0 +1,1102 @@
+/* Copyright James Lomax 2020 */
+
+// Current response schema. Responses are built as these DTOs and older
+// versions convert from them, so new fields only need adding here.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use raildata::compact::{encode_times, manifest_hash};
+use raildata::compare::{compare_journeys, journey_fare};
+use raildata::config::Config;
+use raildata::fares::{FareEstimate, FareTable};
+use raildata::geo::{wgs84_to_osgb36, LatLon};
+use raildata::geojson::stations_geojson;
+use raildata::overlay::TimetableView;
+use raildata::validate::{check, Report};
+use raildata::{
+    Station, StationId, StationList, LookupOptions, LookupOrder,
+    FixedLinkKind,
+    RailTime, Platform, Reservations, Accommodation, Service, ServiceId, ServiceMode, ServiceTime,
+    Journey, JourneyConstraints, Link
+};
+
+use crate::data::{AppState, Current, Results};
+use crate::metrics::METRICS;
+use crate::{blocking, find_journeys, find_profile, request_view, timetable_on, with_scratch, BadRequest, ComputeJourneysRequest};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+fn crs(stations: &StationList, id: StationId) -> String {
+    stations.get(id).unwrap().crs_code.clone()
+}
+
+fn hh_mm(t: &RailTime) -> String {
+    let s = t.to_24h();
+    format!("{}:{}", &s[0..2], &s[2..4])
+}
+
+#[derive(Serialize, Clone)]
+pub struct GridRef {
+    // National Grid units of 100m
+    pub east: i32,
+    pub north: i32
+}
+
+#[derive(Serialize, Clone)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64
+}
+
+#[derive(Serialize, Clone)]
+pub struct StationInfo {
+    pub schema_version: u32,
+    pub id: StationId,
+    pub crs: String,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub tiplocs: Vec<String>,
+    pub min_change_time: u32,
+    pub grid: GridRef,
+    // Absent for the few stations without a grid reference
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>
+}
+
+impl StationInfo {
+    pub fn new(station: &Station) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: station.id,
+            crs: station.crs_code.clone(),
+            name: station.names[0].clone(),
+            aliases: station.names[1..].to_vec(),
+            tiplocs: station.tiplocs.clone(),
+            min_change_time: station.min_change_time,
+            grid: GridRef { east: station.gref_east, north: station.gref_north },
+            location: station.lat_lon().map(|ll| Location { lat: ll.lat, lon: ll.lon })
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct StopInfo {
+    pub station: String,
+    pub arrival: String,
+    pub departure: String,
+    // Days since the service started, 1 after midnight on an overnight service
+    pub arrival_day: u32,
+    pub departure_day: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>
+}
+
+#[derive(Serialize, Clone)]
+pub struct ServiceInfo {
+    pub schema_version: u32,
+    pub id: ServiceId,
+    pub train_uid: String,
+    // ATOC code, empty if unknown
+    pub operator: String,
+    // None if the service runs every day
+    pub calendar: Option<CalendarInfo>,
+    // Classes of seats and of sleeper berths, None for no seats or for no sleeper
+    pub seating: Option<ClassInfo>,
+    pub sleepers: Option<ClassInfo>,
+    // "none", "possible", "recommended", "compulsory" or "bicycles_essential"
+    pub reservations: &'static str,
+    pub stops: Vec<StopInfo>
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassInfo {
+    FirstAndStandard,
+    FirstOnly,
+    StandardOnly
+}
+
+impl ClassInfo {
+    fn new(accommodation: Accommodation) -> Option<Self> {
+        match accommodation {
+            Accommodation::None => None,
+            Accommodation::FirstAndStandard => Some(ClassInfo::FirstAndStandard),
+            Accommodation::FirstOnly => Some(ClassInfo::FirstOnly),
+            Accommodation::StandardOnly => Some(ClassInfo::StandardOnly)
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct CalendarInfo {
+    // YYYY-MM-DD, inclusive
+    pub runs_from: String,
+    pub runs_to: String,
+    // Monday first
+    pub days_run: [bool; 7],
+    // "permanent", "overlay", "new" or "cancellation"
+    pub stp: String
+}
+
+impl ServiceInfo {
+    pub fn new(stations: &StationList, service: &Service) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: service.id,
+            train_uid: service.train_uid.clone(),
+            operator: service.operator.to_string(),
+            calendar: service.calendar.as_ref().map(|calendar| CalendarInfo {
+                runs_from: calendar.runs_from.to_string(),
+                runs_to: calendar.runs_to.to_string(),
+                days_run: calendar.days_run,
+                stp: format!("{:?}", calendar.stp).to_lowercase()
+            }),
+            seating: ClassInfo::new(service.seating),
+            sleepers: ClassInfo::new(service.sleepers),
+            reservations: match service.reservations {
+                Reservations::None => "none",
+                Reservations::Possible => "possible",
+                Reservations::Recommended => "recommended",
+                Reservations::Compulsory => "compulsory",
+                Reservations::BicyclesEssential => "bicycles_essential"
+            },
+            stops: service.stops.iter().map(|stop| {
+                StopInfo {
+                    station: crs(stations, stop.station),
+                    arrival: hh_mm(&stop.arrival.time()),
+                    departure: hh_mm(&stop.departure.time()),
+                    arrival_day: stop.arrival.day(),
+                    departure_day: stop.departure.day(),
+                    platform: stop.platform
+                }
+            }).collect()
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Rail,
+    Walk,
+    Tube,
+    Metro,
+    Bus,
+    Ferry,
+    Transfer
+}
+
+impl From<FixedLinkKind> for Mode {
+    fn from(kind: FixedLinkKind) -> Self {
+        match kind {
+            FixedLinkKind::Walk => Mode::Walk,
+            FixedLinkKind::Tube => Mode::Tube,
+            FixedLinkKind::Metro => Mode::Metro,
+            FixedLinkKind::Bus => Mode::Bus,
+            FixedLinkKind::Ferry => Mode::Ferry,
+            FixedLinkKind::Transfer => Mode::Transfer
+        }
+    }
+}
+
+/** Days count from the day of the search's start time, so an overnight leg can arrive on day 1 */
+#[derive(Serialize, Clone)]
+pub struct LegInfo {
+    pub mode: Mode,
+    pub from: String,
+    pub to: String,
+    pub depart: String,
+    pub depart_day: u32,
+    pub arrive: String,
+    pub arrive_day: u32,
+    // Seconds
+    pub duration: u32,
+    // Seconds spent at $from before the leg departs, e.g. waiting for a connection
+    pub wait: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceId>,
+    // Platforms at $from and $to, for rail legs where they're published
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depart_platform: Option<Platform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrive_platform: Option<Platform>,
+    // Whether the service needs a seat or bicycle booked ahead, always false for other legs
+    pub reservation_compulsory: bool,
+    pub cycle_reservation_required: bool,
+    // Where a rail leg's train calls between $from and $to, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calling_at: Vec<CallInfo>
+}
+
+/** A stop on the way through a leg, with days counted as for the leg */
+#[derive(Serialize, Clone)]
+pub struct CallInfo {
+    pub station: String,
+    pub arrive: String,
+    pub arrive_day: u32,
+    pub depart: String,
+    pub depart_day: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>
+}
+
+/** A journey to one destination. Times and legs are absent if it can't be reached */
+#[derive(Serialize, Clone)]
+pub struct JourneyInfo {
+    pub schema_version: u32,
+    pub origin: String,
+    pub destination: String,
+    pub depart: Option<String>,
+    pub depart_day: Option<u32>,
+    pub arrive: Option<String>,
+    pub arrive_day: Option<u32>,
+    // Date and time of departure and arrival (YYYY-MM-DDTHH:MM), for searches on a date
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depart_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrive_at: Option<String>,
+    // Seconds
+    pub duration: Option<u32>,
+    pub changes: Option<u32>,
+    pub legs: Vec<LegInfo>,
+    // Estimated walk-up prices from where the journey starts to where it ends, when fares are loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fare: Option<FareInfo>,
+    // Slower journeys by other trains, when more than one was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<JourneyInfo>
+}
+
+/** Pence, from the cheapest Anytime fares of any route. None where there's no such ticket */
+#[derive(Serialize, Clone)]
+pub struct FareInfo {
+    pub single: Option<u32>,
+    #[serde(rename = "return")]
+    pub return_fare: Option<u32>
+}
+
+impl From<FareEstimate> for FareInfo {
+    fn from(estimate: FareEstimate) -> Self {
+        Self { single: estimate.single, return_fare: estimate.return_fare }
+    }
+}
+
+impl JourneyInfo {
+    /** A journey from $origin to $destination, each a CRS or group code, that can't be made */
+    pub fn unreachable(origin: &str, destination: &str) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            origin: origin.to_string(),
+            destination: destination.to_string(),
+            depart: None,
+            depart_day: None,
+            arrive: None,
+            arrive_day: None,
+            depart_at: None,
+            arrive_at: None,
+            duration: None,
+            changes: None,
+            legs: Vec::new(),
+            fare: None,
+            alternatives: Vec::new()
+        }
+    }
+
+    /** $journey labelled with the requested $origin and $destination, which may be groups its legs start and end in */
+    pub fn new(view: &TimetableView, origin: &str, destination: &str, journey: &Journey) -> Self {
+        let stations = &view.rail.stations;
+        let mut info = Self::unreachable(origin, destination);
+        if journey.time == u32::MAX {
+            return info;
+        }
+
+        for leg in journey.legs() {
+            let (mode, duration, service, platforms) = match leg.link {
+                Link::Rail(rl) => (Mode::Rail, rl.time, Some(rl.service), (rl.depart_platform, rl.arrive_platform)),
+                Link::Fixed(fl) => (Mode::from(fl.kind), fl.time, None, (None, None)),
+                Link::Dummy => continue
+            };
+            let calling_at = match leg.link {
+                Link::Rail(rl) => rl.calls.iter().map(|call| {
+                    // Days of the search from the seconds into the leg
+                    let day = |time: &ServiceTime| leg.depart_day + (leg.depart.secs() + time.since(&rl.depart)) / DAY;
+                    CallInfo {
+                        station: crs(stations, call.station),
+                        arrive: hh_mm(&call.arrive.time()),
+                        arrive_day: day(&call.arrive),
+                        depart: hh_mm(&call.depart.time()),
+                        depart_day: day(&call.depart),
+                        platform: call.platform
+                    }
+                }).collect(),
+                _ => Vec::new()
+            };
+            let scheduled = service.and_then(|id| view.service(id));
+            let reservations = scheduled.map_or(Reservations::None, |service| service.reservations);
+            // Buses and ships in the timetable are shown as such rather than as trains
+            let mode = match scheduled.map(Service::mode) {
+                Some(ServiceMode::Bus) => Mode::Bus,
+                Some(ServiceMode::Ship) => Mode::Ferry,
+                _ => mode
+            };
+
+            info.legs.push(LegInfo {
+                mode,
+                from: crs(stations, leg.from),
+                to: crs(stations, leg.to),
+                depart: hh_mm(&leg.depart),
+                depart_day: leg.depart_day,
+                arrive: hh_mm(&leg.arrive),
+                arrive_day: leg.arrive_day,
+                duration,
+                wait: leg.wait,
+                service,
+                depart_platform: platforms.0,
+                arrive_platform: platforms.1,
+                reservation_compulsory: reservations == Reservations::Compulsory,
+                cycle_reservation_required: reservations == Reservations::BicyclesEssential,
+                calling_at
+            });
+        }
+
+        let (depart, depart_day) = info.legs.first().map_or((hh_mm(&journey.depart), 0), |leg| (leg.depart.clone(), leg.depart_day));
+        let (arrive, arrive_day) = info.legs.last().map_or((depart.clone(), depart_day), |leg| (leg.arrive.clone(), leg.arrive_day));
+        info.depart = Some(depart);
+        info.depart_day = Some(depart_day);
+        info.arrive = Some(arrive);
+        info.arrive_day = Some(arrive_day);
+        if let Some(date) = view.date {
+            info.depart_at = journey.departure(date).map(|at| at.format("%Y-%m-%dT%H:%M").to_string());
+            info.arrive_at = journey.arrival(date).map(|at| at.format("%Y-%m-%dT%H:%M").to_string());
+        }
+        info.duration = Some(journey.time);
+        info.changes = Some(journey.changes());
+        info
+    }
+
+    /** Estimate the fare from the first leg's station to the last's, valid on $date if given, and of each alternative */
+    pub fn price(&mut self, fares: &FareTable, date: Option<NaiveDate>) {
+        if let (Some(first), Some(last)) = (self.legs.first(), self.legs.last()) {
+            self.fare = fares.estimate(&first.from, &last.to, date).map(FareInfo::from);
+        }
+        for alternative in &mut self.alternatives {
+            alternative.price(fares, date);
+        }
+    }
+
+    /** $self with its fare estimated when there are $fares, as for price */
+    pub fn priced(mut self, fares: &Option<FareTable>, date: Option<NaiveDate>) -> Self {
+        if let Some(fares) = fares {
+            self.price(fares, date);
+        }
+        self
+    }
+
+    /** The first of $journeys with the rest as its alternatives, or an unreachable journey if there are none */
+    pub fn with_alternatives(view: &TimetableView, origin: &str, destination: &str, journeys: &[Journey]) -> Self {
+        let mut infos = journeys.iter().map(|journey| Self::new(view, origin, destination, journey));
+        match infos.next() {
+            Some(mut info) => {
+                info.alternatives = infos.collect();
+                info
+            }
+            None => Self::unreachable(origin, destination)
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct TimetableVersionInfo {
+    // From the extract's header record, dates as YYYY-MM-DD
+    pub file_ref: String,
+    pub mainframe_identity: String,
+    pub extracted: String,
+    pub update: bool,
+    pub start_date: String,
+    pub end_date: String
+}
+
+#[derive(Serialize, Clone)]
+pub struct VersionInfo {
+    pub schema_version: u32,
+    pub server_version: String,
+    // The file prefix, archive or GTFS feed the data was loaded from, and when (RFC 3339)
+    pub source: String,
+    pub loaded: String,
+    pub stations: usize,
+    pub services: usize,
+    // Absent for data without a CIF header, e.g. from GTFS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timetable: Option<TimetableVersionInfo>
+}
+
+pub(crate) async fn version(data: Current) -> Json<VersionInfo> {
+    let timetable = data.rail.timetable.header.as_ref().map(|header| TimetableVersionInfo {
+        file_ref: header.file_ref.clone(),
+        mainframe_identity: header.mainframe_identity.clone(),
+        extracted: header.extracted.to_string(),
+        update: header.update,
+        start_date: header.start_date.to_string(),
+        end_date: header.end_date.to_string()
+    });
+    Json(VersionInfo {
+        schema_version: SCHEMA_VERSION,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        source: data.source.clone(),
+        loaded: data.loaded.to_rfc3339(),
+        stations: data.rail.stations.count(),
+        services: data.rail.timetable.services().len(),
+        timetable
+    })
+}
+
+#[derive(Deserialize)]
+pub struct DataReportQuery {
+    // CRS code of a station every other should be reachable from
+    #[serde(default)]
+    hub: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct DataReport {
+    pub schema_version: u32,
+    // Where the data was loaded from
+    pub source: String,
+    pub errors: usize,
+    pub warnings: usize,
+    #[serde(flatten)]
+    pub report: Report
+}
+
+/** Problems with the current data, as from `raildata validate` (bar those with the records themselves, which aren't kept) */
+pub(crate) async fn data_report(data: Current, Query(query): Query<DataReportQuery>) -> Result<Json<DataReport>, BadRequest> {
+    let hub = match &query.hub {
+        Some(crs) => Some(data.rail.stations.get_by_crs(&crs.to_uppercase()).ok_or_else(|| BadRequest(format!("Could not find CRS {}", crs)))?.id),
+        None => None
+    };
+    blocking(move || {
+        let rail = &data.rail;
+        let report = check(&rail.stations, &rail.fixedlinks, &rail.timetable, hub);
+        Ok(Json(DataReport {
+            schema_version: SCHEMA_VERSION,
+            source: data.source.clone(),
+            errors: report.error_count(),
+            warnings: report.warning_count(),
+            report
+        }))
+    }).await
+}
+
+pub(crate) async fn station_info(data: Current, Path(crs): Path<String>) -> Result<Json<StationInfo>, StatusCode> {
+    data.rail.stations.get_by_crs(&crs).map(|station| Json(StationInfo::new(station))).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Deserialize)]
+pub struct LookupQuery {
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    order: Option<String>,
+    #[serde(default)]
+    crs_only: bool,
+    // min lon,min lat,max lon,max lat as in GeoJSON
+    #[serde(default)]
+    bbox: Option<String>
+}
+
+impl LookupQuery {
+    fn options(&self) -> Result<LookupOptions, BadRequest> {
+        let order = match self.order.as_deref() {
+            None | Some("score") => LookupOrder::Score,
+            Some("name") => LookupOrder::Name,
+            Some(order) => return Err(BadRequest(format!("Unknown order {}, expected score or name", order)))
+        };
+        if self.limit == Some(0) {
+            return Err(BadRequest("limit must be at least 1".to_string()));
+        }
+        let within = self.bbox.as_deref().map(|bbox| {
+            let invalid = || BadRequest(format!("Invalid bbox {}, expected min lon,min lat,max lon,max lat", bbox));
+            let corners = bbox.split(',').map(|n| n.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>().map_err(|_| invalid())?;
+            match corners[..] {
+                [min_lon, min_lat, max_lon, max_lat] if min_lon <= max_lon && min_lat <= max_lat =>
+                    Ok((LatLon { lat: min_lat, lon: min_lon }, LatLon { lat: max_lat, lon: max_lon })),
+                _ => Err(invalid())
+            }
+        }).transpose()?;
+        Ok(LookupOptions { order, crs_only: self.crs_only, within, offset: self.offset, limit: self.limit })
+    }
+}
+
+/**
+ * Stations for $name: the exact CRS match first (if any), then name matches best first, or
+ * by name with order=name. Filtered to those with a CRS code by crs_only=true and to a bbox
+ * if given, then paged by $offset and $limit (default all). X-Total-Count gives the matches over all pages.
+ */
+pub(crate) async fn station_lookup(data: Current, Path(name): Path<String>, Query(query): Query<LookupQuery>)
+        -> Result<([(&'static str, String); 1], Json<Vec<StationInfo>>), BadRequest>
+{
+    let stations = &data.rail.stations;
+    let (found, total) = stations.lookup(&name, &query.options()?);
+    let found = found.into_iter().map(|id| StationInfo::new(stations.get(id).unwrap())).collect();
+    Ok(([("x-total-count", total.to_string())], Json(found)))
+}
+
+const DAY: u32 = 24*60*60;
+
+// Minutes between the departure times of a journey profile when the request doesn't say
+const DEFAULT_PROFILE_EVERY: u32 = 15;
+
+// Stations returned by nearest when the request doesn't say, and at most
+const DEFAULT_NEAREST: usize = 5;
+const MAX_NEAREST: usize = 50;
+
+#[derive(Serialize, Clone)]
+pub struct NearbyStationInfo {
+    pub station: StationInfo,
+    // Metres from the requested point
+    pub distance: u32
+}
+
+#[derive(Deserialize)]
+pub struct NearestQuery {
+    lat: f64,
+    lon: f64,
+    #[serde(default)]
+    n: Option<usize>
+}
+
+/** The $n (default 5) stations closest to a WGS84 $lat, $lon, closest first, e.g. for a point clicked on a map */
+pub(crate) async fn nearest(data: Current, Query(query): Query<NearestQuery>) -> Result<Json<Vec<NearbyStationInfo>>, BadRequest> {
+    let NearestQuery { lat, lon, n } = query;
+    let n = n.unwrap_or(DEFAULT_NEAREST);
+    if n == 0 || n > MAX_NEAREST {
+        return Err(BadRequest(format!("n must be from 1 to {}", MAX_NEAREST)));
+    }
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(BadRequest(format!("Invalid location {}, {}", lat, lon)));
+    }
+
+    let stations = &data.rail.stations;
+    let (east, north) = wgs84_to_osgb36(LatLon { lat, lon });
+    Ok(Json(stations.nearest(n, east, north).into_iter().map(|(id, distance)| NearbyStationInfo {
+        station: StationInfo::new(stations.get(id).unwrap()),
+        distance: distance.round() as u32
+    }).collect()))
+}
+
+#[derive(Serialize, Clone)]
+pub struct DepartureInfo {
+    pub service: ServiceId,
+    pub train_uid: String,
+    pub operator: String,
+    pub departure: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    // CRS of the station the service ends at
+    pub destination: String
+}
+
+#[derive(Serialize, Clone)]
+pub struct DepartureBoardInfo {
+    pub schema_version: u32,
+    pub station: String,
+    pub from: String,
+    // Minutes
+    pub window: u32,
+    pub departures: Vec<DepartureInfo>
+}
+
+#[derive(Deserialize)]
+pub struct DeparturesQuery {
+    from: String,
+    #[serde(default)]
+    window: Option<u32>
+}
+
+/**
+ * Services leaving $crs within $window minutes (default 60, at most a day) of $from (HHMM),
+ * in departure order. These are from the base timetable whatever days they run.
+ */
+pub(crate) async fn departures(data: Current, Path(crs): Path<String>, Query(query): Query<DeparturesQuery>)
+        -> Result<Json<DepartureBoardInfo>, BadRequest>
+{
+    let stations = &data.rail.stations;
+    let station = stations.get_by_crs(&crs).ok_or_else(|| BadRequest(format!("Could not find CRS {}", crs)))?;
+    let start = RailTime::from_24h(&query.from).ok_or_else(|| BadRequest(format!("Could not parse time {}", query.from)))?;
+    let window = query.window.unwrap_or(60);
+    if window == 0 || window > 24*60 {
+        return Err(BadRequest("window must be from 1 to 1440 minutes".to_string()));
+    }
+
+    let departures = data.rail.timetable.departures(station.id, start, window*60).into_iter().map(|departure| DepartureInfo {
+        service: departure.service.id,
+        train_uid: departure.service.train_uid.clone(),
+        operator: departure.service.operator.to_string(),
+        departure: hh_mm(&departure.stop.departure.time()),
+        platform: departure.stop.platform,
+        destination: stations.get(departure.destination).unwrap().crs_code.clone()
+    }).collect();
+    Ok(Json(DepartureBoardInfo { schema_version: SCHEMA_VERSION, station: station.crs_code.clone(), from: hh_mm(&start), window, departures }))
+}
+
+#[derive(Deserialize, Default)]
+pub struct ServiceQuery {
+    #[serde(default)]
+    date: Option<String>
+}
+
+/** A service running on $date (YYYY-MM-DD), by default in the base timetable. Overlay services are only found on their dates */
+pub(crate) async fn service_info(data: Current, Path(id): Path<ServiceId>, Query(query): Query<ServiceQuery>)
+        -> Result<Json<ServiceInfo>, StatusCode>
+{
+    let view = timetable_on(&data, query.date.as_deref()).map_err(|_| StatusCode::NOT_FOUND)?;
+    view.service(id).map(|service| Json(ServiceInfo::new(&data.rail.stations, service))).ok_or(StatusCode::NOT_FOUND)
+}
+
+/**
+ * Every schedule of the train $uid, e.g. its permanent schedule with overlays and
+ * cancellations of it, with their calendars. Unlike service IDs, train UIDs are the
+ * same between loads. $date is as for service_info. Not found if there are none
+ */
+pub(crate) async fn service_by_uid(data: Current, Path(uid): Path<String>, Query(query): Query<ServiceQuery>)
+        -> Result<Json<Vec<ServiceInfo>>, StatusCode>
+{
+    let view = timetable_on(&data, query.date.as_deref()).map_err(|_| StatusCode::NOT_FOUND)?;
+    let services = view.services_by_uid(&uid.to_uppercase());
+    if services.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(services.into_iter().map(|service| ServiceInfo::new(&data.rail.stations, service)).collect()))
+}
+
+/** The journey from the origin to each destination, searched on the blocking pool unless an identical request is cached */
+pub(crate) async fn compute_journeys(data: Current, State(state): State<AppState>, Json(req): Json<ComputeJourneysRequest>)
+        -> Result<Json<Vec<JourneyInfo>>, BadRequest>
+{
+    blocking(move || {
+        let search = || {
+            let realtime = data.realtime();
+            let view = request_view(&data, &realtime, &req).map_err(BadRequest)?;
+            let journeys = find_journeys(&data, &view, &state.config, &req).map_err(BadRequest)?;
+            Ok(req.dests.iter().zip(&journeys).map(|(dest, journeys)| {
+                JourneyInfo::with_alternatives(&view, &req.origin, dest, journeys).priced(&state.fares, view.date)
+            }).collect())
+        };
+        // Real-time running is replaced without a new generation of the data, so isn't cached
+        if req.realtime {
+            return search();
+        }
+        state.results.journeys.get_or_try_insert_with(data.generation, req.clone(), search)
+    }).await.map(Json)
+}
+
+#[derive(Deserialize)]
+pub struct JourneyProfileRequest {
+    // As for computejourneys, with start the first departure time
+    #[serde(flatten)]
+    journeys: ComputeJourneysRequest,
+    // HHMM of the last departure time, and minutes between them (default 15)
+    until: String,
+    #[serde(default)]
+    every: Option<u32>
+}
+
+#[derive(Serialize, Clone)]
+pub struct ProfileDepartureInfo {
+    // HH:MM
+    pub depart: String,
+    // By destination: seconds from depart until arriving, and changes of train, None where unreachable
+    pub durations: Vec<Option<u32>>,
+    pub changes: Vec<Option<u32>>,
+    pub journeys: Vec<JourneyInfo>
+}
+
+#[derive(Serialize, Clone)]
+pub struct JourneyProfileInfo {
+    pub schema_version: u32,
+    pub origin: String,
+    pub destinations: Vec<String>,
+    pub departures: Vec<ProfileDepartureInfo>
+}
+
+// Seconds from $depart until $journey arrives, which may be after waiting for its first train
+fn total_time(journey: &Journey, depart: RailTime) -> Option<u32> {
+    if journey.time == u32::MAX {
+        return None;
+    }
+    Some(journey.legs().last().map_or(0, |leg| (leg.arrive_day*DAY + leg.arrive.secs()).saturating_sub(depart.secs())))
+}
+
+/**
+ * How journeys vary through the day: the journey to each destination leaving every $every
+ * minutes from start until $until, e.g. to see when to leave for the fastest commute
+ */
+pub(crate) async fn journey_profile(data: Current, State(state): State<AppState>, Json(req): Json<JourneyProfileRequest>)
+        -> Result<Json<JourneyProfileInfo>, BadRequest>
+{
+    blocking(move || {
+        let realtime = data.realtime();
+        let JourneyProfileRequest { journeys: req, until, every } = req;
+        let view = request_view(&data, &realtime, &req).map_err(BadRequest)?;
+        let profile = find_profile(&data, &view, &state.config, &req, &until, every.unwrap_or(DEFAULT_PROFILE_EVERY)).map_err(BadRequest)?;
+
+        Ok(Json(JourneyProfileInfo {
+            schema_version: SCHEMA_VERSION,
+            origin: req.origin.clone(),
+            destinations: req.dests.clone(),
+            departures: profile.iter().map(|(depart, journeys)| ProfileDepartureInfo {
+                depart: hh_mm(depart),
+                durations: journeys.iter().map(|journey| total_time(journey, *depart)).collect(),
+                changes: journeys.iter().map(|journey| (journey.time != u32::MAX).then(|| journey.changes())).collect(),
+                journeys: req.dests.iter().zip(journeys).map(|(dest, journey)| {
+                    JourneyInfo::new(&view, &req.origin, dest, journey).priced(&state.fares, view.date)
+                }).collect()
+            }).collect()
+        }))
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct JourneyMatrixRequest {
+    start: String,
+    // CRS codes of the rows and columns of the matrix
+    origins: Vec<String>,
+    destinations: Vec<String>,
+    // Seconds, defaulting to the configured routing settings
+    #[serde(default)]
+    contingency: Option<u32>,
+    #[serde(default)]
+    flexi_depart: Option<u32>,
+    // YYYY-MM-DD, as for computejourneys
+    #[serde(default)]
+    date: Option<String>
+}
+
+#[derive(Serialize, Clone)]
+pub struct JourneyMatrixInfo {
+    pub schema_version: u32,
+    pub origins: Vec<String>,
+    pub destinations: Vec<String>,
+    // Seconds from each origin to each destination, None where unreachable
+    pub durations: Vec<Vec<Option<u32>>>,
+    pub journeys: Vec<Vec<JourneyInfo>>
+}
+
+// Bounds the work of a single matrix request
+const MAX_MATRIX_CELLS: usize = 10_000;
+
+/** The best journey from each origin to each destination, with rows by origin. Origins are searched in parallel */
+pub(crate) async fn journey_matrix(data: Current, State(state): State<AppState>, Json(req): Json<JourneyMatrixRequest>)
+        -> Result<Json<JourneyMatrixInfo>, BadRequest>
+{
+    if req.origins.len() * req.destinations.len() > MAX_MATRIX_CELLS {
+        return Err(BadRequest(format!("At most {} origin and destination pairs", MAX_MATRIX_CELLS)));
+    }
+    blocking(move || {
+        let view = timetable_on(&data, req.date.as_deref()).map_err(BadRequest)?;
+        let rail = &data.rail;
+        let start = RailTime::from_24h(&req.start).ok_or_else(|| BadRequest(format!("Could not parse time {}", req.start)))?;
+        let station_id = |code: &String| rail.stations.get_by_crs(code).map(|s| s.id).ok_or_else(|| BadRequest(format!("Could not find CRS {}", code)));
+        let origins = req.origins.iter().map(station_id).collect::<Result<Vec<_>, _>>()?;
+        let destinations = req.destinations.iter().map(station_id).collect::<Result<Vec<_>, _>>()?;
+
+        let config = &state.config;
+        let contingency = req.contingency.unwrap_or(config.routing.contingency_mins*60);
+        let flexi_depart = req.flexi_depart.unwrap_or(config.routing.flexi_depart_mins*60);
+        let matrix = view.graph().compute_journey_matrix(view.date, start, &origins, &destinations, contingency, flexi_depart, &JourneyConstraints::default());
+        METRICS.record_journeys(matrix.iter().map(Vec::len).sum());
+
+        Ok(Json(JourneyMatrixInfo {
+            schema_version: SCHEMA_VERSION,
+            origins: req.origins.clone(),
+            destinations: req.destinations.clone(),
+            durations: matrix.iter().map(|row| {
+                row.iter().map(|journey| Some(journey.time).filter(|time| *time != u32::MAX)).collect()
+            }).collect(),
+            journeys: req.origins.iter().zip(&matrix).map(|(origin, row)| {
+                req.destinations.iter().zip(row).map(|(destination, journey)| {
+                    JourneyInfo::new(&view, origin, destination, journey).priced(&state.fares, view.date)
+                }).collect()
+            }).collect()
+        }))
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct CompareRequest {
+    origin: String,
+    destination: String,
+    start: String,
+    // Seconds, defaulting to the configured flexi_depart and contingency
+    #[serde(default)]
+    window: Option<u32>,
+    #[serde(default)]
+    contingency: Option<u32>,
+    // YYYY-MM-DD, as for computejourneys
+    #[serde(default)]
+    date: Option<String>
+}
+
+/**
+ * The trade-off between duration, changes and, when fares are loaded, the single fare
+ * by the trains taken, for journeys leaving within the window, ordered by departure
+ */
+pub(crate) async fn compare(data: Current, State(state): State<AppState>, Json(req): Json<CompareRequest>)
+        -> Result<Json<Vec<JourneyInfo>>, BadRequest>
+{
+    blocking(move || {
+        let view = timetable_on(&data, req.date.as_deref()).map_err(BadRequest)?;
+        let rail = &data.rail;
+        let start = RailTime::from_24h(&req.start).ok_or_else(|| BadRequest(format!("Could not parse time {}", req.start)))?;
+        let origin = rail.stations.get_by_crs(&req.origin).ok_or_else(|| BadRequest(format!("Could not find CRS {}", req.origin)))?.id;
+        let destination = rail.stations.get_by_crs(&req.destination)
+            .ok_or_else(|| BadRequest(format!("Could not find CRS {}", req.destination)))?.id;
+
+        let window = req.window.unwrap_or(state.config.routing.flexi_depart_mins*60);
+        let contingency = req.contingency.unwrap_or(state.config.routing.contingency_mins*60);
+        let fares = state.fares.as_ref().as_ref();
+        let journeys = with_scratch(&data, |scratch| compare_journeys(&view, scratch, start, window, origin, destination, contingency, fares));
+        METRICS.record_journeys(journeys.len());
+        Ok(Json(journeys.iter().map(|journey| {
+            // Priced as compared, by the trains taken rather than any route between the stations
+            let mut info = JourneyInfo::new(&view, &req.origin, &req.destination, journey);
+            info.fare = fares.and_then(|fares| journey_fare(&view, fares, journey)).map(FareInfo::from);
+            info
+        }).collect()))
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct LatestDepartureRequest {
+    origin: String,
+    destination: String,
+    // HHMM, on $date if given
+    arrive_by: String,
+    // Seconds, defaulting to the configured contingency
+    #[serde(default)]
+    contingency: Option<u32>,
+    // YYYY-MM-DD, as for computejourneys
+    #[serde(default)]
+    date: Option<String>
+}
+
+/** The journey leaving the origin as late as possible while still arriving by arrive_by, unreachable if none does */
+pub(crate) async fn latest_departure(data: Current, State(state): State<AppState>, Json(req): Json<LatestDepartureRequest>)
+        -> Result<Json<JourneyInfo>, BadRequest>
+{
+    blocking(move || {
+        let view = timetable_on(&data, req.date.as_deref()).map_err(BadRequest)?;
+        let rail = &data.rail;
+        let arrive_by = RailTime::from_24h(&req.arrive_by).ok_or_else(|| BadRequest(format!("Could not parse time {}", req.arrive_by)))?;
+        let origin = rail.stations.get_by_crs(&req.origin).ok_or_else(|| BadRequest(format!("Could not find CRS {}", req.origin)))?.id;
+        let destination = rail.stations.get_by_crs(&req.destination)
+            .ok_or_else(|| BadRequest(format!("Could not find CRS {}", req.destination)))?.id;
+
+        let contingency = req.contingency.unwrap_or(state.config.routing.contingency_mins*60);
+        let found = with_scratch(&data, |scratch| view.graph().latest_departure(scratch, view.date, origin, destination, arrive_by, contingency));
+        METRICS.record_journeys(found.iter().count());
+        Ok(Json(match found {
+            Some((depart, journey)) => {
+                // Days count from the departure, which may be the day before
+                let date = view.date.map(|date| if depart.secs() > arrive_by.secs() { date - chrono::Duration::days(1) } else { date });
+                JourneyInfo::new(&TimetableView { date, ..view }, &req.origin, &req.destination, &journey).priced(&state.fares, date)
+            }
+            None => JourneyInfo::unreachable(&req.origin, &req.destination)
+        }))
+    }).await
+}
+
+#[derive(Serialize, Clone)]
+pub struct IsochroneInfo {
+    pub schema_version: u32,
+    pub origin: String,
+    pub depart: String,
+    // Seconds to each station which can be reached, by CRS
+    pub times: BTreeMap<String, u32>
+}
+
+// Journey times in seconds from $crs to every station, as for isochrone. A search, so to be run on the blocking pool
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn all_times(data: &Current, config: &Config, results: &Results, crs: &str, start: &str, date: Option<&str>,
+        contingency: Option<u32>, flexi_depart: Option<u32>) -> Result<(StationId, RailTime, Vec<Option<u32>>), BadRequest>
+{
+    let key = (crs.to_string(), start.to_string(), date.map(str::to_string), contingency, flexi_depart);
+    results.times.get_or_try_insert_with(data.generation, key, || {
+        let view = timetable_on(data, date).map_err(BadRequest)?;
+        let depart = RailTime::from_24h(start).ok_or_else(|| BadRequest(format!("Could not parse time {}", start)))?;
+        let origin = data.rail.stations.get_by_crs(crs).ok_or_else(|| BadRequest(format!("Could not find CRS {}", crs)))?;
+
+        let contingency = contingency.unwrap_or(config.routing.contingency_mins*60);
+        let flexi_depart = flexi_depart.unwrap_or(config.routing.flexi_depart_mins*60);
+        let times = with_scratch(data, |scratch| {
+            view.graph().compute_all_times(scratch, view.date, depart, origin.id, contingency, flexi_depart)
+        });
+        Ok((origin.id, depart, times))
+    })
+}
+
+#[derive(Deserialize)]
+pub struct IsochroneQuery {
+    start: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    contingency: Option<u32>,
+    #[serde(default)]
+    flexi_depart: Option<u32>
+}
+
+/**
+ * Journey times from $code to every station, leaving at $start (HHMM). Contingency and
+ * flexi_depart (seconds) default to the configured settings, and $date is as for computejourneys.
+ */
+pub(crate) async fn isochrone(data: Current, State(state): State<AppState>, Path(code): Path<String>, Query(query): Query<IsochroneQuery>)
+        -> Result<Json<IsochroneInfo>, BadRequest>
+{
+    blocking(move || {
+        let (origin, depart, times) = all_times(&data, &state.config, &state.results, &code, &query.start, query.date.as_deref(),
+            query.contingency, query.flexi_depart)?;
+        let stations = &data.rail.stations;
+
+        Ok(Json(IsochroneInfo {
+            schema_version: SCHEMA_VERSION,
+            origin: crs(stations, origin),
+            depart: hh_mm(&depart),
+            times: stations.iter().zip(times).filter_map(|(station, time)| time.map(|t| (station.crs_code.clone(), t))).collect()
+        }))
+    }).await
+}
+
+/**
+ * Journey times as for isochrone, as the compact binary payload of `raildata::compact`: minutes
+ * for every station in the order of the stations manifest. Gzipped for clients which accept it
+ */
+pub(crate) async fn isochrone_compact(data: Current, State(state): State<AppState>, Path(code): Path<String>, Query(query): Query<IsochroneQuery>,
+        headers: HeaderMap) -> Result<impl IntoResponse, BadRequest>
+{
+    let gzip = headers.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()).is_some_and(|value| value.contains("gzip"));
+    let body = blocking(move || {
+        let (_, _, times) = all_times(&data, &state.config, &state.results, &code, &query.start, query.date.as_deref(),
+            query.contingency, query.flexi_depart)?;
+        let bytes = encode_times(&data.rail.stations, &times);
+        if !gzip {
+            return Ok(bytes);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes).and_then(|_| encoder.finish()).map_err(|e| BadRequest(e.to_string()))
+    }).await?;
+
+    let encoding = if gzip { "gzip" } else { "identity" };
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream"), (header::CONTENT_ENCODING, encoding), (header::VARY, "accept-encoding")], body))
+}
+
+#[derive(Serialize, Clone)]
+pub struct StationManifestInfo {
+    pub schema_version: u32,
+    // Of the stations in this order, as in compact payloads
+    pub hash: u32,
+    // Columns by station ID, with no location for the few stations without a grid reference
+    pub crs: Vec<String>,
+    pub name: Vec<String>,
+    pub lat: Vec<Option<f64>>,
+    pub lon: Vec<Option<f64>>
+}
+
+/** Every station in ID order, to line up with compact isochrones. The order changes when the data is reloaded, with the hash */
+pub(crate) async fn stations_manifest(data: Current) -> Json<StationManifestInfo> {
+    let stations = &data.rail.stations;
+    let locations: Vec<_> = stations.iter().map(Station::lat_lon).collect();
+    Json(StationManifestInfo {
+        schema_version: SCHEMA_VERSION,
+        hash: manifest_hash(stations),
+        crs: stations.iter().map(|station| station.crs_code.clone()).collect(),
+        name: stations.iter().map(|station| station.names[0].clone()).collect(),
+        lat: locations.iter().map(|ll| ll.map(|ll| ll.lat)).collect(),
+        lon: locations.iter().map(|ll| ll.map(|ll| ll.lon)).collect()
+    })
+}
+
+#[derive(Deserialize)]
+pub struct StationsGeojsonQuery {
+    #[serde(default)]
+    origin: Option<String>,
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    contingency: Option<u32>,
+    #[serde(default)]
+    flexi_depart: Option<u32>
+}
+
+/**
+ * All stations with a location as a GeoJSON FeatureCollection. Given an $origin and $start,
+ * only the stations reached are included, with their journey times as for isochrone.
+ */
+pub(crate) async fn stations_geojson_map(data: Current, State(state): State<AppState>, Query(query): Query<StationsGeojsonQuery>)
+        -> Result<impl IntoResponse, BadRequest>
+{
+    let collection = blocking(move || {
+        let times = match (&query.origin, &query.start) {
+            (Some(origin), Some(start)) => Some(all_times(&data, &state.config, &state.results, origin, start, query.date.as_deref(),
+                query.contingency, query.flexi_depart)?.2),
+            (None, None) => None,
+            _ => return Err(BadRequest("origin and start must be given together".to_string()))
+        };
+        Ok(stations_geojson(&data.rail.stations, times.as_deref()))
+    }).await?;
+    Ok(([(header::CONTENT_TYPE, "application/geo+json")], Json(collection)))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/version", get(version))
+        .route("/datareport", get(data_report))
+        .route("/station/{crs}", get(station_info))
+        .route("/lookup/{name}", get(station_lookup))
+        .route("/nearest", get(nearest))
+        .route("/departures/{crs}", get(departures))
+        .route("/service/{id}", get(service_info))
+        .route("/service/uid/{uid}", get(service_by_uid))
+        .route("/computejourneys", post(compute_journeys))
+        .route("/journeyprofile", post(journey_profile))
+        .route("/journeymatrix", post(journey_matrix))
+        .route("/compare", post(compare))
+        .route("/latestdeparture", post(latest_departure))
+        .route("/isochrone/{crs}", get(isochrone))
+        .route("/isochrone/{crs}/compact", get(isochrone_compact))
+        .route("/stations/manifest", get(stations_manifest))
+        .route("/stations.geojson", get(stations_geojson_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_data_report() {
+        // Served without an admin token configured
+        let app = routes().with_state(AppState::synthetic());
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(get("/datareport?hub=AAA")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(app.oneshot(get("/datareport?hub=ZZZ")).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+}
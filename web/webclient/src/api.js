@@ -1,50 +1,73 @@
-import * as geotools from "@/thirdparty/geotools2.js";
-
-const API = "http://localhost:8000";
-
-export class StationInfo {
-    constructor(jsobj) {
-        this.crs = jsobj.crs;
-        this.names = jsobj.names;
-        this.gref_east = jsobj.gref_east;
-        this.gref_north = jsobj.gref_north;
-    }
-
-    getLatLon() {
-        let wgs84 = geotools.osgb2wgs84(this.gref_east*100, this.gref_north*100);
-        console.log("osgb east=" + this.gref_east + " north=" + this.gref_north);
-        console.log("wgs lat=" + wgs84.latitude + " and long=" + wgs84.longitude);
-        return [wgs84.longitude, wgs84.latitude];
-    }
-}
-
-export async function stationSearch(search) {
-    let rs = await fetch(`${API}/lookup/${search}`);
-    let r = await rs.json();
-    return r.map((v) => new StationInfo(v));
-}
-
-export class ComputeJourneyRequest {
-    constructor(start_time, origin, contingency, flexi_depart) {
-        this.start = start_time;
-        this.origin = origin;
-        this.dests = [];
-        this.contingency = contingency;
-        this.flexi_depart = flexi_depart;
-    }
-}
-
-export async function computeJourneys(journeyReq) {
-    let rs = await fetch(`${API}/computejourneys`, {
-        method: "POST",
-        body: JSON.stringify(journeyReq)
-    });
-    let journeys = await rs.json();
-    let dsts = {};
-    for (const journey of journeys) {
-        const dst = journey.links[journey.links.length-1].dst;
-        const t = journey.time / 60;
-        dsts[dst] = t;
-    }
-    return dsts;
-}
+import * as geotools from "@/thirdparty/geotools2.js";
+
+const API = "http://localhost:8000";
+
+export class StationInfo {
+    constructor(jsobj) {
+        this.crs = jsobj.crs;
+        this.names = jsobj.names;
+        this.gref_east = jsobj.gref_east;
+        this.gref_north = jsobj.gref_north;
+    }
+
+    getLatLon() {
+        let wgs84 = geotools.osgb2wgs84(this.gref_east*100, this.gref_north*100);
+        console.log("osgb east=" + this.gref_east + " north=" + this.gref_north);
+        console.log("wgs lat=" + wgs84.latitude + " and long=" + wgs84.longitude);
+        return [wgs84.longitude, wgs84.latitude];
+    }
+}
+
+export async function stationSearch(search) {
+    let rs = await fetch(`${API}/v1/lookup/${search}`);
+    let r = await rs.json();
+    return r.map((v) => new StationInfo(v));
+}
+
+export class ComputeJourneyRequest {
+    constructor(start_time, origin, contingency, flexi_depart) {
+        this.start = start_time;
+        this.origin = origin;
+        this.dests = [];
+        this.contingency = contingency;
+        this.flexi_depart = flexi_depart;
+    }
+}
+
+export async function computeJourneys(journeyReq) {
+    let rs = await fetch(`${API}/v1/computejourneys`, {
+        method: "POST",
+        headers: {"Content-Type": "application/json"},
+        body: JSON.stringify(journeyReq)
+    });
+    let journeys = await rs.json();
+    let dsts = {};
+    for (const journey of journeys) {
+        const dst = journey.links[journey.links.length-1].dst;
+        const t = journey.time / 60;
+        dsts[dst] = t;
+    }
+    return dsts;
+}
+
+// Options between two stations leaving within window seconds of start, trading
+// duration against changes and, where the server has fares, price. Times are
+// "HH:MM", durations in seconds and fares in pence
+export async function compareJourneys(origin, destination, start_time, window) {
+    let rs = await fetch(`${API}/v2/compare`, {
+        method: "POST",
+        headers: {"Content-Type": "application/json"},
+        body: JSON.stringify({ origin, destination, start: start_time, window })
+    });
+    return await rs.json();
+}
+
+// Of compareJourneys' options, the fastest, or with cheapest the one with the
+// cheapest single fare (the fastest of those). Options without a fare come last
+export function pickOption(options, cheapest) {
+    const single = (option) => option.fare && option.fare.single != null ? option.fare.single : Infinity;
+    const better = (a, b) => cheapest && single(a) !== single(b)
+        ? single(a) < single(b)
+        : a.duration < b.duration;
+    return options.reduce((best, option) => best === undefined || better(option, best) ? option : best, undefined);
+}